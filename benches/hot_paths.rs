@@ -0,0 +1,121 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use yobmef::chess::{Board, Movement};
+use yobmef::eval;
+use yobmef::movegen::{self, gen_moves_once, MoveGen};
+use yobmef::search::{SearchLimits, Searcher};
+use yobmef::zobrist;
+
+// Benchmarks for the code every node runs, so a slowdown shows up as a number
+// instead of a feeling. `cargo bench`, or `cargo bench -- movegen` for some.
+
+const POSITIONS: &[(&str, &str)] = &[
+    (
+        "startpos",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    ),
+    // Castling, pins and en passant all over the place.
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ),
+    ("endgame", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+    (
+        "promotions",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    ),
+    // Evasions only.
+    ("check", "4k3/8/8/8/1b6/8/3K4/8 w - - 0 1"),
+];
+
+fn boards() -> Vec<(&'static str, Board)> {
+    gen_moves_once();
+    POSITIONS
+        .iter()
+        .map(|(name, fen)| (*name, Board::from_fen(fen).unwrap()))
+        .collect()
+}
+
+fn bench_movegen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("movegen");
+    for (name, board) in boards() {
+        group.bench_with_input(BenchmarkId::new("legal", name), &board, |b, board| {
+            b.iter(|| MoveGen::new_legal(black_box(board)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("count", name), &board, |b, board| {
+            b.iter(|| movegen::count_legal_moves(black_box(board)))
+        });
+        group.bench_with_input(BenchmarkId::new("has_legal", name), &board, |b, board| {
+            b.iter(|| movegen::has_legal_move(black_box(board)))
+        });
+        group.bench_with_input(BenchmarkId::new("pseudolegal", name), &board, |b, board| {
+            b.iter(|| movegen::get_pseudolegal_moves(black_box(board)).len())
+        });
+    }
+    group.finish();
+
+    let board = Board::from_start_pos();
+    c.bench_function("perft 3", |b| {
+        b.iter(|| movegen::perft(black_box(&board), 3))
+    });
+}
+
+// We copy-make, there's no unmake to bench.
+fn bench_make_move(c: &mut Criterion) {
+    let mut group = c.benchmark_group("make_move");
+    for (name, board) in boards() {
+        let moves: Vec<Movement> = MoveGen::new_legal(&board).collect();
+        group.bench_with_input(BenchmarkId::new("all", name), &board, |b, board| {
+            b.iter(|| {
+                for mv in &moves {
+                    black_box(board.make_move(black_box(mv)));
+                }
+            })
+        });
+    }
+    group.finish();
+
+    let board = boards()[1].1.clone();
+    c.bench_function("zobrist hash", |b| {
+        b.iter(|| zobrist::hash(black_box(&board)))
+    });
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    for (name, board) in boards() {
+        group.bench_with_input(BenchmarkId::new("static", name), &board, |b, board| {
+            b.iter(|| eval::get_score_ongoing(black_box(board)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    // Up to a few hundred ms each, the default 100 samples would take minutes.
+    // Kiwipete takes seconds, so it's left out.
+    group.sample_size(10);
+    let mut searcher = Searcher::new();
+    for (name, board) in boards() {
+        if !["startpos", "endgame"].contains(&name) {
+            continue;
+        }
+        group.bench_with_input(BenchmarkId::new("depth 4", name), &board, |b, board| {
+            b.iter(|| {
+                // Same tree every time, not one cut short by the last run's TP.
+                searcher.clear_hash();
+                searcher.go(black_box(board), SearchLimits::depth(4))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_movegen,
+    bench_make_move,
+    bench_eval,
+    bench_search
+);
+criterion_main!(benches);
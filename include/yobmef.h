@@ -0,0 +1,76 @@
+/*
+ * C API for embedding yobmef, see src/ffi.rs. Link against the cdylib
+ * (libyobmef.so, yobmef.dll, libyobmef.dylib) built by `cargo build --release`.
+ *
+ * Functions returning int return 0 on success and -1 on failure, with the
+ * reason in yobmef_last_error. Strings returned stay valid until the next call
+ * on the same engine.
+ */
+
+#ifndef YOBMEF_H
+#define YOBMEF_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct YobmefEngine YobmefEngine;
+
+/* Zero means no limit, nothing set thinks as if playing a 10 minute game. */
+typedef struct YobmefLimits {
+    int32_t depth;
+    uint64_t nodes;
+    uint64_t movetime_ms;
+    uint64_t wtime_ms;
+    uint64_t btime_ms;
+    uint64_t winc_ms;
+    uint64_t binc_ms;
+    uint32_t movestogo;
+} YobmefLimits;
+
+/* Returns NULL only if the engine couldn't be created. */
+YobmefEngine *yobmef_engine_new(void);
+
+/* Waits for a running search to finish, so stop it first. */
+void yobmef_engine_free(YobmefEngine *engine);
+
+/* Set a UCI option, value is NULL for buttons. */
+int yobmef_set_option(YobmefEngine *engine, const char *name, const char *value);
+
+/*
+ * Set the position to fen (NULL for the start position) with moves played
+ * from it, in UCI notation separated by spaces (NULL for none).
+ */
+int yobmef_set_position(YobmefEngine *engine, const char *fen, const char *moves);
+
+/*
+ * Search until a limit is hit or yobmef_stop is called from another thread,
+ * blocking until then. limits can be NULL.
+ */
+int yobmef_search(YobmefEngine *engine, const YobmefLimits *limits);
+
+/* Stop a search running on another thread. */
+void yobmef_stop(const YobmefEngine *engine);
+
+/*
+ * Best move of the last search in UCI notation, NULL if there were no legal
+ * moves or nothing has been searched yet.
+ */
+const char *yobmef_bestmove(const YobmefEngine *engine);
+
+/* Score of the last search in centipawns, from the side to move's view. */
+int32_t yobmef_eval(const YobmefEngine *engine);
+
+/* Moves until mate, negative if getting mated, 0 if no mate was found. */
+int32_t yobmef_mate_in(const YobmefEngine *engine);
+
+/* Why the last failing call failed, empty if nothing has yet. */
+const char *yobmef_last_error(const YobmefEngine *engine);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* YOBMEF_H */
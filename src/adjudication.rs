@@ -0,0 +1,273 @@
+use crate::chess::{Board, Color};
+use crate::game::GameResult;
+use crate::syzygy::{Tablebases, Wdl};
+use std::sync::Arc;
+
+// Adjudication for engine matches, ending games that are decided long before
+// mate or a draw rule would end them, like cutechess's -resign and -draw. Test
+// matches spend most of their time shuffling in won or dead drawn positions
+// otherwise.
+//
+// Scores are what the engines said about their own moves. A rule only fires
+// when both engines agree, for the last `moves` moves of each. With tablebases,
+// positions in them end right away with what the tables say, whatever the
+// engines think.
+
+// Resign when both engines think one side is at least `score` ahead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResignRule {
+    pub moves: u16,
+    pub score: i16,
+}
+
+// Call it a draw when both engines think it's within `score` of equal, after
+// move number `after`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawRule {
+    pub after: u16,
+    pub moves: u16,
+    pub score: i16,
+}
+
+impl ResignRule {
+    // "MOVES,SCORE", like 3,500
+    pub fn parse(s: &str) -> Option<ResignRule> {
+        let (moves, score) = s.split_once(',')?;
+        Some(ResignRule {
+            moves: moves.trim().parse().ok().filter(|&n| n > 0)?,
+            score: score.trim().parse().ok().filter(|&cp| cp > 0)?,
+        })
+    }
+}
+
+impl DrawRule {
+    // "AFTER,MOVES,SCORE", like 40,8,10
+    pub fn parse(s: &str) -> Option<DrawRule> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        match parts[..] {
+            [after, moves, score] => Some(DrawRule {
+                after: after.parse().ok()?,
+                moves: moves.parse().ok().filter(|&n| n > 0)?,
+                score: score.parse().ok().filter(|&cp| cp >= 0)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Adjudication {
+    pub resign: Option<ResignRule>,
+    pub draw: Option<DrawRule>,
+    pub tablebases: Option<Arc<Tablebases>>,
+}
+
+// Follows one game, see update.
+#[derive(Debug, Clone)]
+pub struct Adjudicator {
+    rules: Adjudication,
+    // What the engine said about every move so far, from white's point of view.
+    scores: Vec<Option<i16>>,
+}
+
+impl Adjudicator {
+    pub fn new(rules: Adjudication) -> Adjudicator {
+        Adjudicator {
+            rules,
+            scores: Vec::new(),
+        }
+    }
+
+    // Tell it about the move just played, by `mover` who scored it `score`
+    // (from its own point of view, None if it didn't say), leaving `board`.
+    // Returns how the game ends if that's decided now. The game must not be
+    // over already.
+    pub fn update(
+        &mut self,
+        board: &Board,
+        mover: Color,
+        score: Option<i16>,
+    ) -> Option<(GameResult, &'static str)> {
+        self.scores.push(score.map(|s| s * mover.polarize()));
+
+        if let Some(result) = self.probe(board) {
+            return Some(result);
+        }
+
+        if let Some(rule) = self.rules.resign {
+            if self.agree(rule.moves, |s| s >= rule.score) {
+                return Some((GameResult::WhiteWins, "Black resigns"));
+            }
+            if self.agree(rule.moves, |s| s <= -rule.score) {
+                return Some((GameResult::BlackWins, "White resigns"));
+            }
+        }
+
+        if let Some(rule) = self.rules.draw {
+            // Moves into the game, counting from its start position.
+            let move_number = self.scores.len().div_ceil(2);
+            if move_number >= rule.after as usize
+                && self.agree(rule.moves, |s| s.abs() <= rule.score)
+            {
+                return Some((GameResult::Draw, "Draw by adjudication"));
+            }
+        }
+        None
+    }
+
+    // What the tablebases say about `board`, if they have it. Cursed wins and
+    // blessed losses are draws, the fifty move rule comes first.
+    fn probe(&self, board: &Board) -> Option<(GameResult, &'static str)> {
+        let tablebases = self.rules.tablebases.as_ref()?;
+        if board.combined().count_ones() as usize > tablebases.max_pieces() {
+            return None;
+        }
+        Some(match tablebases.probe_wdl(board)? {
+            Wdl::Win => (GameResult::win_for(board.side_to_move), "Tablebase win"),
+            Wdl::Loss => (
+                GameResult::win_for(board.side_to_move.other()),
+                "Tablebase win",
+            ),
+            _ => (GameResult::Draw, "Tablebase draw"),
+        })
+    }
+
+    // Do the scores of the last `moves` moves of each side all pass `test`?
+    fn agree(&self, moves: u16, test: impl Fn(i16) -> bool) -> bool {
+        let plies = 2 * moves as usize;
+        self.scores.len() >= plies
+            && self.scores[self.scores.len() - plies..]
+                .iter()
+                .all(|s| s.is_some_and(&test))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syzygy::testing::{temp_dir, write_krk};
+
+    fn play(rules: &Adjudication, scores: &[Option<i16>]) -> Option<(GameResult, &'static str)> {
+        let mut adjudicator = Adjudicator::new(rules.clone());
+        let mut board = Board::from_start_pos();
+        for (i, score) in scores.iter().enumerate() {
+            let mover = board.side_to_move;
+            board = board.other_side();
+            if let Some(result) = adjudicator.update(&board, mover, *score) {
+                assert_eq!(i, scores.len() - 1, "adjudicated early");
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ResignRule::parse("3,500"),
+            Some(ResignRule {
+                moves: 3,
+                score: 500
+            })
+        );
+        assert_eq!(ResignRule::parse("3"), None);
+        assert_eq!(ResignRule::parse("0,500"), None);
+        assert_eq!(
+            DrawRule::parse("40, 8, 10"),
+            Some(DrawRule {
+                after: 40,
+                moves: 8,
+                score: 10
+            })
+        );
+        assert_eq!(DrawRule::parse("40,8"), None);
+        assert_eq!(DrawRule::parse("40,8,-1"), None);
+    }
+
+    #[test]
+    fn test_resign() {
+        let rules = Adjudication {
+            resign: Some(ResignRule::parse("2,500").unwrap()),
+            ..Adjudication::default()
+        };
+        // White's scores, then black's from black's point of view.
+        let winning = [Some(600), Some(-550), Some(700), Some(-500)];
+        assert_eq!(
+            play(&rules, &winning),
+            Some((GameResult::WhiteWins, "Black resigns"))
+        );
+        assert_eq!(play(&rules, &winning[..3]), None);
+
+        // Black doesn't agree.
+        assert_eq!(
+            play(&rules, &[Some(600), Some(-550), Some(700), Some(0)]),
+            None
+        );
+        // Black didn't say.
+        assert_eq!(
+            play(&rules, &[Some(600), None, Some(700), Some(-500)]),
+            None
+        );
+
+        let losing = [Some(0), Some(500), Some(-500), Some(600), Some(-900)];
+        assert_eq!(
+            play(&rules, &losing),
+            Some((GameResult::BlackWins, "White resigns"))
+        );
+    }
+
+    #[test]
+    fn test_draw() {
+        let rules = Adjudication {
+            draw: Some(DrawRule::parse("3,1,10").unwrap()),
+            ..Adjudication::default()
+        };
+        let equal = [Some(5); 6];
+        // Move 3 is the 5th ply.
+        assert_eq!(play(&rules, &equal[..4]), None);
+        assert_eq!(
+            play(&rules, &equal[..5]),
+            Some((GameResult::Draw, "Draw by adjudication"))
+        );
+        assert_eq!(
+            play(&rules, &[Some(5), Some(5), Some(5), Some(5), Some(50)]),
+            None
+        );
+
+        assert_eq!(play(&Adjudication::default(), &[Some(0); 100]), None);
+    }
+
+    #[test]
+    fn test_tablebases() {
+        let dir = temp_dir("adjudication");
+        write_krk(&dir, false);
+        let rules = Adjudication {
+            tablebases: Some(Arc::new(Tablebases::open(dir.to_str().unwrap()).unwrap())),
+            ..Adjudication::default()
+        };
+        let update = |fen: &str, mover: Color, score: Option<i16>| {
+            let board = Board::from_fen(fen).unwrap();
+            Adjudicator::new(rules.clone()).update(&board, mover, score)
+        };
+
+        // Whatever the engines say.
+        assert_eq!(
+            update("8/8/8/4k3/8/8/8/R3K3 b - - 0 1", Color::White, Some(-500)),
+            Some((GameResult::WhiteWins, "Tablebase win"))
+        );
+        assert_eq!(
+            update("8/8/8/4k3/8/8/8/r3K3 w - - 0 1", Color::Black, None),
+            Some((GameResult::BlackWins, "Tablebase win"))
+        );
+        // The king takes the rook.
+        assert_eq!(
+            update("8/8/8/8/8/8/3kR3/7K b - - 0 1", Color::White, Some(500)),
+            Some((GameResult::Draw, "Tablebase draw"))
+        );
+        // Not in the tables.
+        assert_eq!(
+            update("8/8/8/4k3/8/8/8/RN2K3 b - - 0 1", Color::White, None),
+            None
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::env;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use yobmef::chess::{Board, Movement};
+use yobmef::engine::{Engine, SearchEvent};
+use yobmef::movegen::gen_moves_once;
+use yobmef::search::{mate_in, SearchLimits};
+
+// Analysis over HTTP, for web front-ends and scripts that don't want to speak UCI.
+//
+//     $ analyse_server 127.0.0.1:8080
+//     $ curl -d '{"fen": "<fen>", "depth": 10, "multipv": 2}' localhost:8080/analyse
+//     {"depth":10,"bestmove":"e2e4","lines":[{"score":{"cp":35},"pv":["e2e4",...]},...]}
+//
+// Requests are handled one at a time on a single engine, so the hash carries over
+// between requests.
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+const DEFAULT_MOVETIME_MS: u64 = 1000;
+const MAX_MULTIPV: usize = 20;
+const MAX_BODY: usize = 64 * 1024;
+
+// Just enough JSON for the requests we take: one object of strings and numbers.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+}
+
+fn parse_object(s: &str) -> Result<HashMap<String, Value>, String> {
+    let mut chars = s.trim().chars().peekable();
+    let mut object = HashMap::new();
+
+    fn skip_ws(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match chars.next().ok_or("unterminated string")? {
+                '"' => return Ok(s),
+                '\\' => match chars.next().ok_or("unterminated string")? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    if chars.next() != Some('{') {
+        return Err("expected an object".into());
+    }
+    skip_ws(&mut chars);
+    if chars.peek() == Some(&'}') {
+        return Ok(object);
+    }
+
+    loop {
+        skip_ws(&mut chars);
+        if chars.next() != Some('"') {
+            return Err("expected a key".into());
+        }
+        let key = string(&mut chars)?;
+        skip_ws(&mut chars);
+        if chars.next() != Some(':') {
+            return Err(format!("expected : after {}", key));
+        }
+        skip_ws(&mut chars);
+
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            Value::String(string(&mut chars)?)
+        } else {
+            let mut n = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '}' || c.is_whitespace() {
+                    break;
+                }
+                n.push(c);
+                chars.next();
+            }
+            Value::Number(n.parse().map_err(|_| format!("bad value for {}", key))?)
+        };
+        object.insert(key, value);
+
+        skip_ws(&mut chars);
+        match chars.next() {
+            Some(',') => {}
+            Some('}') => return Ok(object),
+            _ => return Err("expected , or }".into()),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+struct Request {
+    board: Board,
+    limits: SearchLimits,
+    multipv: usize,
+}
+
+fn parse_request(body: &str) -> Result<Request, String> {
+    let object = parse_object(body)?;
+    let number = |key: &str| match object.get(key) {
+        None => Ok(None),
+        Some(Value::Number(n)) if *n >= 0.0 => Ok(Some(*n as u64)),
+        Some(_) => Err(format!("{} should be a positive number", key)),
+    };
+
+    let board = match object.get("fen") {
+        None => Board::from_start_pos(),
+        Some(Value::String(fen)) => Board::from_fen(fen).ok_or(format!("bad fen {}", fen))?,
+        Some(_) => return Err("fen should be a string".into()),
+    };
+
+    let mut limits = match (number("depth")?, number("movetime")?) {
+        (None, None) => SearchLimits::movetime(Duration::from_millis(DEFAULT_MOVETIME_MS)),
+        (depth, movetime) => SearchLimits {
+            depth: depth.map(|d| d.min(100) as i16),
+            movetime: movetime.map(Duration::from_millis),
+            ..SearchLimits::default()
+        },
+    };
+    limits.nodes = number("nodes")?;
+
+    let multipv = number("multipv")?.unwrap_or(1).clamp(1, MAX_MULTIPV as u64) as usize;
+    Ok(Request {
+        board,
+        limits,
+        multipv,
+    })
+}
+
+// The best line, then the best line without that move, and so on. Each line gets
+// the full limits, so multipv 3 takes three times as long.
+fn analyse(engine: &mut Engine, req: Request) -> Result<String, String> {
+    engine.set_position(req.board, &[])?;
+
+    let mut lines = Vec::new();
+    let mut exclude: Vec<Movement> = Vec::new();
+    let mut depth = None;
+    while lines.len() < req.multipv {
+        let limits = SearchLimits {
+            exclude_moves: exclude.clone(),
+            ..req.limits.clone()
+        };
+
+        let mut last = None;
+        let mut best = None;
+        for event in engine.search(limits) {
+            match event {
+                SearchEvent::Info(info) if info.score.is_some() && !info.pv.is_empty() => {
+                    last = Some(info)
+                }
+                SearchEvent::Info(_) => {}
+                SearchEvent::BestMove(mv, _) => best = mv,
+            }
+        }
+
+        let (best, info) = match (best, last) {
+            (Some(best), Some(info)) => (best, info),
+            _ => break, // out of moves
+        };
+
+        let score = match mate_in(info.score.unwrap()) {
+            Some(moves) => format!("{{\"mate\":{}}}", moves),
+            None => format!("{{\"cp\":{}}}", info.score.unwrap()),
+        };
+        let pv: Vec<String> = info.pv.iter().map(|m| format!("\"{}\"", m)).collect();
+        lines.push(format!("{{\"score\":{},\"pv\":[{}]}}", score, pv.join(",")));
+
+        depth = depth.or(info.depth);
+        exclude.push(best);
+    }
+
+    let bestmove = match exclude.first() {
+        Some(mv) => format!("\"{}\"", mv),
+        None => "null".into(),
+    };
+    Ok(format!(
+        "{{\"depth\":{},\"bestmove\":{},\"lines\":[{}]}}",
+        depth.unwrap_or(0),
+        bestmove,
+        lines.join(",")
+    ))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn handle(engine: &mut Engine, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("POST"), Some("/analyse")) => {}
+        (Some(_), Some("/analyse")) => {
+            return respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "{\"error\":\"use POST\"}",
+            )
+        }
+        _ => return respond(&mut stream, "404 Not Found", "{\"error\":\"not found\"}"),
+    }
+    if content_length > MAX_BODY {
+        return respond(
+            &mut stream,
+            "413 Payload Too Large",
+            "{\"error\":\"too big\"}",
+        );
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+
+    match parse_request(&body).and_then(|req| analyse(engine, req)) {
+        Ok(json) => respond(&mut stream, "200 OK", &json),
+        Err(e) => {
+            let json = format!("{{\"error\":\"{}\"}}", escape(&e));
+            respond(&mut stream, "400 Bad Request", &json)
+        }
+    }
+}
+
+fn main() {
+    gen_moves_once();
+
+    let addr = env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDR.into());
+    let listener = TcpListener::bind(&addr).expect("failed to listen");
+    eprintln!(
+        "Listening on http://{}/analyse",
+        listener.local_addr().unwrap()
+    );
+
+    let mut engine = Engine::new();
+    for stream in listener.incoming() {
+        if let Err(e) = stream.and_then(|stream| handle(&mut engine, stream)) {
+            eprintln!("connection: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_object() {
+        let object = parse_object(r#" { "fen": "8/8 \"x\"", "depth" : 5 } "#).unwrap();
+        assert_eq!(object["fen"], Value::String("8/8 \"x\"".into()));
+        assert_eq!(object["depth"], Value::Number(5.0));
+        assert_eq!(parse_object("{}").unwrap().len(), 0);
+
+        assert!(parse_object("[1]").is_err());
+        assert!(parse_object(r#"{"depth": five}"#).is_err());
+        assert!(parse_object(r#"{"fen": "unterminated}"#).is_err());
+    }
+
+    #[test]
+    fn test_analyse() {
+        gen_moves_once();
+        let mut engine = Engine::new();
+        let req = parse_request(
+            r#"{"fen": "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "depth": 4, "multipv": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(req.multipv, 2);
+
+        let json = analyse(&mut engine, req).unwrap();
+        assert!(
+            json.starts_with(
+                r#"{"depth":4,"bestmove":"a1a8","lines":[{"score":{"mate":1},"pv":["a1a8"]},"#
+            ),
+            "{}",
+            json
+        );
+        assert_eq!(json.matches("\"score\"").count(), 2);
+
+        assert!(parse_request(r#"{"fen": "nonsense"}"#).is_err());
+        assert!(parse_request(r#"{"depth": "deep"}"#).is_err());
+    }
+}
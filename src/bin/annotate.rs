@@ -0,0 +1,464 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+use std::time::Duration;
+use yobmef::chess::{Board, Movement};
+use yobmef::engine::{Engine, SearchEvent};
+use yobmef::eval::MATE;
+use yobmef::game::{self, Game, Nag};
+use yobmef::movegen::{self, gen_moves_once};
+use yobmef::openings;
+use yobmef::search::{mate_in, SearchLimits};
+
+// Annotate the games in a PGN: the eval after every move, question marks on
+// the moves that lose a lot, the line we would have played instead and the
+// opening's name.
+//
+//     annotate --movetime 500 --in games.pgn --out annotated.pgn
+//
+// Every position is searched once, so a 40 move game at 500ms takes 40 seconds.
+//
+// --graph also writes the eval after every move, for plotting an eval graph
+// like lichess's. CSV, or JSON if the file name ends in .json:
+//
+//     game,ply,san,score,cp,depth
+//     1,1,e4,+0.35,35,12
+//
+// `score` is what the PGN comment says, `cp` is for plotting: from white's
+// point of view, with mates and anything past SCORE_CAP at SCORE_CAP.
+
+const USAGE: &str = "usage: annotate [--depth D | --nodes N | --movetime MS] \
+[--blunder CP] [--mistake CP] [--dubious CP] [--in FILE] [--out FILE] [--graph FILE]";
+
+// Past ten pawns the game is decided, whether we're +12 or +20. Without a cap
+// every wobble in a won position would get a question mark.
+const SCORE_CAP: i16 = 1000;
+
+// Length of the "better was" lines.
+const MAX_VARIATION: usize = 6;
+
+struct Options {
+    limits: SearchLimits,
+    // Centipawns lost by a move to get ?? ? and ?!
+    blunder: i16,
+    mistake: i16,
+    dubious: i16,
+    input: Box<dyn Read>,
+    out: Box<dyn Write>,
+    graph: Option<(Box<dyn Write>, GraphFormat)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GraphFormat {
+    Csv,
+    Json,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        limits: SearchLimits::movetime(Duration::from_millis(1000)),
+        blunder: 300,
+        mistake: 100,
+        dubious: 50,
+        input: Box::new(io::stdin()),
+        out: Box::new(io::stdout()),
+        graph: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--depth" => options.limits = SearchLimits::depth(value.parse().map_err(|_| bad())?),
+            "--nodes" => {
+                options.limits = SearchLimits {
+                    nodes: Some(value.parse().map_err(|_| bad())?),
+                    ..SearchLimits::default()
+                }
+            }
+            "--movetime" => {
+                let ms = value.parse().map_err(|_| bad())?;
+                options.limits = SearchLimits::movetime(Duration::from_millis(ms))
+            }
+            "--blunder" => options.blunder = value.parse().map_err(|_| bad())?,
+            "--mistake" => options.mistake = value.parse().map_err(|_| bad())?,
+            "--dubious" => options.dubious = value.parse().map_err(|_| bad())?,
+            "--in" => {
+                let file = File::open(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.input = Box::new(file);
+            }
+            "--out" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.out = Box::new(file);
+            }
+            "--graph" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                let format = if value.ends_with(".json") {
+                    GraphFormat::Json
+                } else {
+                    GraphFormat::Csv
+                };
+                options.graph = Some((Box::new(BufWriter::new(file)), format));
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+// What the engine thinks of a position, from the side to move's point of view.
+#[derive(Debug, Clone, PartialEq)]
+struct Analysis {
+    score: i16,
+    depth: i16,
+    pv: Vec<Movement>, // empty when there are no legal moves
+}
+
+fn analyse(engine: &mut Engine, game: &Game, ply: usize, limits: &SearchLimits) -> Analysis {
+    engine
+        .set_position(game.start().clone(), &game.moves()[..ply])
+        .expect("illegal move in a parsed game");
+
+    // Mate and stalemate need no search, and the engine has no move to give.
+    let board = engine.position();
+    if !movegen::has_legal_move(board) {
+        return Analysis {
+            score: if board.in_check() { -MATE } else { 0 },
+            depth: 0,
+            pv: Vec::new(),
+        };
+    }
+
+    let mut analysis = Analysis {
+        score: 0,
+        depth: 0,
+        pv: Vec::new(),
+    };
+    for event in engine.search(limits.clone()) {
+        match event {
+            SearchEvent::Info(info) if info.depth.is_some() => {
+                analysis.score = info.score.unwrap_or(analysis.score);
+                analysis.depth = info.depth.unwrap_or(0);
+                if !info.pv.is_empty() {
+                    analysis.pv = info.pv;
+                }
+            }
+            SearchEvent::Info(_) => {}
+            SearchEvent::BestMove(mv, _) => {
+                // The best move can come from an iteration that didn't finish.
+                let mv = mv.expect("no best move with legal moves");
+                if analysis.pv.first() != Some(&mv) {
+                    analysis.pv = vec![mv];
+                }
+            }
+        }
+    }
+    analysis
+}
+
+// +0.35 or -M3, from white's point of view. `score` is from white's point of
+// view if `white` is set, black's otherwise.
+fn format_score(score: i16, white: bool) -> String {
+    let score = if white { score } else { -score };
+    match mate_in(score) {
+        Some(moves) if score > 0 => format!("+M{}", moves),
+        Some(moves) => format!("-M{}", -moves),
+        None => format!("{:+.2}", score as f64 / 100.0),
+    }
+}
+
+fn judge(loss: i16, options: &Options) -> Option<(Nag, &'static str)> {
+    if loss >= options.blunder {
+        Some((Nag::Blunder, "Blunder"))
+    } else if loss >= options.mistake {
+        Some((Nag::Mistake, "Mistake"))
+    } else if loss >= options.dubious {
+        Some((Nag::Dubious, "Inaccuracy"))
+    } else {
+        None
+    }
+}
+
+// Annotates `game`, and returns the analysis of every position in it.
+fn annotate(engine: &mut Engine, game: &mut Game, options: &Options) -> Vec<Analysis> {
+    engine.new_game();
+
+    // Analysis of every position, the one after the last move included.
+    let mut boards = vec![game.start().clone()];
+    for mv in game.moves() {
+        boards.push(boards.last().unwrap().make_move(mv));
+    }
+    let analyses: Vec<Analysis> = (0..boards.len())
+        .map(|ply| analyse(engine, game, ply, &options.limits))
+        .collect();
+
+    for ply in 0..game.moves().len() {
+        let (before, after) = (&analyses[ply], &analyses[ply + 1]);
+        let board: &Board = &boards[ply];
+        let played = &game.moves()[ply];
+        let white = board.side_to_move == yobmef::chess::Color::White;
+
+        // Both from the point of view of the side that moved.
+        let best = before.score.clamp(-SCORE_CAP, SCORE_CAP);
+        let got = (-after.score).clamp(-SCORE_CAP, SCORE_CAP);
+        // Mate and stalemate already get a comment saying so.
+        let mut comment = match after.pv.is_empty() {
+            true => String::new(),
+            false => format!("{}/{} ", format_score(-after.score, white), after.depth),
+        };
+
+        let better = before.pv.first().filter(|best| *best != played);
+        if let (Some(better), Some((nag, what))) = (better, judge(best - got, options)) {
+            comment.push_str(&format!(
+                "{}, better was {}",
+                what,
+                game::san(board, better)
+            ));
+            game.set_nag(ply, Some(nag));
+            let line = before.pv.iter().take(MAX_VARIATION).cloned().collect();
+            let line_comment = format!("{}/{}", format_score(before.score, white), before.depth);
+            game.set_variation(ply, line, Some(line_comment));
+        }
+        let comment = comment.trim_end();
+        game.set_comment(ply, Some(comment.to_string()).filter(|c| !c.is_empty()));
+    }
+    analyses
+}
+
+// One point of the eval graph, the position after a move.
+#[derive(Debug, Clone, PartialEq)]
+struct GraphPoint {
+    ply: usize, // from 1, the first move
+    san: String,
+    score: String,
+    cp: i16,
+    depth: i16,
+}
+
+fn graph(game: &Game, analyses: &[Analysis]) -> Vec<GraphPoint> {
+    let mut board = game.start().clone();
+    let mut points = Vec::new();
+    for (ply, mv) in game.moves().iter().enumerate() {
+        let san = game::san(&board, mv);
+        board.make_move_mut(mv);
+        let analysis = &analyses[ply + 1];
+        let white = board.side_to_move == yobmef::chess::Color::White;
+        let cp = if white {
+            analysis.score
+        } else {
+            -analysis.score
+        };
+        points.push(GraphPoint {
+            ply: ply + 1,
+            san,
+            score: format_score(analysis.score, white),
+            cp: cp.clamp(-SCORE_CAP, SCORE_CAP),
+            depth: analysis.depth,
+        });
+    }
+    points
+}
+
+// Every game's points, numbered from 1.
+fn write_graph<W: Write>(
+    out: &mut W,
+    format: GraphFormat,
+    games: &[Vec<GraphPoint>],
+) -> io::Result<()> {
+    let points = games
+        .iter()
+        .enumerate()
+        .flat_map(|(i, points)| points.iter().map(move |point| (i + 1, point)));
+    match format {
+        GraphFormat::Csv => {
+            writeln!(out, "game,ply,san,score,cp,depth")?;
+            for (game, p) in points {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    game, p.ply, p.san, p.score, p.cp, p.depth
+                )?;
+            }
+        }
+        GraphFormat::Json => {
+            let points: Vec<String> = points
+                .map(|(game, p)| {
+                    format!(
+                        concat!(
+                            "{{\"game\":{},\"ply\":{},\"san\":\"{}\",",
+                            "\"score\":\"{}\",\"cp\":{},\"depth\":{}}}"
+                        ),
+                        game, p.ply, p.san, p.score, p.cp, p.depth
+                    )
+                })
+                .collect();
+            writeln!(out, "[{}]", points.join(",\n "))?;
+        }
+    }
+    out.flush()
+}
+
+// ECO and Opening tags, unless the game has them already (lichess's are more
+// precise than our table).
+fn tag_opening(game: &mut Game) {
+    let tagged = game.tags.iter().any(|(name, _)| name == "ECO");
+    if let Some(opening) = openings::classify(game.start(), game.moves()).filter(|_| !tagged) {
+        game.set_tag("ECO", opening.eco);
+        game.set_tag("Opening", opening.name);
+    }
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut pgn = String::new();
+    if let Err(e) = options.input.read_to_string(&mut pgn) {
+        eprintln!("reading pgn: {}", e);
+        process::exit(1);
+    }
+    let mut games = match game::parse_pgn(&pgn) {
+        Ok(games) => games,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut engine = Engine::new();
+    let total = games.len();
+    let mut graphs = Vec::new();
+    for (i, game) in games.iter_mut().enumerate() {
+        let analyses = annotate(&mut engine, game, &options);
+        graphs.push(graph(game, &analyses));
+        game.set_tag("Annotator", "Yobmef");
+        tag_opening(game);
+        eprintln!("game {}/{}: {} plies", i + 1, total, game.moves().len());
+
+        if let Err(e) = options.out.write_all(game.to_pgn().as_bytes()) {
+            eprintln!("writing pgn: {}", e);
+            process::exit(1);
+        }
+        let _ = options.out.flush();
+    }
+
+    if let Some((out, format)) = &mut options.graph {
+        if let Err(e) = write_graph(out, *format, &graphs) {
+            eprintln!("writing graph: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args("--depth 4 --blunder 250")).unwrap();
+        assert_eq!(options.limits, SearchLimits::depth(4));
+        assert_eq!(options.blunder, 250);
+
+        assert!(parse_args(&args("--depth")).is_err());
+        assert!(parse_args(&args("--mistake lots")).is_err());
+        assert!(parse_args(&args("--in /nonexistent/games.pgn")).is_err());
+    }
+
+    #[test]
+    fn test_format_score() {
+        assert_eq!(format_score(35, true), "+0.35");
+        assert_eq!(format_score(35, false), "-0.35");
+        assert_eq!(format_score(MATE - 1, true), "+M1");
+        assert_eq!(format_score(MATE, false), "-M0");
+    }
+
+    #[test]
+    fn test_annotate() {
+        gen_moves_once();
+        let pgn = "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0";
+        let mut games = game::parse_pgn(pgn).unwrap();
+        let options = parse_args(&args("--depth 3")).unwrap();
+        let analyses = annotate(&mut Engine::new(), &mut games[0], &options);
+        assert_eq!(analyses.len(), 8);
+
+        // Nf6 allows mate in one, the game over position isn't searched.
+        let pgn = games[0].to_pgn().replace('\n', " ");
+        assert!(pgn.contains("Nf6 $4 {+M1/3 Blunder, better was"), "{}", pgn);
+        assert!(pgn.contains("Qxf7# {White mates} 1-0"), "{}", pgn);
+        assert!(pgn.contains("1. e4 {"), "{}", pgn);
+    }
+
+    #[test]
+    fn test_tag_opening() {
+        gen_moves_once();
+        let mut games = game::parse_pgn("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 b5 *").unwrap();
+        tag_opening(&mut games[0]);
+        let pgn = games[0].to_pgn();
+        assert!(pgn.contains("[ECO \"C70\"]"), "{}", pgn);
+        assert!(
+            pgn.contains("[Opening \"Ruy Lopez: Morphy Defense\"]"),
+            "{}",
+            pgn
+        );
+
+        let mut games = game::parse_pgn("[ECO \"C60\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 *").unwrap();
+        tag_opening(&mut games[0]);
+        assert!(!games[0].to_pgn().contains("[Opening"));
+    }
+
+    #[test]
+    fn test_graph() {
+        gen_moves_once();
+        let pgn = "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0";
+        let mut games = game::parse_pgn(pgn).unwrap();
+        let options = parse_args(&args("--depth 3")).unwrap();
+        let analyses = annotate(&mut Engine::new(), &mut games[0], &options);
+        let points = graph(&games[0], &analyses);
+
+        assert_eq!(points.len(), 7);
+        assert_eq!(points[0].ply, 1);
+        assert_eq!(points[0].san, "e4");
+        // After Nf6 white mates in one, then black is mated.
+        assert_eq!((points[5].score.as_str(), points[5].cp), ("+M1", SCORE_CAP));
+        assert_eq!((points[6].score.as_str(), points[6].cp), ("+M0", SCORE_CAP));
+
+        let mut csv = Vec::new();
+        write_graph(&mut csv, GraphFormat::Csv, std::slice::from_ref(&points)).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "game,ply,san,score,cp,depth");
+        assert!(lines[7].starts_with("1,7,Qxf7#,+M0,1000,"), "{}", csv);
+
+        let mut json = Vec::new();
+        write_graph(&mut json, GraphFormat::Json, &[points.clone(), points]).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(
+            json.starts_with("[{\"game\":1,\"ply\":1,\"san\":\"e4\""),
+            "{}",
+            json
+        );
+        let last =
+            "{\"game\":2,\"ply\":7,\"san\":\"Qxf7#\",\"score\":\"+M0\",\"cp\":1000,\"depth\":0}]";
+        assert!(json.trim_end().ends_with(last), "{}", json);
+    }
+}
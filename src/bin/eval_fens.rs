@@ -0,0 +1,240 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use yobmef::chess::Board;
+use yobmef::eval;
+use yobmef::movegen::{self, gen_moves_once};
+use yobmef::search::{SearchLimits, Searcher};
+
+// Score a lot of positions at once, for tuning and labelling datasets, where
+// starting the UCI engine for every position is far too slow:
+//
+//     eval_fens --in positions.fen --out scored.csv
+//     eval_fens --depth 6 --threads 8 < positions.fen > scored.csv
+//
+// One FEN per line in, CSV out in the same order: the FEN and its score in
+// centipawns from white's point of view. Without --depth that's the static
+// eval, with it the score of a search to that depth. Every search starts with
+// an empty hash, so scores don't depend on the order or the threads.
+
+const USAGE: &str =
+    "usage: eval_fens [--depth D] [--threads N] [--hash MB] [--in FILE] [--out FILE]";
+
+// Plenty for the depths this is for, and clearing it for every position is
+// most of the work at small depths.
+const DEFAULT_HASH_MB: usize = 4;
+
+struct Options {
+    depth: Option<i16>,
+    threads: usize,
+    hash_mb: usize,
+    input: Box<dyn BufRead>,
+    out: Box<dyn Write>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        depth: None,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        hash_mb: DEFAULT_HASH_MB,
+        input: Box::new(BufReader::new(io::stdin())),
+        out: Box::new(io::stdout()),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--depth" => {
+                options.depth = Some(value.parse().ok().filter(|&d| d > 0).ok_or_else(bad)?)
+            }
+            "--threads" => {
+                options.threads = value.parse().ok().filter(|&n| n > 0).ok_or_else(bad)?
+            }
+            "--hash" => options.hash_mb = value.parse().ok().filter(|&n| n > 0).ok_or_else(bad)?,
+            "--in" => {
+                let file = File::open(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.input = Box::new(BufReader::new(file));
+            }
+            "--out" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.out = Box::new(BufWriter::new(file));
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+// The FENs to score, skipping blank lines and # comments.
+fn read_fens<R: BufRead>(input: R) -> Result<Vec<(String, Board)>, String> {
+    let mut positions = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let fen = line.trim();
+        if fen.is_empty() || fen.starts_with('#') {
+            continue;
+        }
+        let board = Board::from_fen(fen).ok_or(format!("line {}: bad fen {}", i + 1, fen))?;
+        positions.push((fen.to_string(), board));
+    }
+    Ok(positions)
+}
+
+// From white's point of view. Positions without a legal move are scored by the
+// rules, there's nothing to search.
+fn score(board: &Board, searcher: Option<&mut Searcher>, depth: Option<i16>) -> i16 {
+    let game_over = !movegen::has_legal_move(board);
+    match (searcher, depth) {
+        (Some(searcher), Some(depth)) if !game_over => {
+            searcher.clear_hash();
+            searcher.go(board, SearchLimits::depth(depth)).eval
+        }
+        _ => eval::get_score(board, game_over),
+    }
+}
+
+// Score every position on `threads` threads, in the order they came in.
+fn score_all(
+    positions: &[(String, Board)],
+    depth: Option<i16>,
+    threads: usize,
+    hash_mb: usize,
+) -> Vec<i16> {
+    let next = AtomicUsize::new(0);
+    let mut scores = vec![0; positions.len()];
+    let scored: Vec<Vec<(usize, i16)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let next = &next;
+                scope.spawn(move || {
+                    let mut searcher = depth.map(|_| {
+                        let mut searcher = Searcher::new();
+                        searcher.set_hash_size(hash_mb);
+                        searcher
+                    });
+                    let mut scored = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let (_, board) = match positions.get(i) {
+                            Some(position) => position,
+                            None => break,
+                        };
+                        scored.push((i, score(board, searcher.as_mut(), depth)));
+                    }
+                    scored
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("scoring thread panicked"))
+            .collect()
+    });
+    for (i, score) in scored.into_iter().flatten() {
+        scores[i] = score;
+    }
+    scores
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let positions = match read_fens(&mut options.input) {
+        Ok(positions) => positions,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let scores = score_all(&positions, options.depth, options.threads, options.hash_mb);
+
+    let mut rows = vec!["fen,score".to_string()];
+    rows.extend(
+        positions
+            .iter()
+            .zip(scores)
+            .map(|((fen, _), score)| format!("{},{}", fen, score)),
+    );
+    for row in rows {
+        if let Err(e) = writeln!(options.out, "{}", row) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    let _ = options.out.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args("--depth 3 --threads 2 --hash 1")).unwrap();
+        assert_eq!(options.depth, Some(3));
+        assert_eq!(options.threads, 2);
+        assert_eq!(options.hash_mb, 1);
+
+        assert!(parse_args(&args("--depth 0")).is_err());
+        assert!(parse_args(&args("--threads 0")).is_err());
+        assert!(parse_args(&args("--threads")).is_err());
+        assert!(parse_args(&args("--bogus 1")).is_err());
+    }
+
+    #[test]
+    fn test_read_fens() {
+        gen_moves_once();
+        let input = "# comment\n\n4k3/8/8/8/8/8/8/R3K3 w - - 0 1\n";
+        let positions = read_fens(input.as_bytes()).unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].0, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        let err = read_fens("4k3/8/8/8/8/8/8/R3K3 w - - 0 1\nnonsense\n".as_bytes()).unwrap_err();
+        assert!(err.starts_with("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_score_all() {
+        gen_moves_once();
+        let fens = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1\n\
+                    4k3/8/8/8/8/8/8/r3K3 w - - 0 1\n\
+                    7k/5Q2/6K1/8/8/8/8/8 b - - 0 1\n\
+                    R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1\n";
+        let positions = read_fens(fens.as_bytes()).unwrap();
+
+        let scores = score_all(&positions, None, 3, 1);
+        assert!(scores[0] > 300 && scores[1] < -300, "{:?}", scores);
+        // Stalemate and mate.
+        assert_eq!(&scores[2..], &[0, eval::MATE]);
+        for ((_, board), s) in positions.iter().zip(&scores) {
+            assert_eq!(*s, score(board, None, None));
+        }
+
+        // Searches come out the same on any number of threads.
+        let searched = score_all(&positions, Some(3), 3, 1);
+        assert_eq!(score_all(&positions, Some(3), 1, 1), searched);
+        assert_eq!(&searched[2..], &[0, eval::MATE]);
+    }
+}
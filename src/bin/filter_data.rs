@@ -0,0 +1,417 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process;
+use yobmef::chess::Board;
+use yobmef::game::{self, Game, GameResult};
+use yobmef::movegen::{self, gen_moves_once};
+use yobmef::zobrist;
+
+// Turn raw games into a dataset for tuning the eval: positions labeled with
+// the result of the game they're from, one per line, from white's side:
+//
+//     rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]
+//
+//     filter_data --in selfplay.pgn --in more.pgn --mate-plies 10 --balance \
+//         --shards 4 --out data.txt
+//
+// Input is PGN (selfplay, match_runner), or datasets like the output to filter
+// again. Positions are deduplicated by zobrist key, the first one is kept. Ones
+// in check or without a legal move are dropped, they say nothing about the
+// eval, and with --mate-plies so are ones that close to a game ending in mate,
+// where the result is the search's doing. (Only known for games, not datasets.)
+//
+// --balance keeps as many of each result as the rarest one has. The output is
+// shuffled, then dealt out to data.txt.0, data.txt.1, ... with --shards. The
+// same --seed gives the same output.
+
+const USAGE: &str = "usage: filter_data --in FILE... --out FILE [--mate-plies N] [--balance] \
+                     [--shards N] [--seed S]";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    inputs: Vec<String>,
+    out: String,
+    mate_plies: usize,
+    balance: bool,
+    shards: usize,
+    seed: u64,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        inputs: Vec::new(),
+        out: String::new(),
+        mate_plies: 0,
+        balance: false,
+        shards: 1,
+        seed: 0,
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--balance" {
+            options.balance = true;
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .clone();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--in" => options.inputs.push(value),
+            "--out" => options.out = value,
+            "--mate-plies" => options.mate_plies = value.parse().map_err(|_| bad())?,
+            "--shards" => options.shards = value.parse().ok().filter(|&n| n > 0).ok_or_else(bad)?,
+            "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err("--in is required".into());
+    }
+    if options.out.is_empty() {
+        return Err("--out is required".into());
+    }
+    Ok(options)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    board: Board,
+    result: GameResult,
+    // Plies until the game ended in mate, if it did.
+    to_mate: Option<usize>,
+}
+
+impl Sample {
+    fn to_line(&self) -> String {
+        let score = match self.result {
+            GameResult::WhiteWins => "1.0",
+            GameResult::Draw => "0.5",
+            GameResult::BlackWins => "0.0",
+        };
+        format!("{} [{}]", self.board.to_fen(), score)
+    }
+}
+
+// Every position of every finished game.
+fn samples_from_games(games: &[Game]) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for game in games {
+        let result = match game.result() {
+            Some(result) => result,
+            None => continue,
+        };
+        let end = game.board();
+        let mated = end.in_check() && !movegen::has_legal_move(end);
+        let plies = game.moves().len();
+
+        let mut board = game.start().clone();
+        for ply in 0..=plies {
+            samples.push(Sample {
+                board: board.clone(),
+                result,
+                to_mate: Some(plies - ply).filter(|_| mated),
+            });
+            if let Some(mv) = game.moves().get(ply) {
+                board.make_move_mut(mv);
+            }
+        }
+    }
+    samples
+}
+
+// A dataset, as written by Sample::to_line. Blank lines and # comments are skipped.
+fn read_dataset(text: &str) -> Result<Vec<Sample>, String> {
+    let mut samples = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || format!("line {}: bad sample {}", i + 1, line);
+        let (fen, score) = line.rsplit_once('[').ok_or_else(bad)?;
+        let result = match score.trim_end_matches(']').trim() {
+            "1.0" | "1" | "1-0" => GameResult::WhiteWins,
+            "0.5" | "1/2-1/2" => GameResult::Draw,
+            "0.0" | "0" | "0-1" => GameResult::BlackWins,
+            _ => return Err(bad()),
+        };
+        samples.push(Sample {
+            board: Board::from_fen(fen.trim()).ok_or_else(bad)?,
+            result,
+            to_mate: None,
+        });
+    }
+    Ok(samples)
+}
+
+// What got dropped, and why.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Dropped {
+    duplicates: usize,
+    in_check: usize,
+    game_over: usize,
+    near_mate: usize,
+    balance: usize,
+}
+
+fn filter(samples: Vec<Sample>, options: &Options, rng: &mut StdRng) -> (Vec<Sample>, Dropped) {
+    let mut dropped = Dropped::default();
+    let mut seen = HashSet::new();
+    let mut kept = Vec::new();
+    for sample in samples {
+        if !seen.insert(zobrist::hash(&sample.board)) {
+            dropped.duplicates += 1;
+        } else if !movegen::has_legal_move(&sample.board) {
+            dropped.game_over += 1;
+        } else if sample.board.in_check() {
+            dropped.in_check += 1;
+        } else if sample
+            .to_mate
+            .is_some_and(|plies| plies <= options.mate_plies)
+        {
+            dropped.near_mate += 1;
+        } else {
+            kept.push(sample);
+        }
+    }
+
+    kept.shuffle(rng);
+    if options.balance {
+        let results = [
+            GameResult::WhiteWins,
+            GameResult::Draw,
+            GameResult::BlackWins,
+        ];
+        let count = |result| kept.iter().filter(|s| s.result == result).count();
+        let rarest = results.iter().map(|&r| count(r)).min().unwrap();
+        let mut taken = [0; 3];
+        let before = kept.len();
+        kept.retain(|sample| {
+            let i = results.iter().position(|&r| r == sample.result).unwrap();
+            taken[i] += 1;
+            taken[i] <= rarest
+        });
+        dropped.balance = before - kept.len();
+    }
+    (kept, dropped)
+}
+
+fn write_shards(samples: &[Sample], out: &str, shards: usize) -> Result<(), String> {
+    for shard in 0..shards {
+        let path = if shards == 1 {
+            out.to_string()
+        } else {
+            format!("{}.{}", out, shard)
+        };
+        let file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        samples
+            .iter()
+            .skip(shard)
+            .step_by(shards)
+            .try_for_each(|sample| writeln!(writer, "{}", sample.to_line()))
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("writing {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut samples = Vec::new();
+    for path in &options.inputs {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let read = if path.ends_with(".pgn") {
+            game::parse_pgn(&text).map(|games| samples_from_games(&games))
+        } else {
+            read_dataset(&text)
+        };
+        samples.extend(read.map_err(|e| format!("{}: {}", path, e))?);
+    }
+
+    let total = samples.len();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let (kept, dropped) = filter(samples, options, &mut rng);
+    eprintln!(
+        "{} positions, dropped {} duplicates, {} in check, {} game over, {} near mate, {} to balance",
+        total,
+        dropped.duplicates,
+        dropped.in_check,
+        dropped.game_over,
+        dropped.near_mate,
+        dropped.balance
+    );
+    write_shards(&kept, &options.out, options.shards)?;
+    eprintln!("wrote {} positions", kept.len());
+    Ok(())
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn options(s: &str) -> Options {
+        parse_args(&args(&format!("--in x.pgn --out y {}", s))).unwrap()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args(
+                "--in a.pgn --in b.txt --out data --balance --mate-plies 6 --shards 3 --seed 2"
+            )),
+            Ok(Options {
+                inputs: vec!["a.pgn".into(), "b.txt".into()],
+                out: "data".into(),
+                mate_plies: 6,
+                balance: true,
+                shards: 3,
+                seed: 2,
+            })
+        );
+        assert_eq!(options("").shards, 1);
+        assert!(parse_args(&args("--out data")).is_err());
+        assert!(parse_args(&args("--in a.pgn")).is_err());
+        assert!(parse_args(&args("--in a.pgn --out data --shards 0")).is_err());
+    }
+
+    #[test]
+    fn test_samples_from_games() {
+        gen_moves_once();
+        let pgn = "[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n\
+                   [Result \"1/2-1/2\"]\n\n1. e4 e5 1/2-1/2\n\n\
+                   [Result \"*\"]\n\n1. d4 *\n";
+        let samples = samples_from_games(&game::parse_pgn(pgn).unwrap());
+        assert_eq!(samples.len(), 5 + 3);
+        assert_eq!(samples[0].board, Board::from_start_pos());
+        assert_eq!(samples[0].result, GameResult::BlackWins);
+        assert_eq!(samples[0].to_mate, Some(4));
+        assert_eq!(samples[4].to_mate, Some(0));
+        assert_eq!(samples[5].result, GameResult::Draw);
+        assert_eq!(samples[5].to_mate, None);
+    }
+
+    #[test]
+    fn test_read_dataset() {
+        gen_moves_once();
+        let sample = Sample {
+            board: Board::from_start_pos(),
+            result: GameResult::Draw,
+            to_mate: None,
+        };
+        let text = format!(
+            "# comment\n\n{}\n8/8/8/8/8/8/k7/7K w - - 0 1 [1-0]\n",
+            sample.to_line()
+        );
+        let samples = read_dataset(&text).unwrap();
+        assert_eq!(samples[0], sample);
+        assert_eq!(samples[1].result, GameResult::WhiteWins);
+
+        let err = read_dataset("8/8/8/8/8/8/k7/7K w - - 0 1 [2]")
+            .err()
+            .unwrap();
+        assert!(err.starts_with("line 1:"), "{}", err);
+        assert!(read_dataset("8/8/8/8/8/8/k7/7K w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        gen_moves_once();
+        // The mate, and a draw through the same first position.
+        let pgn = "[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n\
+                   [Result \"1/2-1/2\"]\n\n1. f3 e6 2. Kf2 Ke7 1/2-1/2\n";
+        let samples = samples_from_games(&game::parse_pgn(pgn).unwrap());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let (kept, dropped) = filter(samples.clone(), &options(""), &mut rng);
+        // Qh4# is game over, the start and 1.f3 are in both games.
+        assert_eq!(
+            dropped,
+            Dropped {
+                duplicates: 2,
+                game_over: 1,
+                ..Dropped::default()
+            }
+        );
+        assert_eq!(kept.len(), 7);
+
+        let (kept, dropped) = filter(samples.clone(), &options("--mate-plies 2"), &mut rng);
+        assert_eq!(dropped.near_mate, 2);
+        assert_eq!(kept.len(), 5);
+
+        // 4 black wins and 3 draws left, but no white wins.
+        let (kept, dropped) = filter(samples, &options("--balance"), &mut rng);
+        assert_eq!(dropped.balance, 7);
+        assert!(kept.is_empty());
+
+        let results = [
+            GameResult::WhiteWins,
+            GameResult::WhiteWins,
+            GameResult::WhiteWins,
+            GameResult::Draw,
+            GameResult::Draw,
+            GameResult::BlackWins,
+        ];
+        let fens = [
+            "7k/8/8/8/8/8/8/K7 w - - 0 1",
+            "7k/8/8/8/8/8/8/1K6 w - - 0 1",
+            "7k/8/8/8/8/8/8/2K5 w - - 0 1",
+            "7k/8/8/8/8/8/8/3K4 w - - 0 1",
+            "7k/8/8/8/8/8/8/4K3 w - - 0 1",
+            "7k/8/8/8/8/8/8/5K2 w - - 0 1",
+        ];
+        let lopsided: Vec<Sample> = fens
+            .iter()
+            .zip(&results)
+            .map(|(fen, &result)| Sample {
+                board: Board::from_fen(fen).unwrap(),
+                result,
+                to_mate: None,
+            })
+            .collect();
+        let (kept, dropped) = filter(lopsided, &options("--balance"), &mut rng);
+        assert_eq!(dropped.balance, 3);
+        assert_eq!(kept.len(), 3);
+        assert!(kept.iter().any(|s| s.result == GameResult::Draw));
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1").unwrap();
+        let check = vec![Sample {
+            board,
+            result: GameResult::BlackWins,
+            to_mate: None,
+        }];
+        let (kept, dropped) = filter(check, &options(""), &mut rng);
+        assert!(kept.is_empty());
+        assert_eq!(dropped.in_check, 1);
+    }
+}
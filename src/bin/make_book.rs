@@ -0,0 +1,172 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process;
+use yobmef::book::{self, polyglot};
+use yobmef::game::{self, Game, GameResult};
+use yobmef::movegen::gen_moves_once;
+
+// Write a polyglot opening book, for GUIs and other engines. From the games
+// in a PGN (selfplay's, say), or the built-in book without one:
+//
+//     make_book --in selfplay.pgn --plies 16 --out yobmef.bin
+//
+// Moves from games are weighted like polyglot does, 2 for a win and 1 for a
+// draw.
+
+const USAGE: &str = "usage: make_book --out FILE [--in PGN] [--plies N]";
+
+// Deep enough for an opening book, from games that go on for a hundred moves.
+const DEFAULT_PLIES: usize = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    out: String,
+    pgn: Option<String>,
+    plies: usize,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut out = None;
+    let mut pgn = None;
+    let mut plies = DEFAULT_PLIES;
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .clone();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--out" => out = Some(value),
+            "--in" => pgn = Some(value),
+            "--plies" => plies = value.parse().map_err(|_| bad())?,
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(Options {
+        out: out.ok_or("--out is required")?,
+        pgn,
+        plies,
+    })
+}
+
+// Add the first `plies` moves of every finished game.
+fn add_games(writer: &mut polyglot::BookWriter, games: &[Game], plies: usize) {
+    for game in games {
+        let result = match game.result() {
+            Some(result) => result,
+            None => continue,
+        };
+        let mut board = game.start().clone();
+        for mv in game.moves().iter().take(plies) {
+            let weight = match result {
+                GameResult::Draw => 1,
+                result if result == GameResult::win_for(board.side_to_move) => 2,
+                _ => 0,
+            };
+            writer.add(&board, mv, weight);
+            board.make_move_mut(mv);
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<usize, String> {
+    let writer = match &options.pgn {
+        Some(path) => {
+            let pgn = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let games = game::parse_pgn(&pgn).map_err(|e| format!("{}: {}", path, e))?;
+            let mut writer = polyglot::BookWriter::new();
+            add_games(&mut writer, &games, options.plies);
+            writer
+        }
+        None => book::to_polyglot(),
+    };
+
+    let file = File::create(&options.out).map_err(|e| format!("{}: {}", options.out, e))?;
+    let mut out = BufWriter::new(file);
+    writer
+        .write(&mut out)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("writing {}: {}", options.out, e))?;
+    Ok(writer.positions())
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(&options) {
+        Ok(positions) => eprintln!("wrote {} positions to {}", positions, options.out),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yobmef::chess::{Board, Movement};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args("--out book.bin --in games.pgn --plies 8")),
+            Ok(Options {
+                out: "book.bin".into(),
+                pgn: Some("games.pgn".into()),
+                plies: 8,
+            })
+        );
+        assert_eq!(
+            parse_args(&args("--out book.bin")).unwrap().plies,
+            DEFAULT_PLIES
+        );
+        assert!(parse_args(&args("--in games.pgn")).is_err());
+        assert!(parse_args(&args("--out book.bin --plies")).is_err());
+    }
+
+    #[test]
+    fn test_add_games() {
+        gen_moves_once();
+        let pgn = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+                   [Result \"1/2-1/2\"]\n\n1. e4 c5 1/2-1/2\n\n\
+                   [Result \"*\"]\n\n1. d4 *\n";
+        let games = game::parse_pgn(pgn).unwrap();
+        let mut writer = polyglot::BookWriter::new();
+        add_games(&mut writer, &games, 2);
+        // 1.e4 twice, then e5 and c5 from there. Nf3 is past --plies, and the
+        // unfinished game is left out.
+        assert_eq!(writer.positions(), 2);
+
+        let start = Board::from_start_pos();
+        let e4 = Movement::from_notation("e2e4").unwrap();
+        let mut want = polyglot::BookWriter::new();
+        want.add(&start, &e4, 3);
+        let after = start.make_move(&e4);
+        want.add(&after, &Movement::from_notation("e7e5").unwrap(), 0);
+        want.add(&after, &Movement::from_notation("c7c5").unwrap(), 1);
+
+        let (mut got_bytes, mut want_bytes) = (Vec::new(), Vec::new());
+        writer.write(&mut got_bytes).unwrap();
+        want.write(&mut want_bytes).unwrap();
+        assert_eq!(got_bytes, want_bytes);
+        // e4 and c5, the losing e5 has no weight.
+        assert_eq!(got_bytes.len(), 2 * 16);
+    }
+}
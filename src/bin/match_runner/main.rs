@@ -0,0 +1,430 @@
+mod sprt;
+mod tournament;
+
+use sprt::{Score, Sprt, Verdict};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use yobmef::adjudication::{Adjudication, DrawRule, ResignRule};
+use yobmef::chess::Board;
+use yobmef::game::{Game, GameResult};
+use yobmef::matches::{self, play_game, start_engine, Limit};
+use yobmef::movegen::gen_moves_once;
+use yobmef::syzygy::Tablebases;
+use yobmef::uci_engine::UciEngine;
+
+// Play two UCI engines against each other until the SPRT says whether the first
+// is an improvement on the second, like a tiny fishtest.
+//
+//     match_runner --engine ./yobmef-branch --engine ./yobmef-master --tc 10+0.1 \
+//         --concurrency 4 --openings openings.fen --pgn match.pgn
+//
+// Scores are from the first engine's point of view. Each opening is played
+// twice with colors swapped, so a lopsided opening doesn't favor either side.
+// --resign, --draw and --syzygy adjudicate decided games, like selfplay's.
+//
+// With --tournament it plays more than two engines instead, round-robin or a
+// gauntlet (the first engine against each of the others), --games of them per
+// pairing, and ends with an Elo table. No SPRT then. Engines can be the same
+// binary with different options, named to tell them apart:
+//
+//     match_runner --tournament round-robin --tc 5+0.05 \
+//         --engine ./yobmef --name default \
+//         --engine ./yobmef --name no-book --engine-option OwnBook=false \
+//         --engine ./yobmef-old --games 100 --pgn tournament.pgn
+//
+// --option is for every engine, --engine-option and --name for the last
+// --engine before them.
+
+const USAGE: &str = "usage: match_runner --engine CMD [--name NAME] [--engine-option NAME=VALUE]... \
+--engine CMD ... [--tournament round-robin|gauntlet] \
+[--depth D | --nodes N | --movetime MS | --tc SECS[+INC]] [--games N] [--concurrency N] \
+[--openings FILE] [--pgn FILE] [--option NAME=VALUE]... [--resign MOVES,CP] [--draw AFTER,MOVES,CP] \
+[--syzygy DIR] [--elo0 E] [--elo1 E] [--alpha A] [--beta B]";
+
+// A few versions of the engine at a fast time control take long enough.
+const TOURNAMENT_GAMES: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct EngineConfig {
+    command: String,
+    name: Option<String>,           // instead of the one it tells us
+    options: Vec<(String, String)>, // setoptions, --option's first
+}
+
+struct Options {
+    engines: Vec<EngineConfig>,
+    tournament: Option<tournament::Kind>,
+    games: Option<usize>, // per pairing in a tournament
+    concurrency: usize,
+    limit: Limit,
+    openings: Vec<Board>,
+    pgn: Option<File>,
+    adjudication: Adjudication,
+    sprt: Sprt,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        engines: Vec::new(),
+        tournament: None,
+        games: None,
+        concurrency: 1,
+        limit: Limit::Clock(Duration::from_secs(10), Duration::from_millis(100)),
+        openings: vec![Board::from_start_pos()],
+        pgn: None,
+        adjudication: Adjudication::default(),
+        sprt: Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        },
+    };
+
+    let mut shared_options = Vec::new();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        if let Some(limit) = matches::parse_limit(flag, value)? {
+            options.limit = limit;
+            continue;
+        }
+        match flag.as_str() {
+            "--engine" => options.engines.push(EngineConfig {
+                command: value.to_string(),
+                ..EngineConfig::default()
+            }),
+            "--name" | "--engine-option" => {
+                let engine = options
+                    .engines
+                    .last_mut()
+                    .ok_or(format!("{} before any --engine", flag))?;
+                if flag == "--name" {
+                    engine.name = Some(value.to_string());
+                } else {
+                    let (name, value) = value.split_once('=').ok_or_else(bad)?;
+                    engine.options.push((name.to_string(), value.to_string()));
+                }
+            }
+            "--tournament" => {
+                options.tournament = Some(tournament::Kind::parse(value).ok_or_else(bad)?)
+            }
+            "--games" => options.games = Some(value.parse().map_err(|_| bad())?),
+            "--concurrency" => options.concurrency = value.parse().map_err(|_| bad())?,
+            "--openings" => options.openings = matches::read_openings(value)?,
+            "--pgn" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.pgn = Some(file);
+            }
+            "--option" => {
+                let (name, value) = value.split_once('=').ok_or_else(bad)?;
+                shared_options.push((name.to_string(), value.to_string()));
+            }
+            "--resign" => {
+                options.adjudication.resign = Some(ResignRule::parse(value).ok_or_else(bad)?)
+            }
+            "--draw" => options.adjudication.draw = Some(DrawRule::parse(value).ok_or_else(bad)?),
+            "--syzygy" => {
+                options.adjudication.tablebases = Some(Arc::new(Tablebases::open(value)?))
+            }
+            "--elo0" => options.sprt.elo0 = value.parse().map_err(|_| bad())?,
+            "--elo1" => options.sprt.elo1 = value.parse().map_err(|_| bad())?,
+            "--alpha" => options.sprt.alpha = value.parse().map_err(|_| bad())?,
+            "--beta" => options.sprt.beta = value.parse().map_err(|_| bad())?,
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    match options.tournament {
+        None if options.engines.len() != 2 => {
+            return Err("need exactly two engines, or a --tournament".into())
+        }
+        Some(_) if options.engines.len() < 2 => return Err("need at least two engines".into()),
+        _ => {}
+    }
+    for engine in &mut options.engines {
+        let own = std::mem::take(&mut engine.options);
+        engine.options = shared_options.iter().cloned().chain(own).collect();
+    }
+    options.concurrency = options.concurrency.max(1);
+    Ok(options)
+}
+
+// Engine names for the PGN and the results, with a number added to the ones
+// that would be the same.
+fn unique_names(mut names: Vec<String>) -> Vec<String> {
+    let same: Vec<bool> = names
+        .iter()
+        .map(|name| names.iter().filter(|n| *n == name).count() > 1)
+        .collect();
+    for (i, name) in names.iter_mut().enumerate() {
+        if same[i] {
+            name.push_str(&format!(" ({})", i + 1));
+        }
+    }
+    names
+}
+
+// Play `games` games on `options.concurrency` threads, game i between the
+// engines of pairings[i % pairings.len()]. Each opening is played twice by a
+// pairing, with colors swapped. `finished` gets the games as they come in with
+// the engines that had white and black, and stops the lot by returning false.
+fn play_games<F>(options: &Options, pairings: &[(usize, usize)], games: usize, mut finished: F)
+where
+    F: FnMut(usize, [usize; 2], Game) -> bool,
+{
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (results, played) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..options.concurrency {
+            let results = results.clone();
+            let (next_game, stop) = (&next_game, &stop);
+            scope.spawn(move || {
+                let mut engines: Vec<Option<UciEngine>> =
+                    options.engines.iter().map(|_| None).collect();
+                loop {
+                    let i = next_game.fetch_add(1, Ordering::Relaxed);
+                    if i >= games || stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let (a, b) = pairings[i % pairings.len()];
+                    let round = i / pairings.len();
+                    let opening = options.openings[round / 2 % options.openings.len()].clone();
+                    let white = round % 2;
+
+                    let mut pair = [engines[a].take(), engines[b].take()];
+                    let game = play_game(
+                        &mut pair,
+                        &[
+                            options.engines[a].command.clone(),
+                            options.engines[b].command.clone(),
+                        ],
+                        [&options.engines[a].options, &options.engines[b].options],
+                        white,
+                        opening,
+                        &options.limit,
+                        options.adjudication.clone(),
+                    );
+                    let [engine_a, engine_b] = pair;
+                    engines[a] = engine_a;
+                    engines[b] = engine_b;
+
+                    let players = if white == 0 { [a, b] } else { [b, a] };
+                    if results.send((i, players, game)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(results);
+
+        for (i, players, game) in played.iter() {
+            if !finished(i, players, game) {
+                // Games still going get finished, but don't count.
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    });
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    // Start them once up front, for their names and to fail early on typos.
+    let mut names = Vec::new();
+    for config in &options.engines {
+        match start_engine(&config.command, &config.options) {
+            Ok(engine) => {
+                for (name, _) in &config.options {
+                    if !engine.options.iter().any(|o| o.eq_ignore_ascii_case(name)) {
+                        eprintln!("{}: no option {}", engine.name, name);
+                        process::exit(1);
+                    }
+                }
+                names.push(config.name.clone().unwrap_or(engine.name.clone()))
+            }
+            Err(e) => {
+                eprintln!("{}: {}", config.command, e);
+                process::exit(1);
+            }
+        }
+    }
+    let names = unique_names(names);
+
+    let time_control = match options.limit {
+        Limit::Clock(time, inc) => format!("{}+{}", time.as_secs_f64(), inc.as_secs_f64()),
+        _ => "-".into(),
+    };
+    let mut pgn = options.pgn.take();
+    let mut record = |i: usize, players: [usize; 2], game: &mut Game, event: &str| {
+        game.set_tag("Event", event);
+        game.set_tag("Round", &(i + 1).to_string());
+        game.set_tag("White", &names[players[0]]);
+        game.set_tag("Black", &names[players[1]]);
+        game.set_tag("TimeControl", &time_control);
+        println!(
+            "Finished game {} ({} vs {}): {} {{{}}}",
+            i + 1,
+            names[players[0]],
+            names[players[1]],
+            game.result().expect("game not over"),
+            game.termination().unwrap_or("")
+        );
+        if let Some(pgn) = &mut pgn {
+            if let Err(e) = pgn.write_all(game.to_pgn().as_bytes()) {
+                eprintln!("writing pgn: {}", e);
+            }
+        }
+    };
+
+    if let Some(kind) = options.tournament {
+        let pairings = kind.pairings(names.len());
+        let games = options.games.unwrap_or(TOURNAMENT_GAMES) * pairings.len();
+        let mut standings = tournament::Standings::new(names.len());
+        let mut done = 0;
+        play_games(&options, &pairings, games, |i, players, mut game| {
+            record(i, players, &mut game, "Yobmef tournament");
+            standings.add(players[0], players[1], game.result().unwrap());
+            // Standings every time each pairing has played a game pair.
+            done += 1;
+            if done % (2 * pairings.len()) == 0 && done < games {
+                println!("{}", standings.table(&names));
+            }
+            true
+        });
+        println!("{}", standings.table(&names));
+        return;
+    }
+
+    let (lower, upper) = options.sprt.bounds();
+    let mut score = Score::default();
+    let games = options.games.unwrap_or(1000);
+    play_games(&options, &[(0, 1)], games, |i, players, mut game| {
+        record(i, players, &mut game, "Yobmef match");
+        match (game.result().unwrap(), players[0]) {
+            (GameResult::Draw, _) => score.draws += 1,
+            (GameResult::WhiteWins, 0) | (GameResult::BlackWins, 1) => score.wins += 1,
+            _ => score.losses += 1,
+        }
+
+        println!(
+            "Score of {} vs {}: {} - {} - {}  [{:.3}] {}",
+            names[0],
+            names[1],
+            score.wins,
+            score.losses,
+            score.draws,
+            score.ratio(),
+            score.games()
+        );
+        let elo = match score.elo() {
+            Some((elo, error)) => format!("{:.1} +/- {:.1}", elo, error),
+            None => "?".into(),
+        };
+        println!(
+            "Elo difference: {}, LLR: {:.2} ({:.2}, {:.2}) [{:.2}, {:.2}]",
+            elo,
+            options.sprt.llr(&score),
+            lower,
+            upper,
+            options.sprt.elo0,
+            options.sprt.elo1
+        );
+
+        match options.sprt.verdict(&score) {
+            Some(verdict) => {
+                println!(
+                    "SPRT: {} accepted",
+                    match verdict {
+                        Verdict::H0 => "H0",
+                        Verdict::H1 => "H1",
+                    }
+                );
+                false
+            }
+            None => true,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        gen_moves_once();
+        let options = parse_args(&args(
+            "--engine ./a --option Hash=16 --engine ./b --name b2 --engine-option OwnBook=false",
+        ))
+        .unwrap();
+        assert_eq!(options.tournament, None);
+        assert_eq!(
+            options.engines,
+            vec![
+                EngineConfig {
+                    command: "./a".into(),
+                    name: None,
+                    options: vec![("Hash".into(), "16".into())],
+                },
+                EngineConfig {
+                    command: "./b".into(),
+                    name: Some("b2".into()),
+                    options: vec![
+                        ("Hash".into(), "16".into()),
+                        ("OwnBook".into(), "false".into())
+                    ],
+                },
+            ]
+        );
+
+        let options = parse_args(&args(
+            "--tournament gauntlet --engine a --engine b --engine c",
+        ))
+        .unwrap();
+        assert_eq!(options.tournament, Some(tournament::Kind::Gauntlet));
+        assert_eq!(options.engines.len(), 3);
+
+        assert!(parse_args(&args("--engine a --engine b --engine c")).is_err());
+        assert!(parse_args(&args("--tournament round-robin --engine a")).is_err());
+        assert!(parse_args(&args("--tournament swiss --engine a --engine b")).is_err());
+        assert!(parse_args(&args("--name a --engine a --engine b")).is_err());
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            unique_names(names(&["yobmef", "other", "yobmef"])),
+            names(&["yobmef (1)", "other", "yobmef (3)"])
+        );
+        assert_eq!(unique_names(names(&["a", "b"])), names(&["a", "b"]));
+    }
+}
@@ -0,0 +1,164 @@
+// Match statistics: Elo with error bars from a score, and the sequential
+// probability ratio test (SPRT) to decide if a change is an improvement using as
+// few games as we can get away with.
+//
+// H0 is "the engine is elo0 stronger", H1 is "the engine is elo1 stronger". We
+// keep playing until the log likelihood ratio (LLR) of H1 over H0 crosses one
+// of the bounds. The LLR uses the normal approximation from fishtest, good
+// enough once there are a few dozen games.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// Expected score of a player this many elo stronger.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_of_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl Score {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // Points per game, 0 to 1.
+    pub fn ratio(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // Variance of a single game's result.
+    fn variance(&self) -> f64 {
+        let s = self.ratio();
+        let n = self.games() as f64;
+        (self.wins as f64 * (1.0 - s).powi(2)
+            + self.draws as f64 * (0.5 - s).powi(2)
+            + self.losses as f64 * s.powi(2))
+            / n
+    }
+
+    // Elo difference and its 95% error, None until it's finite.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        if self.games() == 0 || self.wins + self.draws == 0 || self.losses + self.draws == 0 {
+            return None;
+        }
+        let s = self.ratio();
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let low = elo_of_score((s - margin).max(1e-6));
+        let high = elo_of_score((s + margin).min(1.0 - 1e-6));
+        Some((elo_of_score(s), (high - low) / 2.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64, // chance of accepting H1 when H0 is true
+    pub beta: f64,  // and the other way around
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    H0, // not an improvement (of at least elo0)
+    H1, // an improvement of at least elo1
+}
+
+impl Sprt {
+    // LLR to cross for H0, and for H1.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn llr(&self, score: &Score) -> f64 {
+        let var = if score.games() == 0 {
+            0.0
+        } else {
+            score.variance()
+        };
+        // All draws, or all wins etc. Nothing to go on yet.
+        if var == 0.0 {
+            return 0.0;
+        }
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        let s = score.ratio();
+        score.games() as f64 * (s1 - s0) * (2.0 * s - s0 - s1) / (2.0 * var)
+    }
+
+    pub fn verdict(&self, score: &Score) -> Option<Verdict> {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(Verdict::H1)
+        } else if llr <= lower {
+            Some(Verdict::H0)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(wins: u32, draws: u32, losses: u32) -> Score {
+        Score {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(score(0, 0, 0).elo(), None);
+        assert_eq!(score(10, 0, 0).elo(), None);
+
+        let (elo, error) = score(100, 100, 100).elo().unwrap();
+        assert!(elo.abs() < 1e-9);
+        assert!(error > 20.0 && error < 40.0, "error {}", error);
+
+        // 75% is about +191.
+        let (elo, _) = score(70, 10, 20).elo().unwrap();
+        assert!((elo - 190.85).abs() < 0.1, "elo {}", elo);
+
+        // More games, smaller error.
+        let (_, small) = score(1000, 1000, 1000).elo().unwrap();
+        let (_, big) = score(10, 10, 10).elo().unwrap();
+        assert!(small < big);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 0.001 && (upper - 2.944).abs() < 0.001);
+
+        assert_eq!(sprt.llr(&score(0, 0, 0)), 0.0);
+        assert_eq!(sprt.llr(&score(0, 10, 0)), 0.0);
+
+        // Even results are evidence against an improvement.
+        assert!(sprt.llr(&score(100, 100, 100)) < 0.0);
+        assert_eq!(sprt.verdict(&score(100, 100, 100)), None);
+        assert_eq!(sprt.verdict(&score(10000, 10000, 10000)), Some(Verdict::H0));
+
+        // Winning clearly is for one.
+        assert_eq!(sprt.verdict(&score(600, 300, 400)), Some(Verdict::H1));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::sprt::Score;
+use yobmef::game::GameResult;
+
+// Tournaments between more than two engines, for comparing a handful of
+// versions at once. Every engine gets an Elo against the field, from its score
+// over all its games, the same way match_runner rates a single match.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    RoundRobin, // everyone plays everyone
+    Gauntlet,   // the first engine plays everyone else
+}
+
+impl Kind {
+    pub fn parse(s: &str) -> Option<Kind> {
+        match s {
+            "round-robin" | "roundrobin" => Some(Kind::RoundRobin),
+            "gauntlet" => Some(Kind::Gauntlet),
+            _ => None,
+        }
+    }
+
+    // The pairs of engines that play each other.
+    pub fn pairings(&self, engines: usize) -> Vec<(usize, usize)> {
+        match self {
+            Kind::RoundRobin => (0..engines)
+                .flat_map(|a| (a + 1..engines).map(move |b| (a, b)))
+                .collect(),
+            Kind::Gauntlet => (1..engines).map(|b| (0, b)).collect(),
+        }
+    }
+}
+
+// Every engine's score, from its own point of view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Standings {
+    scores: Vec<Score>,
+}
+
+impl Standings {
+    pub fn new(engines: usize) -> Standings {
+        Standings {
+            scores: vec![Score::default(); engines],
+        }
+    }
+
+    // A game between engines `white` and `black`.
+    pub fn add(&mut self, white: usize, black: usize, result: GameResult) {
+        match result {
+            GameResult::Draw => {
+                self.scores[white].draws += 1;
+                self.scores[black].draws += 1;
+            }
+            GameResult::WhiteWins => {
+                self.scores[white].wins += 1;
+                self.scores[black].losses += 1;
+            }
+            GameResult::BlackWins => {
+                self.scores[black].wins += 1;
+                self.scores[white].losses += 1;
+            }
+        }
+    }
+
+    // Best first, like cutechess:
+    //
+    //     Rank Name                       Elo     +/-   Games   Score   Draws
+    //        1 yobmef-new                12.2    25.6     400   51.8%   38.0%
+    pub fn table(&self, names: &[String]) -> String {
+        let mut order: Vec<usize> = (0..self.scores.len()).collect();
+        let ratio = |i: usize| match self.scores[i].games() {
+            0 => 0.0,
+            _ => self.scores[i].ratio(),
+        };
+        order.sort_by(|&a, &b| ratio(b).partial_cmp(&ratio(a)).unwrap());
+
+        let mut table = format!(
+            "{:>4} {:<24} {:>7} {:>7} {:>7} {:>7} {:>7}\n",
+            "Rank", "Name", "Elo", "+/-", "Games", "Score", "Draws"
+        );
+        for (rank, &i) in order.iter().enumerate() {
+            let score = &self.scores[i];
+            let (elo, error) = match score.elo() {
+                Some((elo, error)) => (format!("{:.1}", elo), format!("{:.1}", error)),
+                None => ("?".into(), "?".into()),
+            };
+            let draws = match score.games() {
+                0 => 0.0,
+                games => score.draws as f64 / games as f64,
+            };
+            table.push_str(&format!(
+                "{:>4} {:<24} {:>7} {:>7} {:>7} {:>6.1}% {:>6.1}%\n",
+                rank + 1,
+                names[i],
+                elo,
+                error,
+                score.games(),
+                100.0 * ratio(i),
+                100.0 * draws
+            ));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairings() {
+        assert_eq!(
+            Kind::RoundRobin.pairings(4),
+            vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]
+        );
+        assert_eq!(Kind::Gauntlet.pairings(4), vec![(0, 1), (0, 2), (0, 3)]);
+        assert_eq!(Kind::RoundRobin.pairings(2), Kind::Gauntlet.pairings(2));
+        assert_eq!(Kind::parse("gauntlet"), Some(Kind::Gauntlet));
+        assert_eq!(Kind::parse("swiss"), None);
+    }
+
+    #[test]
+    fn test_standings() {
+        let mut standings = Standings::new(3);
+        standings.add(0, 1, GameResult::WhiteWins);
+        standings.add(1, 0, GameResult::WhiteWins);
+        standings.add(0, 2, GameResult::Draw);
+        standings.add(2, 0, GameResult::BlackWins);
+        standings.add(1, 2, GameResult::BlackWins);
+        standings.add(2, 1, GameResult::Draw);
+
+        // 0: 2 wins, 1 draw, 1 loss. 2: 1 win, 2 draws, 1 loss. 1: 1 win, 1 draw, 2 losses.
+        let names: Vec<String> = vec!["a".into(), "b".into(), "c".into()];
+        let table = standings.table(&names);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Rank Name"));
+        assert!(lines[1].starts_with("   1 a "), "{}", table);
+        assert!(lines[1].ends_with("  62.5%   25.0%"), "{}", table);
+        assert!(lines[2].starts_with("   2 c "), "{}", table);
+        assert!(lines[3].starts_with("   3 b "), "{}", table);
+
+        // Nothing but wins has no finite Elo.
+        let mut standings = Standings::new(2);
+        standings.add(0, 1, GameResult::WhiteWins);
+        assert!(standings
+            .table(&names)
+            .lines()
+            .nth(1)
+            .unwrap()
+            .contains(" ? "));
+    }
+}
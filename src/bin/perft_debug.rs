@@ -3,8 +3,8 @@ use std::io::prelude::*;
 use std::io::BufReader;
 use std::process::{Command, Stdio};
 use std::str::FromStr;
-use yobmef::chess::{Board, Movement};
-use yobmef::movegen::{gen_moves_once, perft, MoveGen};
+use yobmef::movegen::perft;
+use yobmef::prelude::*;
 
 #[derive(Debug, PartialEq, Eq)]
 struct PerftResult {
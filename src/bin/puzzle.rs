@@ -0,0 +1,258 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+use std::time::Duration;
+use yobmef::chess::{Board, Movement};
+use yobmef::epd::Epd;
+use yobmef::game;
+use yobmef::movegen::gen_moves_once;
+use yobmef::puzzle::{self, Claim};
+use yobmef::search::{SearchLimits, Searcher};
+
+// Check a file of puzzles, for validating puzzle sets and testing the search.
+//
+//     puzzle --unique --in mates.epd
+//
+// Puzzles are EPD, one per line. `dm N` claims a mate in N, without it the
+// claim is that there's a winning move. `bm` gives the solution (SAN, several
+// separated by spaces), `id` names the puzzle:
+//
+//     6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; dm 1; id "back rank";
+//
+// A puzzle fails if the claim is false or we found a key move that's not in
+// bm. With --unique, more than one key move fails it too. Exits with 1 if any
+// puzzle failed.
+
+const USAGE: &str = "usage: puzzle [--unique] [--movetime MS | --nodes N] [--in FILE]";
+
+struct Options {
+    unique: bool,
+    // Caps the search, mate claims are searched as deep as they need.
+    limits: SearchLimits,
+    input: Box<dyn BufRead>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        unique: false,
+        limits: SearchLimits::movetime(Duration::from_secs(10)),
+        input: Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--unique" {
+            options.unique = true;
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--movetime" => {
+                let ms = value.parse().map_err(|_| bad())?;
+                options.limits = SearchLimits::movetime(Duration::from_millis(ms))
+            }
+            "--nodes" => {
+                options.limits = SearchLimits {
+                    nodes: Some(value.parse().map_err(|_| bad())?),
+                    ..SearchLimits::default()
+                }
+            }
+            "--in" => {
+                let file = File::open(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.input = Box::new(BufReader::new(file));
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Puzzle {
+    id: String,
+    board: Board,
+    claim: Claim,
+    solution: Vec<Movement>, // empty if we weren't told
+}
+
+fn parse_epd(line: &str) -> Result<Puzzle, String> {
+    let epd = Epd::parse(line)?;
+    let claim = match epd.operation("dm") {
+        Some(moves) => Claim::MateIn(moves.parse().map_err(|_| format!("bad dm {}", moves))?),
+        None => Claim::Win,
+    };
+    let solution = epd
+        .operation("bm")
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|san| {
+            game::parse_san(&epd.board, san).ok_or(format!("illegal bm {} in {}", san, epd.fen))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Puzzle {
+        id: epd.id().unwrap_or(&epd.fen).to_string(),
+        board: epd.board,
+        claim,
+        solution,
+    })
+}
+
+// Why the puzzle is broken, if it is.
+fn check(searcher: &mut Searcher, puzzle: &Puzzle, options: &Options) -> Result<(), String> {
+    let solution = puzzle::solve(
+        searcher,
+        &puzzle.board,
+        puzzle.claim,
+        options.limits.clone(),
+    );
+    let keys: Vec<String> = solution
+        .keys
+        .iter()
+        .map(|rm| game::san(&puzzle.board, &rm.mv))
+        .collect();
+
+    if !solution.complete {
+        return Err(format!("ran out of time, found {}", keys.join(" ")));
+    }
+    if keys.is_empty() {
+        return Err(match puzzle.claim {
+            Claim::MateIn(moves) => format!("no mate in {}", moves),
+            Claim::Win => "no winning move".into(),
+        });
+    }
+    let unexpected: Vec<&String> = solution
+        .keys
+        .iter()
+        .zip(&keys)
+        .filter(|(rm, _)| !puzzle.solution.is_empty() && !puzzle.solution.contains(&rm.mv))
+        .map(|(_, san)| san)
+        .collect();
+    if !unexpected.is_empty() {
+        let unexpected: Vec<&str> = unexpected.iter().map(|s| s.as_str()).collect();
+        return Err(format!("{} also works", unexpected.join(" ")));
+    }
+    if options.unique && !solution.is_unique() {
+        return Err(format!("not unique: {}", keys.join(" ")));
+    }
+    Ok(())
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let lines: Vec<String> = match (&mut options.input).lines().collect() {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("reading puzzles: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut searcher = Searcher::new();
+    let (mut total, mut failed) = (0, 0);
+    for line in lines.iter().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        total += 1;
+        let result =
+            parse_epd(line).and_then(|puzzle| match check(&mut searcher, &puzzle, &options) {
+                Ok(()) => Ok(puzzle.id),
+                Err(e) => Err(format!("{}: {}", puzzle.id, e)),
+            });
+        match result {
+            Ok(id) => println!("ok {}", id),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}", e);
+            }
+        }
+    }
+
+    eprintln!("{}/{} puzzles ok", total - failed, total);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(s: &str) -> Options {
+        let args: Vec<String> = s.split_whitespace().map(String::from).collect();
+        parse_args(&args).unwrap()
+    }
+
+    #[test]
+    fn test_parse_epd() {
+        gen_moves_once();
+        let puzzle =
+            parse_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; dm 1; id \"back rank\";").unwrap();
+        assert_eq!(puzzle.id, "back rank");
+        assert_eq!(puzzle.claim, Claim::MateIn(1));
+        assert_eq!(
+            puzzle.solution,
+            vec![Movement::from_notation("a1a8").unwrap()]
+        );
+
+        let puzzle = parse_epd("4k3/8/8/8/3q4/8/8/3RK3 w - -").unwrap();
+        assert_eq!(puzzle.claim, Claim::Win);
+        assert!(puzzle.solution.is_empty());
+
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Rb8;").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - dm one;").is_err());
+        assert!(parse_epd("6k1/5ppp").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        gen_moves_once();
+        let mut searcher = Searcher::new();
+        let mut check =
+            |epd: &str, options: &Options| check(&mut searcher, &parse_epd(epd).unwrap(), options);
+
+        let defaults = options("--nodes 1000000");
+        let unique = options("--unique --nodes 1000000");
+        let two_rooks = "6k1/5ppp/8/8/8/8/8/RR4K1 w - -";
+
+        assert_eq!(
+            check("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; dm 1;", &unique),
+            Ok(())
+        );
+        assert_eq!(
+            check(&format!("{} bm Ra8# Rb8#; dm 1;", two_rooks), &defaults),
+            Ok(())
+        );
+        assert_eq!(
+            check(&format!("{} dm 1;", two_rooks), &unique),
+            Err("not unique: Ra8# Rb8#".into())
+        );
+        assert_eq!(
+            check(&format!("{} bm Ra8#; dm 1;", two_rooks), &defaults),
+            Err("Rb8# also works".into())
+        );
+        assert_eq!(
+            check(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - dm 2;",
+                &defaults
+            ),
+            Err("no mate in 2".into())
+        );
+    }
+}
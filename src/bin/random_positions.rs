@@ -0,0 +1,150 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use yobmef::chess::Board;
+use yobmef::movegen::{self, gen_moves_once};
+use yobmef::random;
+
+// Write random legal positions as FENs, one per line, for benchmark and test
+// corpora (search_stats --epd, match_runner --openings):
+//
+//     random_positions --count 1000 --plies 60 --seed 7 > middlegames.fen
+//     random_positions --count 1000 --pieces RPPrp > rook_endings.fen
+//
+// Either from random games of up to --plies moves, or with --pieces from
+// random placements of that material (see random.rs). Positions without a
+// legal move are skipped. The same seed gives the same positions.
+
+const USAGE: &str =
+    "usage: random_positions [--count N] [--seed S] [--plies N | --pieces LETTERS] [--out FILE]";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Source {
+    Games(usize), // up to this many plies
+    Placements(String),
+}
+
+struct Options {
+    count: usize,
+    seed: u64,
+    source: Source,
+    out: Box<dyn Write>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        count: 100,
+        seed: 0,
+        source: Source::Games(40),
+        out: Box::new(io::stdout()),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--count" => options.count = value.parse().map_err(|_| bad())?,
+            "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+            "--plies" => options.source = Source::Games(value.parse().map_err(|_| bad())?),
+            "--pieces" => {
+                // Fail here instead of on the first position.
+                random::random_placement(value, &mut StdRng::seed_from_u64(0)).ok_or_else(bad)?;
+                options.source = Source::Placements(value.to_string());
+            }
+            "--out" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.out = Box::new(BufWriter::new(file));
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn positions(source: &Source, count: usize, seed: u64) -> Vec<Board> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut positions = Vec::with_capacity(count);
+    while positions.len() < count {
+        let board = match source {
+            Source::Games(plies) => random::random_game(rng.gen_range(0..=*plies), &mut rng),
+            Source::Placements(pieces) => random::random_placement(pieces, &mut rng).unwrap(),
+        };
+        if movegen::has_legal_move(&board) {
+            positions.push(board);
+        }
+    }
+    positions
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    for board in positions(&options.source, options.count, options.seed) {
+        if let Err(e) = writeln!(options.out, "{}", board.to_fen()) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    let _ = options.out.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        gen_moves_once();
+        let options = parse_args(&args("--count 5 --seed 9 --pieces QRr")).unwrap();
+        assert_eq!(options.count, 5);
+        assert_eq!(options.seed, 9);
+        assert_eq!(options.source, Source::Placements("QRr".into()));
+
+        let options = parse_args(&args("--plies 80")).unwrap();
+        assert_eq!(options.source, Source::Games(80));
+
+        assert!(parse_args(&args("--pieces Qx")).is_err());
+        assert!(parse_args(&args("--count")).is_err());
+    }
+
+    #[test]
+    fn test_positions() {
+        gen_moves_once();
+        let fens = |source: &Source, seed| -> Vec<String> {
+            positions(source, 50, seed)
+                .iter()
+                .map(|b| b.to_fen())
+                .collect()
+        };
+        let games = Source::Games(30);
+        assert_eq!(fens(&games, 1).len(), 50);
+        assert_eq!(fens(&games, 1), fens(&games, 1));
+        assert_ne!(fens(&games, 1), fens(&games, 2));
+
+        // A lone king against a queen gets stalemated or mated now and then.
+        for board in positions(&Source::Placements("q".into()), 200, 3) {
+            assert!(movegen::has_legal_move(&board));
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+use std::time::{Duration, Instant};
+use yobmef::chess::Board;
+use yobmef::epd::Epd;
+use yobmef::movegen::gen_moves_once;
+use yobmef::search::{SearchFeatures, SearchLimits, Searcher};
+use yobmef::stats::SearchStats;
+
+// Search a set of positions and write what the search did as CSV, one row per
+// position, for comparing search changes over more than just the startpos:
+//
+//     search_stats --epd positions.epd --depth 8 > before.csv
+//     search_stats --epd positions.epd --depth 8 --disable killers > after.csv
+//
+// Positions are EPD (or FEN), one per line, named by their `id` if they have
+// one. Counts are for the whole search, see stats.rs for what they mean.
+// Every position starts with an empty hash, so rows don't depend on the order.
+
+const USAGE: &str = "usage: search_stats [--fen FEN | --epd FILE] \
+                     [--depth D | --movetime MS | --nodes N] \
+                     [--disable FEATURE,...] [--hash MB] [--out FILE]";
+
+const DEFAULT_DEPTH: i16 = 6;
+
+// A feature's on/off switch in SearchFeatures.
+type Switch = fn(&mut SearchFeatures) -> &mut bool;
+
+// Names for --disable, and the switch they turn off.
+const FEATURES: &[(&str, Switch)] = &[
+    ("tt-cutoffs", |f| &mut f.tt_cutoffs),
+    ("upcoming-repetitions", |f| &mut f.upcoming_repetitions),
+    ("check-extensions", |f| &mut f.check_extensions),
+    ("recapture-extensions", |f| &mut f.recapture_extensions),
+    ("killers", |f| &mut f.killers),
+    ("delta-pruning", |f| &mut f.delta_pruning),
+    ("razoring", |f| &mut f.razoring),
+    ("iid", |f| &mut f.iid),
+    ("continuation-history", |f| &mut f.continuation_history),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Position {
+    id: String,
+    board: Board,
+}
+
+struct Options {
+    positions: Vec<Position>,
+    limits: SearchLimits,
+    features: SearchFeatures,
+    hash_mb: Option<usize>,
+    out: Box<dyn Write>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        positions: vec![Position {
+            id: "startpos".into(),
+            board: Board::from_start_pos(),
+        }],
+        limits: SearchLimits::depth(DEFAULT_DEPTH),
+        features: SearchFeatures::default(),
+        hash_mb: None,
+        out: Box::new(io::stdout()),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--fen" => options.positions = vec![parse_epd(value)?],
+            "--epd" => {
+                let file = File::open(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.positions =
+                    read_epd(BufReader::new(file)).map_err(|e| format!("{}: {}", value, e))?;
+            }
+            "--depth" => options.limits = SearchLimits::depth(value.parse().map_err(|_| bad())?),
+            "--movetime" => {
+                let ms = value.parse().map_err(|_| bad())?;
+                options.limits = SearchLimits::movetime(Duration::from_millis(ms))
+            }
+            "--nodes" => {
+                options.limits = SearchLimits {
+                    nodes: Some(value.parse().map_err(|_| bad())?),
+                    ..SearchLimits::default()
+                }
+            }
+            "--disable" => {
+                for name in value.split(',') {
+                    let (_, switch) = FEATURES.iter().find(|(n, _)| *n == name).ok_or(format!(
+                        "no search feature {}, there's {}",
+                        name,
+                        feature_names()
+                    ))?;
+                    *switch(&mut options.features) = false;
+                }
+            }
+            "--hash" => options.hash_mb = Some(value.parse().map_err(|_| bad())?),
+            "--out" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.out = Box::new(BufWriter::new(file));
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn feature_names() -> String {
+    FEATURES
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+// The position of an EPD line, or a FEN. Operations other than id are ignored.
+fn parse_epd(line: &str) -> Result<Position, String> {
+    let epd = Epd::parse(line)?;
+    Ok(Position {
+        id: epd.id().unwrap_or(&epd.fen).to_string(),
+        board: epd.board,
+    })
+}
+
+fn read_epd<R: BufRead>(input: R) -> Result<Vec<Position>, String> {
+    let mut positions = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        positions.push(parse_epd(line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+    }
+    Ok(positions)
+}
+
+fn csv_header() -> String {
+    format!(
+        "id,fen,depth,seldepth,score,best,time_ms,{}",
+        SearchStats::CSV_HEADER
+    )
+}
+
+fn csv_row(position: &Position, searcher: &mut Searcher, limits: &SearchLimits) -> String {
+    searcher.clear_hash();
+    let start = Instant::now();
+    let sr = searcher.go(&position.board, limits.clone());
+    let time = start.elapsed();
+
+    format!(
+        "\"{}\",{},{},{},{},{},{},{}",
+        position.id.replace('"', "\"\""),
+        position.board.to_fen(),
+        sr.depth,
+        searcher.seldepth,
+        sr.eval * position.board.side_to_move.polarize(),
+        sr.mv.to_notation(),
+        time.as_millis(),
+        searcher.total_stats.to_csv()
+    )
+}
+
+fn run(options: &mut Options) -> io::Result<()> {
+    let mut searcher = Searcher::new();
+    if let Some(mb) = options.hash_mb {
+        searcher.set_hash_size(mb);
+    }
+    searcher.set_features(options.features);
+
+    writeln!(options.out, "{}", csv_header())?;
+    for position in &options.positions {
+        let row = csv_row(position, &mut searcher, &options.limits);
+        writeln!(options.out, "{}", row)?;
+    }
+    options.out.flush()
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(&mut options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        gen_moves_once();
+        let options = parse_args(&args("--nodes 5000 --disable killers,tt-cutoffs")).unwrap();
+        assert_eq!(options.limits.nodes, Some(5000));
+        assert_eq!(options.positions[0].id, "startpos");
+        assert_eq!(
+            options.features,
+            SearchFeatures {
+                killers: false,
+                tt_cutoffs: false,
+                ..SearchFeatures::default()
+            }
+        );
+
+        let options = parse_args(&[
+            "--fen".to_string(),
+            "8/8/8/8/8/8/k7/7K b - - 0 1".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(options.limits.depth, Some(DEFAULT_DEPTH));
+        assert_eq!(
+            options.positions[0].board.to_fen(),
+            "8/8/8/8/8/8/k7/7K b - - 0 1"
+        );
+
+        // We don't have these (yet).
+        let err = parse_args(&args("--disable null-move")).err().unwrap();
+        assert!(err.contains("no search feature null-move"), "{}", err);
+        assert!(parse_args(&args("--disable lmr")).is_err());
+        assert!(parse_args(&args("--depth")).is_err());
+        assert!(parse_args(&args("--depth x")).is_err());
+    }
+
+    #[test]
+    fn test_read_epd() {
+        gen_moves_once();
+        let epd = "# comment\n\
+                   6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";\n\
+                   \n\
+                   rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\n";
+        let positions = read_epd(epd.as_bytes()).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].id, "back rank");
+        assert_eq!(
+            positions[1].id,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"
+        );
+        assert_eq!(positions[1].board, Board::from_start_pos());
+
+        let err = read_epd("8/8 w\n".as_bytes()).err().unwrap();
+        assert!(err.starts_with("line 1:"), "{}", err);
+    }
+
+    #[test]
+    fn test_csv_row() {
+        gen_moves_once();
+        let position = parse_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - id \"back rank\";").unwrap();
+        let row = csv_row(&position, &mut Searcher::new(), &SearchLimits::depth(2));
+        let columns: Vec<&str> = row.split(',').collect();
+        assert_eq!(columns.len(), csv_header().split(',').count());
+        assert_eq!(columns[0], "\"back rank\"");
+        assert_eq!(columns[5], "a1a8");
+        assert!(columns[7].parse::<u64>().unwrap() > 0);
+    }
+}
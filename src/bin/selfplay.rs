@@ -0,0 +1,311 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use yobmef::adjudication::{Adjudication, Adjudicator, DrawRule, ResignRule};
+use yobmef::chess::{Board, Color};
+use yobmef::engine::{Engine, SearchEvent};
+use yobmef::game::{Game, GameResult};
+use yobmef::movegen::gen_moves_once;
+use yobmef::search::{mate_in, SearchInfo, SearchLimits};
+use yobmef::syzygy::Tablebases;
+
+// Play the engine against itself and write the games as PGN, with the eval,
+// depth and time of every move in its comment (like cutechess: {+0.35/12 0.51s}).
+//
+//     selfplay --games 100 --tc 10+0.1 --openings openings.fen --out games.pgn
+//
+// Openings are FENs, one per line, used in turn. Without them every game starts
+// from the start position, and games only differ by what's in the hash.
+//
+// --resign 3,500 ends games where both sides agree one is 5 pawns up for 3
+// moves each, --draw 40,8,10 ones that are within 0.1 of equal for 8 moves each
+// after move 40. --syzygy DIR ends them once they're in the tablebases there.
+// (see adjudication.rs)
+
+const USAGE: &str =
+    "usage: selfplay [--games N] [--depth D | --nodes N | --movetime MS | --tc SECS[+INC]] \
+[--openings FILE] [--out FILE] [--resign MOVES,CP] [--draw AFTER,MOVES,CP] \
+[--syzygy DIR]";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Limit {
+    Depth(i16),
+    Nodes(u64),
+    MoveTime(Duration),
+    Clock(Duration, Duration), // time, increment
+}
+
+struct Options {
+    games: usize,
+    limit: Limit,
+    openings: Vec<Board>,
+    out: Box<dyn Write>,
+    adjudication: Adjudication,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        games: 1,
+        limit: Limit::MoveTime(Duration::from_millis(100)),
+        openings: vec![Board::from_start_pos()],
+        out: Box::new(io::stdout()),
+        adjudication: Adjudication::default(),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(|_| bad())?,
+            "--depth" => options.limit = Limit::Depth(value.parse().map_err(|_| bad())?),
+            "--nodes" => options.limit = Limit::Nodes(value.parse().map_err(|_| bad())?),
+            "--movetime" => {
+                let ms = value.parse().map_err(|_| bad())?;
+                options.limit = Limit::MoveTime(Duration::from_millis(ms))
+            }
+            "--tc" => {
+                let mut parts = value.splitn(2, '+');
+                let mut secs = || -> Result<Duration, String> {
+                    let secs: f64 = parts.next().unwrap_or("0").parse().map_err(|_| bad())?;
+                    Ok(Duration::from_secs_f64(secs.max(0.0)))
+                };
+                options.limit = Limit::Clock(secs()?, secs()?);
+            }
+            "--openings" => {
+                let file = fs::read_to_string(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.openings = file
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|fen| Board::from_fen(fen).ok_or(format!("bad fen {}", fen)))
+                    .collect::<Result<_, _>>()?;
+                if options.openings.is_empty() {
+                    return Err(format!("no openings in {}", value));
+                }
+            }
+            "--out" => {
+                let file = File::create(value).map_err(|e| format!("{}: {}", value, e))?;
+                options.out = Box::new(file);
+            }
+            "--resign" => {
+                options.adjudication.resign = Some(ResignRule::parse(value).ok_or_else(bad)?)
+            }
+            "--draw" => options.adjudication.draw = Some(DrawRule::parse(value).ok_or_else(bad)?),
+            "--syzygy" => {
+                options.adjudication.tablebases = Some(Arc::new(Tablebases::open(value)?))
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+// {+0.35/12 0.51s}, from the side to move's point of view.
+fn comment(info: Option<&SearchInfo>, time: Duration) -> String {
+    let (score, depth) = match info {
+        Some(info) => (
+            match info.score.map(|s| (s, mate_in(s))) {
+                Some((_, Some(moves))) if moves > 0 => format!("+M{}", moves),
+                Some((_, Some(moves))) => format!("-M{}", -moves),
+                Some((cp, None)) => format!("{:+.2}", cp as f64 / 100.0),
+                None => "0.00".into(),
+            },
+            info.depth.unwrap_or(0),
+        ),
+        // Only book moves come without a search.
+        None => return format!("book {:.2}s", time.as_secs_f64()),
+    };
+    format!("{}/{} {:.2}s", score, depth, time.as_secs_f64())
+}
+
+fn play_game(
+    engines: &mut [Engine; 2],
+    start: Board,
+    limit: &Limit,
+    adjudication: Adjudication,
+) -> Game {
+    let mut game = Game::new(start);
+    let mut adjudicator = Adjudicator::new(adjudication);
+    let mut clocks = match limit {
+        Limit::Clock(time, _) => [*time; 2],
+        _ => [Duration::default(); 2],
+    };
+    for engine in engines.iter_mut() {
+        engine.new_game();
+    }
+
+    while !game.is_over() {
+        let side = game.board().side_to_move;
+        let engine = &mut engines[side as usize];
+        engine
+            .set_position(game.start().clone(), game.moves())
+            .expect("illegal move in self-play");
+
+        let limits = match *limit {
+            Limit::Depth(depth) => SearchLimits::depth(depth),
+            Limit::Nodes(nodes) => SearchLimits {
+                nodes: Some(nodes),
+                ..SearchLimits::default()
+            },
+            Limit::MoveTime(time) => SearchLimits::movetime(time),
+            Limit::Clock(_, inc) => SearchLimits {
+                wtime: Some(clocks[Color::White as usize]),
+                btime: Some(clocks[Color::Black as usize]),
+                winc: Some(inc),
+                binc: Some(inc),
+                ..SearchLimits::default()
+            },
+        };
+
+        let start = Instant::now();
+        let mut last_info = None;
+        let mut best = None;
+        for event in engine.go(limits) {
+            match event {
+                SearchEvent::Info(info) if info.depth.is_some() => last_info = Some(info),
+                SearchEvent::Info(_) => {}
+                SearchEvent::BestMove(mv, _) => best = mv,
+            }
+        }
+        let elapsed = start.elapsed();
+
+        if let Limit::Clock(_, inc) = limit {
+            let clock = &mut clocks[side as usize];
+            if elapsed > *clock {
+                game.end(GameResult::win_for(side.other()), "Time forfeit");
+                break;
+            }
+            *clock = *clock - elapsed + *inc;
+        }
+
+        let mv = best.expect("no move in an unfinished game");
+        game.play(mv, Some(comment(last_info.as_ref(), elapsed)));
+        if !game.is_over() {
+            let score = last_info.and_then(|info| info.score);
+            if let Some((result, why)) = adjudicator.update(game.board(), side, score) {
+                game.end(result, why);
+            }
+        }
+    }
+    game
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let time_control = match options.limit {
+        Limit::Clock(time, inc) => format!("{}+{}", time.as_secs_f64(), inc.as_secs_f64()),
+        _ => "-".into(),
+    };
+
+    let mut engines = [Engine::new(), Engine::new()];
+    let mut score = [0, 0, 0]; // white wins, black wins, draws
+    for i in 0..options.games {
+        let opening = options.openings[i % options.openings.len()].clone();
+        let mut game = play_game(
+            &mut engines,
+            opening,
+            &options.limit,
+            options.adjudication.clone(),
+        );
+
+        game.set_tag("Event", "Yobmef self-play");
+        game.set_tag("Round", &(i + 1).to_string());
+        game.set_tag("White", "Yobmef");
+        game.set_tag("Black", "Yobmef");
+        game.set_tag("TimeControl", &time_control);
+
+        let result = game.result().expect("game not over");
+        score[match result {
+            GameResult::WhiteWins => 0,
+            GameResult::BlackWins => 1,
+            GameResult::Draw => 2,
+        }] += 1;
+        eprintln!(
+            "game {}/{}: {} ({}) after {} plies",
+            i + 1,
+            options.games,
+            result,
+            game.termination().unwrap_or(""),
+            game.moves().len()
+        );
+
+        if let Err(e) = options.out.write_all(game.to_pgn().as_bytes()) {
+            eprintln!("writing pgn: {}", e);
+            process::exit(1);
+        }
+        let _ = options.out.flush();
+    }
+
+    eprintln!(
+        "white wins {}, black wins {}, draws {}",
+        score[0], score[1], score[2]
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        gen_moves_once();
+        let options = parse_args(&args("--games 10 --tc 5+0.1")).unwrap();
+        assert_eq!(options.games, 10);
+        assert_eq!(
+            options.limit,
+            Limit::Clock(Duration::from_secs(5), Duration::from_millis(100))
+        );
+        let options = parse_args(&args("--depth 4")).unwrap();
+        assert_eq!(options.limit, Limit::Depth(4));
+
+        assert!(parse_args(&args("--games")).is_err());
+        assert!(parse_args(&args("--games many")).is_err());
+        assert!(parse_args(&args("--colour white")).is_err());
+
+        let options = parse_args(&args("--resign 3,500 --draw 40,8,10")).unwrap();
+        assert_eq!(
+            options.adjudication.resign,
+            Some(ResignRule {
+                moves: 3,
+                score: 500
+            })
+        );
+        assert!(options.adjudication.draw.is_some());
+        assert!(parse_args(&args("--resign 500")).is_err());
+        assert!(parse_args(&args("--syzygy /no/such/dir")).is_err());
+    }
+
+    #[test]
+    fn test_comment() {
+        let info = SearchInfo {
+            depth: Some(12),
+            score: Some(-35),
+            ..SearchInfo::default()
+        };
+        let time = Duration::from_millis(510);
+        assert_eq!(comment(Some(&info), time), "-0.35/12 0.51s");
+        assert_eq!(comment(None, time), "book 0.51s");
+    }
+}
@@ -0,0 +1,407 @@
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use yobmef::adjudication::{Adjudication, DrawRule, ResignRule};
+use yobmef::chess::Board;
+use yobmef::game::GameResult;
+use yobmef::matches::{self, play_game, start_engine, Limit};
+use yobmef::movegen::gen_moves_once;
+use yobmef::syzygy::Tablebases;
+use yobmef::tune::{self, Tunable};
+use yobmef::uci_engine::UciEngine;
+
+// Tune the engine's search and eval constants (src/tune.rs) with SPSA,
+// simultaneous perturbation stochastic approximation, in self-play.
+//
+//     spsa --engine ./yobmef-tune --iterations 5000 --tc 5+0.05 --concurrency 4 \
+//         --openings openings.fen --param KnightValue --param BishopValue=300
+//
+// The engine has to be built with `--features tune`. Every iteration nudges
+// every parameter up or down at random, plays a game pair (same opening, colors
+// swapped) between the nudged up values and the nudged down ones, and moves the
+// parameters toward whichever side did better. Without --param, everything in
+// src/tune.rs is tuned, from its default or the given start.
+//
+// The gains are fishtest's: the nudges shrink from about a twentieth of the
+// parameter's range, the steps from --r-end. The values are printed after every
+// iteration and as match_runner flags at the end, check them with an SPRT
+// before believing them. --resign, --draw and --syzygy adjudicate like in
+// selfplay, and save a lot of time here.
+
+const USAGE: &str = "usage: spsa --engine CMD [--param NAME[=START]]... [--iterations N] \
+[--depth D | --nodes N | --movetime MS | --tc SECS[+INC]] [--concurrency N] \
+[--openings FILE] [--option NAME=VALUE]... [--resign MOVES,CP] [--draw AFTER,MOVES,CP] \
+[--syzygy DIR] [--r-end R]";
+
+// Fishtest's exponents for how fast the gains decay.
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Param {
+    name: &'static str,
+    value: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Param {
+    fn new(tunable: &Tunable) -> Param {
+        Param {
+            name: tunable.name,
+            value: tunable.default as f64,
+            min: tunable.min as f64,
+            max: tunable.max as f64,
+        }
+    }
+
+    // How far it's nudged in the last iteration.
+    fn c_end(&self) -> f64 {
+        (self.max - self.min) / 20.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Spsa {
+    params: Vec<Param>,
+    iterations: usize,
+    r_end: f64,
+}
+
+impl Spsa {
+    // The stability constant, keeps the first steps from being huge.
+    fn big_a(&self) -> f64 {
+        0.1 * self.iterations as f64
+    }
+
+    // How far each parameter gets nudged in iteration k.
+    fn c(&self, k: usize) -> Vec<f64> {
+        let n = self.iterations as f64;
+        self.params
+            .iter()
+            .map(|p| p.c_end() * (n / (k + 1) as f64).powf(GAMMA))
+            .collect()
+    }
+
+    // The step size of each parameter in iteration k, such that the last one
+    // is r_end * c_end^2.
+    fn a(&self, k: usize) -> Vec<f64> {
+        let n = self.iterations as f64;
+        let big_a = self.big_a();
+        self.params
+            .iter()
+            .map(|p| {
+                let a = self.r_end * p.c_end().powi(2) * (big_a + n).powf(ALPHA);
+                a / (big_a + (k + 1) as f64).powf(ALPHA)
+            })
+            .collect()
+    }
+
+    // The setoptions for the nudged up and down engines, `flips` says which way
+    // (+1 or -1) each parameter goes.
+    fn sides(&self, k: usize, flips: &[f64]) -> [Vec<(String, String)>; 2] {
+        let c = self.c(k);
+        let side = |sign: f64| {
+            self.params
+                .iter()
+                .zip(&c)
+                .zip(flips)
+                .map(|((p, c), flip)| {
+                    let value = (p.value + sign * c * flip).round().clamp(p.min, p.max);
+                    (p.name.to_string(), value.to_string())
+                })
+                .collect()
+        };
+        [side(1.0), side(-1.0)]
+    }
+
+    // Move toward the side that did better. `result` is the nudged up engine's
+    // wins minus losses.
+    fn update(&mut self, k: usize, flips: &[f64], result: f64) {
+        let (a, c) = (self.a(k), self.c(k));
+        for (i, p) in self.params.iter_mut().enumerate() {
+            p.value = (p.value + a[i] / c[i] * result * flips[i]).clamp(p.min, p.max);
+        }
+    }
+}
+
+struct Options {
+    engine: String,
+    spsa: Spsa,
+    concurrency: usize,
+    limit: Limit,
+    openings: Vec<Board>,
+    adjudication: Adjudication,
+    options: Vec<(String, String)>, // setoptions besides the parameters
+}
+
+fn parse_param(value: &str) -> Result<Param, String> {
+    let (name, start) = match value.split_once('=') {
+        Some((name, start)) => (name, Some(start)),
+        None => (value, None),
+    };
+    let tunable = tune::TUNABLES
+        .iter()
+        .find(|t| t.name.eq_ignore_ascii_case(name))
+        .ok_or(format!("no tunable {}", name))?;
+    let mut param = Param::new(tunable);
+    if let Some(start) = start {
+        let start: f64 = start
+            .parse()
+            .map_err(|_| format!("bad start for {}: {}", name, start))?;
+        param.value = start.clamp(param.min, param.max);
+    }
+    Ok(param)
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        engine: String::new(),
+        spsa: Spsa {
+            params: Vec::new(),
+            iterations: 1000,
+            r_end: 0.002,
+        },
+        concurrency: 1,
+        limit: Limit::Clock(Duration::from_secs(5), Duration::from_millis(50)),
+        openings: vec![Board::from_start_pos()],
+        adjudication: Adjudication::default(),
+        options: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or(format!("{} needs a value", flag))?
+            .as_str();
+        let bad = || format!("bad value for {}: {}", flag, value);
+
+        if let Some(limit) = matches::parse_limit(flag, value)? {
+            options.limit = limit;
+            continue;
+        }
+        match flag.as_str() {
+            "--engine" => options.engine = value.to_string(),
+            "--param" => options.spsa.params.push(parse_param(value)?),
+            "--iterations" => options.spsa.iterations = value.parse().map_err(|_| bad())?,
+            "--r-end" => options.spsa.r_end = value.parse().map_err(|_| bad())?,
+            "--concurrency" => options.concurrency = value.parse().map_err(|_| bad())?,
+            "--openings" => options.openings = matches::read_openings(value)?,
+            "--option" => {
+                let (name, value) = value.split_once('=').ok_or_else(bad)?;
+                options.options.push((name.to_string(), value.to_string()));
+            }
+            "--resign" => {
+                options.adjudication.resign = Some(ResignRule::parse(value).ok_or_else(bad)?)
+            }
+            "--draw" => options.adjudication.draw = Some(DrawRule::parse(value).ok_or_else(bad)?),
+            "--syzygy" => {
+                options.adjudication.tablebases = Some(Arc::new(Tablebases::open(value)?))
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    if options.engine.is_empty() {
+        return Err("need an engine".into());
+    }
+    if options.spsa.params.is_empty() {
+        options.spsa.params = tune::TUNABLES.iter().map(Param::new).collect();
+    }
+    options.spsa.iterations = options.spsa.iterations.max(1);
+    options.concurrency = options.concurrency.max(1);
+    Ok(options)
+}
+
+// Wins minus losses for engines[0] from a game it played as white if `white`
+// is 0, black otherwise.
+fn score(result: GameResult, white: usize) -> f64 {
+    match (result, white) {
+        (GameResult::Draw, _) => 0.0,
+        (GameResult::WhiteWins, 0) | (GameResult::BlackWins, 1) => 1.0,
+        _ => -1.0,
+    }
+}
+
+fn values(spsa: &Spsa) -> String {
+    let values: Vec<String> = spsa
+        .params
+        .iter()
+        .map(|p| format!("{}={:.1}", p.name, p.value))
+        .collect();
+    values.join(" ")
+}
+
+// Play iterations on this thread until they're all taken.
+fn run(options: &Options, spsa: &Mutex<Spsa>, next: &AtomicUsize) {
+    let mut engines: [Option<UciEngine>; 2] = [None, None];
+    let commands = [options.engine.clone(), options.engine.clone()];
+    loop {
+        let k = next.fetch_add(1, Ordering::Relaxed);
+        if k >= options.spsa.iterations {
+            break;
+        }
+        let flips: Vec<f64> = (0..options.spsa.params.len())
+            .map(|_| if rand::random() { 1.0 } else { -1.0 })
+            .collect();
+        let sides = spsa.lock().unwrap().sides(k, &flips);
+        let sides: Vec<Vec<(String, String)>> = sides
+            .iter()
+            .map(|side| options.options.iter().chain(side).cloned().collect())
+            .collect();
+
+        // Running engines get the new values, ones that died get them on restart.
+        for (engine, side) in engines.iter_mut().zip(&sides) {
+            if let Some(running) = engine {
+                if side.iter().any(|(n, v)| running.set_option(n, v).is_err()) {
+                    *engine = None;
+                }
+            }
+        }
+
+        let opening = &options.openings[k % options.openings.len()];
+        let mut result = 0.0;
+        for white in 0..2 {
+            let game = play_game(
+                &mut engines,
+                &commands,
+                [&sides[0], &sides[1]],
+                white,
+                opening.clone(),
+                &options.limit,
+                options.adjudication.clone(),
+            );
+            result += score(game.result().expect("game not over"), white);
+        }
+
+        let mut spsa = spsa.lock().unwrap();
+        spsa.update(k, &flips, result);
+        println!("iteration {} ({:+}): {}", k + 1, result, values(&spsa));
+    }
+}
+
+fn main() {
+    gen_moves_once();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    // Fail early if it wasn't built for tuning.
+    match start_engine(&options.engine, &options.options) {
+        Ok(engine) => {
+            for param in &options.spsa.params {
+                if !engine.options.iter().any(|o| o == param.name) {
+                    eprintln!(
+                        "{}: no option {}, build it with --features tune",
+                        engine.name, param.name
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("{}: {}", options.engine, e);
+            process::exit(1);
+        }
+    }
+
+    let spsa = Mutex::new(options.spsa.clone());
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..options.concurrency {
+            let (options, spsa, next) = (&options, &spsa, &next);
+            scope.spawn(move || run(options, spsa, next));
+        }
+    });
+
+    let spsa = spsa.into_inner().unwrap();
+    let flags: Vec<String> = spsa
+        .params
+        .iter()
+        .map(|p| format!("--option {}={}", p.name, p.value.round()))
+        .collect();
+    println!("final: {}", flags.join(" "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        gen_moves_once();
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn spsa(params: &str) -> Spsa {
+        parse_args(&args(&format!(
+            "--engine yobmef --iterations 100 {}",
+            params
+        )))
+        .unwrap()
+        .spsa
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options =
+            parse_args(&args("--engine yobmef --param knightvalue=300 --depth 4")).unwrap();
+        assert_eq!(options.limit, Limit::Depth(4));
+        assert_eq!(options.spsa.params.len(), 1);
+        assert_eq!(options.spsa.params[0].name, "KnightValue");
+        assert_eq!(options.spsa.params[0].value, 300.0);
+
+        // Everything by default.
+        let options = parse_args(&args("--engine yobmef")).unwrap();
+        assert_eq!(options.spsa.params.len(), tune::TUNABLES.len());
+
+        assert!(parse_args(&args("--param KnightValue")).is_err());
+        assert!(parse_args(&args("--engine yobmef --param PawnValue")).is_err());
+        assert!(parse_args(&args("--engine yobmef --param KnightValue=lots")).is_err());
+    }
+
+    #[test]
+    fn test_gains() {
+        let spsa = spsa("--param RookValue");
+        // The range is 350, so nudges end at 17.5.
+        assert!((spsa.c(99)[0] - 17.5).abs() < 1e-9);
+        assert!(spsa.c(0)[0] > spsa.c(99)[0]);
+        assert!((spsa.a(99)[0] - 0.002 * 17.5 * 17.5).abs() < 1e-9);
+        assert!(spsa.a(0)[0] > spsa.a(99)[0]);
+    }
+
+    #[test]
+    fn test_sides() {
+        let spsa = spsa("--param KnightValue=440 --param Tempo");
+        let [plus, minus] = spsa.sides(99, &[1.0, -1.0]);
+        // Clamped to the range, and whole numbers for the spin options.
+        assert_eq!(plus[0], ("KnightValue".to_string(), "450".to_string()));
+        assert_eq!(minus[0], ("KnightValue".to_string(), "428".to_string()));
+        assert_eq!(plus[1], ("Tempo".to_string(), "8".to_string()));
+        assert_eq!(minus[1], ("Tempo".to_string(), "13".to_string()));
+    }
+
+    #[test]
+    fn test_update() {
+        let mut spsa = spsa("--param KnightValue --param BishopValue");
+        let before = spsa.clone();
+        // The nudged up side won both games: knight went up, bishop down.
+        spsa.update(0, &[1.0, -1.0], 2.0);
+        assert!(spsa.params[0].value > before.params[0].value);
+        assert!(spsa.params[1].value < before.params[1].value);
+
+        // A drawn pair changes nothing.
+        let before = spsa.clone();
+        spsa.update(0, &[1.0, -1.0], 0.0);
+        assert_eq!(spsa, before);
+    }
+}
@@ -1,7 +1,7 @@
 use rand::Rng;
 
 use crate::chess::Square;
-use std::fmt;
+use core::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BitBoard(pub u64);
@@ -119,7 +119,7 @@ impl Iterator for BitBoard {
 
 macro_rules! impl_op {
     ($op:ident, $fun:ident) => {
-        use std::ops::$op;
+        use core::ops::$op;
         impl $op<Self> for BitBoard {
             type Output = Self;
 
@@ -146,7 +146,7 @@ macro_rules! impl_op {
 
 macro_rules! impl_op_assign {
     ($op:ident, $fun:ident) => {
-        use std::ops::$op;
+        use core::ops::$op;
         impl $op<Self> for BitBoard {
             fn $fun(&mut self, rhs: Self) {
                 self.0.$fun(rhs.0);
@@ -195,7 +195,7 @@ impl_op_assign!(ShlAssign, shl_assign);
 impl_op!(Shr, shr);
 impl_op_assign!(ShrAssign, shr_assign);
 
-use std::ops::Mul;
+use core::ops::Mul;
 impl Mul<u64> for BitBoard {
     type Output = Self;
 
@@ -211,7 +211,7 @@ impl Mul<BitBoard> for BitBoard {
     }
 }
 
-use std::ops::Not;
+use core::ops::Not;
 impl Not for BitBoard {
     type Output = Self;
 
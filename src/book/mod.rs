@@ -0,0 +1,220 @@
+use crate::chess::{Board, Movement};
+use crate::game;
+use crate::zobrist;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+pub mod polyglot;
+mod random64;
+
+// Opening books, played from before searching when OwnBook is on (see
+// Engine::go): a polyglot BookFile if there is one, otherwise a small book
+// compiled into the engine, so it doesn't think for ages about 1.e4 and plays
+// something different every game. That one is a few hundred main lines
+// (lines.txt) played out into a few thousand positions, enough to get out of
+// the opening, not to win it.
+//
+// Positions are looked up by zobrist key, so transpositions into a line are
+// found too. A move is weighted by how many lines play it.
+
+const LINES: &str = include_str!("lines.txt");
+
+// How many times a move is played from a position, by zobrist key.
+type Entries = HashMap<u64, Vec<(Movement, u32)>>;
+
+static BOOK: OnceLock<Entries> = OnceLock::new();
+
+// Moves from `board` in the built-in book and their weights, most popular first. Empty when
+// we're out of book.
+pub fn moves(board: &Board) -> Vec<(Movement, u32)> {
+    let mut moves = book()
+        .get(&zobrist::hash(board))
+        .cloned()
+        .unwrap_or_default();
+    moves.sort_by_key(|&(_, weight)| Reverse(weight));
+    moves
+}
+
+// A move from the built-in book to play from `board`, see pick_from.
+pub fn pick<R: Rng>(board: &Board, variety: u8, rng: &mut R) -> Option<Movement> {
+    pick_from(moves(board), variety, rng)
+}
+
+// A move from a book's `moves`. With `variety` 0 it's always the one with the
+// most weight, at 100 moves are picked in proportion to their weights, and in
+// between heavier moves are favoured more the lower it goes. None when there
+// are no moves, or none with any weight.
+pub fn pick_from<R: Rng>(
+    moves: Vec<(Movement, u32)>,
+    variety: u8,
+    rng: &mut R,
+) -> Option<Movement> {
+    let moves: Vec<(Movement, u32)> = moves.into_iter().filter(|&(_, w)| w > 0).collect();
+    if variety == 0 {
+        let most = moves.iter().map(|&(_, w)| w).max()?;
+        return moves
+            .into_iter()
+            .find(|&(_, w)| w == most)
+            .map(|(mv, _)| mv);
+    }
+
+    let exponent = 100.0 / variety as f64;
+    let weights: Vec<f64> = moves
+        .iter()
+        .map(|(_, w)| (*w as f64).powf(exponent))
+        .collect();
+    let mut r = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for ((mv, _), weight) in moves.into_iter().zip(weights) {
+        if r < weight {
+            return Some(mv);
+        }
+        r -= weight;
+    }
+    None
+}
+
+// The whole book as a polyglot book, for other GUIs and engines.
+pub fn to_polyglot() -> polyglot::BookWriter {
+    let mut writer = polyglot::BookWriter::new();
+    for line in LINES.lines() {
+        let _ = play_line(line, |board, mv| writer.add(board, mv, 1));
+    }
+    writer
+}
+
+fn book() -> &'static Entries {
+    BOOK.get_or_init(|| {
+        let mut entries = Entries::new();
+        for line in LINES.lines() {
+            // Lines are checked by the tests, a bad one is cut short here.
+            let _ = add_line(&mut entries, line);
+        }
+        entries
+    })
+}
+
+fn add_line(entries: &mut Entries, line: &str) -> Result<(), String> {
+    play_line(line, |board, mv| {
+        let moves = entries.entry(zobrist::hash(board)).or_default();
+        match moves.iter_mut().find(|(m, _)| m == mv) {
+            Some((_, weight)) => *weight += 1,
+            None => moves.push((mv.clone(), 1)),
+        }
+    })
+}
+
+// Call `f` with every move of a line from lines.txt and the position it's
+// played from, up to the first one that isn't legal.
+fn play_line<F: FnMut(&Board, &Movement)>(line: &str, mut f: F) -> Result<(), String> {
+    let line = line.split('#').next().unwrap();
+    let mut board = Board::from_start_pos();
+    for san in line.split_whitespace() {
+        let mv = game::parse_san(&board, san)
+            .ok_or_else(|| format!("illegal move {} in {}", san, board.to_fen()))?;
+        f(&board, &mv);
+        board.make_move_mut(&mv);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::{gen_moves_once, MoveGen};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn notation(moves: &[(Movement, u32)]) -> Vec<(String, u32)> {
+        moves.iter().map(|(mv, w)| (mv.to_notation(), *w)).collect()
+    }
+
+    #[test]
+    fn test_lines_are_legal() {
+        gen_moves_once();
+        let mut entries = Entries::new();
+        for (i, line) in LINES.lines().enumerate() {
+            if let Err(e) = add_line(&mut entries, line) {
+                panic!("lines.txt:{}: {}", i + 1, e);
+            }
+        }
+        // A few thousand positions, out of a few hundred lines.
+        assert!(entries.len() > 2000, "only {} positions", entries.len());
+    }
+
+    #[test]
+    fn test_moves() {
+        gen_moves_once();
+        let start = Board::from_start_pos();
+        let first = notation(&moves(&start));
+        assert_eq!(first[0].0, "e2e4");
+        assert_eq!(first[1].0, "d2d4");
+        assert!(first.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        // Out of book.
+        let board = Board::from_fen("K1k5/8/8/8/8/8/8/8 w - - 0 1").unwrap();
+        assert!(moves(&board).is_empty());
+        assert_eq!(pick(&board, 50, &mut StdRng::seed_from_u64(1)), None);
+    }
+
+    #[test]
+    fn test_transposition() {
+        gen_moves_once();
+        // 1.c4 Nf6 2.d4 e6 3.Nf3 d5 isn't a line, 1.d4 Nf6 2.c4 e6 3.Nf3 d5 is.
+        let board = ["c2c4", "g8f6", "d2d4", "e7e6", "g1f3", "d7d5"]
+            .iter()
+            .fold(Board::from_start_pos(), |b, mv| {
+                b.make_move(&Movement::from_notation(mv).unwrap())
+            });
+        assert!(!moves(&board).is_empty());
+    }
+
+    #[test]
+    fn test_pick() {
+        gen_moves_once();
+        let start = Board::from_start_pos();
+        let best = moves(&start)[0].0.clone();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for _ in 0..20 {
+            assert_eq!(pick(&start, 0, &mut rng), Some(best.clone()));
+        }
+
+        let picked: Vec<Movement> = (0..200)
+            .map(|_| pick(&start, 100, &mut rng).unwrap())
+            .collect();
+        assert!(picked
+            .iter()
+            .all(|mv| MoveGen::new_legal(&start).any(|m| m == *mv)));
+        let different = picked.iter().filter(|mv| **mv != best).count();
+        assert!(different > 20, "{} of 200 weren't {}", different, best);
+
+        // Less variety, more of the most popular move.
+        let less = (0..200)
+            .filter(|_| pick(&start, 25, &mut rng).unwrap() != best)
+            .count();
+        assert!(less < different, "{} vs {}", less, different);
+    }
+
+    #[test]
+    fn test_pick_from() {
+        let mv = |s| Movement::from_notation(s).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(pick_from(Vec::new(), 100, &mut rng), None);
+        assert_eq!(pick_from(vec![(mv("e2e4"), 0)], 100, &mut rng), None);
+        assert_eq!(pick_from(vec![(mv("e2e4"), 0)], 0, &mut rng), None);
+
+        // 3 to 1, give or take.
+        let moves = vec![(mv("e2e4"), 3), (mv("d2d4"), 1), (mv("a2a3"), 0)];
+        let e4 = (0..400)
+            .filter(|_| pick_from(moves.clone(), 100, &mut rng) == Some(mv("e2e4")))
+            .count();
+        assert!(250 < e4 && e4 < 350, "e2e4 {} of 400", e4);
+        assert!((0..400).all(|_| pick_from(moves.clone(), 100, &mut rng) != Some(mv("a2a3"))));
+
+        // The heaviest first, wherever it is.
+        let moves = vec![(mv("d2d4"), 1), (mv("e2e4"), 3), (mv("c2c4"), 3)];
+        assert_eq!(pick_from(moves, 0, &mut rng), Some(mv("e2e4")));
+    }
+}
@@ -0,0 +1,386 @@
+use super::random64::RANDOM64;
+use crate::chess::{Board, CastlingSide, Color, Movement, Piece, Square};
+use crate::movegen::MoveGen;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Write};
+
+// Reading and writing polyglot (.bin) opening books, the format most GUIs
+// read. A book is a list of 16 byte entries sorted by key, all big endian:
+//
+//     key: u64, move: u16, weight: u16, learn: u32
+//
+// Keys are polyglot's own zobrist hash, made from its table of 781 random
+// numbers (random64.rs), not ours.
+
+const ENTRY_SIZE: usize = 16;
+
+// Offsets into Random64 after the 768 piece-square keys.
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+// The polyglot key of `board`.
+pub fn key(board: &Board) -> u64 {
+    let mut key = 0;
+    for piece in 0..6 {
+        let piece = Piece::from_usize(piece).unwrap();
+        for &color in &[Color::White, Color::Black] {
+            // Black pawn, white pawn, black knight, ... white king.
+            let kind = 2 * piece as usize + (color == Color::White) as usize;
+            for sq in *board.pieces(piece) & *board.color_combined(color) {
+                key ^= RANDOM64[64 * kind + sq.0 as usize];
+            }
+        }
+    }
+
+    for side in CastlingSide::all().iter() {
+        if board.can_castle_unchecked(*side) {
+            key ^= RANDOM64[CASTLE_OFFSET + *side as usize];
+        }
+    }
+
+    // Only when a pawn could take en passant, legal or not.
+    if let Some(ep) = board.en_passant {
+        let rank = match board.side_to_move {
+            Color::White => ep.rank() - 1,
+            Color::Black => ep.rank() + 1,
+        };
+        let pawns = *board.pieces(Piece::Pawn) & *board.color_combined(board.side_to_move);
+        let takers = [ep.file().wrapping_sub(1), ep.file() + 1]
+            .iter()
+            .filter(|&&file| file < 8)
+            .any(|&file| pawns.get(Square::new(rank, file)));
+        if takers {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + ep.file() as usize];
+        }
+    }
+
+    if board.side_to_move == Color::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+// A move as polyglot writes it: to file, to rank, from file, from rank and
+// promotion piece, 3 bits each from the bottom. Castling is the king taking
+// its own rook, like we do in Chess960.
+pub fn encode_move(board: &Board, mv: &Movement) -> u16 {
+    let to = match board.castling_side(mv) {
+        Some(side) => board.castling_rooks[side as usize],
+        None => mv.to_square,
+    };
+    let promote = match mv.promote {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    to.file() as u16
+        | (to.rank() as u16) << 3
+        | (mv.from_square.file() as u16) << 6
+        | (mv.from_square.rank() as u16) << 9
+        | promote << 12
+}
+
+// A polyglot book read into memory, for playing from.
+pub struct Book {
+    // key, move, weight, sorted by key.
+    entries: Vec<(u64, u16, u16)>,
+}
+
+impl Book {
+    pub fn open(path: &str) -> Result<Book, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Book::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(format!(
+                "{} bytes isn't a whole number of entries",
+                bytes.len()
+            ));
+        }
+        let mut entries: Vec<(u64, u16, u16)> = bytes
+            .chunks(ENTRY_SIZE)
+            .map(|e| {
+                (
+                    u64::from_be_bytes(e[0..8].try_into().unwrap()),
+                    u16::from_be_bytes(e[8..10].try_into().unwrap()),
+                    u16::from_be_bytes(e[10..12].try_into().unwrap()),
+                )
+            })
+            .collect();
+        // Books are sorted already, but nothing checks that they are.
+        entries.sort_by_key(|&(key, _, _)| key);
+        Ok(Book { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Book moves from `board` and their weights, heaviest first, like
+    // book::moves. Moves with no weight are left out, and so are ones that
+    // aren't legal here, since keys can collide.
+    pub fn moves(&self, board: &Board) -> Vec<(Movement, u32)> {
+        let key = key(board);
+        let start = self.entries.partition_point(|&(k, _, _)| k < key);
+        let legal: Vec<Movement> = MoveGen::new_legal(board).collect();
+        let mut moves: Vec<(Movement, u32)> = self.entries[start..]
+            .iter()
+            .take_while(|&&(k, _, _)| k == key)
+            .filter(|&&(_, _, weight)| weight > 0)
+            .filter_map(|&(_, mv, weight)| {
+                let mv = legal.iter().find(|m| encode_move(board, m) == mv)?;
+                Some((mv.clone(), weight as u32))
+            })
+            .collect();
+        moves.sort_by_key(|&(_, weight)| Reverse(weight));
+        moves
+    }
+}
+
+// Collects weighted moves, then writes them out as a book. Weights add up for
+// a move added more than once, from the same position or a transposition.
+#[derive(Default)]
+pub struct BookWriter {
+    weights: BTreeMap<(u64, u16), u64>,
+}
+
+impl BookWriter {
+    pub fn new() -> BookWriter {
+        BookWriter::default()
+    }
+
+    pub fn add(&mut self, board: &Board, mv: &Movement, weight: u64) {
+        let key = key(board);
+        *self
+            .weights
+            .entry((key, encode_move(board, mv)))
+            .or_default() += weight;
+    }
+
+    // Positions in the book so far.
+    pub fn positions(&self) -> usize {
+        let mut keys: Vec<u64> = self.weights.keys().map(|(key, _)| *key).collect();
+        keys.dedup();
+        keys.len()
+    }
+
+    // Entries sorted by key, and by weight within a position, best first. Moves
+    // with no weight are left out, GUIs never play them anyway.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut entries: Vec<(u64, u16, u64)> = self
+            .weights
+            .iter()
+            .filter(|(_, &weight)| weight > 0)
+            .map(|(&(key, mv), &weight)| (key, mv, weight))
+            .collect();
+        entries.sort_by_key(|&(key, _, weight)| (key, std::cmp::Reverse(weight)));
+
+        for position in entries.chunk_by(|a, b| a.0 == b.0) {
+            for (key, mv, weight) in position {
+                out.write_all(&key.to_be_bytes())?;
+                out.write_all(&mv.to_be_bytes())?;
+                out.write_all(&scale_weight(*weight, position[0].2).to_be_bytes())?;
+                out.write_all(&0u32.to_be_bytes())?; // learn
+            }
+        }
+        Ok(())
+    }
+}
+
+// Weights are only compared within a position, so a position with more than
+// fits in a u16 is scaled down as a whole, keeping every move at least 1.
+fn scale_weight(weight: u64, max: u64) -> u16 {
+    if max <= u16::MAX as u64 {
+        return weight as u16;
+    }
+    (weight * u16::MAX as u64 / max).max(1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::gen_moves_once;
+
+    fn mv(s: &str) -> Movement {
+        Movement::from_notation(s).unwrap()
+    }
+
+    #[test]
+    fn test_key() {
+        gen_moves_once();
+        let random = &RANDOM64;
+        let key = |fen| key(&Board::from_fen(fen).unwrap());
+
+        // White king e1 (kind 11), black king e8 (kind 10), and white to move.
+        let kings = random[64 * 11 + 4] ^ random[64 * 10 + 60];
+        assert_eq!(key("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), kings ^ random[780]);
+        assert_eq!(key("4k3/8/8/8/8/8/8/4K3 b - - 0 1"), kings);
+
+        let rooks = random[64 * 7 + 7] ^ random[64 * 6 + 56];
+        assert_eq!(
+            key("r3k3/8/8/8/8/8/8/4K2R b Kq - 0 1"),
+            kings ^ rooks ^ random[768] ^ random[771]
+        );
+
+        // En passant only counts with a pawn next to it.
+        let ep = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        let no_ep = "4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1";
+        assert_eq!(key(ep), key(no_ep) ^ random[772 + 3]);
+        assert_eq!(
+            key("4k3/8/8/3p3P/8/8/8/4K3 w - d6 0 1"),
+            key("4k3/8/8/3p3P/8/8/8/4K3 w - - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_polyglot_key() {
+        gen_moves_once();
+        let play = |moves: &str| {
+            moves
+                .split_whitespace()
+                .fold(Board::from_start_pos(), |b, m| b.make_move(&mv(m)))
+        };
+        // The examples from polyglot's book format description.
+        for (moves, want) in &[
+            ("", 0x463b96181691fc9c),
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            // En passant, e5 can take on f6.
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            // White loses its castling rights, then black.
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+        ] {
+            assert_eq!(key(&play(moves)), *want, "after {:?}", moves);
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        gen_moves_once();
+        let start = Board::from_start_pos();
+        let entry = |key: u64, mv: u16, weight: u16| {
+            let mut e = key.to_be_bytes().to_vec();
+            e.extend(mv.to_be_bytes());
+            e.extend(weight.to_be_bytes());
+            e.extend(0u32.to_be_bytes());
+            e
+        };
+        let start_key = key(&start);
+        let bytes: Vec<u8> = [
+            entry(start_key + 1, encode_move(&start, &mv("a2a3")), 5),
+            entry(start_key, encode_move(&start, &mv("d2d4")), 3),
+            entry(start_key, encode_move(&start, &mv("e2e4")), 10),
+            entry(start_key, encode_move(&start, &mv("g1f3")), 0),
+            // A collision, not legal from the start.
+            entry(start_key, encode_move(&start, &mv("e2e5")), 20),
+            entry(start_key - 1, encode_move(&start, &mv("c2c4")), 5),
+        ]
+        .concat();
+
+        let book = Book::from_bytes(&bytes).unwrap();
+        assert_eq!(book.len(), 6);
+        let moves: Vec<(String, u32)> = book
+            .moves(&start)
+            .iter()
+            .map(|(m, w)| (m.to_notation(), *w))
+            .collect();
+        assert_eq!(moves, [("e2e4".to_string(), 10), ("d2d4".to_string(), 3)]);
+        assert!(book.moves(&start.make_move(&mv("e2e4"))).is_empty());
+
+        assert!(Book::from_bytes(&bytes[1..]).is_err());
+        assert!(Book::open("no such book.bin").is_err());
+    }
+
+    #[test]
+    fn test_read_castling() {
+        gen_moves_once();
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let mut writer = BookWriter::new();
+        writer.add(&board, &mv("e1g1"), 1);
+        let mut bytes = Vec::new();
+        writer.write(&mut bytes).unwrap();
+        let book = Book::from_bytes(&bytes).unwrap();
+        assert_eq!(book.moves(&board), [(mv("e1g1"), 1)]);
+    }
+
+    #[test]
+    fn test_encode_move() {
+        gen_moves_once();
+        let start = Board::from_start_pos();
+        // e2e4: to e4 (4, 3), from e2 (4, 1)
+        assert_eq!(
+            encode_move(&start, &mv("e2e4")),
+            4 | 3 << 3 | 4 << 6 | 1 << 9
+        );
+
+        let board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(
+            encode_move(&board, &mv("e1g1")),
+            encode_move(&board, &mv("e1h1"))
+        );
+        assert_eq!(encode_move(&board, &mv("e1c1")), 4 << 6);
+        assert_eq!(
+            encode_move(&board, &mv("b7a8q")),
+            7 << 3 | 1 << 6 | 6 << 9 | 4 << 12
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        gen_moves_once();
+        let start = Board::from_start_pos();
+        let after = start.make_move(&mv("e2e4"));
+        let mut writer = BookWriter::new();
+        writer.add(&start, &mv("d2d4"), 1);
+        writer.add(&start, &mv("e2e4"), 2);
+        writer.add(&start, &mv("e2e4"), 2);
+        writer.add(&start, &mv("a2a3"), 0);
+        writer.add(&after, &mv("c7c5"), 100_000);
+        writer.add(&after, &mv("e7e5"), 10);
+        assert_eq!(writer.positions(), 2);
+
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let entries: Vec<(u64, u16, u16, u32)> = out
+            .chunks(16)
+            .map(|e| {
+                (
+                    u64::from_be_bytes(e[0..8].try_into().unwrap()),
+                    u16::from_be_bytes(e[8..10].try_into().unwrap()),
+                    u16::from_be_bytes(e[10..12].try_into().unwrap()),
+                    u32::from_be_bytes(e[12..16].try_into().unwrap()),
+                )
+            })
+            .collect();
+
+        // Polyglot's keys for the start position and 1.e4, in that order.
+        let want = vec![
+            (0x463b96181691fc9c, encode_move(&start, &mv("e2e4")), 4, 0),
+            (0x463b96181691fc9c, encode_move(&start, &mv("d2d4")), 1, 0),
+            (
+                0x823c9b50fd114196,
+                encode_move(&after, &mv("c7c5")),
+                65535,
+                0,
+            ),
+            (0x823c9b50fd114196, encode_move(&after, &mv("e7e5")), 6, 0),
+        ];
+        assert_eq!(entries, want);
+    }
+}
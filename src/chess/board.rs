@@ -1,42 +1,56 @@
 use crate::bitboard::BitBoard;
 use crate::chess::*;
 use crate::movegen;
-use std::fmt;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use crate::zobrist;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Board {
+    // Zobrist key of the pieces, side to move, castling and en passant, kept up
+    // to date by make_move_mut. First, so comparing boards compares it first.
+    // zobrist::hash adds the checks in three-check.
+    pub key: u64,
+
     pub pieces: [BitBoard; NUM_PIECES],
     pub color_combined: [BitBoard; NUM_COLORS],
     pub en_passant: Option<Square>,
     pub side_to_move: Color,
     pub castling: u8, // 4 bits needed, from rtl: white kingside, white queenside, black kingside, black queenside
     pub attacked: [BitBoard; NUM_COLORS], // Colors white attacks, Colors black attacks.
+    pub halfmove_clock: u16, // Plies since the last capture or pawn move, for the 50 move rule.
+
+    // Where the rooks we can castle with started, indexed by CastlingSide. Only differs
+    // from the corners in Chess960, where castling is written as the king taking the rook.
+    pub castling_rooks: [Square; 4],
+    pub chess960: bool,
+
+    // The rules we play by, and the checks each color has given (for three-check).
+    pub variant: Variant,
+    pub checks: [u8; NUM_COLORS],
+
+    // Where the kings are, the pieces giving the side to move check and the side to
+    // move's pieces pinned to its king. Kept up to date with `attacked`.
+    pub kings: [Square; NUM_COLORS],
+    pub checkers: BitBoard,
+    pub pinned: BitBoard,
+}
+
+// Equal boards have equal keys, so the key is all a hash map needs.
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
 }
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "side to move: {:?}", self.side_to_move)?;
-
-        // Go by rank, printing each row
-        for rank_index in 0..8 {
-            let rank_index = 7 - rank_index;
-            write!(f, "{}", rank_index + 1)?;
-
-            for file_index in 0..8 {
-                let sq = Square::new(rank_index, file_index);
-
-                let character = match self.piece_on(sq) {
-                    Some(piece) => piece.as_char_color(self.color_on(sq).unwrap()),
-                    None => '.',
-                };
-
-                write!(f, " {}", character)?;
-            }
-            write!(f, "\n")?;
-        }
-
-        write!(f, "  a b c d e f g h\n")?;
-        write!(f, "fen: {}\n", self.to_fen())
+        write!(f, "{}", self.render(&RenderOptions::default()))?;
+        writeln!(f, "fen: {}", self.to_fen())
     }
 }
 
@@ -56,11 +70,15 @@ impl Board {
     }
 
     pub fn in_check(&self) -> bool {
-        let attacked = self.attacked(self.side_to_move.other());
+        self.checkers.0 != 0
+    }
+
+    pub fn checkers(&self) -> BitBoard {
+        self.checkers
+    }
 
-        let our_pieces = self.color_combined(self.side_to_move);
-        let our_king = *self.pieces(Piece::King) & our_pieces;
-        (our_king & attacked).0 != 0
+    pub fn pinned(&self) -> BitBoard {
+        self.pinned
     }
 
     pub fn is_capture(&self, mv: &Movement) -> bool {
@@ -73,6 +91,8 @@ impl Board {
     pub fn other_side(&self) -> Self {
         let mut board = self.clone();
         board.side_to_move = board.side_to_move.other();
+        board.update_attackers();
+        board.update_key();
         board
     }
 
@@ -129,12 +149,21 @@ impl Board {
 
     pub fn empty() -> Board {
         Board {
+            key: 0,
             pieces: [BitBoard(0); NUM_PIECES],
             color_combined: [BitBoard(0); NUM_COLORS],
             en_passant: None,
             castling: 0b1111,
             side_to_move: Color::White,
             attacked: [BitBoard(0); NUM_COLORS],
+            halfmove_clock: 0,
+            castling_rooks: [Square(7), Square(0), Square(63), Square(56)],
+            chess960: false,
+            variant: Variant::Standard,
+            checks: [0; NUM_COLORS],
+            kings: [Square(64); NUM_COLORS],
+            checkers: BitBoard(0),
+            pinned: BitBoard(0),
         }
     }
 
@@ -173,9 +202,24 @@ impl Board {
         self.attacked[color as usize]
     }
 
+    // Call after changing the pieces by hand, make_move_mut and from_fen do.
     pub fn update_attackers(&mut self) {
         self.attacked[Color::White as usize] = movegen::get_attacked_squares(self, Color::White);
         self.attacked[Color::Black as usize] = movegen::get_attacked_squares(self, Color::Black);
+
+        for &color in &[Color::White, Color::Black] {
+            let king_bb = self.pieces[Piece::King as usize] & self.color_combined[color as usize];
+            self.kings[color as usize] = Square(king_bb.0.trailing_zeros() as u8);
+        }
+        let (checkers, pinned) = movegen::get_checkers_and_pinned(self, self.side_to_move);
+        self.checkers = checkers;
+        self.pinned = pinned;
+    }
+
+    // Same for the key, after changing the pieces, side to move, castling or en
+    // passant by hand.
+    pub fn update_key(&mut self) {
+        self.key = zobrist::board_key(self);
     }
 
     pub fn from_fen(s: &str) -> Option<Board> {
@@ -219,23 +263,75 @@ impl Board {
             Color::Black
         };
 
-        let castling_string = fen_split.next()?;
-        board.set_castling_mut(CastlingSide::WhiteKingside, castling_string.contains('K'));
-        board.set_castling_mut(CastlingSide::WhiteQueenside, castling_string.contains('Q'));
-        board.set_castling_mut(CastlingSide::BlackKingside, castling_string.contains('k'));
-        board.set_castling_mut(CastlingSide::BlackQueenside, castling_string.contains('q'));
+        // KQkq castle with the outermost rook on that side (X-FEN), and a file letter
+        // with the rook on that file (Shredder-FEN, or X-FEN for an inner rook).
+        board.castling = 0;
+        for c in fen_split.next()?.chars() {
+            let (side, rook) = match c {
+                '-' => continue,
+                'K' | 'Q' | 'k' | 'q' => {
+                    let side = match c {
+                        'K' => CastlingSide::WhiteKingside,
+                        'Q' => CastlingSide::WhiteQueenside,
+                        'k' => CastlingSide::BlackKingside,
+                        _ => CastlingSide::BlackQueenside,
+                    };
+                    (side, board.outermost_rook(side))
+                }
+                'A'..='H' | 'a'..='h' => {
+                    let color = if c.is_ascii_uppercase() {
+                        Color::White
+                    } else {
+                        Color::Black
+                    };
+                    let file = c.to_ascii_lowercase() as u8 - b'a';
+                    let rook = Square::new(color.back_rank(), file);
+                    (board.castling_side_of_rook(color, rook)?, Some(rook))
+                }
+                _ => return None,
+            };
+            board.set_castling_mut(side, true);
+            if let Some(rook) = rook {
+                board.castling_rooks[side as usize] = rook;
+            }
+        }
 
         let en_passant = fen_split.next()?;
         if en_passant.len() == 2 {
             board.en_passant = Square::from_notation(&en_passant[0..2]);
         }
 
+        // Three-check positions have the checks too, either after en passant as the
+        // checks left ("3+3", Stockfish) or at the end as the checks given ("+0+0").
+        let (checks, rest): (Vec<&str>, Vec<&str>) =
+            fen_split.partition(|field| field.contains('+'));
+        if let Some(checks) = checks.first() {
+            board.variant = Variant::ThreeCheck;
+            board.checks = parse_checks(checks)?;
+        }
+
+        // Some GUIs leave off the move counters, so they are optional.
+        if let Some(halfmove_clock) = rest.first() {
+            board.halfmove_clock = halfmove_clock.parse().ok()?;
+        }
+
         board.update_attackers();
+        board.update_key();
         Some(board)
     }
 
-    // TODO: Clean up, this is awful code.
     pub fn to_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    // With castling as the files of the rooks (HAha), for tools that want
+    // Shredder-FEN for Chess960.
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    // TODO: Clean up, this is awful code.
+    fn fen(&self, shredder: bool) -> String {
         let mut buf = String::new();
 
         for rank in 0..8 {
@@ -266,22 +362,7 @@ impl Board {
         buf.push(self.side_to_move.as_char());
         buf.push(' ');
 
-        // tfw tenary is too verbose for you
-        macro_rules! kk {
-            ($side:ident, $character:expr) => {
-                if self.can_castle_unchecked(CastlingSide::$side) {
-                    buf.push($character);
-                }
-            };
-        }
-        if self.castling != 0 {
-            kk!(WhiteKingside, 'K');
-            kk!(WhiteQueenside, 'Q');
-            kk!(BlackKingside, 'k');
-            kk!(BlackQueenside, 'q');
-        } else {
-            buf.push('-');
-        }
+        buf.push_str(&self.castling_field(shredder));
 
         // En-pasasnt
         buf.push(' ');
@@ -292,8 +373,15 @@ impl Board {
                 .unwrap_or("-".to_string()),
         );
 
-        // TODO: Halfmove clock, Fullmove number
-        buf.push_str(" 0 1");
+        if self.variant == Variant::ThreeCheck {
+            let left = |color: Color| CHECKS_TO_WIN.saturating_sub(self.checks[color as usize]);
+            buf.push_str(&format!(" {}+{}", left(Color::White), left(Color::Black)));
+        }
+
+        // TODO: Fullmove number
+        buf.push(' ');
+        buf.push_str(&self.halfmove_clock.to_string());
+        buf.push_str(" 1");
 
         buf
     }
@@ -323,16 +411,168 @@ impl Board {
         (self.castling >> side_bit) & 1 == 1
     }
 
+    // KQkq, unless a right is for a rook that isn't the outermost one (X-FEN) or we
+    // want Shredder-FEN. Then it's the rook's file, in upper case for white.
+    fn castling_field(&self, shredder: bool) -> String {
+        let field: String = CastlingSide::all()
+            .iter()
+            .filter(|side| self.can_castle_unchecked(**side))
+            .map(|&side| {
+                let rook = self.castling_rooks[side as usize];
+                let c = if shredder || self.outermost_rook(side).is_some_and(|r| r != rook) {
+                    (b'a' + rook.file()) as char
+                } else if side.is_kingside() {
+                    'k'
+                } else {
+                    'q'
+                };
+                match side.color() {
+                    Color::White => c.to_ascii_uppercase(),
+                    Color::Black => c,
+                }
+            })
+            .collect();
+        if field.is_empty() {
+            "-".to_string()
+        } else {
+            field
+        }
+    }
+
+    // Which way castling with the rook on `rook` goes, by which side of the king it's
+    // on. None if the king isn't on its back rank to castle.
+    fn castling_side_of_rook(&self, color: Color, rook: Square) -> Option<CastlingSide> {
+        let mut king_bb = *self.pieces(Piece::King) & *self.color_combined(color);
+        let king = king_bb.next()?;
+        if king.rank() != color.back_rank() || king == rook {
+            return None;
+        }
+        let [kingside, queenside] = CastlingSide::of_color(color);
+        Some(if rook.file() > king.file() {
+            kingside
+        } else {
+            queenside
+        })
+    }
+
+    // The rook furthest from the king on the castling side, which is the one KQkq
+    // refer to in Chess960 (X-FEN). Same as the corner in normal chess.
+    fn outermost_rook(&self, side: CastlingSide) -> Option<Square> {
+        let color = side.color();
+        let back_rank = color.back_rank();
+        let mut king_bb = *self.pieces(Piece::King) & *self.color_combined(color);
+        let king = king_bb.next()?;
+        if king.rank() != back_rank {
+            return None;
+        }
+
+        let rooks = *self.pieces(Piece::Rook) & *self.color_combined(color);
+        let mut on_side = (0..8)
+            .filter(|&file| (file > king.file()) == side.is_kingside() && file != king.file())
+            .map(|file| Square::new(back_rank, file))
+            .filter(|sq| rooks.get(*sq));
+        if side.is_kingside() {
+            on_side.next_back()
+        } else {
+            on_side.next()
+        }
+    }
+
+    // Which way `movement` castles, if it's a castling move. The king moves two
+    // squares in normal chess, and takes its own rook in Chess960.
+    pub fn castling_side(&self, movement: &Movement) -> Option<CastlingSide> {
+        if !self.pieces(Piece::King).get(movement.from_square) {
+            return None;
+        }
+        if !self.chess960 {
+            return CastlingSide::from_movement(movement);
+        }
+
+        let color = self.color_on(movement.from_square)?;
+        CastlingSide::of_color(color).iter().copied().find(|side| {
+            self.can_castle_unchecked(*side)
+                && self.castling_rooks[*side as usize] == movement.to_square
+        })
+    }
+
+    // Castling rights go away when their rook moves or gets captured.
+    fn remove_castling_rook_mut(&mut self, square: Square) {
+        for side in CastlingSide::all().iter() {
+            if self.castling_rooks[*side as usize] == square {
+                self.set_castling_mut(*side, false);
+            }
+        }
+    }
+
+    // Move the king and rook to their castled squares. In Chess960 they can start
+    // out on each other's (or their own) destination, so take both off first.
+    fn castle_mut(&mut self, side: CastlingSide) {
+        let color = side.color() as usize;
+        let from = [self.king(side.color()), self.castling_rooks[side as usize]];
+        let to = [
+            side.get_king_movement().to_square,
+            side.get_rook_movement().to_square,
+        ];
+
+        for squares in &[from, to] {
+            self.pieces[Piece::King as usize].flip_mut(squares[0]);
+            self.pieces[Piece::Rook as usize].flip_mut(squares[1]);
+            self.color_combined[color].flip_mut(squares[0]);
+            self.color_combined[color].flip_mut(squares[1]);
+        }
+    }
+
     pub fn make_move(&self, movement: &Movement) -> Board {
         let mut board = self.clone();
         board.make_move_mut(&movement);
         board
     }
 
+    // Why `movement` isn't legal here, if it isn't. make_move_mut doesn't check
+    // (it's the search's hot path), so check moves from outside first.
+    pub fn check_move(&self, movement: &Movement) -> Result<(), MoveError> {
+        let piece = self
+            .piece_on(movement.from_square)
+            .ok_or(MoveError::NoPieceOnSquare)?;
+        let color = self.color_on(movement.from_square).unwrap();
+        if color != self.side_to_move {
+            return Err(MoveError::NotYourPiece);
+        }
+
+        let last_rank =
+            piece == Piece::Pawn && movement.to_square.rank() == color.other().back_rank();
+        match movement.promote {
+            Some(promote) if !last_rank || !promote.can_promote_to() => {
+                return Err(MoveError::IllegalPromotion)
+            }
+            None if last_rank => return Err(MoveError::IllegalPromotion),
+            _ => {}
+        }
+
+        if !movegen::get_pseudolegal_moves(self).contains(movement) {
+            return Err(MoveError::CantMoveThere);
+        }
+        if !movegen::MoveGen::new_legal(self).any(|mv| mv == *movement) {
+            return Err(MoveError::LeavesKingInCheck);
+        }
+        Ok(())
+    }
+
+    // make_move_mut for moves that might not be legal, from a GUI, a bot or the
+    // bindings. The board is left alone if it isn't.
+    pub fn try_make_move_mut(&mut self, movement: &Movement) -> Result<(), MoveError> {
+        self.check_move(movement)?;
+        self.make_move_mut(movement);
+        Ok(())
+    }
+
     // This function WILL break if passed invalid moves
     pub fn make_move_mut(&mut self, movement: &Movement) {
         // TODO: Clean up using math instead of tenary conditionals.
 
+        let (pieces, colors) = (self.pieces, self.color_combined);
+        let state_key = zobrist::state_key(self);
+
         let color = self
             .color_on(movement.from_square)
             .expect("no color on square");
@@ -342,24 +582,37 @@ impl Board {
             .piece_on(movement.from_square)
             .expect("no piece on square");
 
+        let castling = if piece == Piece::King {
+            self.castling_side(movement)
+        } else {
+            None
+        };
+        if let Some(side) = castling {
+            debug_assert!(
+                self.can_castle_unchecked(side),
+                "tried to castle ({:?}) but cannot castle",
+                side,
+            );
+        }
+
+        // Captures and pawn moves are irreversible, so they reset the 50 move counter.
+        // (en-passant is a pawn move, so we don't need to check for it here)
+        // Castling in Chess960 "captures" our own rook, but that doesn't count.
+        let is_capture = castling.is_none() && self.piece_on(movement.to_square).is_some();
+        let halfmove_clock = if piece == Piece::Pawn || is_capture {
+            0
+        } else {
+            self.halfmove_clock + 1
+        };
+
+        // Can't castle with a rook that got captured either.
+        if is_capture {
+            self.remove_castling_rook_mut(movement.to_square);
+        }
+
         // Piece specific logic
         match piece {
             Piece::King => {
-                let castling = CastlingSide::from_movement(movement);
-                if let Some(castling) = castling {
-                    debug_assert!(
-                        self.can_castle_unchecked(castling),
-                        "tried to castle ({:?}) but cannot castle",
-                        castling,
-                    );
-                    self.make_move_mut(&castling.get_rook_movement());
-
-                    // self.make_move_mut changed the side to move, change it back!
-                    // TODO: We should really inline this, or inline a call to a helper.
-                    // Recursion here is not ideal for maintainability.
-                    self.side_to_move = self.side_to_move.other();
-                }
-
                 // No matter what king move, we can no longer castle.
                 // TODO: Optimize using bitwise operations
                 CastlingSide::of_color(color)
@@ -369,8 +622,7 @@ impl Board {
 
             Piece::Rook => {
                 // You can no longer castle on this side after moving your rook.
-                CastlingSide::from_rook_square(movement.from_square)
-                    .map(|side| self.set_castling_mut(side, false));
+                self.remove_castling_rook_mut(movement.from_square);
             }
 
             Piece::Pawn => {
@@ -387,6 +639,8 @@ impl Board {
             _ => {}
         }
 
+        self.halfmove_clock = halfmove_clock;
+
         // Store en passant passing square
         let is_double_move = piece == Piece::Pawn && i8::abs(movement.vdelta()) == 2;
 
@@ -403,39 +657,67 @@ impl Board {
             self.en_passant = None;
         }
 
-        // NOTE: Not checking rank is ok! this function is undefined for invalid moves. <o/
-        if let Some(promotion) = movement.promote {
-            self.replace_mut(promotion, movement.to_square);
+        if let Some(side) = castling {
+            self.castle_mut(side);
         } else {
-            self.replace_mut(piece, movement.to_square);
-        }
+            // NOTE: Not checking rank is ok! this function is undefined for invalid moves. <o/
+            if let Some(promotion) = movement.promote {
+                self.replace_mut(promotion, movement.to_square);
+            } else {
+                self.replace_mut(piece, movement.to_square);
+            }
 
-        // Piece independent logic
+            // Piece independent logic
 
-        // Remove the piece from its old position
-        self.pieces[piece as usize].flip_mut(movement.from_square);
+            // Remove the piece from its old position
+            self.pieces[piece as usize].flip_mut(movement.from_square);
 
-        // Move the piece in the color grid
-        self.color_combined[color as usize].flip_mut(movement.from_square);
-        self.color_combined[color as usize].flip_mut(movement.to_square);
+            // Move the piece in the color grid
+            self.color_combined[color as usize].flip_mut(movement.from_square);
+            self.color_combined[color as usize].flip_mut(movement.to_square);
+        }
 
         // Switch side to move
         self.side_to_move = self.side_to_move.other();
+        self.key ^= state_key
+            ^ zobrist::state_key(self)
+            ^ zobrist::pieces_key_change(&pieces, &colors, self);
 
         // Update attackers (todo: inline for speed)
         self.update_attackers();
+
+        if self.variant.counts_checks() && self.in_check() {
+            self.checks[color as usize] += 1;
+        }
     }
 
-    // TODO: Test
     pub fn king(&self, color: Color) -> Square {
-        let king_bb = self.pieces[Piece::King as usize] & self.color_combined[color as usize];
-
-        if !(king_bb.0.trailing_zeros() < 64) {
-            debug_assert!(king_bb.0.trailing_zeros() < 64);
-        }
+        let king = self.kings[color as usize];
+        debug_assert!(king.0 < 64, "no {:?} king", color);
+        king
+    }
+}
 
-        Square(king_bb.0.trailing_zeros() as u8)
+// "3+3" (checks left) or "+0+0" (checks given), as the checks each color has given.
+fn parse_checks(field: &str) -> Option<[u8; NUM_COLORS]> {
+    let (given, field) = match field.strip_prefix('+') {
+        Some(field) => (true, field),
+        None => (false, field),
+    };
+    let mut checks = [0; NUM_COLORS];
+    let mut counts = field.split('+');
+    for count in checks.iter_mut() {
+        let n: u8 = counts.next()?.parse().ok()?;
+        *count = if given {
+            n
+        } else {
+            CHECKS_TO_WIN.checked_sub(n)?
+        };
     }
+    if counts.next().is_some() {
+        return None;
+    }
+    Some(checks)
 }
 
 #[cfg(test)]
@@ -529,6 +811,23 @@ mod tests {
         assert!(Board::from_fen("").is_none());
     }
 
+    #[test]
+    fn test_fen_three_check() {
+        let fen = "rnbqkbnr/ppppp1pp/8/5p1Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 2+3 1 1";
+        let b = Board::from_fen(fen).unwrap();
+        assert_eq!(b.variant, Variant::ThreeCheck);
+        assert_eq!(b.checks, [1, 0]);
+        assert_eq!(b.halfmove_clock, 1);
+        assert_eq!(b.to_fen(), fen);
+
+        let lichess = "rnbqkbnr/ppppp1pp/8/5p1Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 1 +1+0";
+        assert_eq!(Board::from_fen(lichess), Some(b));
+
+        assert!(Board::from_fen("8/8/8/8/8/8/k7/7K w - - 4+3 0 1").is_none());
+        assert!(Board::from_fen("8/8/8/8/8/8/k7/7K w - - 3+3+3 0 1").is_none());
+        assert_eq!(Board::from_start_pos().variant, Variant::Standard);
+    }
+
     #[test]
     fn test_make_move_e2e4() {
         let mut b = Board::from_start_pos();
@@ -596,6 +895,37 @@ mod tests {
         assert!(board.in_check(), "black should be in check");
     }
 
+    #[test]
+    fn test_checkers_and_pinned() {
+        let squares = |squares: &[&str]| {
+            squares
+                .iter()
+                .fold(BitBoard::empty(), |bb, s| bb | BitBoard::from_square(sq(s)))
+        };
+
+        // Pawns check kings on the first rank too.
+        let board = Board::from_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.checkers(), squares(&["d2"]));
+        assert_eq!(board.king(Color::White), sq("e1"));
+
+        let board = Board::from_fen("4k3/4r3/8/8/1b6/8/3NN3/4K3 w - - 0 1").unwrap();
+        assert!(!board.in_check());
+        assert_eq!(board.pinned(), squares(&["d2", "e2"]));
+
+        // Out of double check only the king moves.
+        let board = Board::from_fen("4k3/8/8/8/1b6/8/8/r3K3 w - - 0 1").unwrap();
+        assert_eq!(board.checkers(), squares(&["a1", "b4"]));
+        assert!(movegen::MoveGen::new_legal(&board).all(|mv| mv.from_square == sq("e1")));
+
+        // Kept up to date by make_move, for the new side to move.
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let board = board.make_move(&Movement::from_notation("e1g1").unwrap());
+        assert_eq!(board.king(Color::White), sq("g1"));
+        let board = board.make_move(&Movement::from_notation("h8h1").unwrap());
+        assert_eq!(board.checkers(), squares(&["h1"]));
+        assert_eq!(board.pinned(), BitBoard::empty());
+    }
+
     #[test]
     fn test_make_move_castle() {
         let mut board =
@@ -618,6 +948,154 @@ mod tests {
         assert!(!board.can_castle_unchecked(CastlingSide::WhiteQueenside));
     }
 
+    fn chess960(fen: &str) -> Board {
+        let mut board = Board::from_fen(fen).unwrap();
+        board.chess960 = true;
+        board
+    }
+
+    #[test]
+    fn test_chess960_castling_rooks() {
+        let board = chess960("rk2r3/8/8/8/8/8/8/1R3KR1 w KQkq - 0 1");
+        assert_eq!(
+            board.castling_rooks,
+            [sq("g1"), sq("b1"), sq("e8"), sq("a8")]
+        );
+
+        // Furthest from the king, when there are two on a side.
+        let board = chess960("4k3/8/8/8/8/8/8/RR2K1RR w KQ - 0 1");
+        assert_eq!(board.castling_rooks[..2], [sq("h1"), sq("a1")]);
+
+        // Same as always in normal chess.
+        assert_eq!(
+            Board::from_start_pos().castling_rooks,
+            Board::empty().castling_rooks
+        );
+    }
+
+    #[test]
+    fn test_try_make_move() {
+        let mv = |s: &str| Movement::from_notation(s).unwrap();
+        let start = Board::from_start_pos();
+        let mut board = start.clone();
+        assert_eq!(
+            board.try_make_move_mut(&mv("e4e5")),
+            Err(MoveError::NoPieceOnSquare)
+        );
+        assert_eq!(
+            board.try_make_move_mut(&mv("e7e5")),
+            Err(MoveError::NotYourPiece)
+        );
+        assert_eq!(
+            board.try_make_move_mut(&mv("e2e5")),
+            Err(MoveError::CantMoveThere)
+        );
+        assert_eq!(
+            board.try_make_move_mut(&mv("a1a3")),
+            Err(MoveError::CantMoveThere)
+        );
+        assert_eq!(
+            board.try_make_move_mut(&mv("g1f3q")),
+            Err(MoveError::IllegalPromotion)
+        );
+        assert_eq!(board, start);
+        assert_eq!(board.try_make_move_mut(&mv("e2e4")), Ok(()));
+        assert_eq!(board, start.make_move(&mv("e2e4")));
+
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            board.check_move(&mv("b7b8")),
+            Err(MoveError::IllegalPromotion)
+        );
+        assert_eq!(
+            board.check_move(&mv("b7b8k")),
+            Err(MoveError::IllegalPromotion)
+        );
+        assert_eq!(board.check_move(&mv("b7b8n")), Ok(()));
+
+        // The knight is pinned by the rook.
+        let board = Board::from_fen("4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            board.check_move(&mv("e2c3")),
+            Err(MoveError::LeavesKingInCheck)
+        );
+        assert_eq!(board.check_move(&mv("e1f1")), Ok(()));
+    }
+
+    #[test]
+    fn test_shredder_fen() {
+        let fen = "rk2r3/8/8/8/8/8/8/1R3KR1 w KQkq - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        let shredder = "rk2r3/8/8/8/8/8/8/1R3KR1 w GBea - 0 1";
+        assert_eq!(board.to_shredder_fen(), shredder);
+        assert_eq!(Board::from_fen(shredder), Some(board.clone()));
+        assert_eq!(board.to_fen(), fen);
+
+        // X-FEN names an inner rook by its file, and can mix the two.
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RR2K1RR w BG - 0 1").unwrap();
+        assert_eq!(board.castling_rooks[..2], [sq("g1"), sq("b1")]);
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/RR2K1RR w GB - 0 1");
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RR2K1RR w KB - 0 1").unwrap();
+        assert_eq!(board.castling_rooks[..2], [sq("h1"), sq("b1")]);
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/RR2K1RR w KB - 0 1");
+        assert_eq!(
+            board.to_shredder_fen(),
+            "4k3/8/8/8/8/8/8/RR2K1RR w HB - 0 1"
+        );
+
+        // Normal chess is the same either way, except for the letters.
+        let board = Board::from_start_pos();
+        assert_eq!(board.to_fen(), STARTING_FEN);
+        assert_eq!(
+            board.to_shredder_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+        assert_eq!(
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"),
+            Some(board)
+        );
+
+        // A file letter needs a king on the back rank to say which side it is.
+        assert!(Board::from_fen("8/8/8/8/8/4k3/8/R3K3 w A - 0 1").is_some());
+        assert!(Board::from_fen("8/8/8/8/8/4k3/R3K3/8 w A - 0 1").is_none());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w X - 0 1").is_none());
+    }
+
+    #[test]
+    fn test_make_move_castle_chess960() {
+        // King on b1 and rook on a1, they swap over to c1 and d1.
+        let mut board = chess960("1k6/8/8/8/8/8/8/RK4R1 w KQ - 3 1");
+        assert_eq!(
+            board.castling_side(&Movement::from_notation("b1a1").unwrap()),
+            Some(CastlingSide::WhiteQueenside)
+        );
+        board.make_move_mut(&Movement::from_notation("b1a1").unwrap());
+        board.assert_valid();
+        assert_eq!(board.to_fen(), "1k6/8/8/8/8/8/8/2KR2R1 b - - 4 1");
+
+        // King already on g1, only the rook moves.
+        let mut board = chess960("1k6/8/8/8/8/8/8/R5KR w KQ - 0 1");
+        board.make_move_mut(&Movement::from_notation("g1h1").unwrap());
+        board.assert_valid();
+        assert_eq!(board.to_fen(), "1k6/8/8/8/8/8/8/R4RK1 b - - 1 1");
+
+        // Not castling, just a king move to where it would castle to.
+        let mut board = chess960("1k6/8/8/8/8/8/8/RK4R1 w KQ - 0 1");
+        assert_eq!(
+            board.castling_side(&Movement::from_notation("b1c1").unwrap()),
+            None
+        );
+        board.make_move_mut(&Movement::from_notation("b1c1").unwrap());
+        assert_eq!(board.to_fen(), "1k6/8/8/8/8/8/8/R1K3R1 b - - 1 1");
+    }
+
+    #[test]
+    fn test_make_move_capture_castling_rook() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        board.make_move_mut(&Movement::from_notation("h1h8").unwrap());
+        assert_eq!(board.to_fen(), "r3k2R/8/8/8/8/8/8/R3K3 b Qq - 0 1");
+    }
+
     #[test]
     fn test_make_move_remove_castling() {
         let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
@@ -667,6 +1145,29 @@ mod tests {
         assert_eq!(board.en_passant, None);
     }
 
+    #[test]
+    fn test_halfmove_clock() {
+        let mut board =
+            Board::from_fen("r3k2r/pppq1ppp/8/8/8/8/PPPQ1PPP/R3K2R w KQkq - 7 12").unwrap();
+        assert_eq!(board.halfmove_clock, 7);
+
+        board.make_move_mut(&Movement::from_notation("e1g1").unwrap());
+        assert_eq!(board.halfmove_clock, 8);
+
+        board.make_move_mut(&Movement::from_notation("d7d2").unwrap());
+        assert_eq!(board.halfmove_clock, 0, "captures reset the clock");
+
+        board.make_move_mut(&Movement::from_notation("f1d1").unwrap());
+        assert_eq!(board.halfmove_clock, 1);
+
+        board.make_move_mut(&Movement::from_notation("a7a6").unwrap());
+        assert_eq!(board.halfmove_clock, 0, "pawn moves reset the clock");
+
+        // Move counters are optional
+        let board = Board::from_fen("8/8/4k3/8/8/4K3/8/8 w - -").unwrap();
+        assert_eq!(board.halfmove_clock, 0);
+    }
+
     #[test]
     fn test_to_fen_startpos() {
         let board = Board::from_start_pos();
@@ -703,6 +1204,8 @@ mod tests {
         "rnbqkbnr/ppp3pp/4p3/3pPp2/8/1P6/P1PP1PPP/RNBQKBNR w KQkq f6 0 1"
     );
 
+    test_to_fen!(halfmove_clock, "8/5k2/8/2K5/8/8/8/8 w - - 42 1");
+
     test_to_fen!(
         no_castling,
         "1rbq1rk1/ppbn1pp1/4p2p/1P1pP3/3P2P1/PQN1BN2/1K3P1P/3R3R w - - 0 1"
@@ -1,5 +1,4 @@
 use super::{Color, Movement, Square};
-use crate::bitboard::BitBoard;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum CastlingSide {
@@ -47,6 +46,24 @@ impl CastlingSide {
         }
     }
 
+    #[inline]
+    pub fn all() -> [Self; 4] {
+        [
+            CastlingSide::WhiteKingside,
+            CastlingSide::WhiteQueenside,
+            CastlingSide::BlackKingside,
+            CastlingSide::BlackQueenside,
+        ]
+    }
+
+    #[inline]
+    pub fn is_kingside(&self) -> bool {
+        matches!(
+            self,
+            CastlingSide::WhiteKingside | CastlingSide::BlackKingside
+        )
+    }
+
     #[inline]
     pub fn from_rook_square(square: Square) -> Option<Self> {
         match square {
@@ -88,24 +105,4 @@ impl CastlingSide {
             CastlingSide::BlackQueenside => Movement::new(Square(60), Square(58), None),
         }
     }
-
-    #[inline]
-    pub fn get_castling_middle(&self) -> BitBoard {
-        match self {
-            CastlingSide::WhiteKingside => BitBoard(0x60),
-            CastlingSide::WhiteQueenside => BitBoard(0xe),
-            CastlingSide::BlackKingside => BitBoard(0x6000000000000000),
-            CastlingSide::BlackQueenside => BitBoard(0xe00000000000000),
-        }
-    }
-
-    #[inline]
-    pub fn get_castling_not_attacked(&self) -> BitBoard {
-        match self {
-            CastlingSide::WhiteKingside => BitBoard(0x70),
-            CastlingSide::WhiteQueenside => BitBoard(0x1c),
-            CastlingSide::BlackKingside => BitBoard(0x7000000000000000),
-            CastlingSide::BlackQueenside => BitBoard(0x1c00000000000000),
-        }
-    }
 }
@@ -28,4 +28,13 @@ impl Color {
             Color::Black => 'b',
         }
     }
+
+    // The rank the pieces start on.
+    #[inline]
+    pub fn back_rank(&self) -> u8 {
+        match self {
+            Color::White => 0,
+            Color::Black => 7,
+        }
+    }
 }
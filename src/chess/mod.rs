@@ -8,11 +8,15 @@ mod castling_side;
 mod color;
 mod movement;
 mod piece;
+mod render;
 mod square;
+mod variant;
 
 pub use board::*;
 pub use castling_side::*;
 pub use color::*;
 pub use movement::*;
 pub use piece::*;
+pub use render::*;
 pub use square::*;
+pub use variant::*;
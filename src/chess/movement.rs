@@ -1,7 +1,8 @@
-use std::fmt;
+use core::fmt;
 
 use crate::chess::Piece;
 use crate::chess::Square;
+use alloc::string::String;
 
 // Calling it Movement and not Move because "move" is a keyword
 #[derive(Clone, PartialEq, Eq)]
@@ -23,6 +24,31 @@ impl fmt::Debug for Movement {
     }
 }
 
+// Why a move can't be played, see Board::try_make_move_mut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    NoPieceOnSquare,
+    NotYourPiece,
+    // Promoting anything but a pawn on the last rank, to a pawn or a king, or a
+    // pawn reaching the last rank without saying what it becomes.
+    IllegalPromotion,
+    // The piece doesn't move like that, or something is in the way.
+    CantMoveThere,
+    LeavesKingInCheck,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MoveError::NoPieceOnSquare => "no piece on that square",
+            MoveError::NotYourPiece => "not the side to move's piece",
+            MoveError::IllegalPromotion => "illegal promotion",
+            MoveError::CantMoveThere => "the piece can't move there",
+            MoveError::LeavesKingInCheck => "leaves the king in check",
+        })
+    }
+}
+
 impl Movement {
     pub fn new(from_square: Square, to_square: Square, promote: Option<Piece>) -> Movement {
         Movement {
@@ -0,0 +1,130 @@
+use crate::chess::{Board, Color, Piece, Square};
+use alloc::string::String;
+
+// Text diagrams of a board, for the "d" command, debug output and anything
+// else that shows a position to a person. Board's Display is the default one.
+//
+//     8 r n b q k b n r
+//     7 p p p p p p p p
+//     ...
+//       a b c d e f g h
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceStyle {
+    Letters, // FEN letters, upper case for white
+    Unicode, // chess symbols, which need a font that has them
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    // The side at the bottom of the diagram.
+    pub orientation: Color,
+    // Rank numbers on the left and file letters underneath.
+    pub coordinates: bool,
+    pub style: PieceStyle,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            orientation: Color::White,
+            coordinates: true,
+            style: PieceStyle::Letters,
+        }
+    }
+}
+
+impl PieceStyle {
+    fn symbol(&self, piece: Piece, color: Color) -> char {
+        match self {
+            PieceStyle::Letters => piece.as_char_color(color),
+            PieceStyle::Unicode => {
+                let symbols = match color {
+                    Color::White => ['♙', '♘', '♗', '♖', '♕', '♔'],
+                    Color::Black => ['♟', '♞', '♝', '♜', '♛', '♚'],
+                };
+                symbols[piece as usize]
+            }
+        }
+    }
+}
+
+impl Board {
+    // The board as lines of text, every line ends in a newline. Empty squares
+    // are dots.
+    pub fn render(&self, options: &RenderOptions) -> String {
+        // Top to bottom and left to right, as seen from the side at the bottom.
+        let flip = |i: u8| match options.orientation {
+            Color::White => 7 - i,
+            Color::Black => i,
+        };
+
+        let mut s = String::new();
+        for row in 0..8 {
+            let rank = flip(row);
+            if options.coordinates {
+                s.push((b'1' + rank) as char);
+                s.push(' ');
+            }
+            for column in 0..8 {
+                let sq = Square::new(rank, 7 - flip(column));
+                s.push(match (self.piece_on(sq), self.color_on(sq)) {
+                    (Some(piece), Some(color)) => options.style.symbol(piece, color),
+                    _ => '.',
+                });
+                s.push(if column < 7 { ' ' } else { '\n' });
+            }
+        }
+
+        if options.coordinates {
+            s.push(' ');
+            for column in 0..8 {
+                s.push(' ');
+                s.push((b'a' + 7 - flip(column)) as char);
+            }
+            s.push('\n');
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_eq!(
+            board.render(&RenderOptions::default()),
+            "8 . . . . k . . .\n\
+             7 . . . . . . . .\n\
+             6 . . . . . . . .\n\
+             5 . . . . . . . .\n\
+             4 . . . . . . . .\n\
+             3 . . . . . . . .\n\
+             2 . . . . . . . .\n\
+             1 R . . . K . . .\n  \
+             a b c d e f g h\n"
+        );
+
+        let black = RenderOptions {
+            orientation: Color::Black,
+            coordinates: true,
+            style: PieceStyle::Unicode,
+        };
+        let rendered = board.render(&black);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "1 . . . ♔ . . . ♖");
+        assert_eq!(lines[7], "8 . . . ♚ . . . .");
+        assert_eq!(lines[8], "  h g f e d c b a");
+
+        let plain = RenderOptions {
+            coordinates: false,
+            ..RenderOptions::default()
+        };
+        let rendered = board.render(&plain);
+        assert_eq!(rendered.lines().count(), 8);
+        assert_eq!(rendered.lines().last(), Some("R . . . K . . ."));
+    }
+}
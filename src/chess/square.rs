@@ -1,4 +1,5 @@
-use std::fmt;
+use alloc::string::String;
+use core::fmt;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Square(pub u8);
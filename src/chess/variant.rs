@@ -0,0 +1,122 @@
+use crate::chess::{Board, Color};
+
+// The rules a game is played by, on top of normal chess. Chess960 isn't one of
+// these, it only changes the start position and castling (Board::chess960), so
+// it goes with any variant.
+//
+// A variant can change how games are won and what the eval cares about. None of
+// them change which moves are legal yet, antichess and the like would need a hook
+// in movegen for that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // Giving check three times wins. Board::checks counts them.
+    ThreeCheck,
+}
+
+// Checks needed to win three-check.
+pub const CHECKS_TO_WIN: u8 = 3;
+
+// What having given some checks is worth to the eval, in centipawns. The third
+// one wins, so two are worth a lot more than one.
+const CHECK_BONUS: [i16; CHECKS_TO_WIN as usize] = [0, 100, 300];
+
+impl Variant {
+    pub const ALL: [Variant; 2] = [Variant::Standard, Variant::ThreeCheck];
+
+    // UCI_Variant names, the same as Stockfish's fork and lichess use.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        let name = name.trim();
+        Variant::ALL
+            .iter()
+            .copied()
+            .find(|variant| variant.name().eq_ignore_ascii_case(name))
+    }
+
+    // Does giving check change the position? Then checks aren't reversible moves.
+    pub fn counts_checks(&self) -> bool {
+        *self == Variant::ThreeCheck
+    }
+
+    // Who won by the variant's own rules, and why. Mates and draws are the same as
+    // in normal chess and up to the caller.
+    pub fn winner(&self, board: &Board) -> Option<(Color, &'static str)> {
+        match self {
+            Variant::Standard => None,
+            Variant::ThreeCheck => [Color::White, Color::Black]
+                .iter()
+                .copied()
+                .find(|&color| board.checks[color as usize] >= CHECKS_TO_WIN)
+                .map(|color| (color, "Three checks")),
+        }
+    }
+
+    // Added to the eval, from white's point of view.
+    pub fn eval(&self, board: &Board) -> i16 {
+        match self {
+            Variant::Standard => 0,
+            Variant::ThreeCheck => {
+                let bonus = |color: Color| {
+                    CHECK_BONUS[board.checks[color as usize].min(CHECKS_TO_WIN - 1) as usize]
+                };
+                bonus(Color::White) - bonus(Color::Black)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::Movement;
+
+    fn play(board: &mut Board, moves: &str) {
+        for mv in moves.split_whitespace() {
+            board.make_move_mut(&Movement::from_notation(mv).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        for variant in Variant::ALL.iter() {
+            assert_eq!(Variant::from_name(variant.name()), Some(*variant));
+        }
+        assert_eq!(Variant::from_name("3Check"), Some(Variant::ThreeCheck));
+        assert_eq!(Variant::from_name("antichess"), None);
+    }
+
+    #[test]
+    fn test_three_check() {
+        let mut board = Board::from_start_pos();
+        board.variant = Variant::ThreeCheck;
+        play(&mut board, "e2e4 f7f5 d1h5");
+        assert_eq!(board.checks, [1, 0]);
+        assert_eq!(Variant::ThreeCheck.eval(&board), 100);
+
+        play(&mut board, "g7g6 h5g6 h7g6");
+        assert_eq!(board.checks, [2, 0]);
+        assert_eq!(Variant::ThreeCheck.winner(&board), None);
+        assert_eq!(Variant::ThreeCheck.eval(&board), 300);
+        assert_eq!(Variant::Standard.eval(&board), 0);
+
+        board.checks = [2, 3];
+        assert_eq!(
+            Variant::ThreeCheck.winner(&board),
+            Some((Color::Black, "Three checks"))
+        );
+        assert_eq!(Variant::Standard.winner(&board), None);
+
+        // Nobody counts in normal chess.
+        let mut board = Board::from_start_pos();
+        play(&mut board, "e2e4 f7f5 d1h5");
+        assert_eq!(board.checks, [0, 0]);
+    }
+}
@@ -0,0 +1,54 @@
+// The clock the search runs on. std::time::Instant panics on
+// wasm32-unknown-unknown (there is no OS to ask), so in the browser we ask
+// JavaScript instead. Everywhere else this is just std's Instant.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+pub use self::wasm::Instant;
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use std::ops::{Add, Sub};
+    use std::time::Duration;
+
+    // Milliseconds since the epoch, from Date.now(). Not monotonic, but it's
+    // only ever used to time a single search, so that's good enough.
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    pub struct Instant(f64);
+
+    impl Instant {
+        pub fn now() -> Instant {
+            Instant(js_sys::Date::now())
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Instant::now() - *self
+        }
+    }
+
+    impl Sub for Instant {
+        type Output = Duration;
+
+        fn sub(self, earlier: Instant) -> Duration {
+            Duration::from_secs_f64(f64::max(self.0 - earlier.0, 0.0) / 1000.0)
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Instant;
+
+        fn add(self, d: Duration) -> Instant {
+            Instant(self.0 + d.as_secs_f64() * 1000.0)
+        }
+    }
+
+    impl Sub<Duration> for Instant {
+        type Output = Instant;
+
+        fn sub(self, d: Duration) -> Instant {
+            Instant(self.0 - d.as_secs_f64() * 1000.0)
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::adjudication::{DrawRule, ResignRule};
+use crate::chess::{Board, Piece};
+
+// When a bot should resign, offer a draw or take one, from what its own searches
+// said. In engine matches the GUI adjudicates (see adjudication.rs), but a bot on
+// a server is on its own and has to decide these itself.
+//
+// Only our own scores count, there's nobody to agree with.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecisionRules {
+    // Resign after our last `moves` scores were all -`score` or worse.
+    pub resign: Option<ResignRule>,
+    // Offer a draw from our move `after` on, when our last `moves` scores were all
+    // within `score` of what a draw is worth to us.
+    pub draw: Option<DrawRule>,
+    // How much worse than equal a draw is for us, in centipawns. With a positive
+    // contempt we play on in equal positions, with a negative one we take draws
+    // while slightly ahead.
+    pub contempt: i16,
+    // Only offer draws with at most this much material left, in pawns (3 for a
+    // minor piece, 5 for a rook, 9 for a queen), when there's nothing left to play
+    // for. Draws get taken with more material if we're worse.
+    pub draw_material: u16,
+}
+
+impl Default for DecisionRules {
+    fn default() -> Self {
+        DecisionRules {
+            resign: Some(ResignRule {
+                moves: 5,
+                score: 800,
+            }),
+            draw: Some(DrawRule {
+                after: 30,
+                moves: 5,
+                score: 15,
+            }),
+            contempt: 0,
+            draw_material: 30,
+        }
+    }
+}
+
+// Follows one game from our side, see update.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decisions {
+    rules: DecisionRules,
+    // What we said about each of our moves, from our point of view.
+    scores: Vec<Option<i16>>,
+}
+
+impl Decisions {
+    pub fn new(rules: DecisionRules) -> Decisions {
+        Decisions {
+            rules,
+            scores: Vec::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &DecisionRules {
+        &self.rules
+    }
+
+    // Tell it about the move we just played and its score, None if the search
+    // didn't give one.
+    pub fn update(&mut self, score: Option<i16>) {
+        self.scores.push(score);
+    }
+
+    // Start over for a new game.
+    pub fn clear(&mut self) {
+        self.scores.clear();
+    }
+
+    pub fn should_resign(&self) -> bool {
+        match self.rules.resign {
+            Some(rule) => self.last(rule.moves, |s| s <= -rule.score),
+            None => false,
+        }
+    }
+
+    pub fn should_offer_draw(&self, board: &Board) -> bool {
+        let rule = match self.rules.draw {
+            Some(rule) => rule,
+            None => return false,
+        };
+        self.scores.len() >= rule.after as usize
+            && material(board) <= self.rules.draw_material
+            && self.last(rule.moves, |s| {
+                s.saturating_add(self.rules.contempt).abs() <= rule.score
+            })
+    }
+
+    // Take the draw the opponent offered? When we would have offered one, or our
+    // last score says we're doing worse than a draw.
+    pub fn accept_draw(&self, board: &Board) -> bool {
+        let rule = match self.rules.draw {
+            Some(rule) => rule,
+            None => return false,
+        };
+        let worse = self.last(1, |s| s.saturating_add(self.rules.contempt) < -rule.score);
+        worse || self.should_offer_draw(board)
+    }
+
+    // Did our last `moves` scores all pass `test`?
+    fn last(&self, moves: u16, test: impl Fn(i16) -> bool) -> bool {
+        let moves = moves as usize;
+        self.scores.len() >= moves
+            && self.scores[self.scores.len() - moves..]
+                .iter()
+                .all(|s| s.is_some_and(&test))
+    }
+}
+
+// Material on the board, both sides together, in pawns.
+fn material(board: &Board) -> u16 {
+    [
+        (Piece::Pawn, 1),
+        (Piece::Knight, 3),
+        (Piece::Bishop, 3),
+        (Piece::Rook, 5),
+        (Piece::Queen, 9),
+    ]
+    .iter()
+    .map(|&(piece, value)| board.pieces(piece).count_ones() as u16 * value)
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decisions(rules: DecisionRules, scores: &[Option<i16>]) -> Decisions {
+        let mut decisions = Decisions::new(rules);
+        scores.iter().for_each(|&s| decisions.update(s));
+        decisions
+    }
+
+    #[test]
+    fn test_material() {
+        assert_eq!(material(&Board::from_start_pos()), 78);
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 0 1").unwrap();
+        assert_eq!(material(&board), 6);
+    }
+
+    #[test]
+    fn test_resign() {
+        let rules = DecisionRules {
+            resign: Some(ResignRule::parse("3,500").unwrap()),
+            ..DecisionRules::default()
+        };
+        assert!(decisions(rules, &[Some(0), Some(-600), Some(-500), Some(-900)]).should_resign());
+        assert!(!decisions(rules, &[Some(-600), Some(-500)]).should_resign());
+        assert!(!decisions(rules, &[Some(-600), None, Some(-900)]).should_resign());
+        assert!(!decisions(rules, &[Some(-600), Some(-400), Some(-900)]).should_resign());
+
+        let never = DecisionRules {
+            resign: None,
+            ..rules
+        };
+        assert!(!decisions(never, &[Some(-30000); 10]).should_resign());
+    }
+
+    #[test]
+    fn test_draws() {
+        let rules = DecisionRules {
+            draw: Some(DrawRule::parse("4,2,10").unwrap()),
+            ..DecisionRules::default()
+        };
+        let endgame = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 0 1").unwrap();
+        let start = Board::from_start_pos();
+
+        let equal = decisions(rules, &[Some(0); 4]);
+        assert!(equal.should_offer_draw(&endgame));
+        assert!(equal.accept_draw(&endgame));
+        // Too early, or too much left to play for.
+        assert!(!decisions(rules, &[Some(0); 3]).should_offer_draw(&endgame));
+        assert!(!equal.should_offer_draw(&start));
+        assert!(!equal.accept_draw(&start));
+
+        // Worse takes a draw whenever it can get one, better doesn't.
+        let worse = decisions(rules, &[Some(0), Some(-50)]);
+        assert!(!worse.should_offer_draw(&start));
+        assert!(worse.accept_draw(&start));
+        assert!(!decisions(rules, &[Some(50); 10]).accept_draw(&endgame));
+
+        // With contempt a draw is only good enough when we're that much worse.
+        let contempt = DecisionRules {
+            contempt: 50,
+            ..rules
+        };
+        assert!(!decisions(contempt, &[Some(0); 4]).should_offer_draw(&endgame));
+        assert!(decisions(contempt, &[Some(-50); 4]).should_offer_draw(&endgame));
+        assert!(!decisions(contempt, &[Some(-50)]).accept_draw(&start));
+        assert!(decisions(contempt, &[Some(-100)]).accept_draw(&start));
+    }
+}
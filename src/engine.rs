@@ -1,150 +1,1314 @@
-use crate::chess::{Board, Color};
-use crate::movegen::{perft, MoveGen};
-use crate::search::Searcher;
-use crate::uci;
-use crate::uci::EngineMessage;
-use std::io;
+use crate::book::{self, polyglot};
+use crate::chess::{Board, CastlingSide, Movement, Variant};
+use crate::decisions::{DecisionRules, Decisions};
+use crate::movegen::{self, MoveGen};
+use crate::options::{self, OptionValue, UciOption};
+use crate::search::{self, SearchFeatures, SearchInfo, SearchLimits, Searcher};
+use crate::stats::StatsLog;
+use crate::strength::{self, Strength};
+use crate::syzygy::Tablebases;
+use crate::trace::{TraceFilter, Tracer};
+use crate::tune;
+use crate::zobrist;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
+// The engine without the protocol: game state, options and a search thread.
+// uci::Uci talks UCI over stdin/stdout with it, but it can be embedded directly
+// in a GUI or a bot too.
+//
+//     let mut engine = Engine::new();
+//     engine.set_position(Board::from_start_pos(), &moves)?;
+//     for event in engine.go(SearchLimits::depth(8)) { ... }
+
+// More than enough for anyone.
+const HASH_MB_MAX: usize = 4096;
+const MAX_THREADS: usize = 256;
+// More than the moves of any position (218 at most).
+const MAX_MULTI_PV: usize = 256;
+
+const DEFAULT_ELO: u16 = 1500;
+
+// Enough for a local GUI, online play wants more.
+const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+
+// Halfway between always the main line and any book move as often as the next.
+const DEFAULT_BOOK_VARIETY: u8 = 50;
+
+// What a search sends back, a bunch of infos then always exactly one best move.
+// The best move is None if there are no legal moves. (mate or stalemate)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchEvent {
+    Info(SearchInfo),
+    BestMove(Option<Movement>, Option<Movement>), // best move, reply to ponder on
+}
+
+// A running search, see Engine::go. Iterating blocks until the next event and
+// ends after the best move.
+pub struct Search {
+    events: Receiver<SearchEvent>,
+    stop: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl Search {
+    // Stop searching, the best move so far still comes through the iterator.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    // The next event if there is one yet, without blocking like next does.
+    pub fn try_next(&mut self) -> Option<SearchEvent> {
+        if self.done {
+            return None;
+        }
+        let event = self.events.try_recv().ok()?;
+        self.done = !matches!(event, SearchEvent::Info(_));
+        Some(event)
+    }
+}
+
+// Live analysis of a game, see Engine::analyse. There's always an infinite
+// search of the current position running, and when a move gets played it
+// carries on from what it knew instead of starting over: the hash, the killers
+// and, if the move was the one it expected, the PV and the depth it got to.
+//
+//     let mut session = engine.analyse(Board::from_start_pos(), &[])?;
+//     loop {
+//         while let Some(event) = session.search().try_next() { ... }
+//         if let Some(mv) = next_move_played() { session.play(&mv)?; }
+//     }
+pub struct AnalysisSession<'a> {
+    engine: &'a mut Engine,
+    search: Search,
+}
+
+impl<'a> AnalysisSession<'a> {
+    // The search of the current position. Infos keep coming until play or stop.
+    pub fn search(&mut self) -> &mut Search {
+        &mut self.search
+    }
+
+    pub fn position(&self) -> &Board {
+        self.engine.position()
+    }
+
+    // The game went on with `mv`. An illegal move is an error, and the search of
+    // the position before it goes on.
+    pub fn play(&mut self, mv: &Movement) -> Result<(), String> {
+        let board = self.engine.position.clone();
+        let mv = legal_move(&board, mv).ok_or_else(|| {
+            let reason = board.check_move(mv).unwrap_err();
+            format!("illegal move {} in {}: {}", mv, board.to_fen(), reason)
+        })?;
+
+        self.finish();
+        self.engine.history.push(zobrist::hash(&board));
+        self.engine.position.make_move_mut(&mv);
+        self.engine.searcher().advance(&mv);
+        self.search = self.engine.search(SearchLimits::infinite());
+        Ok(())
+    }
+
+    // Done analysing. The best move of the current position, if it has one.
+    pub fn stop(mut self) -> Option<Movement> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Option<Movement> {
+        self.search.stop();
+        let best = self.search.by_ref().find_map(|event| match event {
+            SearchEvent::BestMove(mv, _) => Some(mv),
+            SearchEvent::Info(_) => None,
+        });
+        // Wait for the thread, so the searcher is ours to change.
+        self.engine.searcher();
+        best.flatten()
+    }
+}
+
+// A session dropped without stop still ends its search, or the engine's next go
+// would wait on an infinite one.
+impl<'a> Drop for AnalysisSession<'a> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl Iterator for Search {
+    type Item = SearchEvent;
+
+    fn next(&mut self) -> Option<SearchEvent> {
+        if self.done {
+            return None;
+        }
+        let event = self.events.recv().ok();
+        self.done = !matches!(event, Some(SearchEvent::Info(_)));
+        event
+    }
+}
+
+type SearcherUpdate = Box<dyn FnOnce(&mut Searcher) + Send>;
+
 pub struct Engine {
     position: Board,
-    searcher: Searcher,
+    // Zobrist keys of the positions before `position`, for repetitions.
+    history: Vec<u64>,
+
+    // The searcher is moved into the search thread while it runs, so only one
+    // of these is ever set. Use searcher() to get it back.
+    searcher: Option<Searcher>,
+    search_thread: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    ponderhit: Arc<AtomicBool>,
+    // Searcher changes waiting for the search to finish, see update_searcher.
+    pending: Vec<SearcherUpdate>,
+
+    move_overhead: Duration,
+
+    // UCI_LimitStrength and UCI_Elo, the rng picks which weak move to play.
+    limit_strength: bool,
+    elo: u16,
+    rng: StdRng,
+
+    // OwnBook, the BookFile it plays from (the built-in book without one) and
+    // Book Variety, see book::pick_from.
+    own_book: bool,
+    book_file: Option<polyglot::Book>,
+    book_variety: u8,
+
+    // Castling is written king takes rook, and set on every position we get.
+    chess960: bool,
+    // UCI_Variant, also set on every position.
+    variant: Variant,
+
+    // Resigning and draw offers, for bots. See record_score.
+    decisions: Decisions,
 }
 
 impl Engine {
     pub fn new() -> Engine {
+        let mut searcher = Searcher::new();
+        searcher.set_tracer(Engine::tracer_from_env());
+        searcher.set_stats_log(Engine::stats_log_from_env());
+
         Engine {
             position: Board::from_start_pos(),
-            searcher: Searcher::new(),
+            history: Vec::new(),
+            stop: searcher.stop_flag(),
+            ponderhit: searcher.ponderhit_flag(),
+            searcher: Some(searcher),
+            search_thread: None,
+            pending: Vec::new(),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            limit_strength: false,
+            elo: DEFAULT_ELO,
+            rng: StdRng::from_entropy(),
+            own_book: false,
+            book_file: None,
+            book_variety: DEFAULT_BOOK_VARIETY,
+            chess960: false,
+            variant: Variant::Standard,
+            decisions: Decisions::new(DecisionRules::default()),
         }
     }
 
-    pub fn uci_loop(&mut self) -> io::Result<()> {
-        use std::io::BufRead;
+    // Search tracing is for debugging, so it's opt-in through environment variables
+    // instead of a UCI option.
+    // YOBMEF_TRACE=<file> YOBMEF_TRACE_PLY=<max ply> YOBMEF_TRACE_LINE="e2e4 e7e5"
+    fn tracer_from_env() -> Option<Tracer> {
+        use std::env;
 
-        let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            let line = line?;
-            let msg = uci::parse(&line);
+        let path = env::var("YOBMEF_TRACE").ok()?;
+        let mut filter = TraceFilter::default();
+        if let Ok(ply) = env::var("YOBMEF_TRACE_PLY") {
+            filter.max_ply = ply.parse().ok();
+        }
+        if let Ok(line) = env::var("YOBMEF_TRACE_LINE") {
+            filter.subtree = line
+                .split_whitespace()
+                .filter_map(Movement::from_notation)
+                .collect();
+        }
 
-            eprintln!("Got: {}", line);
-            eprintln!("Parse: {:?}", msg);
+        match Tracer::to_file(&path, filter) {
+            Ok(tracer) => Some(tracer),
+            Err(e) => {
+                log::warn!("could not open trace file {}: {}", path, e);
+                None
+            }
+        }
+    }
 
-            if let Some(msg) = msg {
-                self.handle(msg);
+    // Same for search statistics. YOBMEF_STATS=<file>, see stats.rs
+    fn stats_log_from_env() -> Option<StatsLog> {
+        let path = std::env::var("YOBMEF_STATS").ok()?;
+        match StatsLog::to_file(&path) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                log::warn!("could not open stats file {}: {}", path, e);
+                None
             }
         }
+    }
 
-        Ok(())
+    // Everything that can be set with set_option.
+    pub fn options() -> Vec<UciOption> {
+        let mut options = vec![
+            UciOption::spin(
+                "Hash",
+                search::DEFAULT_HASH_MB as i64,
+                1,
+                HASH_MB_MAX as i64,
+            ),
+            UciOption::button("Clear Hash"),
+            // 0 uses every core.
+            UciOption::spin("Threads", 1, 0, MAX_THREADS as i64),
+            UciOption::spin("Move Overhead", DEFAULT_MOVE_OVERHEAD_MS as i64, 0, 5000),
+            UciOption::spin("nodestime", 0, 0, 10000),
+            UciOption::spin("MultiPV", 1, 1, MAX_MULTI_PV as i64),
+            // Tells the GUI we can think on its time, it decides whether we do.
+            UciOption::check("Ponder", false),
+            UciOption::check("UCI_LimitStrength", false),
+            UciOption::spin(
+                "UCI_Elo",
+                DEFAULT_ELO as i64,
+                strength::MIN_ELO as i64,
+                strength::MAX_ELO as i64,
+            ),
+            UciOption::check("UCI_Chess960", false),
+            UciOption::combo(
+                "UCI_Variant",
+                Variant::Standard.name(),
+                Variant::ALL.iter().map(|v| v.name()).collect(),
+            ),
+            UciOption::check("OwnBook", false),
+            // A polyglot .bin, read when it's set.
+            UciOption::string("BookFile", ""),
+            UciOption::spin("Book Variety", DEFAULT_BOOK_VARIETY as i64, 0, 100),
+            // Directories with Syzygy tables, separated by ':' (';' on Windows).
+            UciOption::string("SyzygyPath", ""),
+            UciOption::spin("SyzygyProbeDepth", 1, 1, 100),
+            UciOption::check("Syzygy50MoveRule", true),
+            // Razoring can miss a quiet move that saves a bad position, which
+            // analysis would rather see at the cost of depth.
+            UciOption::check("Razoring", true),
+        ];
+        // Only there when built with the tune feature.
+        options.extend(tune::options());
+        options
     }
 
-    fn perft(&self, depth: u16) {
-        let board = &self.position;
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let options = Engine::options();
+        let option =
+            options::find(&options, name).ok_or_else(|| format!("unknown option {}", name))?;
+        let value = option
+            .parse(value)
+            .ok_or_else(|| format!("bad {} value {:?}", option.name, value))?;
+
+        // Values are already checked against the option's type and range.
+        match (option.name, value) {
+            ("Hash", OptionValue::Spin(mb)) => {
+                self.update_searcher(move |s| s.set_hash_size(mb as usize))
+            }
+            ("Clear Hash", _) => self.update_searcher(|s| s.clear_hash()),
+            ("Threads", OptionValue::Spin(threads)) => {
+                let threads = match threads {
+                    0 => thread::available_parallelism().map_or(1, |n| n.get()),
+                    n => n as usize,
+                };
+                self.update_searcher(move |s| s.set_threads(threads))
+            }
+            ("Move Overhead", OptionValue::Spin(ms)) => {
+                self.move_overhead = Duration::from_millis(ms as u64)
+            }
+            ("nodestime", OptionValue::Spin(nodestime)) => {
+                self.update_searcher(move |s| s.set_nodestime(nodestime as u64))
+            }
+            ("MultiPV", OptionValue::Spin(lines)) => {
+                self.update_searcher(move |s| s.set_multi_pv(lines as usize))
+            }
+            ("UCI_LimitStrength", OptionValue::Check(limit)) => {
+                self.limit_strength = limit;
+                // Picking a weaker move needs to know how bad the others are.
+                self.update_searcher(move |s| s.set_exact_root_scores(limit));
+            }
+            ("UCI_Elo", OptionValue::Spin(elo)) => self.elo = elo as u16,
+            ("UCI_Chess960", OptionValue::Check(chess960)) => self.chess960 = chess960,
+            ("UCI_Variant", OptionValue::String(name)) => {
+                self.variant = Variant::from_name(&name).unwrap()
+            }
+            ("OwnBook", OptionValue::Check(own_book)) => self.own_book = own_book,
+            ("BookFile", OptionValue::String(path)) => {
+                self.book_file = match path.trim() {
+                    "" => None,
+                    path => Some(polyglot::Book::open(path)?),
+                }
+            }
+            ("Book Variety", OptionValue::Spin(variety)) => self.book_variety = variety as u8,
+            ("SyzygyPath", OptionValue::String(paths)) => {
+                let tablebases = Tablebases::open(paths.trim())?;
+                self.searcher().set_tablebases(tablebases)
+            }
+            ("SyzygyProbeDepth", OptionValue::Spin(depth)) => {
+                self.searcher().set_tb_probe_depth(depth as i16)
+            }
+            ("Syzygy50MoveRule", OptionValue::Check(rule50)) => {
+                self.searcher().set_tb_50_move_rule(rule50)
+            }
+            ("Ponder", OptionValue::Check(_)) => {}
+            ("Razoring", OptionValue::Check(razoring)) => self.update_searcher(move |s| {
+                s.set_features(SearchFeatures {
+                    razoring,
+                    ..s.features()
+                })
+            }),
+            #[cfg(feature = "tune")]
+            (name, OptionValue::Spin(value)) if tune::set(name, value as i16) => {}
+            (name, value) => unreachable!("option {} set to {:?}", name, value),
+        }
+        Ok(())
+    }
 
-        let mut nodes = 0;
-        for mv in MoveGen::new_legal(&board) {
-            let n = perft(&board.make_move(&mv), depth - 1);
-            eprintln!("{}: {}", mv, n);
-            nodes += n;
+    // Play `moves` from `board`. We stop at the first illegal move and say so,
+    // playing it anyway would leave us with a corrupt board.
+    pub fn set_position(&mut self, mut board: Board, moves: &[Movement]) -> Result<(), String> {
+        board.chess960 = self.chess960;
+        board.variant = self.variant;
+        let mut history = Vec::new();
+        let mut result = Ok(());
+        for mv in moves {
+            let mv = match legal_move(&board, mv) {
+                Some(mv) => mv,
+                None => {
+                    let reason = board.check_move(mv).unwrap_err();
+                    result = Err(format!(
+                        "illegal move {} in {}: {}",
+                        mv,
+                        board.to_fen(),
+                        reason
+                    ));
+                    break;
+                }
+            };
+            history.push(zobrist::hash(&board));
+            board.make_move_mut(&mv);
         }
+        self.position = board;
+        self.history = history;
+        result
+    }
 
-        eprintln!("\nNodes searched: {}", nodes);
+    pub fn position(&self) -> &Board {
+        &self.position
     }
 
-    fn thinking_time(&self, opts: uci::Go) -> Duration {
-        let (our_time, our_increment) = match self.position.side_to_move {
-            Color::White => (opts.white_time, opts.white_increment),
-            Color::Black => (opts.black_time, opts.black_increment),
-        };
-        // Default to as if we had 10m no inc for correspondence games.
-        let our_time = our_time.unwrap_or(600_000);
-        let our_increment = our_increment.unwrap_or(0);
+    // Forget about the last game, options stay as they are.
+    pub fn new_game(&mut self) {
+        self.position = Board::from_start_pos();
+        self.position.chess960 = self.chess960;
+        self.position.variant = self.variant;
+        self.history.clear();
+        self.decisions.clear();
+        self.update_searcher(|s| s.new_game());
+    }
 
-        // Divide our remaining time by 30 to get the time for this move
-        // (totally arbitrary, fight me :D)
-        let time_for_this_move = our_time / 30;
+    // Bots decide on resigning and draws themselves, GUIs don't ask. After each
+    // of our moves, pass the score of its search (the last info's), then ask.
+    pub fn set_decision_rules(&mut self, rules: DecisionRules) {
+        self.decisions = Decisions::new(rules);
+    }
 
-        Duration::from_millis(time_for_this_move + our_increment)
+    pub fn record_score(&mut self, score: Option<i16>) {
+        self.decisions.update(score);
     }
 
-    fn go(&mut self, opts: uci::Go) {
-        // For debugging
-        if let Some(depth) = opts.perft {
-            self.perft(depth);
-            return;
-        }
+    pub fn should_resign(&self) -> bool {
+        self.decisions.should_resign()
+    }
 
-        let sr = if let Some(depth) = opts.depth {
-            self.searcher.search_depth(&self.position, depth)
+    pub fn should_offer_draw(&self) -> bool {
+        self.decisions.should_offer_draw(&self.position)
+    }
+
+    // The opponent offered a draw, take it?
+    pub fn accept_draw(&self) -> bool {
+        self.decisions.accept_draw(&self.position)
+    }
+
+    // Start searching the current position on another thread. A search that is
+    // still running gets waited on first, so stop it if you don't want that.
+    // In book, the book move comes back right away without searching.
+    pub fn go(&mut self, limits: SearchLimits) -> Search {
+        self.start(limits, true)
+    }
+
+    // Like go, but never from the book. For analysis, and anything else that
+    // wants a score and not just a move.
+    pub fn search(&mut self, limits: SearchLimits) -> Search {
+        self.start(limits, false)
+    }
+
+    fn start(&mut self, mut limits: SearchLimits, use_book: bool) -> Search {
+        let mut searcher = self.take_searcher();
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponderhit.store(false, Ordering::Relaxed);
+
+        let (events, receiver) = mpsc::channel();
+        let book_move = if use_book {
+            self.book_move(&limits)
         } else {
-            let thinking_time = self.thinking_time(opts);
-            self.searcher.search_timed(&self.position, thinking_time)
+            None
         };
+        if let Some(mv) = book_move {
+            self.searcher = Some(searcher);
+            let _ = events.send(SearchEvent::BestMove(Some(mv), None));
+            return Search {
+                events: receiver,
+                stop: self.stop.clone(),
+                done: false,
+            };
+        }
 
-        println!("bestmove {}", sr.mv);
-    }
+        let info_events = events.clone();
+        searcher.set_info_callback(move |info| {
+            // Nobody listening is fine, they can still stop us.
+            let _ = info_events.send(SearchEvent::Info(info));
+        });
+        searcher.set_history(self.history.clone());
+        limits.move_overhead = self.move_overhead;
 
-    fn handle(&mut self, msg: uci::EngineMessage) {
-        match msg {
-            EngineMessage::UCI => {
-                println!("id name Yobmef");
-                println!("id author PwnSquad");
-                println!("uciok");
-            }
-            EngineMessage::IsReady => println!("readyok"),
-            EngineMessage::Quit => std::process::exit(0),
+        let strength = Some(Strength::new(self.elo)).filter(|_| self.limit_strength);
+        if let Some(strength) = strength {
+            let nodes = strength.nodes();
+            limits.nodes = Some(limits.nodes.map_or(nodes, |n| n.min(nodes)));
+        }
+        let mut rng = StdRng::from_rng(&mut self.rng).expect("rng failed");
+        let board = self.position.clone();
+        let stop = self.stop.clone();
+        let ponderhit = self.ponderhit.clone();
 
-            EngineMessage::Position(board, moves) => {
-                let mut board: Board = board.clone();
-                for movement in moves {
-                    board.make_move_mut(&movement);
+        self.search_thread = Some(thread::spawn(move || {
+            // An infinite search can finish on its own (depth limit, forced mate),
+            // but the best move still has to wait for stop. So does a ponder
+            // search, until ponderhit. (UCI spec)
+            let (infinite, ponder) = (limits.infinite, limits.ponder);
+            let wait_for_stop = || {
+                while (infinite || ponder && !ponderhit.load(Ordering::Relaxed))
+                    && !stop.load(Ordering::Relaxed)
+                {
+                    thread::sleep(Duration::from_millis(1));
                 }
-                eprintln!("current position:\n{}", board);
-                self.position = board;
+            };
+
+            // Nothing to search, but the GUI still waits for a bestmove.
+            if !movegen::has_legal_move(&board) {
+                wait_for_stop();
+                let _ = events.send(SearchEvent::BestMove(None, None));
+                return searcher;
             }
 
-            EngineMessage::Go(opts) => self.go(opts),
+            // A bug in the search shouldn't lose the game on time, play any move.
+            let sr = match panic::catch_unwind(AssertUnwindSafe(|| searcher.go(&board, limits))) {
+                Ok(sr) => sr,
+                Err(_) => {
+                    let mv = MoveGen::new_legal(&board).next().unwrap();
+                    log::error!("search panicked in {}, playing {}", board.to_fen(), mv);
+                    wait_for_stop();
+                    let _ = events.send(SearchEvent::BestMove(Some(mv), None));
+                    return searcher;
+                }
+            };
+            let mv = match strength {
+                Some(strength) => strength
+                    .pick(searcher.root_moves(), &mut rng)
+                    .unwrap_or(sr.mv),
+                None => sr.mv,
+            };
+            let ponder = searcher.ponder_move(&board, &mv);
+            wait_for_stop();
+            let _ = events.send(SearchEvent::BestMove(Some(mv), ponder));
+            searcher
+        }));
+
+        Search {
+            events: receiver,
+            stop: self.stop.clone(),
+            done: false,
+        }
+    }
+
+    // Start analysing the position after `moves` from `board`, see AnalysisSession.
+    pub fn analyse(
+        &mut self,
+        board: Board,
+        moves: &[Movement],
+    ) -> Result<AnalysisSession<'_>, String> {
+        self.set_position(board, moves)?;
+        let search = self.search(SearchLimits::infinite());
+        Ok(AnalysisSession {
+            engine: self,
+            search,
+        })
+    }
+
+    // The move to play from the book, if we're in it and should use it. Only for
+    // playing games: a depth, node or mate limit asks for a search, and so does
+    // analysis (infinite, searchmoves, excluded moves) and pondering, whose best
+    // move waits for ponderhit. The built-in book is all
+    // standard chess.
+    fn book_move(&mut self, limits: &SearchLimits) -> Option<Movement> {
+        let wants_search = limits.depth.is_some()
+            || limits.nodes.is_some()
+            || limits.mate.is_some()
+            || limits.infinite
+            || limits.ponder
+            || limits.restricts_root();
+        if !self.own_book || wants_search || self.chess960 || self.variant != Variant::Standard {
+            return None;
+        }
+        let moves = match &self.book_file {
+            Some(book_file) => book_file.moves(&self.position),
+            None => book::moves(&self.position),
+        };
+        let mv = book::pick_from(moves, self.book_variety, &mut self.rng)?;
+        // Zobrist keys can collide.
+        legal_move(&self.position, &mv)
+    }
+
+    // How long a search with these limits will think, None if the clock won't
+    // be what stops it.
+    pub fn thinking_time(&self, limits: &SearchLimits) -> Option<Duration> {
+        let limits = SearchLimits {
+            move_overhead: self.move_overhead,
+            ..limits.clone()
+        };
+        limits.thinking_time(self.position.side_to_move)
+    }
 
-            _ => {}
+    // Stop the running search, if any.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    // The opponent played the move a go ponder search expected, so it's our
+    // move now and the clock it was given is running.
+    pub fn ponder_hit(&self) {
+        self.ponderhit.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search_thread.is_some()
+    }
+
+    // The searcher, waiting for the search using it to finish first.
+    pub fn searcher(&mut self) -> &mut Searcher {
+        if let Some(thread) = self.search_thread.take() {
+            let mut searcher = thread.join().expect("search thread panicked");
+            for update in self.pending.drain(..) {
+                update(&mut searcher);
+            }
+            self.searcher = Some(searcher);
         }
+        self.searcher.as_mut().unwrap()
+    }
+
+    // Change the searcher now, or once the search using it is done. Blocking
+    // until then would leave the GUI hanging (forever, if it's go infinite).
+    fn update_searcher<F: FnOnce(&mut Searcher) + Send + 'static>(&mut self, update: F) {
+        if self.search_thread.is_some() {
+            self.pending.push(Box::new(update));
+        } else {
+            update(self.searcher());
+        }
+    }
+
+    fn take_searcher(&mut self) -> Searcher {
+        self.searcher();
+        self.searcher.take().unwrap()
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+// The legal move matching `mv`, if there is one. Castling can also be given as
+// the king taking its own rook (e1h1) since some GUIs send that. (In Chess960
+// that's the only way, e1g1 is a normal king move there)
+fn legal_move(board: &Board, mv: &Movement) -> Option<Movement> {
+    let legal: Vec<Movement> = MoveGen::new_legal(board).collect();
+    if legal.contains(mv) {
+        return Some(mv.clone());
+    }
+    if board.chess960 {
+        return None;
+    }
+
+    let castle = CastlingSide::from_rook_square(mv.to_square)?.get_king_movement();
+    if mv.from_square == castle.from_square && mv.promote.is_none() && legal.contains(&castle) {
+        return Some(castle);
     }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::adjudication::{DrawRule, ResignRule};
+    use std::time::Instant;
+
+    fn moves(s: &str) -> Vec<Movement> {
+        s.split_whitespace()
+            .map(|mv| Movement::from_notation(mv).unwrap())
+            .collect()
+    }
+
+    fn best_move(search: Search) -> Movement {
+        match search.last() {
+            Some(SearchEvent::BestMove(Some(mv), _)) => mv,
+            event => panic!("search ended with {:?}", event),
+        }
+    }
 
     #[test]
     fn test_position() {
         let mut engine = Engine::new();
-        engine.handle(uci::parse("position startpos").unwrap());
-        assert_eq!(engine.position, Board::from_start_pos());
+        assert_eq!(engine.position(), &Board::from_start_pos());
 
-        engine.handle(uci::parse("position startpos moves e2e4 e7e5").unwrap());
+        engine
+            .set_position(Board::from_start_pos(), &moves("e2e4 e7e5"))
+            .unwrap();
         // NOTE: we assert for en-passant e6, that was failing earlier because lichess
         // does not generate a fen with en-passant unless it is possible to be taken.
         assert_eq!(
-            engine.position,
-            Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2")
+            engine.position(),
+            &Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2")
                 .unwrap()
         );
+        assert_eq!(engine.history.len(), 2);
+
+        let board = Board::from_fen("K1k5/8/8/8/8/8/8/8 w - - 0 1").unwrap();
+        engine.set_position(board.clone(), &[]).unwrap();
+        assert_eq!(engine.position(), &board);
+        assert!(engine.history.is_empty());
+    }
+
+    #[test]
+    fn test_position_illegal_move() {
+        let mut engine = Engine::new();
+
+        // Black can't play e7e5 after e7e6, so we stop there.
+        let result = engine.set_position(Board::from_start_pos(), &moves("e2e4 e7e6 e7e5 d2d4"));
+        assert_eq!(
+            result.unwrap_err(),
+            "illegal move e7e5 in rnbqkbnr/pppp1ppp/4p3/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1: \
+             no piece on that square"
+        );
+        let want = Board::from_start_pos()
+            .make_move(&Movement::from_notation("e2e4").unwrap())
+            .make_move(&Movement::from_notation("e7e6").unwrap());
+        assert_eq!(engine.position(), &want);
 
-        let fen = "K1k5/8/8/8/8/8/8/8 w - - 0 1";
-        engine.handle(uci::parse(&format!("position fen {}", fen)).unwrap());
-        assert_eq!(engine.position, Board::from_fen(fen).unwrap());
+        // Promotions need the piece.
+        let board = Board::from_fen("8/4P3/8/8/8/k7/8/K7 w - - 0 1").unwrap();
+        assert!(engine.set_position(board.clone(), &moves("e7e8")).is_err());
+        assert_eq!(engine.position(), &board);
+        engine.set_position(board, &moves("e7e8n")).unwrap();
+        assert_eq!(
+            engine.position(),
+            &Board::from_fen("4N3/8/8/8/8/k7/8/K7 b - - 0 1").unwrap()
+        );
     }
 
     #[test]
-    fn test_think_time() {
-        // Reasonable bounds on thinking time
+    fn test_position_castling() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castled = Board::from_fen("r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1").unwrap();
+
+        let mut engine = Engine::new();
+        engine.set_position(board.clone(), &moves("e1g1")).unwrap();
+        assert_eq!(engine.position(), &castled);
+        engine.set_position(board.clone(), &moves("e1h1")).unwrap();
+        assert_eq!(engine.position(), &castled);
 
-        let engine = Engine::new();
-        let mut opts = uci::Go::empty();
-        opts.white_time = Some(300_000);
-        // black_time: 300_000,
-        let t = engine.thinking_time(opts).as_millis();
+        engine.set_position(board, &moves("e1a1 e8h8")).unwrap();
+        assert_eq!(
+            engine.position(),
+            &Board::from_fen("r4rk1/8/8/8/8/8/8/2KR3R w - - 2 2").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_chess960() {
+        let mut engine = Engine::new();
+        engine.set_option("UCI_Chess960", Some("true")).unwrap();
+
+        let fen = "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w KQkq - 0 1";
+        engine
+            .set_position(Board::from_fen(fen).unwrap(), &moves("e1g1"))
+            .unwrap();
+        assert!(engine.position().chess960);
+        assert_eq!(
+            engine.position().to_fen(),
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R3RK1 b kq - 1 1"
+        );
+
+        // Castling comes out as king takes rook too.
+        let board = Board::from_fen("rk6/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        engine.set_position(board, &[]).unwrap();
+        let legal: Vec<Movement> = MoveGen::new_legal(engine.position()).collect();
+        assert!(legal.contains(&Movement::from_notation("e1h1").unwrap()));
+        assert!(!legal.contains(&Movement::from_notation("e1g1").unwrap()));
+    }
 
-        // Assume you will think between 1s and 20s per move in a 5 minute game
-        assert!(1_000 < t && t < 20_000, "1s < t({}s) < 10s", t / 1000);
+    #[test]
+    fn test_variant() {
+        let mut engine = Engine::new();
+        assert!(engine
+            .set_option("UCI_Variant", Some("crazyhouse"))
+            .is_err());
+        engine.set_option("UCI_Variant", Some("3check")).unwrap();
+
+        let board = Board::from_start_pos();
+        engine
+            .set_position(board, &moves("e2e4 f7f5 d1h5"))
+            .unwrap();
+        assert_eq!(engine.position().variant, Variant::ThreeCheck);
+        assert_eq!(
+            engine.position().to_fen(),
+            "rnbqkbnr/ppppp1pp/8/5p1Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 2+3 1 1"
+        );
+
+        // The book is for normal chess.
+        let clock = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_secs(60)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(engine.book_move(&clock), None);
+    }
+
+    #[test]
+    fn test_decisions() {
+        let mut engine = Engine::new();
+        engine.set_decision_rules(DecisionRules {
+            resign: Some(ResignRule::parse("2,500").unwrap()),
+            draw: Some(DrawRule::parse("0,2,10").unwrap()),
+            ..DecisionRules::default()
+        });
+        engine.record_score(Some(-600));
+        engine.record_score(Some(-700));
+        assert!(engine.should_resign());
+        assert!(engine.accept_draw());
+        assert!(!engine.should_offer_draw());
+
+        engine.new_game();
+        assert!(!engine.should_resign());
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        engine.set_position(board, &[]).unwrap();
+        engine.record_score(Some(0));
+        engine.record_score(Some(5));
+        assert!(engine.should_offer_draw());
+    }
+
+    #[test]
+    fn test_setoption_hash() {
+        let mut engine = Engine::new();
+        engine.set_option("Hash", Some("1")).unwrap();
+        let capacity = engine.searcher().tp.capacity();
+
+        engine.set_option("hash", Some("2")).unwrap();
+        assert_eq!(engine.searcher().tp.capacity(), capacity * 2);
+
+        best_move(engine.go(SearchLimits::depth(2)));
+        assert!(!engine.searcher().tp.is_empty());
+        engine.set_option("Clear Hash", None).unwrap();
+        assert!(engine.searcher().tp.is_empty());
+    }
+
+    #[test]
+    fn test_setoption_razoring() {
+        let mut engine = Engine::new();
+        assert!(engine.searcher().features().razoring);
+        engine.set_option("Razoring", Some("false")).unwrap();
+        assert_eq!(
+            engine.searcher().features(),
+            SearchFeatures {
+                razoring: false,
+                ..SearchFeatures::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_setoption_multi_pv() {
+        let mut engine = Engine::new();
+        engine.set_option("MultiPV", Some("2")).unwrap();
+        let multipvs: Vec<_> = engine
+            .go(SearchLimits::depth(2))
+            .filter_map(|event| match event {
+                SearchEvent::Info(info) => info.multipv,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(multipvs, [1, 2, 1, 2]);
+        assert_eq!(engine.searcher().pv_lines().len(), 2);
+    }
+
+    #[test]
+    fn test_setoption_tunable() {
+        let mut engine = Engine::new();
+        let result = engine.set_option("RecaptureExtensionDepth", Some("3"));
+        assert_eq!(result.is_ok(), cfg!(feature = "tune"));
+        assert_eq!(tune::recapture_extension_depth(), 3);
+    }
+
+    #[test]
+    fn test_setoption_while_searching() {
+        let mut engine = Engine::new();
+        engine.set_option("Hash", Some("1")).unwrap();
+        let capacity = engine.searcher().tp.capacity();
+
+        // Doesn't wait for the search, which would never end.
+        let search = engine.go(SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        });
+        engine.set_option("Hash", Some("2")).unwrap();
+        engine.new_game();
+        search.stop();
+        best_move(search);
+        assert_eq!(engine.searcher().tp.capacity(), capacity * 2);
+    }
+
+    #[test]
+    fn test_new_game() {
+        let mut engine = Engine::new();
+        engine.set_option("Hash", Some("1")).unwrap();
+        engine.set_option("UCI_Elo", Some("1000")).unwrap();
+        let capacity = engine.searcher().tp.capacity();
+        engine
+            .set_position(Board::from_start_pos(), &moves("e2e4 e7e5"))
+            .unwrap();
+        best_move(engine.go(SearchLimits::depth(3)));
+
+        engine.new_game();
+        assert_eq!(engine.position(), &Board::from_start_pos());
+        assert!(engine.history.is_empty());
+        assert!(engine.searcher().tp.is_empty());
+        assert!(engine.searcher().root_moves().is_empty());
+
+        // Options stick around.
+        assert_eq!(engine.searcher().tp.capacity(), capacity);
+        assert_eq!(engine.elo, 1000);
+    }
+
+    #[test]
+    fn test_setoption_threads() {
+        let mut engine = Engine::new();
+        engine.set_option("Threads", Some("3")).unwrap();
+        let mv = best_move(engine.go(SearchLimits::depth(4)));
+        assert!(MoveGen::new_legal(engine.position()).any(|legal| legal == mv));
+
+        engine.set_option("Threads", Some("0")).unwrap();
+        best_move(engine.go(SearchLimits::depth(2)));
+    }
+
+    #[test]
+    fn test_setoption_strength() {
+        let mut engine = Engine::new();
+        engine
+            .set_option("UCI_LimitStrength", Some("true"))
+            .unwrap();
+        engine.set_option("UCI_Elo", Some("1000")).unwrap();
+        assert!(engine.limit_strength);
+        assert_eq!(engine.elo, 1000);
+
+        // Limited to a small node budget, even with a big depth.
+        best_move(engine.go(SearchLimits::depth(20)));
+        let nodes = Strength::new(1000).nodes();
+        let searched = engine.searcher().nodes;
+        assert!(searched < nodes + 100, "nodes {}", searched);
+
+        engine
+            .set_option("UCI_LimitStrength", Some("false"))
+            .unwrap();
+        assert!(!engine.limit_strength);
+    }
+
+    #[test]
+    fn test_setoption_move_overhead() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.move_overhead, Duration::from_millis(10));
+        engine.set_option("Move Overhead", Some("100")).unwrap();
+        assert_eq!(engine.move_overhead, Duration::from_millis(100));
+
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_millis(3100)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            engine.thinking_time(&limits),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn test_setoption_bad_values() {
+        let mut engine = Engine::new();
+
+        // Ignored, instead of turning strength limiting off.
+        engine
+            .set_option("UCI_LimitStrength", Some("true"))
+            .unwrap();
+        assert!(engine
+            .set_option("UCI_LimitStrength", Some("maybe"))
+            .is_err());
+        assert!(engine.limit_strength);
+
+        // Out of range gets clamped.
+        engine.set_option("UCI_Elo", Some("9000")).unwrap();
+        assert_eq!(engine.elo, strength::MAX_ELO);
+        assert!(engine.set_option("UCI_Elo", Some("lots")).is_err());
+        assert_eq!(engine.elo, strength::MAX_ELO);
+
+        assert!(engine.set_option("Contempt", Some("20")).is_err());
+    }
+
+    #[test]
+    fn test_own_book() {
+        let mut engine = Engine::new();
+        engine
+            .set_position(Board::from_start_pos(), &moves("e2e4 e7e5"))
+            .unwrap();
+        let clock = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_secs(60)),
+            ..SearchLimits::default()
+        };
+
+        // Off by default.
+        let events: Vec<SearchEvent> = engine.go(clock.clone()).take(1).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+        engine.stop();
+
+        // Straight to the best move, no infos and no search.
+        engine.set_option("OwnBook", Some("true")).unwrap();
+        let events: Vec<SearchEvent> = engine.go(clock.clone()).collect();
+        match &events[..] {
+            [SearchEvent::BestMove(Some(mv), None)] => {
+                assert!(book::moves(engine.position()).iter().any(|(m, _)| m == mv))
+            }
+            events => panic!("book move came with {:?}", events),
+        }
+        assert!(!engine.is_searching());
+
+        // Always the most popular move without variety.
+        engine.set_option("Book Variety", Some("0")).unwrap();
+        let popular = book::moves(engine.position())[0].0.clone();
+        for _ in 0..5 {
+            assert_eq!(best_move(engine.go(clock.clone())), popular);
+        }
+
+        // Searched with a depth, when asked to search, or with the book off.
+        let events: Vec<SearchEvent> = engine.go(SearchLimits::depth(2)).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+        let events: Vec<SearchEvent> = engine.search(clock.clone()).take(1).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+        engine.stop();
+        engine.set_option("OwnBook", Some("false")).unwrap();
+        let events: Vec<SearchEvent> = engine.go(clock.clone()).take(1).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+        engine.stop();
+    }
+
+    #[test]
+    fn test_go_events() {
+        let mut engine = Engine::new();
+        let events: Vec<SearchEvent> = engine.go(SearchLimits::depth(3)).collect();
+
+        // An info for each depth, then the best move.
+        let depths: Vec<i16> = events
+            .iter()
+            .filter_map(|event| match event {
+                SearchEvent::Info(info) => info.depth,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(depths, vec![1, 2, 3]);
+        assert!(matches!(events.last(), Some(SearchEvent::BestMove(..))));
+        assert!(engine.is_searching());
+
+        // Searching again waits for the last one to hand back the searcher.
+        let mv = best_move(engine.go(SearchLimits::depth(1)));
+        assert!(MoveGen::new_legal(engine.position()).any(|legal| legal == mv));
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut engine = Engine::new();
+        let search = engine.go(SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        });
+        thread::sleep(Duration::from_millis(100));
+        search.stop();
+        best_move(search);
+
+        // Stopping before go doesn't stop the next search.
+        engine.stop();
+        let depths = engine
+            .go(SearchLimits::depth(4))
+            .filter(|event| match event {
+                SearchEvent::Info(info) => info.depth.is_some(),
+                _ => false,
+            });
+        assert_eq!(depths.count(), 4);
+    }
+
+    #[test]
+    fn test_analysis_session() {
+        let depth = |event: SearchEvent| match event {
+            SearchEvent::Info(info) => info.depth,
+            _ => None,
+        };
+
+        // White has one legal move, so it's the PV's.
+        let mut engine = Engine::new();
+        let board = Board::from_fen("k7/8/8/8/8/8/r7/K1r5 w - - 0 1").unwrap();
+        let mut session = engine.analyse(board, &[]).unwrap();
+        session
+            .search()
+            .find(|event| depth(event.clone()) >= Some(4));
+
+        // Carries on from there instead of starting at depth 1.
+        session.play(&moves("a1a2")[0]).unwrap();
+        let first = session.search().find_map(depth).unwrap();
+        assert!(first >= 3, "started at depth {}", first);
+        let fen = session.position().to_fen();
+        assert!(fen.starts_with("k7/8/8/8/8/8/K7/2r5 b"), "{}", fen);
+
+        let err = session.play(&moves("a1b1")[0]).unwrap_err();
+        assert!(err.starts_with("illegal move a1b1"), "{}", err);
+        assert!(session.search().find_map(depth).is_some());
+
+        let mv = session.stop().unwrap();
+        assert!(MoveGen::new_legal(engine.position()).any(|legal| legal == mv));
+        assert_eq!(engine.history.len(), 1);
+    }
+
+    #[test]
+    fn test_analysis_session_drop() {
+        let mut engine = Engine::new();
+        let mut session = engine.analyse(Board::from_start_pos(), &[]).unwrap();
+        assert!(session.search().next().is_some());
+        drop(session);
+
+        let events: Vec<SearchEvent> = engine.go(SearchLimits::depth(2)).collect();
+        assert!(matches!(
+            events.last(),
+            Some(SearchEvent::BestMove(Some(_), _))
+        ));
+    }
+
+    #[test]
+    fn test_infinite_waits_for_stop() {
+        let mut engine = Engine::new();
+        let search = engine.go(SearchLimits {
+            depth: Some(2),
+            infinite: true,
+            ..SearchLimits::default()
+        });
+
+        let start = Instant::now();
+        let stop = search.stop.clone();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let events: Vec<SearchEvent> = search.collect();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        stopper.join().unwrap();
+
+        // The depth 2 line is the best we found, and comes before the move.
+        let last_info = events.iter().rev().find_map(|event| match event {
+            SearchEvent::Info(info) if info.depth.is_some() => Some(info),
+            _ => None,
+        });
+        assert_eq!(last_info.unwrap().depth, Some(2));
+        match events.last() {
+            Some(SearchEvent::BestMove(Some(mv), _)) => {
+                assert_eq!(Some(mv), last_info.unwrap().pv.first())
+            }
+            event => panic!("search ended with {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_ponder() {
+        let mut engine = Engine::new();
+        let search = engine.go(SearchLimits {
+            movetime: Some(Duration::from_millis(50)),
+            ponder: true,
+            ..SearchLimits::default()
+        });
+
+        // The movetime (less the move overhead) is ours once the opponent plays,
+        // not before.
+        let start = Instant::now();
+        let ponderhit = engine.ponderhit.clone();
+        let hitter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            ponderhit.store(true, Ordering::Relaxed);
+        });
+        let events: Vec<SearchEvent> = search.collect();
+        assert!(start.elapsed() >= Duration::from_millis(250) - engine.move_overhead);
+        hitter.join().unwrap();
+        assert!(matches!(
+            events.last(),
+            Some(SearchEvent::BestMove(Some(_), _))
+        ));
+
+        // Stop ends it without a ponderhit.
+        let search = engine.go(SearchLimits {
+            depth: Some(2),
+            ponder: true,
+            ..SearchLimits::default()
+        });
+        engine.stop();
+        assert!(matches!(
+            search.last(),
+            Some(SearchEvent::BestMove(Some(_), _))
+        ));
+    }
+
+    #[test]
+    fn test_ponder_move() {
+        let mut engine = Engine::new();
+        match engine.go(SearchLimits::depth(4)).last() {
+            Some(SearchEvent::BestMove(Some(mv), Some(ponder))) => {
+                let after = engine.position().make_move(&mv);
+                assert!(MoveGen::new_legal(&after).any(|legal| legal == ponder));
+            }
+            event => panic!("search ended with {:?}", event),
+        }
+
+        // No reply to ponder on after mate.
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        engine.set_position(board, &[]).unwrap();
+        match engine.go(SearchLimits::depth(4)).last() {
+            Some(SearchEvent::BestMove(mv, ponder)) => {
+                assert_eq!(mv, Movement::from_notation("a1a8"));
+                assert_eq!(ponder, None);
+            }
+            event => panic!("search ended with {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_book_file() {
+        let mut engine = Engine::new();
+        engine
+            .set_position(Board::from_start_pos(), &moves("a2a3 a7a6"))
+            .unwrap();
+        let clock = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_secs(60)),
+            ..SearchLimits::default()
+        };
+
+        let board = engine.position().clone();
+        let mut bytes = polyglot::key(&board).to_be_bytes().to_vec();
+        bytes.extend(polyglot::encode_move(&board, &moves("h2h3")[0]).to_be_bytes());
+        bytes.extend([0, 1, 0, 0, 0, 0]); // weight 1, no learn
+        let path = std::env::temp_dir().join("yobmef_test_book_file.bin");
+        std::fs::write(&path, bytes).unwrap();
+        engine.set_option("BookFile", path.to_str()).unwrap();
+
+        // Off by default, like other engines.
+        let events: Vec<SearchEvent> = engine.go(clock.clone()).take(1).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+        engine.stop();
+
+        // Straight to the book move, no infos and no search.
+        engine.set_option("OwnBook", Some("true")).unwrap();
+        let events: Vec<SearchEvent> = engine.go(clock.clone()).collect();
+        assert!(matches!(
+            &events[..],
+            [SearchEvent::BestMove(Some(mv), None)] if mv.to_notation() == "h2h3"
+        ));
+        assert!(!engine.is_searching());
+
+        // Searched with a depth.
+        let events: Vec<SearchEvent> = engine.go(SearchLimits::depth(2)).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+
+        assert!(engine
+            .set_option("BookFile", Some("no such book.bin"))
+            .is_err());
+        engine.set_option("BookFile", Some("<empty>")).unwrap();
+        let events: Vec<SearchEvent> = engine.go(clock).take(1).collect();
+        assert!(matches!(events[0], SearchEvent::Info(_)));
+        engine.stop();
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_syzygy_options() {
+        use crate::syzygy::testing::{temp_dir, write_krk};
+
+        let dir = temp_dir("engine");
+        write_krk(&dir, true);
+        let mut engine = Engine::new();
+        let missing = dir.join("missing");
+        assert!(engine.set_option("SyzygyPath", missing.to_str()).is_err());
+        engine.set_option("SyzygyPath", dir.to_str()).unwrap();
+        engine.set_option("SyzygyProbeDepth", Some("4")).unwrap();
+        engine
+            .set_option("Syzygy50MoveRule", Some("false"))
+            .unwrap();
+
+        // Moving the rook next to the black king gives it away.
+        let board = Board::from_fen("8/8/8/4k3/8/3R4/8/K7 w - - 0 1").unwrap();
+        engine.set_position(board, &[]).unwrap();
+        let events: Vec<SearchEvent> = engine.go(SearchLimits::depth(2)).collect();
+        assert!(events.iter().all(|event| match event {
+            SearchEvent::Info(info) => info.pv.is_empty() || info.tbhits.unwrap() > 0,
+            SearchEvent::BestMove(mv, _) => !moves("d3d4 d3d5 d3d6").contains(mv.as_ref().unwrap()),
+        }));
+
+        // No tables, no tbhits.
+        engine.set_option("SyzygyPath", Some("<empty>")).unwrap();
+        let events: Vec<SearchEvent> = engine.go(SearchLimits::depth(2)).collect();
+        assert!(events.iter().all(|event| match event {
+            SearchEvent::Info(info) => info.tbhits.is_none(),
+            SearchEvent::BestMove(..) => true,
+        }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        let mut engine = Engine::new();
+        for fen in &[
+            "6Rk/5Kpp/8/8/8/8/8/8 b - - 0 1", // mate
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", // stalemate
+        ] {
+            engine
+                .set_position(Board::from_fen(fen).unwrap(), &[])
+                .unwrap();
+            let events: Vec<SearchEvent> = engine.go(SearchLimits::default()).collect();
+            assert_eq!(events, vec![SearchEvent::BestMove(None, None)]);
+        }
     }
 }
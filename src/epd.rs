@@ -0,0 +1,94 @@
+use crate::chess::Board;
+
+// EPD, the usual format for test positions and puzzles: the first four fields
+// of a FEN, then `opcode operands;` operations.
+//
+//     6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; dm 1; id "back rank";
+//
+// Fields can be separated by any whitespace. A FEN parses too, its move
+// counters read as an operation nobody asks for.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epd {
+    pub board: Board,
+    // The four position fields, single spaced.
+    pub fen: String,
+    // Opcode and operands, in order.
+    pub operations: Vec<(String, String)>,
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> Result<Epd, String> {
+        let mut fields = Vec::new();
+        let mut rest = line.trim();
+        while fields.len() < 4 && !rest.is_empty() {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        if fields.len() < 4 {
+            return Err(format!("not an epd: {}", line));
+        }
+        let fen = fields.join(" ");
+        let board = Board::from_fen(&fen).ok_or(format!("bad position {}", fen))?;
+
+        let operations = rest
+            .split(';')
+            .map(str::trim)
+            .filter(|operation| !operation.is_empty())
+            .map(|operation| {
+                let (opcode, operands) = operation
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((operation, ""));
+                (opcode.to_string(), operands.trim().to_string())
+            })
+            .collect();
+        Ok(Epd {
+            board,
+            fen,
+            operations,
+        })
+    }
+
+    // The operands of the first `opcode` operation.
+    pub fn operation(&self, opcode: &str) -> Option<&str> {
+        self.operations
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_str())
+    }
+
+    // The position's name, without its quotes.
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id").map(|id| id.trim_matches('"'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::gen_moves_once;
+
+    #[test]
+    fn test_parse() {
+        gen_moves_once();
+        let epd =
+            Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1  w -\t- bm Ra8#;dm 1; id \"back rank\";").unwrap();
+        assert_eq!(epd.fen, "6k1/5ppp/8/8/8/8/8/R5K1 w - -");
+        assert_eq!(
+            epd.board,
+            Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap()
+        );
+        assert_eq!(epd.operation("bm"), Some("Ra8#"));
+        assert_eq!(epd.operation("dm"), Some("1"));
+        assert_eq!(epd.id(), Some("back rank"));
+        assert_eq!(epd.operation("ce"), None);
+
+        let fen = Epd::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(fen.board, Board::from_start_pos());
+        assert_eq!(fen.id(), None);
+
+        assert!(Epd::parse("6k1/5ppp w -").is_err());
+        assert!(Epd::parse("6k1/5ppp/8/8/8/8/8/R5X1 w - - id \"bad\";").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::bitboard::BitBoard;
 use crate::chess::{Board, Color, Movement, Piece, Square};
+use crate::tune;
 
 // SHITTY SHIT HERE, JUST FOR EXPERIMENTATION, NOT FOR USE IN FINAL PROGRAM
 // Inspiration from:
@@ -96,14 +97,15 @@ fn multiply_table(bitboard: &BitBoard, table: [i16; 64], square_value: i16) -> i
         .sum()
 }
 
+// In centipawns, the king is priceless so it's 0.
 #[inline]
-fn get_piece_value(piece: Piece) -> i16 {
+pub fn get_piece_value(piece: Piece) -> i16 {
     match piece {
         Piece::Pawn => 100,
-        Piece::Knight => 320,
-        Piece::Bishop => 330,
-        Piece::Rook => 500,
-        Piece::Queen => 975,
+        Piece::Knight => tune::knight_value(),
+        Piece::Bishop => tune::bishop_value(),
+        Piece::Rook => tune::rook_value(),
+        Piece::Queen => tune::queen_value(),
         _ => 0,
     }
 }
@@ -148,7 +150,8 @@ pub fn get_score_ongoing(board: &Board) -> i16 {
 
     score += get_piece_score_for_color(board, Color::White);
     score -= get_piece_score_for_color(board, Color::Black);
-    score += 10 * board.side_to_move.polarize(); // Side to move gets inherent advantage
+    score += tune::tempo() * board.side_to_move.polarize(); // Side to move gets inherent advantage
+    score += board.variant.eval(board);
 
     score
 }
@@ -176,7 +179,11 @@ pub fn get_promise(board: &Board, mv: &Movement) -> i16 {
     let moved_piece = board.piece_on(mv.from_square).unwrap();
 
     // most valuable victim, least valuable aggressor
-    if let Some(captured) = board.piece_on(mv.to_square) {
+    // (checking is_capture, castling in Chess960 is the king taking its own rook)
+    if let Some(captured) = board
+        .piece_on(mv.to_square)
+        .filter(|_| board.is_capture(mv))
+    {
         p += get_piece_value(captured);
         p -= get_piece_value(moved_piece) / 100;
     }
@@ -187,7 +194,9 @@ pub fn get_promise(board: &Board, mv: &Movement) -> i16 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{chess::Movement, movegen::MoveGen};
+    use crate::{chess::Movement, movegen::MoveGen, random};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_get_promise() {
@@ -234,6 +243,54 @@ mod tests {
         assert_eq!(score, MATE);
     }
 
+    // The same position with the colors swapped, white's pieces are black's
+    // mirrored to the other side of the board.
+    fn mirror(board: &Board) -> Board {
+        let fen = board.to_fen();
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| match c {
+                    c if c.is_ascii_uppercase() => c.to_ascii_lowercase(),
+                    c => c.to_ascii_uppercase(),
+                })
+                .collect()
+        };
+        let placement: Vec<&str> = fields[0].split('/').rev().collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match board.en_passant {
+            Some(sq) => sq.flip_vertical().to_notation(),
+            None => "-".into(),
+        };
+        let fen = format!(
+            "{} {} {} {} {} 1",
+            swap_case(&placement.join("/")),
+            side,
+            swap_case(fields[2]),
+            en_passant,
+            fields[4]
+        );
+        Board::from_fen(&fen).unwrap()
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for i in 0..500 {
+            let board = if i % 2 == 0 {
+                random::random_game(rng.gen_range(0..80), &mut rng)
+            } else {
+                random::random_placement("QRBNPPPqrbnppp", &mut rng).unwrap()
+            };
+            assert_eq!(
+                get_score_ongoing(&board),
+                -get_score_ongoing(&mirror(&board)),
+                "{}",
+                board.to_fen()
+            );
+        }
+    }
+
     macro_rules! test_move_delta {
         (name: $name:ident, fen: $fen:expr, mv: $mv:expr, assert: $assert:expr,) => {
             #[test]
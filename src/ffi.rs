@@ -0,0 +1,466 @@
+// The safety rules are the same for every function, see below.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::chess::{Board, Movement};
+use crate::engine::{Engine, SearchEvent};
+use crate::movegen;
+use crate::search::{self, SearchLimits};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// A C API, for embedding the engine in a GUI without spawning a process and
+// talking UCI to it. include/yobmef.h declares everything here, keep the two in
+// sync. (test_header_declares_everything checks the prototypes and YobmefLimits)
+//
+//     YobmefEngine *engine = yobmef_engine_new();
+//     yobmef_set_position(engine, NULL, "e2e4 e7e5");
+//     YobmefLimits limits = {.movetime_ms = 1000};
+//     if (yobmef_search(engine, &limits) == 0)
+//         printf("%s %d\n", yobmef_bestmove(engine), yobmef_eval(engine));
+//     yobmef_engine_free(engine);
+//
+// Functions that can fail return 0 on success and -1 on failure, with the reason
+// in yobmef_last_error. Strings we return stay valid until the next call on the
+// same engine.
+//
+// Every pointer we take must be NULL or valid (engines from yobmef_engine_new,
+// NUL-terminated strings), like any C library.
+
+pub struct YobmefEngine {
+    engine: Engine,
+    // The engine's stop flag, so yobmef_stop never has to touch the engine
+    // while yobmef_search is using it on another thread.
+    stop: Arc<AtomicBool>,
+    bestmove: Option<CString>,
+    score: Option<i16>,
+    error: CString,
+}
+
+// Zero means no limit, nothing set thinks as if playing a 10 minute game.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YobmefLimits {
+    pub depth: i32,
+    pub nodes: u64,
+    pub movetime_ms: u64,
+    pub wtime_ms: u64,
+    pub btime_ms: u64,
+    pub winc_ms: u64,
+    pub binc_ms: u64,
+    pub movestogo: u32,
+}
+
+impl From<&YobmefLimits> for SearchLimits {
+    fn from(limits: &YobmefLimits) -> SearchLimits {
+        let ms = |ms: u64| Some(Duration::from_millis(ms)).filter(|_| ms > 0);
+        SearchLimits {
+            depth: Some(limits.depth.min(search::MAX_PLY as i32 / 2) as i16).filter(|&d| d > 0),
+            nodes: Some(limits.nodes).filter(|&n| n > 0),
+            movetime: ms(limits.movetime_ms),
+            wtime: ms(limits.wtime_ms),
+            btime: ms(limits.btime_ms),
+            winc: ms(limits.winc_ms),
+            binc: ms(limits.binc_ms),
+            movestogo: Some(limits.movestogo.min(u8::MAX as u32) as u8).filter(|&n| n > 0),
+            ..SearchLimits::default()
+        }
+    }
+}
+
+impl YobmefEngine {
+    fn fail(&mut self, msg: String) -> c_int {
+        // Our messages never have a NUL in them, but what we quote might.
+        self.error = CString::new(msg.replace('\0', "")).unwrap();
+        -1
+    }
+}
+
+// A string from C, None for NULL or invalid UTF-8.
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+// Returns NULL only if the engine couldn't be created.
+#[no_mangle]
+pub extern "C" fn yobmef_engine_new() -> *mut YobmefEngine {
+    movegen::gen_moves_once();
+    let mut engine = Engine::new();
+    let stop = engine.searcher().stop_flag();
+    Box::into_raw(Box::new(YobmefEngine {
+        engine,
+        stop,
+        bestmove: None,
+        score: None,
+        error: CString::default(),
+    }))
+}
+
+// Waits for a running search to finish, so stop it first.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_engine_free(engine: *mut YobmefEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+// Set a UCI option (see the UCI engine's option list), value is NULL for buttons.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_set_option(
+    engine: *mut YobmefEngine,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let engine = match engine.as_mut() {
+        Some(engine) => engine,
+        None => return -1,
+    };
+    let name = match str_arg(name) {
+        Some(name) => name,
+        None => return engine.fail("option name must be UTF-8".into()),
+    };
+    match engine.engine.set_option(name, str_arg(value)) {
+        Ok(()) => 0,
+        Err(e) => engine.fail(e),
+    }
+}
+
+// Set the position to `fen` (NULL for the start position) with `moves` played
+// from it, in UCI notation separated by spaces (NULL for none). On an illegal
+// move the position is left after the last legal one.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_set_position(
+    engine: *mut YobmefEngine,
+    fen: *const c_char,
+    moves: *const c_char,
+) -> c_int {
+    let engine = match engine.as_mut() {
+        Some(engine) => engine,
+        None => return -1,
+    };
+
+    let board = if fen.is_null() {
+        Board::from_start_pos()
+    } else {
+        match str_arg(fen).and_then(Board::from_fen) {
+            Some(board) => board,
+            None => return engine.fail("invalid fen".into()),
+        }
+    };
+
+    let mut parsed = Vec::new();
+    if !moves.is_null() {
+        let moves = match str_arg(moves) {
+            Some(moves) => moves,
+            None => return engine.fail("moves must be UTF-8".into()),
+        };
+        for mv in moves.split_whitespace() {
+            match Movement::from_notation(mv) {
+                Some(mv) => parsed.push(mv),
+                None => return engine.fail(format!("invalid move {}", mv)),
+            }
+        }
+    }
+
+    match engine.engine.set_position(board, &parsed) {
+        Ok(()) => 0,
+        Err(e) => engine.fail(e),
+    }
+}
+
+// Search the position until a limit is hit or yobmef_stop is called (from
+// another thread), blocking until then. `limits` can be NULL. Never plays from
+// the book, so there's always a score for yobmef_eval.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_search(
+    engine: *mut YobmefEngine,
+    limits: *const YobmefLimits,
+) -> c_int {
+    if engine.is_null() {
+        return -1;
+    }
+    let limits = limits
+        .as_ref()
+        .map_or_else(SearchLimits::default, SearchLimits::from);
+
+    // No reference to the engine is held while searching, yobmef_stop could
+    // come in at any time.
+    let search = (*engine).engine.search(limits);
+    let mut score = None;
+    let mut bestmove = None;
+    for event in search {
+        match event {
+            SearchEvent::Info(info) => score = info.score.or(score),
+            SearchEvent::BestMove(mv, _) => bestmove = mv,
+        }
+    }
+
+    let engine = &mut *engine;
+    engine.bestmove = bestmove.map(|mv| CString::new(mv.to_notation()).unwrap());
+    engine.score = score;
+    0
+}
+
+// Stop a search running on another thread, it returns as soon as it can.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_stop(engine: *const YobmefEngine) {
+    if let Some(engine) = engine.as_ref() {
+        engine.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// Best move of the last search in UCI notation, NULL if there were no legal
+// moves or nothing has been searched yet.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_bestmove(engine: *const YobmefEngine) -> *const c_char {
+    match engine.as_ref().and_then(|e| e.bestmove.as_ref()) {
+        Some(mv) => mv.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+// Score of the last search in centipawns, from the side to move's point of
+// view. Mates score near +-10000, see yobmef_mate_in.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_eval(engine: *const YobmefEngine) -> i32 {
+    engine.as_ref().and_then(|e| e.score).unwrap_or(0) as i32
+}
+
+// Moves until mate if the last search found one, negative if we're getting
+// mated, 0 if not.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_mate_in(engine: *const YobmefEngine) -> i32 {
+    engine
+        .as_ref()
+        .and_then(|e| e.score)
+        .and_then(search::mate_in)
+        .unwrap_or(0) as i32
+}
+
+// Why the last failing call failed, empty if nothing has yet.
+#[no_mangle]
+pub unsafe extern "C" fn yobmef_last_error(engine: *const YobmefEngine) -> *const c_char {
+    match engine.as_ref() {
+        Some(engine) => engine.error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn bestmove(engine: *const YobmefEngine) -> Option<String> {
+        let mv = yobmef_bestmove(engine);
+        if mv.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(mv).to_str().unwrap().to_string())
+    }
+
+    unsafe fn last_error(engine: *const YobmefEngine) -> String {
+        CStr::from_ptr(yobmef_last_error(engine))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_search() {
+        unsafe {
+            let engine = yobmef_engine_new();
+            assert_eq!(bestmove(engine), None);
+
+            let fen = c("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+            assert_eq!(yobmef_set_position(engine, fen.as_ptr(), ptr::null()), 0);
+            let limits = YobmefLimits {
+                depth: 3,
+                ..YobmefLimits::default()
+            };
+            assert_eq!(yobmef_search(engine, &limits), 0);
+            assert_eq!(bestmove(engine).as_deref(), Some("a1a8"));
+            assert_eq!(yobmef_mate_in(engine), 1);
+            assert!(yobmef_eval(engine) > 9000);
+
+            yobmef_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        unsafe {
+            let engine = yobmef_engine_new();
+            let fen = c("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+            assert_eq!(yobmef_set_position(engine, fen.as_ptr(), ptr::null()), 0);
+            assert_eq!(yobmef_search(engine, ptr::null()), 0);
+            assert_eq!(bestmove(engine), None);
+            yobmef_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            let engine = yobmef_engine_new();
+            assert_eq!(last_error(engine), "");
+
+            let fen = c("not a fen");
+            assert_eq!(yobmef_set_position(engine, fen.as_ptr(), ptr::null()), -1);
+            assert_eq!(last_error(engine), "invalid fen");
+
+            let moves = c("e2e4 e2e4");
+            assert_eq!(yobmef_set_position(engine, ptr::null(), moves.as_ptr()), -1);
+            assert!(last_error(engine).starts_with("illegal move e2e4"));
+
+            let (name, value) = (c("Hash"), c("lots"));
+            assert_eq!(yobmef_set_option(engine, name.as_ptr(), value.as_ptr()), -1);
+            let value = c("16");
+            assert_eq!(yobmef_set_option(engine, name.as_ptr(), value.as_ptr()), 0);
+
+            yobmef_engine_free(engine);
+
+            // NULL engines don't crash.
+            assert_eq!(yobmef_search(ptr::null_mut(), ptr::null()), -1);
+            assert!(yobmef_bestmove(ptr::null()).is_null());
+            yobmef_stop(ptr::null());
+            yobmef_engine_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        // Raw pointers aren't Send, the address is.
+        let engine = yobmef_engine_new() as usize;
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            unsafe { yobmef_stop(engine as *const YobmefEngine) };
+        });
+
+        let limits = YobmefLimits {
+            movetime_ms: 60_000,
+            ..YobmefLimits::default()
+        };
+        unsafe {
+            let engine = engine as *mut YobmefEngine;
+            assert_eq!(yobmef_search(engine, &limits), 0);
+            assert!(bestmove(engine).is_some());
+            stopper.join().unwrap();
+            yobmef_engine_free(engine);
+        }
+    }
+
+    // The C spelling of a type in the signatures here.
+    fn c_type(rust: &str) -> String {
+        if let Some(pointee) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(pointee));
+        }
+        if let Some(pointee) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(pointee));
+        }
+        match rust {
+            "" => "void",
+            "c_int" => "int",
+            "c_char" => "char",
+            "i32" => "int32_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "YobmefEngine" | "YobmefLimits" => rust,
+            _ => panic!("no C type for {}", rust),
+        }
+        .to_string()
+    }
+
+    // Whitespace collapsed and stars against the name, like yobmef.h writes them.
+    fn normalize(c: &str) -> String {
+        c.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace("* ", "*")
+    }
+
+    // The C prototype of `name(params) -> ret`, as yobmef.h should declare it.
+    fn c_prototype(signature: &str) -> String {
+        let (name, rest) = signature.split_once('(').unwrap();
+        let (params, ret) = rest.rsplit_once(')').unwrap();
+        let ret = ret.trim().trim_start_matches("->").trim();
+        let params: Vec<String> = params
+            .split(',')
+            .filter(|param| !param.trim().is_empty())
+            .map(|param| {
+                let (name, ty) = param.split_once(':').unwrap();
+                format!("{} {}", c_type(ty.trim()), name.trim())
+            })
+            .collect();
+        let params = match params.is_empty() {
+            true => "void".to_string(),
+            false => params.join(", "),
+        };
+        normalize(&format!("{} {}({});", c_type(ret), name, params))
+    }
+
+    // yobmef.h without its comments and preprocessor lines.
+    fn header_code() -> String {
+        let mut header = include_str!("../include/yobmef.h");
+        let mut code = String::new();
+        while let Some(start) = header.find("/*") {
+            code.push_str(&header[..start]);
+            let end = header[start..].find("*/").unwrap();
+            header = &header[start + end + 2..];
+        }
+        code.push_str(header);
+        code.lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_header_declares_everything() {
+        let source = include_str!("ffi.rs");
+        let mut prototypes: Vec<String> = source
+            .split("extern \"C\" fn ")
+            .skip(1)
+            .map(|rest| c_prototype(rest.split('{').next().unwrap()))
+            .collect();
+        let code = header_code();
+        let mut declared: Vec<String> = code
+            .split_inclusive(';')
+            .filter(|decl| decl.contains('('))
+            .map(normalize)
+            .collect();
+        prototypes.sort();
+        declared.sort();
+        assert_eq!(prototypes, declared);
+
+        // The limits are passed by pointer, so their layouts have to match too.
+        let fields = |code: &str, start: &str| {
+            let body = code.split(start).nth(1).unwrap().split('}').next().unwrap();
+            body.to_string()
+        };
+        let rust_fields: Vec<String> = fields(source, "pub struct YobmefLimits {")
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("pub "))
+            .map(|field| {
+                let (name, ty) = field.trim_end_matches(',').split_once(':').unwrap();
+                format!("{} {};", c_type(ty.trim()), name)
+            })
+            .collect();
+        let c_fields: Vec<String> = fields(&code, "typedef struct YobmefLimits {")
+            .split_inclusive(';')
+            .map(normalize)
+            .filter(|field| !field.is_empty())
+            .collect();
+        assert_eq!(rust_fields, c_fields);
+    }
+}
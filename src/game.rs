@@ -0,0 +1,654 @@
+use crate::chess::{Board, CastlingSide, Color, Movement, Piece, Variant};
+use crate::movegen::{self, MoveGen};
+use crate::zobrist;
+use std::fmt;
+
+// A game from some start position: the moves played, how it ended, and reading
+// and writing it as PGN. PGN wants moves in SAN (Nf3, exd5, O-O) rather than
+// the UCI notation we use everywhere else (g1f3).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(color: Color) -> Self {
+        match color {
+            Color::White => GameResult::WhiteWins,
+            Color::Black => GameResult::BlackWins,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        })
+    }
+}
+
+// Is the game over by the rules? `keys` are the zobrist keys of every position
+// so far, the current one last.
+pub fn outcome(board: &Board, keys: &[u64]) -> Option<(GameResult, &'static str)> {
+    if let Some((winner, reason)) = board.variant.winner(board) {
+        return Some(match winner {
+            Color::White => (GameResult::WhiteWins, reason),
+            Color::Black => (GameResult::BlackWins, reason),
+        });
+    }
+    if !movegen::has_legal_move(board) {
+        return Some(if board.in_check() {
+            match board.side_to_move {
+                Color::White => (GameResult::BlackWins, "Black mates"),
+                Color::Black => (GameResult::WhiteWins, "White mates"),
+            }
+        } else {
+            (GameResult::Draw, "Stalemate")
+        });
+    }
+    if board.halfmove_clock >= 100 {
+        return Some((GameResult::Draw, "Fifty move rule"));
+    }
+    if let Some(key) = keys.last() {
+        if keys.iter().filter(|k| *k == key).count() >= 3 {
+            return Some((GameResult::Draw, "Threefold repetition"));
+        }
+    }
+    // A lone minor piece can still give three checks.
+    if board.variant == Variant::Standard && insufficient_material(board) {
+        return Some((GameResult::Draw, "Insufficient material"));
+    }
+    None
+}
+
+// Kings and at most one minor piece, nobody can mate.
+fn insufficient_material(board: &Board) -> bool {
+    let heavy =
+        *board.pieces(Piece::Pawn) | *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen);
+    let minors = *board.pieces(Piece::Knight) | *board.pieces(Piece::Bishop);
+    heavy.0 == 0 && minors.0.count_ones() <= 1
+}
+
+// Standard algebraic notation for a legal move.
+pub fn san(board: &Board, mv: &Movement) -> String {
+    let mut s = String::new();
+    let piece = board.piece_on(mv.from_square).expect("no piece to move");
+
+    match board.castling_side(mv) {
+        Some(side)
+            if side == CastlingSide::WhiteKingside || side == CastlingSide::BlackKingside =>
+        {
+            s.push_str("O-O")
+        }
+        Some(_) => s.push_str("O-O-O"),
+        None => {
+            // Pawns capture diagonally, that covers en passant too.
+            let capture = board.is_capture(mv)
+                || (piece == Piece::Pawn && mv.from_square.file() != mv.to_square.file());
+            let from = mv.from_square.to_notation();
+
+            if piece == Piece::Pawn {
+                if capture {
+                    s.push_str(&from[..1]);
+                }
+            } else {
+                s.push(piece.as_char().to_ascii_uppercase());
+
+                // Another piece of ours could go there too, say which one we mean.
+                let others: Vec<Movement> = MoveGen::new_legal(board)
+                    .filter(|other| {
+                        other.to_square == mv.to_square
+                            && other.from_square != mv.from_square
+                            && board.piece_on(other.from_square) == Some(piece)
+                    })
+                    .collect();
+                if !others.is_empty() {
+                    let same_file = others
+                        .iter()
+                        .any(|o| o.from_square.file() == mv.from_square.file());
+                    let same_rank = others
+                        .iter()
+                        .any(|o| o.from_square.rank() == mv.from_square.rank());
+                    if !same_file {
+                        s.push_str(&from[..1]);
+                    } else if !same_rank {
+                        s.push_str(&from[1..]);
+                    } else {
+                        s.push_str(&from);
+                    }
+                }
+            }
+
+            if capture {
+                s.push('x');
+            }
+            s.push_str(&mv.to_square.to_notation());
+            if let Some(promote) = mv.promote {
+                s.push('=');
+                s.push(promote.as_char().to_ascii_uppercase());
+            }
+        }
+    }
+
+    let after = board.make_move(mv);
+    if after.in_check() {
+        if !movegen::has_legal_move(&after) {
+            s.push('#');
+        } else {
+            s.push('+');
+        }
+    }
+    s
+}
+
+// The legal move written as `s` in SAN. Check, mate and annotation suffixes
+// (+ # ! ?) are optional, and castling can be written with zeros.
+pub fn parse_san(board: &Board, s: &str) -> Option<Movement> {
+    let bare = |s: &str| s.trim_end_matches(|c| "+#!?".contains(c)).replace('0', "O");
+    let want = bare(s);
+    MoveGen::new_legal(board).find(|mv| bare(&san(board, mv)) == want)
+}
+
+// What a move is worth, written after it as a NAG. ($2 is shown as ?, etc.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nag {
+    Good = 1,
+    Mistake = 2,
+    Brilliant = 3,
+    Blunder = 4,
+    Interesting = 5,
+    Dubious = 6,
+}
+
+// Every game in a PGN file, mainline only. Comments, variations and NAGs are
+// skipped, games are played through from their FEN tag if they have one.
+pub fn parse_pgn(pgn: &str) -> Result<Vec<Game>, String> {
+    let mut games = Vec::new();
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let mut game: Option<Game> = None;
+    let mut depth = 0; // of variations, we only play moves at 0
+    let mut chars = pgn.chars().peekable();
+
+    // Starts the game on its first move, once we have all the tags.
+    fn start(tags: &[(String, String)]) -> Result<Game, String> {
+        let board = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Board::from_fen(fen).ok_or(format!("bad fen {}", fen))?,
+            None => Board::from_start_pos(),
+        };
+        let mut game = Game::new(board);
+        for (name, value) in tags {
+            if name != "FEN" && name != "SetUp" && name != "Result" {
+                game.set_tag(name, value);
+            }
+        }
+        Ok(game)
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if depth == 0 => {
+                if let Some(game) = game.take() {
+                    games.push(game);
+                    tags.clear();
+                }
+                let tag: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                let tag = tag.trim_end().trim_end_matches(']');
+                let (name, value) = tag.split_once(' ').ok_or(format!("bad tag [{}", tag))?;
+                let value = value.trim();
+                let value = value.strip_prefix('"').unwrap_or(value);
+                let value = value.strip_suffix('"').unwrap_or(value);
+                tags.push((
+                    name.to_string(),
+                    value.replace("\\\"", "\"").replace("\\\\", "\\"),
+                ));
+            }
+            '{' => while chars.next().is_some_and(|c| c != '}') {},
+            ';' => while chars.next().is_some_and(|c| c != '\n') {},
+            '(' => depth += 1,
+            ')' => depth = usize::saturating_sub(depth, 1),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}();[".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+
+                let result = match token.as_str() {
+                    "1-0" => Some(Some(GameResult::WhiteWins)),
+                    "0-1" => Some(Some(GameResult::BlackWins)),
+                    "1/2-1/2" => Some(Some(GameResult::Draw)),
+                    "*" => Some(None),
+                    _ => None,
+                };
+                if let Some(result) = result {
+                    let mut finished = match game.take() {
+                        Some(game) => game,
+                        None => start(&tags)?,
+                    };
+                    if let (Some(result), false) = (result, finished.is_over()) {
+                        finished.end(result, "");
+                    }
+                    games.push(finished);
+                    tags.clear();
+                    continue;
+                }
+
+                // Move numbers, possibly stuck to the move. (1.e4)
+                let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if san.is_empty() {
+                    continue;
+                }
+                if game.is_none() {
+                    game = Some(start(&tags)?);
+                }
+                let game = game.as_mut().unwrap();
+                let mv = parse_san(game.board(), san).ok_or(format!(
+                    "illegal move {} in {}",
+                    san,
+                    game.board().to_fen()
+                ))?;
+                game.play(mv, None);
+            }
+        }
+    }
+    if let Some(game) = game {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    // Besides the seven tag roster, which always gets written.
+    pub tags: Vec<(String, String)>,
+    start: Board,
+    board: Board,
+    keys: Vec<u64>,
+    moves: Vec<Movement>,
+    comments: Vec<Option<String>>,
+    nags: Vec<Option<Nag>>,
+    // Alternatives to each move, from the position before it, with a comment
+    // for the first move.
+    variations: Vec<Option<(Vec<Movement>, Option<String>)>>,
+    result: Option<(GameResult, String)>, // result, why (can be empty)
+}
+
+impl Game {
+    pub fn new(start: Board) -> Game {
+        Game {
+            tags: Vec::new(),
+            keys: vec![zobrist::hash(&start)],
+            board: start.clone(),
+            start,
+            moves: Vec::new(),
+            comments: Vec::new(),
+            nags: Vec::new(),
+            variations: Vec::new(),
+            result: None,
+        }
+    }
+
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    // The current position.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn moves(&self) -> &[Movement] {
+        &self.moves
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result.as_ref().map(|(result, _)| *result)
+    }
+
+    pub fn termination(&self) -> Option<&str> {
+        self.result.as_ref().map(|(_, why)| why.as_str())
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        self.tags.retain(|(n, _)| n != name);
+        self.tags.push((name.to_string(), value.to_string()));
+    }
+
+    // Play a legal move, ending the game if that's mate, a draw by the rules etc.
+    // The comment goes after the move in the PGN.
+    pub fn play(&mut self, mv: Movement, comment: Option<String>) {
+        self.board.make_move_mut(&mv);
+        self.keys.push(zobrist::hash(&self.board));
+        self.moves.push(mv);
+        self.comments.push(comment);
+        self.nags.push(None);
+        self.variations.push(None);
+
+        if let Some((result, why)) = outcome(&self.board, &self.keys) {
+            self.end(result, why);
+        }
+    }
+
+    // Annotations for the move at `ply`, counting from 0.
+    pub fn set_comment(&mut self, ply: usize, comment: Option<String>) {
+        self.comments[ply] = comment;
+    }
+
+    pub fn set_nag(&mut self, ply: usize, nag: Option<Nag>) {
+        self.nags[ply] = nag;
+    }
+
+    // `moves` are played instead of the move at `ply`, `comment` goes after the
+    // first one.
+    pub fn set_variation(&mut self, ply: usize, moves: Vec<Movement>, comment: Option<String>) {
+        self.variations[ply] = Some((moves, comment)).filter(|(moves, _)| !moves.is_empty());
+    }
+
+    // End the game for reasons outside the rules, time forfeit, adjudication etc.
+    pub fn end(&mut self, result: GameResult, why: &str) {
+        self.result = Some((result, why.to_string()));
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let result = self.result().map_or("*".to_string(), |r| r.to_string());
+
+        let mut pgn = String::new();
+        let roster = ["Event", "Site", "Date", "Round", "White", "Black"];
+        for name in roster.iter() {
+            let value = self.tag(name).unwrap_or(match *name {
+                "Date" => "????.??.??",
+                _ => "?",
+            });
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+        }
+        pgn.push_str(&format!("[Result \"{}\"]\n", result));
+        if self.start != Board::from_start_pos() {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{}\"]\n", self.start.to_fen()));
+        }
+        for (name, value) in &self.tags {
+            if !roster.contains(&name.as_str()) && name != "Result" {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut board = self.start.clone();
+        let mut number = 1;
+        // Black's moves get a number too at the start and after variations.
+        let mut need_number = true;
+        for (i, mv) in self.moves.iter().enumerate() {
+            let first = tokens.len();
+            push_move(&mut tokens, &board, mv, number, need_number);
+            if let Some(nag) = self.nags[i] {
+                tokens.push(format!("${}", nag as u8));
+            }
+            if let Some(comment) = &self.comments[i] {
+                tokens.push(format!("{{{}}}", comment.replace('}', ")")));
+            }
+            need_number = false;
+
+            if let Some((moves, comment)) = &self.variations[i] {
+                let start = tokens.len();
+                let mut line = board.clone();
+                let mut line_number = number;
+                for (j, mv) in moves.iter().enumerate() {
+                    push_move(&mut tokens, &line, mv, line_number, j == 0);
+                    if let (0, Some(comment)) = (j, comment) {
+                        tokens.push(format!("{{{}}}", comment.replace('}', ")")));
+                    }
+                    if line.side_to_move == Color::Black {
+                        line_number += 1;
+                    }
+                    line.make_move_mut(mv);
+                }
+                tokens[start].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+                need_number = true;
+            }
+            debug_assert!(tokens.len() > first);
+
+            if board.side_to_move == Color::Black {
+                number += 1;
+            }
+            board.make_move_mut(mv);
+        }
+        if let Some(why) = self.termination().filter(|why| !why.is_empty()) {
+            tokens.push(format!("{{{}}}", why.replace('}', ")")));
+        }
+        tokens.push(result);
+
+        // Lines should be at most 80 characters.
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+        pgn
+    }
+
+    fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// The move in SAN, after its number if white is moving or `number` is set.
+fn push_move(tokens: &mut Vec<String>, board: &Board, mv: &Movement, number: u16, force: bool) {
+    if board.side_to_move == Color::White {
+        tokens.push(format!("{}.", number));
+    } else if force {
+        tokens.push(format!("{}...", number));
+    }
+    tokens.push(san(board, mv));
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::gen_moves_once;
+
+    fn play(game: &mut Game, moves: &str) {
+        for mv in moves.split_whitespace() {
+            game.play(Movement::from_notation(mv).unwrap(), None);
+        }
+    }
+
+    fn san_of(fen: &str, mv: &str) -> String {
+        gen_moves_once();
+        let board = Board::from_fen(fen).unwrap();
+        san(&board, &Movement::from_notation(mv).unwrap())
+    }
+
+    #[test]
+    fn test_san() {
+        let start = crate::chess::STARTING_FEN;
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san_of(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san_of(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san_of(kiwipete, "e2a6"), "Bxa6");
+        assert_eq!(san_of(kiwipete, "f3f7"), "Qxf7+");
+
+        // En passant, promotion and mate.
+        assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+        assert_eq!(san_of("8/P6k/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), "a8=Q");
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+
+        // Disambiguation by file, rank, then both.
+        assert_eq!(san_of("4k3/8/8/8/8/4K3/8/R6R w - - 0 1", "a1d1"), "Rad1");
+        assert_eq!(san_of("4k3/8/R7/8/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+        assert_eq!(
+            san_of("k7/8/8/8/8/2Q1Q3/8/4Q1K1 w - - 0 1", "e3d2"),
+            "Qe3d2"
+        );
+    }
+
+    #[test]
+    fn test_outcome() {
+        gen_moves_once();
+        let mut game = Game::new(Board::from_start_pos());
+        play(&mut game, "f2f3 e7e5 g2g4");
+        assert!(!game.is_over());
+        play(&mut game, "d8h4");
+        assert_eq!(game.result(), Some(GameResult::BlackWins));
+        assert_eq!(game.termination(), Some("Black mates"));
+
+        let mut game = Game::new(Board::from_start_pos());
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1");
+        assert!(!game.is_over());
+        play(&mut game, "f6g8");
+        assert_eq!(game.termination(), Some("Threefold repetition"));
+
+        let mut game = Game::new(Board::from_fen("4k3/8/8/8/8/8/4r3/4KB2 w - - 0 1").unwrap());
+        play(&mut game, "e1e2");
+        assert_eq!(game.result(), Some(GameResult::Draw));
+        assert_eq!(game.termination(), Some("Insufficient material"));
+
+        let board = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(outcome(&board, &[]), Some((GameResult::Draw, "Stalemate")));
+
+        let board = Board::from_fen("4k3/8/8/5N2/8/8/8/4K3 w - - 1+3 0 1").unwrap();
+        let mut game = Game::new(board);
+        assert!(!game.is_over());
+        play(&mut game, "f5d6");
+        assert_eq!(game.result(), Some(GameResult::WhiteWins));
+        assert_eq!(game.termination(), Some("Three checks"));
+    }
+
+    #[test]
+    fn test_to_pgn() {
+        gen_moves_once();
+        let mut game = Game::new(Board::from_start_pos());
+        game.set_tag("White", "Yobmef");
+        game.set_tag("TimeControl", "10+0.1");
+        game.play(
+            Movement::from_notation("f2f3").unwrap(),
+            Some("+0.10/5".into()),
+        );
+        play(&mut game, "e7e5 g2g4 d8h4");
+
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"Yobmef\"]\n[Black \"?\"]\n[Result \"0-1\"]\n[TimeControl \"10+0.1\"]\n\n\
+             1. f3 {+0.10/5} e5 2. g4 Qh4# {Black mates} 0-1\n\n"
+        );
+
+        // Black to move first, with the position in the tags.
+        let board = Board::from_fen("4k3/8/8/8/8/8/4r3/4K3 b - - 0 1").unwrap();
+        let mut game = Game::new(board);
+        play(&mut game, "e2a2");
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4r3/4K3 b - - 0 1\"]\n"));
+        assert!(pgn.ends_with("\n1... Ra2 *\n\n"), "{}", pgn);
+    }
+
+    #[test]
+    fn test_parse_san() {
+        gen_moves_once();
+        let kiwipete =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let parse = |san| parse_san(&kiwipete, san).map(|mv| mv.to_notation());
+        assert_eq!(parse("O-O").as_deref(), Some("e1g1"));
+        assert_eq!(parse("0-0-0").as_deref(), Some("e1c1"));
+        assert_eq!(parse("Qxf6").as_deref(), Some("f3f6"));
+        assert_eq!(parse("dxe6!?").as_deref(), Some("d5e6"));
+        assert_eq!(parse("Nxf7+").as_deref(), Some("e5f7"));
+        assert_eq!(parse("Qxf7"), None);
+        assert_eq!(parse("Nc3"), None);
+    }
+
+    #[test]
+    fn test_parse_pgn() {
+        gen_moves_once();
+        let pgn = "[Event \"Casual \\\"game\\\"\"]\n[White \"Me\"]\n[Result \"0-1\"]\n\n\
+                   1. f3 {weak} e5 (1... d5 2. e4) 2.g4?? $4 ; yikes\n Qh4# 0-1\n\n\
+                   [FEN \"4k3/8/8/8/8/8/4r3/4K3 b - - 0 1\"]\n\n1... Ra2 *\n";
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let moves: Vec<String> = games[0].moves().iter().map(|m| m.to_notation()).collect();
+        assert_eq!(moves, vec!["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(games[0].result(), Some(GameResult::BlackWins));
+        assert_eq!(games[0].tag("Event"), Some("Casual \"game\""));
+        assert_eq!(games[0].tag("White"), Some("Me"));
+
+        assert_eq!(games[1].start().to_fen(), "4k3/8/8/8/8/8/4r3/4K3 b - - 0 1");
+        assert_eq!(games[1].moves().len(), 1);
+        assert_eq!(games[1].result(), None);
+        assert_eq!(games[1].tag("FEN"), None);
+
+        // Read back what we write.
+        let again = parse_pgn(&games[1].to_pgn()).unwrap();
+        assert_eq!(again[0].start(), games[1].start());
+        assert_eq!(again[0].moves(), games[1].moves());
+
+        assert!(parse_pgn("1. e4 e5 2. Ke3 *").is_err());
+    }
+
+    #[test]
+    fn test_to_pgn_annotations() {
+        gen_moves_once();
+        let mut game = Game::new(Board::from_start_pos());
+        play(&mut game, "e2e4 e7e5 d1h5 b8c6 f1c4 g8f6 h5f7");
+        game.set_nag(5, Some(Nag::Blunder));
+        game.set_comment(5, Some("+M1".into()));
+        let better = ["g7g6", "h5f3"].iter();
+        game.set_variation(
+            5,
+            better
+                .map(|mv| Movement::from_notation(mv).unwrap())
+                .collect(),
+            Some("+0.10".into()),
+        );
+
+        let pgn = game.to_pgn();
+        let moves = pgn.lines().last().unwrap_or("");
+        assert!(
+            pgn.ends_with(
+                "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 $4 {+M1} (3... g6 {+0.10} 4. Qf3) 4. Qxf7#\n\
+             {White mates} 1-0\n\n"
+            ),
+            "{}",
+            moves
+        );
+    }
+}
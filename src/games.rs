@@ -0,0 +1,262 @@
+use crate::chess::{Board, Movement};
+use crate::engine::{Engine, Search, SearchEvent};
+use crate::search::SearchLimits;
+use crate::tt::TranspositionTable;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// Several games at once, for a bot that takes on more than one opponent. Each
+// game has an Engine of its own, so its own board, history, searcher and search
+// thread, and the clock is whatever its go gets. Nothing is shared between
+// engines but the move tables, which never change after gen_moves_once.
+//
+//     let mut games = Games::new(4);
+//     games.start("abc123", Board::from_start_pos(), &[])?;
+//     games.go("abc123", limits)?;
+//     loop {
+//         for (id, event) in games.poll() { ... }
+//     }
+//
+// Every game gets its own hash by default. With_shared_hash gives them one
+// between them instead, for when there isn't the memory for one each: games
+// crowd each other's entries out, but the ones that share positions (the same
+// opening) help each other.
+
+pub struct Games {
+    // Ordered, so poll hands out events in the same order every time.
+    games: BTreeMap<String, Running>,
+    max_games: usize,
+    // Set on every game, including ones started later.
+    options: Vec<(String, Option<String>)>,
+    shared_hash: Option<Arc<TranspositionTable>>,
+}
+
+struct Running {
+    engine: Engine,
+    search: Option<Search>,
+}
+
+impl Games {
+    pub fn new(max_games: usize) -> Games {
+        Games {
+            games: BTreeMap::new(),
+            max_games,
+            options: Vec::new(),
+            shared_hash: None,
+        }
+    }
+
+    pub fn with_shared_hash(max_games: usize, hash_mb: usize) -> Games {
+        Games {
+            shared_hash: Some(Arc::new(TranspositionTable::new(hash_mb))),
+            ..Games::new(max_games)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+
+    // No room for another game, decline the challenge.
+    pub fn is_full(&self) -> bool {
+        self.games.len() >= self.max_games
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.games.keys().map(String::as_str)
+    }
+
+    // Set a UCI option on every game, now and from now on. Threads is per game,
+    // so 4 games with Threads 2 search on 8. A shared hash can't be resized.
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if self.shared_hash.is_some() && name.eq_ignore_ascii_case("Hash") {
+            return Err("the hash is shared by all games, its size is fixed".to_string());
+        }
+        for running in self.games.values_mut() {
+            running.engine.set_option(name, value)?;
+        }
+        self.options
+            .push((name.to_string(), value.map(String::from)));
+        Ok(())
+    }
+
+    // A new game `id`, at the position after `moves` from `board`.
+    pub fn start(&mut self, id: &str, board: Board, moves: &[Movement]) -> Result<(), String> {
+        if self.games.contains_key(id) {
+            return Err(format!("already playing game {}", id));
+        }
+        if self.is_full() {
+            return Err(format!("already playing {} games", self.max_games));
+        }
+
+        let mut engine = Engine::new();
+        for (name, value) in &self.options {
+            engine.set_option(name, value.as_deref())?;
+        }
+        if let Some(tt) = &self.shared_hash {
+            engine.searcher().tp = tt.clone();
+        }
+        engine.set_position(board, moves)?;
+        self.games.insert(
+            id.to_string(),
+            Running {
+                engine,
+                search: None,
+            },
+        );
+        Ok(())
+    }
+
+    // The engine playing `id`, for its position, draw offers and resigning.
+    pub fn engine(&mut self, id: &str) -> Option<&mut Engine> {
+        Some(&mut self.games.get_mut(id)?.engine)
+    }
+
+    pub fn set_position(
+        &mut self,
+        id: &str,
+        board: Board,
+        moves: &[Movement],
+    ) -> Result<(), String> {
+        self.running(id)?.engine.set_position(board, moves)
+    }
+
+    // Start searching game `id`, its events come out of poll. One search per
+    // game at a time.
+    pub fn go(&mut self, id: &str, limits: SearchLimits) -> Result<(), String> {
+        let running = self.running(id)?;
+        if running.search.is_some() {
+            return Err(format!("already searching game {}", id));
+        }
+        running.search = Some(running.engine.go(limits));
+        Ok(())
+    }
+
+    pub fn is_searching(&self, id: &str) -> bool {
+        self.games.get(id).is_some_and(|r| r.search.is_some())
+    }
+
+    // Every event the searches have sent since the last poll, without waiting
+    // for more. A game's search is over with its best move.
+    pub fn poll(&mut self) -> Vec<(String, SearchEvent)> {
+        let mut events = Vec::new();
+        for (id, running) in &mut self.games {
+            while let Some(search) = &mut running.search {
+                let event = match search.try_next() {
+                    Some(event) => event,
+                    None => break,
+                };
+                if let SearchEvent::BestMove(..) = event {
+                    running.search = None;
+                }
+                events.push((id.clone(), event));
+            }
+        }
+        events
+    }
+
+    // Stop searching game `id`, the best move so far comes out of poll.
+    pub fn stop(&self, id: &str) {
+        if let Some(running) = self.games.get(id) {
+            running.engine.stop();
+        }
+    }
+
+    // Game `id` is over. Its search is stopped and waited for, without sending
+    // its best move.
+    pub fn end(&mut self, id: &str) -> bool {
+        match self.games.remove(id) {
+            Some(mut running) => {
+                running.engine.stop();
+                running.engine.searcher();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn running(&mut self, id: &str) -> Result<&mut Running, String> {
+        self.games
+            .get_mut(id)
+            .ok_or_else(|| format!("no game {}", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::gen_moves_once;
+    use std::thread;
+    use std::time::Duration;
+
+    // Poll until every search is over, the best move of each game.
+    fn best_moves(games: &mut Games) -> BTreeMap<String, Option<Movement>> {
+        let mut best = BTreeMap::new();
+        while games.ids().any(|id| games.is_searching(id)) {
+            for (id, event) in games.poll() {
+                if let SearchEvent::BestMove(mv, _) = event {
+                    best.insert(id, mv);
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        best
+    }
+
+    #[test]
+    fn test_games() {
+        gen_moves_once();
+        let mut games = Games::new(2);
+        games.set_option("Hash", Some("1")).unwrap();
+        let white_mates = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let black_mates = Board::from_fen("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        games.start("a", white_mates.clone(), &[]).unwrap();
+        games.start("b", black_mates, &[]).unwrap();
+
+        assert!(games.is_full());
+        assert!(games.start("c", white_mates.clone(), &[]).is_err());
+        assert!(games.start("a", white_mates, &[]).is_err());
+        assert!(games.go("c", SearchLimits::depth(1)).is_err());
+
+        // Both think at once, each about its own position.
+        games.go("a", SearchLimits::depth(4)).unwrap();
+        games.go("b", SearchLimits::depth(4)).unwrap();
+        assert!(games.go("a", SearchLimits::depth(4)).is_err());
+        let best = best_moves(&mut games);
+        assert_eq!(best["a"], Movement::from_notation("a1a8"));
+        assert_eq!(best["b"], Movement::from_notation("a8a1"));
+
+        // The other game plays on without it.
+        games.go("a", SearchLimits::infinite()).unwrap();
+        assert!(games.end("a"));
+        assert!(!games.end("a"));
+        assert!(!games.is_full());
+        assert_eq!(games.ids().collect::<Vec<_>>(), ["b"]);
+        let after = Movement::from_notation("a8a1").unwrap();
+        games
+            .set_position(
+                "b",
+                Board::from_fen("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap(),
+                &[after],
+            )
+            .unwrap();
+        games.go("b", SearchLimits::depth(2)).unwrap();
+        assert_eq!(best_moves(&mut games)["b"], None);
+    }
+
+    #[test]
+    fn test_shared_hash() {
+        gen_moves_once();
+        let mut games = Games::with_shared_hash(2, 1);
+        assert!(games.set_option("Hash", Some("8")).is_err());
+        games.start("a", Board::from_start_pos(), &[]).unwrap();
+        games.start("b", Board::from_start_pos(), &[]).unwrap();
+        let a = games.engine("a").unwrap().searcher().tp.clone();
+        let b = games.engine("b").unwrap().searcher().tp.clone();
+        assert!(Arc::ptr_eq(&a, &b));
+    }
+}
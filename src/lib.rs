@@ -1,8 +1,63 @@
+// Without the std feature only the core is built (board, movegen, eval), for
+// embedded targets and sandboxes that want the move generator and nothing else.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod adjudication;
 pub mod bitboard;
+#[cfg(feature = "std")]
+pub mod book;
 pub mod chess;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod decisions;
+#[cfg(feature = "std")]
 pub mod engine;
+#[cfg(feature = "std")]
+pub mod epd;
 pub mod eval;
+#[cfg(feature = "std")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod game;
+#[cfg(feature = "std")]
+pub mod games;
+#[cfg(feature = "std")]
+pub mod logger;
+#[cfg(feature = "std")]
+pub mod matches;
 pub mod movegen;
+#[cfg(feature = "std")]
+pub mod openings;
+#[cfg(feature = "std")]
+pub mod options;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod puzzle;
+#[cfg(feature = "std")]
+pub mod random;
+#[cfg(feature = "std")]
 pub mod search;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod strength;
+mod sync;
+#[cfg(feature = "std")]
+pub mod syzygy;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod tt;
+pub mod tune;
+#[cfg(feature = "std")]
 pub mod uci;
+#[cfg(feature = "std")]
+pub mod uci_engine;
 pub mod zobrist;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,231 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// A logger for the engine's diagnostics, which are `log` events: what the GUI
+// sent and how it parsed, every search iteration, move table setup and so on.
+// Only info and up by default, a bot or server gets the rest without a
+// rebuild:
+//
+//     YOBMEF_LOG_LEVEL=debug yobmef
+//     YOBMEF_LOG_LEVEL=warn,search=trace YOBMEF_LOG_FILE=engine.log yobmef --listen :4000
+//
+// The level is a comma separated list of `level` or `target=level`, where a
+// target is a module (uci, search, yobmef::engine) and the longest one that
+// matches wins. Lines look like the UCI transcript's:
+//
+//     [    1.042] DEBUG yobmef::search: depth 7 score 35 nodes 48211 pv e2e4 e7e5
+//
+// This isn't the transcript (YOBMEF_LOG), which is only the UCI lines in and
+// out. Anything else using the library can set up its own `log` logger.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    default: LevelFilter,
+    // Most specific first.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    pub fn new(default: LevelFilter) -> Filter {
+        Filter {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    // The most verbose level anything gets, so `log` can skip the rest.
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+
+    pub fn level(&self, target: &str) -> LevelFilter {
+        let short = target.strip_prefix("yobmef::").unwrap_or(target);
+        self.targets
+            .iter()
+            .find(|(t, _)| is_module(target, t) || is_module(short, t))
+            .map_or(self.default, |&(_, level)| level)
+    }
+}
+
+// `target` is `module` or inside it.
+fn is_module(target: &str, module: &str) -> bool {
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        let parse_level = |level: &str| {
+            LevelFilter::from_str(level.trim()).map_err(|_| format!("bad log level {}", level))
+        };
+
+        let mut filter = Filter::new(LevelFilter::Off);
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => filter
+                    .targets
+                    .push((target.trim().to_string(), parse_level(level)?)),
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        filter
+            .targets
+            .sort_by_key(|(target, _)| -(target.len() as isize));
+        Ok(filter)
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    out: Mutex<Box<dyn Write + Send>>,
+    start: Instant,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
+    }
+
+    // Best effort, a full disk shouldn't stop the engine.
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let t = self.start.elapsed();
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(
+                out,
+                "[{:5}.{:03}] {:5} {}: {}",
+                t.as_secs(),
+                t.subsec_millis(),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = out.flush();
+        }
+    }
+}
+
+// Log everything `filter` lets through to `out`. There's one logger per
+// process, so this fails if there already is one.
+pub fn init(filter: Filter, out: Box<dyn Write + Send>) -> Result<(), String> {
+    let max_level = filter.max_level();
+    let logger = Logger {
+        filter,
+        out: Mutex::new(out),
+        start: Instant::now(),
+    };
+    log::set_logger(Box::leak(Box::new(logger))).map_err(|e| e.to_string())?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+// YOBMEF_LOG_LEVEL and YOBMEF_LOG_FILE, see above. Without a file it's stderr,
+// which GUIs ignore, and the file is appended to so several runs can share one.
+pub fn init_from_env() -> Result<(), String> {
+    let filter = match std::env::var("YOBMEF_LOG_LEVEL") {
+        Ok(spec) => spec.parse()?,
+        Err(_) => Filter::new(LevelFilter::Info),
+    };
+    let out: Box<dyn Write + Send> = match std::env::var("YOBMEF_LOG_FILE") {
+        Ok(path) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("could not open log file {}: {}", path, e))?,
+        ),
+        Err(_) => Box::new(io::stderr()),
+    };
+    init(filter, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter: Filter = "warn, search=trace, search::qs=off, uci=debug"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.level("yobmef::engine"), LevelFilter::Warn);
+        assert_eq!(filter.level("yobmef::search"), LevelFilter::Trace);
+        assert_eq!(filter.level("search::qs"), LevelFilter::Off);
+        assert_eq!(filter.level("yobmef::uci"), LevelFilter::Debug);
+        // Not a module of search.
+        assert_eq!(filter.level("yobmef::searcher"), LevelFilter::Warn);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+        assert_eq!("".parse::<Filter>().unwrap().max_level(), LevelFilter::Off);
+        assert!("loud".parse::<Filter>().is_err());
+        assert!("search=loud".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn test_logger() {
+        let buffer = Buffer::default();
+        let logger = Logger {
+            filter: "info,yobmef::search=debug".parse().unwrap(),
+            out: Mutex::new(Box::new(buffer.clone())),
+            start: Instant::now(),
+        };
+        let log = |level, target, msg| {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target(target)
+                    .args(format_args!("{}", msg))
+                    .build(),
+            )
+        };
+        log(Level::Debug, "yobmef::search", "depth 1");
+        log(Level::Debug, "yobmef::uci", "dropped");
+        log(Level::Warn, "yobmef::uci", "bad option");
+
+        let out = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", out);
+        assert!(
+            lines[0].ends_with("] DEBUG yobmef::search: depth 1"),
+            "{}",
+            out
+        );
+        assert!(
+            lines[1].ends_with("] WARN  yobmef::uci: bad option"),
+            "{}",
+            out
+        );
+    }
+}
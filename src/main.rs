@@ -1,10 +1,26 @@
+use std::env;
+use std::process;
 use yobmef::*;
 
+const USAGE: &str = "usage: yobmef [--listen <address:port>]";
+
 fn main() {
+    if let Err(e) = logger::init_from_env() {
+        eprintln!("{}", e);
+    }
     movegen::gen_moves_once();
 
-    let mut engine = engine::Engine::new();
-    if let Err(e) = engine.uci_loop() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match &args[..] {
+        [] => uci::Uci::new(engine::Engine::new()).run(),
+        [flag, addr] if flag == "--listen" => uci::listen(addr.as_str()),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
         eprintln!("{}", e);
     }
 }
@@ -0,0 +1,201 @@
+use crate::adjudication::{Adjudication, Adjudicator};
+use crate::chess::{Board, Color};
+use crate::eval::MATE;
+use crate::game::{Game, GameResult};
+use crate::movegen::MoveGen;
+use crate::uci_engine::{Thinking, UciEngine};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+// Playing games between UCI engines, for match_runner and spsa.
+
+// How long an engine gets to answer when the clock doesn't say, before we
+// decide it's hung.
+pub const HANG_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit {
+    Depth(i16),
+    Nodes(u64),
+    MoveTime(Duration),
+    Clock(Duration, Duration), // time, increment
+}
+
+// The limit set by a --depth, --nodes, --movetime or --tc flag, None for other flags.
+pub fn parse_limit(flag: &str, value: &str) -> Result<Option<Limit>, String> {
+    let bad = || format!("bad value for {}: {}", flag, value);
+    Ok(Some(match flag {
+        "--depth" => Limit::Depth(value.parse().map_err(|_| bad())?),
+        "--nodes" => Limit::Nodes(value.parse().map_err(|_| bad())?),
+        "--movetime" => Limit::MoveTime(Duration::from_millis(value.parse().map_err(|_| bad())?)),
+        "--tc" => {
+            let mut parts = value.splitn(2, '+');
+            let mut secs = || -> Result<Duration, String> {
+                let secs: f64 = parts.next().unwrap_or("0").parse().map_err(|_| bad())?;
+                Ok(Duration::from_secs_f64(secs.max(0.0)))
+            };
+            Limit::Clock(secs()?, secs()?)
+        }
+        _ => return Ok(None),
+    }))
+}
+
+// Opening positions from a file of FENs, one per line.
+pub fn read_openings(path: &str) -> Result<Vec<Board>, String> {
+    let file = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let openings: Vec<Board> = file
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|fen| Board::from_fen(fen).ok_or(format!("bad fen {}", fen)))
+        .collect::<Result<_, _>>()?;
+    if openings.is_empty() {
+        return Err(format!("no openings in {}", path));
+    }
+    Ok(openings)
+}
+
+pub fn start_engine(command: &str, options: &[(String, String)]) -> io::Result<UciEngine> {
+    let mut engine = UciEngine::start(command)?;
+    for (name, value) in options {
+        engine.set_option(name, value)?;
+    }
+    Ok(engine)
+}
+
+// {+0.35/12 0.51s}, from the side to move's point of view.
+fn comment(thinking: &Thinking, time: Duration) -> String {
+    let score = match (thinking.mate, thinking.score) {
+        (Some(moves), _) if moves > 0 => format!("+M{}", moves),
+        (Some(moves), _) => format!("-M{}", -moves),
+        (None, Some(cp)) => format!("{:+.2}", cp as f64 / 100.0),
+        (None, None) => "0.00".into(),
+    };
+    format!(
+        "{}/{} {:.2}s",
+        score,
+        thinking.depth.unwrap_or(0),
+        time.as_secs_f64()
+    )
+}
+
+// In centipawns, mates as far beyond any score as the engine said.
+fn score(thinking: &Thinking) -> Option<i16> {
+    match (thinking.mate, thinking.score) {
+        (Some(moves), _) if moves > 0 => Some(MATE - moves),
+        (Some(moves), _) => Some(-MATE - moves),
+        (None, score) => score,
+    }
+}
+
+// Play one game, engines[white] has white. Engines that crash or hang lose, and
+// get replaced (started with options[i]) for the next game.
+pub fn play_game(
+    engines: &mut [Option<UciEngine>; 2],
+    commands: &[String],
+    options: [&[(String, String)]; 2],
+    white: usize,
+    start: Board,
+    limit: &Limit,
+    adjudication: Adjudication,
+) -> Game {
+    let mut game = Game::new(start);
+    let mut adjudicator = Adjudicator::new(adjudication);
+    let mut clocks = match limit {
+        Limit::Clock(time, _) => [*time; 2],
+        _ => [Duration::default(); 2],
+    };
+    let player = |color: Color| match color {
+        Color::White => white,
+        Color::Black => 1 - white,
+    };
+
+    for (i, engine) in engines.iter_mut().enumerate() {
+        if engine.is_none() {
+            *engine = start_engine(&commands[i], options[i]).ok();
+        }
+        let ready = match engine {
+            Some(engine) => engine.new_game().is_ok(),
+            None => false,
+        };
+        if !ready {
+            *engine = None;
+            let color = if i == white {
+                Color::White
+            } else {
+                Color::Black
+            };
+            game.end(GameResult::win_for(color.other()), "Engine failed to start");
+            return game;
+        }
+    }
+
+    while !game.is_over() {
+        let side = game.board().side_to_move;
+        let (go, timeout) = match *limit {
+            Limit::Depth(depth) => (format!("depth {}", depth), HANG_TIMEOUT),
+            Limit::Nodes(nodes) => (format!("nodes {}", nodes), HANG_TIMEOUT),
+            Limit::MoveTime(time) => (
+                format!("movetime {}", time.as_millis()),
+                time * 2 + HANG_TIMEOUT,
+            ),
+            Limit::Clock(_, inc) => (
+                format!(
+                    "wtime {} btime {} winc {} binc {}",
+                    clocks[Color::White as usize].as_millis(),
+                    clocks[Color::Black as usize].as_millis(),
+                    inc.as_millis(),
+                    inc.as_millis()
+                ),
+                // A little grace so we can tell slow from hung.
+                clocks[side as usize] + Duration::from_secs(1),
+            ),
+        };
+
+        let engine = &mut engines[player(side)];
+        let started = Instant::now();
+        let answer = engine
+            .as_mut()
+            .unwrap()
+            .go(game.start(), game.moves(), &go, timeout);
+        let elapsed = started.elapsed();
+
+        let (mv, thinking) = match answer {
+            Ok(answer) => answer,
+            Err(e) => {
+                *engine = None;
+                let why = if e.kind() == io::ErrorKind::TimedOut {
+                    "Time forfeit"
+                } else {
+                    "Engine crashed"
+                };
+                game.end(GameResult::win_for(side.other()), why);
+                break;
+            }
+        };
+
+        if let Limit::Clock(_, inc) = limit {
+            let clock = &mut clocks[side as usize];
+            if elapsed > *clock {
+                game.end(GameResult::win_for(side.other()), "Time forfeit");
+                break;
+            }
+            *clock = *clock - elapsed + *inc;
+        }
+
+        match mv.filter(|mv| MoveGen::new_legal(game.board()).any(|m| &m == mv)) {
+            Some(mv) => game.play(mv, Some(comment(&thinking, elapsed))),
+            None => {
+                game.end(GameResult::win_for(side.other()), "Illegal move");
+                break;
+            }
+        }
+        if !game.is_over() {
+            if let Some((result, why)) = adjudicator.update(game.board(), side, score(&thinking)) {
+                game.end(result, why);
+            }
+        }
+    }
+    game
+}
@@ -1,7 +1,10 @@
 #![allow(dead_code)]
 
+#[cfg(test)]
 use super::MoveGen;
+#[cfg(test)]
 use crate::bitboard::BitBoard;
+#[cfg(test)]
 use crate::chess::{Board, Movement, Square};
 
 // We could inline shifts of the different files but this is more readable
@@ -30,6 +33,7 @@ pub const NOT_GH_FILE: u64 = !(G_FILE | H_FILE);
 
 pub const NOT_EDGES: u64 = !(A_FILE | H_FILE | RANK_1 | RANK_8);
 
+#[cfg(test)]
 pub fn moves_to_str(moves: &Vec<Movement>) -> String {
     let s = moves
         .iter()
@@ -43,6 +47,7 @@ pub fn moves_to_str(moves: &Vec<Movement>) -> String {
     }
 }
 
+#[cfg(test)]
 pub fn moves_test(board: &Board, legal: &str, illegal: &str) {
     let moves: Vec<Movement> = MoveGen::new_legal(&board).collect();
 
@@ -65,6 +70,7 @@ pub fn moves_test(board: &Board, legal: &str, illegal: &str) {
     }
 }
 
+#[cfg(test)]
 pub fn bitboard_test(board: &BitBoard, included: &str, excluded: &str) {
     let squares: Vec<Square> = board.collect();
 
@@ -89,6 +95,7 @@ pub fn bitboard_test(board: &BitBoard, included: &str, excluded: &str) {
 // Note that the board is passed for better error messages. and ORDER DOES NOT MATTER!
 // TODO: Refactor to use a hashset or a double `contains()` to better show how order doesn't
 // matter.
+#[cfg(test)]
 pub fn assert_moves(board: &Board, mut got_moves: Vec<Movement>, moves: &str) {
     let mut want_moves = vec_moves(moves);
     want_moves.sort_by_key(|m| m.hash());
@@ -102,6 +109,7 @@ pub fn assert_moves(board: &Board, mut got_moves: Vec<Movement>, moves: &str) {
     }
 }
 
+#[cfg(test)]
 fn vec_moves(moves_str: &str) -> Vec<Movement> {
     let mut moves = Vec::new();
     for lan in moves_str.split(' ') {
@@ -1,14 +1,21 @@
 use super::helpers::*;
 use crate::bitboard::*;
 use crate::chess::*;
+use crate::sync::OnceLock;
+use alloc::vec::Vec;
 
-static mut KING_MOVES: [BitBoard; 64] = [BitBoard::empty(); 64];
+static KING_MOVES: OnceLock<[BitBoard; 64]> = OnceLock::new();
 
 fn king_moves(square: Square) -> BitBoard {
-    unsafe { KING_MOVES[square.0 as usize] }
+    KING_MOVES.get_or_init(king_table)[square.0 as usize]
 }
 
 pub fn gen_king_moves() {
+    KING_MOVES.get_or_init(king_table);
+}
+
+fn king_table() -> [BitBoard; 64] {
+    let mut table = [BitBoard::empty(); 64];
     for from_sq_index in 0..64 {
         let mut king_moves: u64 = 0;
         let only_from_sq = 1 << from_sq_index;
@@ -24,10 +31,9 @@ pub fn gen_king_moves() {
         king_moves |= (only_from_sq >> 1) & !H_FILE; // Left
         king_moves |= (only_from_sq << 1) & !A_FILE; // Right
 
-        unsafe {
-            KING_MOVES[from_sq_index as usize] = BitBoard(king_moves);
-        }
+        table[from_sq_index as usize] = BitBoard(king_moves);
     }
+    table
 }
 
 pub fn get_king_attacks(board: &Board, color: Color) -> BitBoard {
@@ -69,24 +75,43 @@ pub fn get_king_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
             return;
         }
 
-        let middle = side.get_castling_middle();
-        let not_attacked = side.get_castling_not_attacked();
-        let attacked = (attacks & not_attacked).count_ones() > 0;
-        let blocked = (all_pieces & middle).count_ones() > 0;
-
-        // This is really bad and horrible and needs optimization
-        let king_movement = side.get_king_movement();
-        let king_placed = king_movement.from_square == king_sq;
-
-        let rook_movement = side.get_rook_movement();
-        let rook_placed = our_rooks.get(rook_movement.from_square);
+        let rook_sq = board.castling_rooks[*side as usize];
+        let king_to = side.get_king_movement().to_square;
+        let rook_to = side.get_rook_movement().to_square;
+
+        // Everything the king and rook pass over has to be empty, apart from the
+        // two of them (they can be in each other's way in Chess960), and the king
+        // can't castle out of, through or into check.
+        let king_path = span(king_sq, king_to);
+        let rook_path = span(rook_sq, rook_to);
+        let others = all_pieces & !BitBoard::from_square(king_sq) & !BitBoard::from_square(rook_sq);
+        let blocked = ((king_path | rook_path) & others).count_ones() > 0;
+        let attacked = (attacks & king_path).count_ones() > 0;
+
+        // The rights alone don't say the king and rook are still where they started.
+        let rook_placed = our_rooks.get(rook_sq);
+        let king_placed = if board.chess960 {
+            king_sq.rank() == rook_sq.rank()
+        } else {
+            side.get_king_movement().from_square == king_sq
+        };
 
         if !blocked && !attacked && king_placed && rook_placed {
-            moves.push(side.get_king_movement());
+            moves.push(if board.chess960 {
+                Movement::new(king_sq, rook_sq, None)
+            } else {
+                side.get_king_movement()
+            });
         }
     });
 }
 
+// Squares from a to b on a rank, including both.
+fn span(a: Square, b: Square) -> BitBoard {
+    let (lo, hi) = if a.0 < b.0 { (a.0, b.0) } else { (b.0, a.0) };
+    BitBoard((2u64 << hi).wrapping_sub(1) & !((1u64 << lo) - 1))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,14 +1,21 @@
 use super::helpers::{NOT_AB_FILE, NOT_A_FILE, NOT_GH_FILE, NOT_H_FILE};
 use crate::chess::{Board, Movement, Piece, Square};
+use crate::sync::OnceLock;
 use crate::{bitboard::BitBoard, chess::Color};
+use alloc::vec::Vec;
 
-static mut KNIGHT_MOVES: [BitBoard; 64] = [BitBoard::empty(); 64];
+static KNIGHT_MOVES: OnceLock<[BitBoard; 64]> = OnceLock::new();
 
-fn knight_moves(square: Square) -> BitBoard {
-    unsafe { KNIGHT_MOVES[square.0 as usize] }
+pub fn knight_moves(square: Square) -> BitBoard {
+    KNIGHT_MOVES.get_or_init(knight_table)[square.0 as usize]
 }
 
 pub fn gen_knight_moves() {
+    KNIGHT_MOVES.get_or_init(knight_table);
+}
+
+fn knight_table() -> [BitBoard; 64] {
+    let mut table = [BitBoard::empty(); 64];
     for from_sq_index in 0..64 {
         let only_from_sq = 1 << from_sq_index;
 
@@ -22,10 +29,9 @@ pub fn gen_knight_moves() {
         knight_moves |= (only_from_sq >> 10) & NOT_GH_FILE;
         knight_moves |= (only_from_sq >> 17) & NOT_H_FILE;
 
-        unsafe {
-            KNIGHT_MOVES[from_sq_index as usize] = BitBoard(knight_moves);
-        }
+        table[from_sq_index as usize] = BitBoard(knight_moves);
     }
+    table
 }
 
 pub fn get_knight_attacks(board: &Board, color: Color) -> BitBoard {
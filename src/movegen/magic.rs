@@ -5,10 +5,21 @@ use super::magic_utils::{get_occupancy_mask, get_questions_and_answers, NUM_MOVE
 
 use crate::bitboard::BitBoard;
 use crate::chess::{Board, Color, Movement, Piece, Square};
+use crate::sync::OnceLock;
+use alloc::vec;
+use alloc::vec::Vec;
+
+struct Magics {
+    moves: Vec<BitBoard>,
+    rook: [MagicSquare; 64],
+    bishop: [MagicSquare; 64],
+}
+
+static MAGICS: OnceLock<Magics> = OnceLock::new();
 
-static mut MOVES: [BitBoard; NUM_MOVES] = [BitBoard::empty(); NUM_MOVES];
-static mut ROOK_MAGICS: [MagicSquare; 64] = [MagicSquare::empty(); 64];
-static mut BISHOP_MAGICS: [MagicSquare; 64] = [MagicSquare::empty(); 64];
+fn magics() -> &'static Magics {
+    MAGICS.get_or_init(magic_tables)
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct MagicSquare {
@@ -16,7 +27,6 @@ pub struct MagicSquare {
     occupancy_mask: BitBoard,
     offset: u32,
     right_shift: u8,
-    empty: bool,
 }
 
 impl MagicSquare {
@@ -31,7 +41,6 @@ impl MagicSquare {
             occupancy_mask,
             offset,
             right_shift,
-            empty: false,
         }
     }
 
@@ -41,29 +50,27 @@ impl MagicSquare {
             occupancy_mask: BitBoard::empty(),
             offset: 0,
             right_shift: 0,
-            empty: true,
         }
     }
 
-    pub fn lookup_hash(&self, occupancy: &BitBoard) -> BitBoard {
-        if self.empty {
-            panic!(
-                "tried to lookup hash on an empty magic square, did you forget to run gen_moves?"
-            );
-        }
-
+    pub fn lookup_hash(&self, moves: &[BitBoard], occupancy: &BitBoard) -> BitBoard {
         let raw_hash = self.number * (self.occupancy_mask & occupancy);
         let shifted_hash = (raw_hash.0 as usize) >> (self.right_shift as usize);
         let hash = (self.offset as usize) + shifted_hash;
 
-        unsafe { MOVES[hash] }
+        moves[hash]
     }
 }
 
 static SEEDS: [u64; 8] = [8198, 15098, 15153, 12593, 16340, 19763, 55569, 7831];
 
 // TODO: Go through, fully re-comprehend, and refactor this BS
-fn gen_single_magic(from_sq: Square, piece: Piece, cur_offset: usize) -> usize {
+fn gen_single_magic(
+    magics: &mut Magics,
+    from_sq: Square,
+    piece: Piece,
+    cur_offset: usize,
+) -> usize {
     let (questions, answers) = get_questions_and_answers(from_sq, piece);
 
     let occupancy_mask = get_occupancy_mask(from_sq, piece);
@@ -104,49 +111,59 @@ fn gen_single_magic(from_sq: Square, piece: Piece, cur_offset: usize) -> usize {
         }
     }
 
-    unsafe {
-        if piece == Piece::Rook {
-            ROOK_MAGICS[from_sq.0 as usize] = new_magic;
-        } else {
-            BISHOP_MAGICS[from_sq.0 as usize] = new_magic;
-        }
+    if piece == Piece::Rook {
+        magics.rook[from_sq.0 as usize] = new_magic;
+    } else {
+        magics.bishop[from_sq.0 as usize] = new_magic;
+    }
 
-        for i in 0..questions.len() {
-            let hash = (new_magic.number * questions[i]) >> (new_magic.right_shift as u64);
-            let j = hash.0 as usize;
-            MOVES[(new_magic.offset as usize) + j] = answers[i];
-        }
+    for i in 0..questions.len() {
+        let hash = (new_magic.number * questions[i]) >> (new_magic.right_shift as u64);
+        let j = hash.0 as usize;
+        magics.moves[(new_magic.offset as usize) + j] = answers[i];
     }
 
     cur_offset + questions.len()
 }
 
 pub fn gen_all_magics() {
+    magics();
+}
+
+fn magic_tables() -> Magics {
+    log::debug!("generating magic tables");
+    let mut magics = Magics {
+        moves: vec![BitBoard::empty(); NUM_MOVES],
+        rook: [MagicSquare::empty(); 64],
+        bishop: [MagicSquare::empty(); 64],
+    };
     let mut cur_offset = 0;
 
     for sq_index in 0..64 {
-        cur_offset = gen_single_magic(Square(sq_index), Piece::Bishop, cur_offset);
+        cur_offset = gen_single_magic(&mut magics, Square(sq_index), Piece::Bishop, cur_offset);
     }
     for sq_index in 0..64 {
-        cur_offset = gen_single_magic(Square(sq_index), Piece::Rook, cur_offset);
+        cur_offset = gen_single_magic(&mut magics, Square(sq_index), Piece::Rook, cur_offset);
     }
+    log::debug!("magic tables ready");
+    magics
 }
 
-fn get_sliding_moves_bb(sq: Square, piece: Piece, occupancy: &BitBoard) -> BitBoard {
-    unsafe {
-        if piece == Piece::Rook {
-            let magic = ROOK_MAGICS[sq.0 as usize];
-            magic.lookup_hash(occupancy)
-        } else if piece == Piece::Bishop {
-            let magic = BISHOP_MAGICS[sq.0 as usize];
-            magic.lookup_hash(occupancy)
-        } else if piece == Piece::Queen {
-            let bishop_magic = BISHOP_MAGICS[sq.0 as usize];
-            let rook_magic = ROOK_MAGICS[sq.0 as usize];
-            bishop_magic.lookup_hash(occupancy) | rook_magic.lookup_hash(occupancy)
-        } else {
-            panic!("{:?} is not a sliding piece", piece);
-        }
+pub fn get_sliding_moves_bb(sq: Square, piece: Piece, occupancy: &BitBoard) -> BitBoard {
+    let magics = magics();
+    if piece == Piece::Rook {
+        let magic = magics.rook[sq.0 as usize];
+        magic.lookup_hash(&magics.moves, occupancy)
+    } else if piece == Piece::Bishop {
+        let magic = magics.bishop[sq.0 as usize];
+        magic.lookup_hash(&magics.moves, occupancy)
+    } else if piece == Piece::Queen {
+        let bishop_magic = magics.bishop[sq.0 as usize];
+        let rook_magic = magics.rook[sq.0 as usize];
+        bishop_magic.lookup_hash(&magics.moves, occupancy)
+            | rook_magic.lookup_hash(&magics.moves, occupancy)
+    } else {
+        panic!("{:?} is not a sliding piece", piece);
     }
 }
 
@@ -2,6 +2,8 @@ use crate::bitboard::BitBoard;
 use crate::chess::{Piece, Square};
 
 use super::helpers::NOT_EDGES;
+use alloc::vec;
+use alloc::vec::Vec;
 
 const ROOK_BITS: usize = 12;
 const BISHOP_BITS: usize = 9;
@@ -1,6 +1,7 @@
-use crate::chess::{Board, Movement};
+use crate::chess::{Board, Movement, Piece, Square};
+use crate::zobrist;
 use crate::{bitboard::BitBoard, chess::Color};
-use std::sync::Once;
+use alloc::vec::Vec;
 
 mod helpers;
 mod king;
@@ -9,19 +10,16 @@ mod magic;
 mod magic_utils;
 mod pawn;
 
-static START: Once = Once::new();
-
+// The tables are built the first time they're used, this builds them all up
+// front so the first search doesn't pay for it.
 pub fn gen_moves_once() {
-    START.call_once(|| {
-        gen_moves();
-    });
-}
-
-fn gen_moves() {
     pawn::gen_pawn_moves();
     knight::gen_knight_moves();
     king::gen_king_moves();
     magic::gen_all_magics();
+
+    // Not movegen, but everything that needs movegen tables wants zobrist keys too.
+    zobrist::init_once();
 }
 
 pub struct MoveGen {
@@ -63,14 +61,7 @@ impl Iterator for MoveGen {
                 continue;
             }
 
-            // After the move, are we in check?
-
-            let after_move = self.board.make_move(&mv);
-            let attacks = after_move.attacked(after_move.side_to_move);
-
-            let only_our_king = 1 << after_move.king(self.board.side_to_move).0;
-            let is_in_check = (attacks.0 & only_our_king).count_ones() > 0;
-            if !is_in_check {
+            if is_legal(&self.board, mv) {
                 return Some(mv.clone());
             }
         }
@@ -79,6 +70,62 @@ impl Iterator for MoveGen {
     }
 }
 
+// After the move, are we in check?
+fn is_legal(board: &Board, mv: &Movement) -> bool {
+    let king = board.king(board.side_to_move);
+    let from = BitBoard::from_square(mv.from_square);
+    let en_passant =
+        board.en_passant == Some(mv.to_square) && board.pieces(Piece::Pawn).get(mv.from_square);
+
+    // Anything but the king, en passant (which takes a second piece off the board)
+    // and pinned pieces only has to deal with the check, if there is one. Out of
+    // double check only the king can move.
+    if mv.from_square != king && !en_passant && (from & board.pinned()).0 == 0 {
+        return match board.checkers().count_ones() {
+            0 => true,
+            1 => {
+                let checker = board.checkers().0.trailing_zeros() as u8;
+                let blocks = zobrist::between(king, Square(checker)) | board.checkers();
+                blocks.get(mv.to_square)
+            }
+            _ => false,
+        };
+    }
+
+    let after_move = board.make_move(mv);
+    let attacks = after_move.attacked(after_move.side_to_move);
+
+    let only_our_king = 1 << after_move.king(board.side_to_move).0;
+    (attacks.0 & only_our_king).count_ones() == 0
+}
+
+// Is there any legal move, i.e. is it not checkmate or stalemate? The king's
+// moves go first, since it can usually step somewhere, and the other pieces'
+// moves are only generated when nothing before them was legal.
+pub fn has_legal_move(board: &Board) -> bool {
+    let generators: [fn(&Board, &mut Vec<Movement>, Color); 4] = [
+        king::get_king_moves,
+        knight::get_knight_moves,
+        pawn::get_pawn_moves,
+        magic::get_sliding_moves,
+    ];
+
+    let mut moves = Vec::new();
+    generators.iter().any(|generate| {
+        moves.clear();
+        generate(board, &mut moves, board.side_to_move);
+        moves.iter().any(|mv| is_legal(board, mv))
+    })
+}
+
+// MoveGen::new_legal(board).count(), without copying the board or the moves.
+pub fn count_legal_moves(board: &Board) -> usize {
+    get_pseudolegal_moves(board)
+        .iter()
+        .filter(|mv| is_legal(board, mv))
+        .count()
+}
+
 pub fn get_pseudolegal_moves(board: &Board) -> Vec<Movement> {
     let mut moves = Vec::new();
     pawn::get_pawn_moves(board, &mut moves, board.side_to_move);
@@ -97,10 +144,51 @@ pub fn get_attacked_squares(board: &Board, color: Color) -> BitBoard {
     attacks
 }
 
+// The other color's pieces giving `color`'s king check, and `color`'s pieces
+// pinned to it. Board keeps these for the side to move, see Board::checkers.
+pub fn get_checkers_and_pinned(board: &Board, color: Color) -> (BitBoard, BitBoard) {
+    let ours = *board.color_combined(color);
+    let theirs = *board.color_combined(color.other());
+    let king_bb = *board.pieces(Piece::King) & ours;
+    if king_bb.0 == 0 {
+        return (BitBoard::empty(), BitBoard::empty());
+    }
+    let king = Square(king_bb.0.trailing_zeros() as u8);
+
+    let mut checkers = (knight::knight_moves(king) & *board.pieces(Piece::Knight)
+        | pawn::pawn_attackers(king, color.other()) & *board.pieces(Piece::Pawn))
+        & theirs;
+
+    // Sliders that would see the king on an empty board check it with nothing in
+    // between, and pin the one piece in between if it's ours.
+    let queens = *board.pieces(Piece::Queen);
+    let empty = BitBoard::empty();
+    let snipers = (magic::get_sliding_moves_bb(king, Piece::Bishop, &empty)
+        & (*board.pieces(Piece::Bishop) | queens)
+        | magic::get_sliding_moves_bb(king, Piece::Rook, &empty)
+            & (*board.pieces(Piece::Rook) | queens))
+        & theirs;
+
+    let mut pinned = BitBoard::empty();
+    let occupied = ours | theirs;
+    for sniper in snipers {
+        let blockers = zobrist::between(king, sniper) & occupied;
+        match blockers.count_ones() {
+            0 => checkers |= BitBoard::from_square(sniper),
+            1 => pinned |= blockers & ours,
+            _ => {}
+        }
+    }
+    (checkers, pinned)
+}
+
 // For debugging, used in tests and for a debug command 'go perft depth'
 pub fn perft(board: &Board, depth: u16) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
     if depth == 1 {
-        return MoveGen::new_legal(board).count() as u64;
+        return count_legal_moves(board) as u64;
     } else {
         let mut n = 0;
         for mv in MoveGen::new_legal(board) {
@@ -169,4 +257,23 @@ mod tests {
         legal_iter.set_iterator_mask(attacked & enemy_pieces);
         assert_moves(&board, legal_iter.collect(), "c4f7 h5e5 h5f7 h5h7");
     }
+
+    #[test]
+    fn test_has_and_count_legal_moves() {
+        let fens = [
+            crate::chess::STARTING_FEN,
+            "8/2r5/6k1/6r1/3K2r1/6r1/4P3/8 w - - 0 1",
+            "8/8/2p2k2/5n2/1N1P4/1K6/8/8 w - - 0 1",
+            // Mate, and stalemate with a pawn that can't move.
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            "k7/P7/1K6/8/8/8/8/8 b - - 0 1",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        ];
+        for fen in fens.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let count = MoveGen::new_legal(&board).count();
+            assert_eq!(count_legal_moves(&board), count, "{}", fen);
+            assert_eq!(has_legal_move(&board), count > 0, "{}", fen);
+        }
+    }
 }
@@ -2,20 +2,39 @@ use crate::bitboard::BitBoard;
 use crate::chess::{Board, Color, Movement, Piece, Square};
 
 use super::helpers::{NOT_A_FILE, NOT_H_FILE};
+use crate::sync::OnceLock;
+use alloc::vec::Vec;
+
+// By color, then square. 48 because we don't need the top or bottom rows for pawns
+struct PawnTables {
+    attacks: [[BitBoard; 48]; 2],
+    pushes: [[BitBoard; 48]; 2],
+    dbl_pushes: [[BitBoard; 48]; 2],
+}
+
+static PAWN_TABLES: OnceLock<PawnTables> = OnceLock::new();
 
-// 48 because we don't need the top or bottom rows for pawns
-static mut PAWN_ATTACKS: [[BitBoard; 48]; 64] = [[BitBoard::empty(); 48]; 64];
-static mut PAWN_PUSHES: [[BitBoard; 48]; 64] = [[BitBoard::empty(); 48]; 64];
-static mut PAWN_DBL_PUSHES: [[BitBoard; 48]; 64] = [[BitBoard::empty(); 48]; 64];
+fn tables() -> &'static PawnTables {
+    PAWN_TABLES.get_or_init(pawn_tables)
+}
 
 pub fn pawn_attacks(square: Square, color: Color) -> BitBoard {
-    unsafe { PAWN_ATTACKS[color as usize][(square.0 - 8) as usize] }
+    tables().attacks[color as usize][(square.0 - 8) as usize]
+}
+// The squares `color`'s pawns attack `square` from. Unlike pawn_attacks this
+// works on the first and last ranks too, where kings can be.
+pub fn pawn_attackers(square: Square, color: Color) -> BitBoard {
+    let only_sq = 1 << square.0;
+    BitBoard(match color {
+        Color::White => ((only_sq >> 9) & NOT_H_FILE) | ((only_sq >> 7) & NOT_A_FILE),
+        Color::Black => ((only_sq << 9) & NOT_A_FILE) | ((only_sq << 7) & NOT_H_FILE),
+    })
 }
 fn pawn_pushes(square: Square, color: Color) -> BitBoard {
-    unsafe { PAWN_PUSHES[color as usize][(square.0 - 8) as usize] }
+    tables().pushes[color as usize][(square.0 - 8) as usize]
 }
 fn pawn_dbl_pushes(square: Square, color: Color) -> BitBoard {
-    unsafe { PAWN_DBL_PUSHES[color as usize][(square.0 - 8) as usize] }
+    tables().dbl_pushes[color as usize][(square.0 - 8) as usize]
 }
 
 pub fn get_pawn_attacks(board: &Board, color: Color) -> BitBoard {
@@ -34,6 +53,15 @@ pub fn get_pawn_attacks(board: &Board, color: Color) -> BitBoard {
 }
 
 pub fn gen_pawn_moves() {
+    tables();
+}
+
+fn pawn_tables() -> PawnTables {
+    let mut tables = PawnTables {
+        attacks: [[BitBoard::empty(); 48]; 2],
+        pushes: [[BitBoard::empty(); 48]; 2],
+        dbl_pushes: [[BitBoard::empty(); 48]; 2],
+    };
     for from_sq_index in 0..48 {
         let from_sq = Square(from_sq_index + 8);
         let only_from_sq = 1 << (from_sq_index + 8);
@@ -48,27 +76,22 @@ pub fn gen_pawn_moves() {
         let black_pawn_pushes = BitBoard(only_from_sq >> 8);
         if from_sq.rank() == 1 {
             let white_dbl_pawn_pushes = BitBoard(only_from_sq << 16);
-            unsafe {
-                PAWN_DBL_PUSHES[Color::White as usize][from_sq_index as usize] =
-                    white_dbl_pawn_pushes;
-            }
+            tables.dbl_pushes[Color::White as usize][from_sq_index as usize] =
+                white_dbl_pawn_pushes;
         }
 
         if from_sq.rank() == 6 {
             let black_dbl_pawn_pushes = BitBoard(only_from_sq >> 16);
-            unsafe {
-                PAWN_DBL_PUSHES[Color::Black as usize][from_sq_index as usize] =
-                    black_dbl_pawn_pushes;
-            }
+            tables.dbl_pushes[Color::Black as usize][from_sq_index as usize] =
+                black_dbl_pawn_pushes;
         }
 
-        unsafe {
-            PAWN_ATTACKS[Color::White as usize][from_sq_index as usize] = white_pawn_attacks;
-            PAWN_ATTACKS[Color::Black as usize][from_sq_index as usize] = black_pawn_attacks;
-            PAWN_PUSHES[Color::White as usize][from_sq_index as usize] = white_pawn_pushes;
-            PAWN_PUSHES[Color::Black as usize][from_sq_index as usize] = black_pawn_pushes;
-        }
+        tables.attacks[Color::White as usize][from_sq_index as usize] = white_pawn_attacks;
+        tables.attacks[Color::Black as usize][from_sq_index as usize] = black_pawn_attacks;
+        tables.pushes[Color::White as usize][from_sq_index as usize] = white_pawn_pushes;
+        tables.pushes[Color::Black as usize][from_sq_index as usize] = black_pawn_pushes;
     }
+    tables
 }
 
 pub fn get_pawn_moves(board: &Board, moves: &mut Vec<Movement>, color: Color) {
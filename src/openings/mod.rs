@@ -0,0 +1,139 @@
+use crate::chess::{Board, Movement};
+use crate::game;
+use crate::zobrist;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Opening names by ECO code, for tagging games. The table (eco.txt) has the
+// common openings and their main variations, not all 500 codes and every
+// sideline, so a game is named after the last position in it that the table
+// knows.
+//
+// Like the book, positions are looked up by zobrist key, so a game that
+// transposes into an opening gets its name.
+
+const TABLE: &str = include_str!("eco.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+    // The moves that reach it from the start position, in SAN.
+    pub moves: &'static str,
+}
+
+static OPENINGS: OnceLock<HashMap<u64, Opening>> = OnceLock::new();
+
+// The opening `board` is a position of, if it's in the table.
+pub fn lookup(board: &Board) -> Option<Opening> {
+    openings().get(&zobrist::hash(board)).copied()
+}
+
+// The opening a game from `start` went into: the last position after one of
+// `moves` that's in the table. Later positions are out of the table, and often
+// get back into it by transposition, but that's still the same opening.
+pub fn classify(start: &Board, moves: &[Movement]) -> Option<Opening> {
+    let mut board = start.clone();
+    let mut opening = lookup(&board);
+    for mv in moves {
+        board.make_move_mut(mv);
+        opening = lookup(&board).or(opening);
+    }
+    opening
+}
+
+fn openings() -> &'static HashMap<u64, Opening> {
+    OPENINGS.get_or_init(|| {
+        let mut openings = HashMap::new();
+        for row in TABLE.lines() {
+            // Rows are checked by the tests, a bad one is left out here.
+            if let Ok(Some((board, opening))) = parse_row(row) {
+                openings.entry(zobrist::hash(&board)).or_insert(opening);
+            }
+        }
+        openings
+    })
+}
+
+// A row of eco.txt as the opening and the position it names, None for blank
+// lines and comments.
+fn parse_row(row: &'static str) -> Result<Option<(Board, Opening)>, String> {
+    let row = row.split('#').next().unwrap().trim();
+    if row.is_empty() {
+        return Ok(None);
+    }
+
+    let (opening, moves) = row.split_once('|').ok_or("no | between name and moves")?;
+    let (eco, name) = opening.trim().split_once(' ').ok_or("no name")?;
+    let moves = moves.trim();
+
+    let mut board = Board::from_start_pos();
+    for san in moves.split_whitespace() {
+        let mv = game::parse_san(&board, san)
+            .ok_or_else(|| format!("illegal move {} in {}", san, board.to_fen()))?;
+        board.make_move_mut(&mv);
+    }
+    Ok(Some((board, Opening { eco, name, moves })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::gen_moves_once;
+
+    fn play(sans: &str) -> Vec<Movement> {
+        let mut board = Board::from_start_pos();
+        sans.split_whitespace()
+            .map(|san| {
+                let mv = game::parse_san(&board, san).unwrap();
+                board.make_move_mut(&mv);
+                mv
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_table() {
+        gen_moves_once();
+        let mut seen = HashMap::new();
+        for (i, row) in TABLE.lines().enumerate() {
+            match parse_row(row) {
+                Ok(Some((board, opening))) => {
+                    let code = opening.eco.as_bytes();
+                    assert!(
+                        code.len() == 3
+                            && (b'A'..=b'E').contains(&code[0])
+                            && code[1..].iter().all(u8::is_ascii_digit),
+                        "eco.txt:{}: bad code {}",
+                        i + 1,
+                        opening.eco
+                    );
+                    if let Some(line) = seen.insert(zobrist::hash(&board), i + 1) {
+                        panic!("eco.txt:{}: same position as line {}", i + 1, line);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => panic!("eco.txt:{}: {}", i + 1, e),
+            }
+        }
+        assert!(seen.len() > 100, "only {} openings", seen.len());
+    }
+
+    #[test]
+    fn test_classify() {
+        gen_moves_once();
+        let start = Board::from_start_pos();
+        let najdorf = classify(&start, &play("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 Be3 e5"));
+        assert_eq!(najdorf.map(|o| o.eco), Some("B90"));
+        assert_eq!(
+            najdorf.map(|o| o.name),
+            Some("Sicilian Defense: Najdorf Variation")
+        );
+
+        // Transpositions count, and so does the table's last position.
+        let qgd = classify(&start, &play("Nf3 d5 d4 Nf6 c4 e6 Nc3"));
+        assert_eq!(qgd.map(|o| o.eco), Some("D37"));
+        assert_eq!(classify(&start, &play("a3 a6")), None);
+        assert_eq!(classify(&start, &[]), None);
+    }
+}
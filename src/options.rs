@@ -0,0 +1,209 @@
+use std::fmt;
+
+// UCI options. The engine declares the options it has (sent to the GUI on `uci`),
+// and setoption values get checked against them before the engine applies them.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionType {
+    Check {
+        default: bool,
+    },
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    String {
+        default: &'static str,
+    },
+    Combo {
+        default: &'static str,
+        vars: Vec<&'static str>,
+    },
+    Button,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Check(bool),
+    Spin(i64),
+    String(String),
+    Button,
+}
+
+impl UciOption {
+    pub fn check(name: &'static str, default: bool) -> Self {
+        UciOption {
+            name,
+            kind: OptionType::Check { default },
+        }
+    }
+
+    pub fn spin(name: &'static str, default: i64, min: i64, max: i64) -> Self {
+        UciOption {
+            name,
+            kind: OptionType::Spin { default, min, max },
+        }
+    }
+
+    pub fn string(name: &'static str, default: &'static str) -> Self {
+        UciOption {
+            name,
+            kind: OptionType::String { default },
+        }
+    }
+
+    pub fn combo(name: &'static str, default: &'static str, vars: Vec<&'static str>) -> Self {
+        UciOption {
+            name,
+            kind: OptionType::Combo { default, vars },
+        }
+    }
+
+    pub fn button(name: &'static str) -> Self {
+        UciOption {
+            name,
+            kind: OptionType::Button,
+        }
+    }
+
+    // Parse a value from setoption, None if it doesn't make sense for this option.
+    // Out of range spins get clamped, GUIs don't always respect min and max.
+    pub fn parse(&self, value: Option<&str>) -> Option<OptionValue> {
+        Some(match &self.kind {
+            OptionType::Check { .. } => match value?.trim() {
+                "true" => OptionValue::Check(true),
+                "false" => OptionValue::Check(false),
+                _ => return None,
+            },
+            OptionType::Spin { min, max, .. } => {
+                let n: i64 = value?.trim().parse().ok()?;
+                OptionValue::Spin(n.clamp(*min, *max))
+            }
+            // Some GUIs send <empty> since there is no other way to set an empty string.
+            OptionType::String { .. } => match value.unwrap_or("") {
+                "<empty>" => OptionValue::String(String::new()),
+                s => OptionValue::String(s.to_string()),
+            },
+            // Comes back as the var's own spelling, so it can be matched on.
+            OptionType::Combo { vars, .. } => {
+                let value = value?.trim();
+                let var = vars.iter().find(|var| var.eq_ignore_ascii_case(value))?;
+                OptionValue::String(var.to_string())
+            }
+            OptionType::Button => OptionValue::Button,
+        })
+    }
+}
+
+// The `option ...` line sent in response to `uci`.
+impl fmt::Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {}", self.name)?;
+        match &self.kind {
+            OptionType::Check { default } => write!(f, " type check default {}", default),
+            OptionType::Spin { default, min, max } => {
+                write!(f, " type spin default {} min {} max {}", default, min, max)
+            }
+            OptionType::String { default } => {
+                let default = if default.is_empty() {
+                    "<empty>"
+                } else {
+                    default
+                };
+                write!(f, " type string default {}", default)
+            }
+            OptionType::Combo { default, vars } => {
+                write!(f, " type combo default {}", default)?;
+                vars.iter().try_for_each(|var| write!(f, " var {}", var))
+            }
+            OptionType::Button => write!(f, " type button"),
+        }
+    }
+}
+
+// UCI option names are case insensitive.
+pub fn find<'a>(options: &'a [UciOption], name: &str) -> Option<&'a UciOption> {
+    options
+        .iter()
+        .find(|option| option.name.eq_ignore_ascii_case(name.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            UciOption::spin("Hash", 64, 1, 4096).to_string(),
+            "option name Hash type spin default 64 min 1 max 4096"
+        );
+        assert_eq!(
+            UciOption::check("UCI_LimitStrength", false).to_string(),
+            "option name UCI_LimitStrength type check default false"
+        );
+        assert_eq!(
+            UciOption::string("Book File", "").to_string(),
+            "option name Book File type string default <empty>"
+        );
+        assert_eq!(
+            UciOption::combo("UCI_Variant", "chess", vec!["chess", "3check"]).to_string(),
+            "option name UCI_Variant type combo default chess var chess var 3check"
+        );
+        assert_eq!(
+            UciOption::button("Clear Hash").to_string(),
+            "option name Clear Hash type button"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let hash = UciOption::spin("Hash", 64, 1, 4096);
+        assert_eq!(hash.parse(Some("128")), Some(OptionValue::Spin(128)));
+        assert_eq!(hash.parse(Some("100000")), Some(OptionValue::Spin(4096)));
+        assert_eq!(hash.parse(Some("lots")), None);
+        assert_eq!(hash.parse(None), None);
+
+        let check = UciOption::check("Ponder", false);
+        assert_eq!(check.parse(Some("true")), Some(OptionValue::Check(true)));
+        assert_eq!(check.parse(Some("yes")), None);
+
+        let string = UciOption::string("Book File", "");
+        assert_eq!(
+            string.parse(Some("my book.bin")),
+            Some(OptionValue::String("my book.bin".into()))
+        );
+        assert_eq!(
+            string.parse(Some("<empty>")),
+            Some(OptionValue::String("".into()))
+        );
+
+        let combo = UciOption::combo("UCI_Variant", "chess", vec!["chess", "3check"]);
+        assert_eq!(
+            combo.parse(Some("3Check")),
+            Some(OptionValue::String("3check".into()))
+        );
+        assert_eq!(combo.parse(Some("atomic")), None);
+
+        let button = UciOption::button("Clear Hash");
+        assert_eq!(button.parse(None), Some(OptionValue::Button));
+    }
+
+    #[test]
+    fn test_find() {
+        let options = vec![
+            UciOption::spin("Hash", 64, 1, 4096),
+            UciOption::button("Clear Hash"),
+        ];
+        assert_eq!(find(&options, "clear hash"), Some(&options[1]));
+        assert_eq!(find(&options, "HASH"), Some(&options[0]));
+        assert_eq!(find(&options, "Threads"), None);
+    }
+}
@@ -0,0 +1,17 @@
+// What most users of the crate need, in one import:
+//
+//     use yobmef::prelude::*;
+//
+// Everything here also has a home of its own (chess, movegen, engine, ...), this
+// only re-exports it.
+
+pub use crate::chess::{
+    Board, CastlingSide, Color, MoveError, Movement, Piece, RenderOptions, Square, Variant,
+    STARTING_FEN,
+};
+pub use crate::movegen::{gen_moves_once, MoveGen};
+
+#[cfg(feature = "std")]
+pub use crate::engine::{Engine, SearchEvent};
+#[cfg(feature = "std")]
+pub use crate::search::SearchLimits;
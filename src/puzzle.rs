@@ -0,0 +1,149 @@
+use crate::chess::Board;
+use crate::movegen;
+use crate::search::{mate_in, RootMove, SearchLimits, Searcher};
+
+// Checking puzzles: is the claimed mate or win really there, which moves get
+// it (the keys), and is there only one? Every root move is searched with an
+// exact score, so we see all the keys and not just the best one.
+//
+// Mate claims are proven: searching 2N-1 plies finds every mate in N, since
+// nothing is pruned before a mate can be seen. Win claims are only as good as
+// the search, so give them enough time.
+
+// A move wins if it's at least this good for us, two pawns.
+pub const WIN_SCORE: i16 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    // The side to move mates in at most this many moves.
+    MateIn(u8),
+    // The side to move has a winning move.
+    Win,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    // Moves that make the claim true, best first. Empty if it's false.
+    pub keys: Vec<RootMove>,
+    // False if the search was stopped (by the limits) before it could prove
+    // a mate claim. The keys are what was found until then.
+    pub complete: bool,
+}
+
+impl Solution {
+    pub fn is_unique(&self) -> bool {
+        self.keys.len() == 1
+    }
+}
+
+// Solve `board` for `claim`. `limits` can cap the search with nodes or time,
+// for mate claims the depth comes from the claim. The hash is cleared first so
+// earlier puzzles can't cut the root short, and exact root scores are left on.
+pub fn solve(
+    searcher: &mut Searcher,
+    board: &Board,
+    claim: Claim,
+    mut limits: SearchLimits,
+) -> Solution {
+    if !movegen::has_legal_move(board) {
+        return Solution {
+            keys: Vec::new(),
+            complete: true,
+        };
+    }
+
+    let depth = match claim {
+        Claim::MateIn(moves) => {
+            let depth = 2 * moves.max(1) as i16 - 1;
+            limits.depth = Some(depth);
+            limits.mate = None;
+            Some(depth)
+        }
+        Claim::Win => None,
+    };
+
+    searcher.new_game();
+    searcher.set_exact_root_scores(true);
+    searcher.go(board, limits);
+
+    let root_moves = searcher.root_moves();
+    let keys = root_moves
+        .iter()
+        .filter(|rm| match claim {
+            Claim::MateIn(moves) => mate_in(rm.score).is_some_and(|n| n > 0 && n <= moves as i16),
+            Claim::Win => rm.score >= WIN_SCORE,
+        })
+        .cloned()
+        .collect();
+    let complete = match depth {
+        Some(depth) => root_moves.first().is_some_and(|rm| rm.depth >= depth),
+        None => true,
+    };
+    Solution { keys, complete }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(fen: &str, claim: Claim) -> (Vec<String>, bool) {
+        let board = Board::from_fen(fen).unwrap();
+        let solution = solve(&mut Searcher::new(), &board, claim, SearchLimits::depth(4));
+        let mut keys: Vec<String> = solution.keys.iter().map(|rm| rm.mv.to_string()).collect();
+        keys.sort();
+        (keys, solution.complete)
+    }
+
+    #[test]
+    fn test_mate_in() {
+        let back_rank = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(
+            keys(back_rank, Claim::MateIn(1)),
+            (vec!["a1a8".into()], true)
+        );
+
+        // Two rooks, two ways to mate.
+        let two_rooks = "6k1/5ppp/8/8/8/8/8/RR4K1 w - - 0 1";
+        assert_eq!(
+            keys(two_rooks, Claim::MateIn(1)),
+            (vec!["a1a8".into(), "b1b8".into()], true)
+        );
+
+        // Mate in 2 with either rook move, but not in 1.
+        let fen = "8/p4p1k/3p1P2/1p1br3/3p4/1Pr5/P6K/8 b - - 0 1";
+        assert_eq!(
+            keys(fen, Claim::MateIn(2)),
+            (vec!["e5e1".into(), "e5e2".into()], true)
+        );
+        assert_eq!(keys(fen, Claim::MateIn(1)), (vec![], true));
+    }
+
+    #[test]
+    fn test_false_claims() {
+        let start = crate::chess::STARTING_FEN;
+        assert_eq!(keys(start, Claim::MateIn(2)), (vec![], true));
+        assert_eq!(keys(start, Claim::Win), (vec![], true));
+
+        // Already mated, nothing to solve.
+        let mated = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1";
+        assert_eq!(keys(mated, Claim::MateIn(1)), (vec![], true));
+    }
+
+    #[test]
+    fn test_win() {
+        // Only taking the queen wins.
+        let fen = "4k3/8/8/8/3q4/8/8/3RK3 w - - 0 1";
+        assert_eq!(keys(fen, Claim::Win), (vec!["d1d4".into()], true));
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let board = Board::from_fen("8/p4p1k/3p1P2/1p1br3/3p4/1Pr5/P6K/8 b - - 0 1").unwrap();
+        let limits = SearchLimits {
+            nodes: Some(1),
+            ..SearchLimits::default()
+        };
+        let solution = solve(&mut Searcher::new(), &board, Claim::MateIn(5), limits);
+        assert!(!solution.complete);
+    }
+}
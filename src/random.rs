@@ -0,0 +1,151 @@
+use crate::chess::{Board, Color, Piece, Square};
+use crate::movegen::MoveGen;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// Random legal positions, for fuzzing movegen, checking the eval is symmetric
+// and making benchmark corpora. Two ways to get one:
+//
+// - random_game plays random moves from the start position. The positions are
+//   reachable, with castling rights and en passant, but look like games.
+// - random_placement drops pieces on random squares, for material and endgames
+//   no random game gets to. No castling or en passant, and the checks can be
+//   ones no move could give.
+//
+// The rng is the caller's, seed it (StdRng::seed_from_u64) to get the same
+// positions again.
+
+// Placements with the side that just moved in check are thrown away, this many
+// in a row means the pieces can't be placed legally.
+const MAX_PLACEMENTS: usize = 10_000;
+
+// The position after `plies` random legal moves from the start, or the mate or
+// stalemate the game ended in before that.
+pub fn random_game<R: Rng>(plies: usize, rng: &mut R) -> Board {
+    let mut board = Board::from_start_pos();
+    for _ in 0..plies {
+        let moves: Vec<_> = MoveGen::new_legal(&board).collect();
+        match moves.choose(rng) {
+            Some(mv) => board.make_move_mut(mv),
+            None => break,
+        }
+    }
+    board
+}
+
+// Both kings and `pieces` (FEN letters, "QRr" is a queen and rook against a
+// rook) on random squares, with a random side to move. Pawns stay off the
+// first and last ranks. None if `pieces` isn't FEN letters, or they don't fit.
+pub fn random_placement<R: Rng>(pieces: &str, rng: &mut R) -> Option<Board> {
+    let mut placed = vec![(Color::White, Piece::King), (Color::Black, Piece::King)];
+    for c in pieces.chars() {
+        let piece = Piece::from_char(c.to_ascii_lowercase()).filter(|&p| p != Piece::King)?;
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        placed.push((color, piece));
+    }
+    if placed.len() > 64 {
+        return None;
+    }
+
+    for _ in 0..MAX_PLACEMENTS {
+        let mut squares = [None; 64];
+        for &(color, piece) in &placed {
+            let free: Vec<usize> = (0..64)
+                .filter(|&sq| squares[sq].is_none())
+                .filter(|&sq| piece != Piece::Pawn || (8..56).contains(&sq))
+                .collect();
+            let sq = *free.choose(rng)?;
+            squares[sq] = Some(piece.as_char_color(color));
+        }
+        let side = if rng.gen() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let board = Board::from_fen(&placement_fen(&squares, side))?;
+        if !board.attacked(side).get(board.king(side.other())) {
+            return Some(board);
+        }
+    }
+    None
+}
+
+fn placement_fen(squares: &[Option<char>; 64], side: Color) -> String {
+    let mut fen = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match squares[Square::new(rank, file).0 as usize] {
+                Some(c) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(c);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+    format!("{} {} - - 0 1", fen, side.as_char())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::movegen::gen_moves_once;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_random_game() {
+        gen_moves_once();
+        let fens = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| random_game(40, &mut rng).to_fen())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(fens(1), fens(1));
+        assert_ne!(fens(1), fens(2));
+
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(random_game(0, &mut rng), Board::from_start_pos());
+        for _ in 0..20 {
+            random_game(60, &mut rng).assert_valid();
+        }
+    }
+
+    #[test]
+    fn test_random_placement() {
+        gen_moves_once();
+        let mut rng = StdRng::seed_from_u64(1);
+        let back_ranks = BitBoard(0xFF000000000000FF);
+        for _ in 0..200 {
+            let board = random_placement("QRPPPrnpp", &mut rng).unwrap();
+            board.assert_valid();
+            assert_eq!(board.combined().count_ones(), 11);
+            assert_eq!((*board.pieces(Piece::Pawn) & back_ranks).count_ones(), 0);
+            let side = board.side_to_move;
+            assert!(!board.attacked(side).get(board.king(side.other())));
+            assert_eq!(board.castling, 0);
+        }
+
+        assert!(random_placement("", &mut rng).is_some());
+        assert!(random_placement("Kq", &mut rng).is_none());
+        assert!(random_placement("x", &mut rng).is_none());
+        assert!(random_placement(&"Q".repeat(63), &mut rng).is_none());
+    }
+}
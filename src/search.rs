@@ -1,25 +1,210 @@
-use crate::chess::{Board, Movement};
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, Color, Movement, Piece, Square, Variant, NUM_PIECES};
+use crate::clock::Instant;
 use crate::eval;
 use crate::movegen::MoveGen;
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use crate::stats::{IterationStats, SearchStats, StatsLog};
+use crate::syzygy::{self, Tablebases};
+use crate::trace::Tracer;
+use crate::tt::TranspositionTable;
+use crate::tune;
+use crate::zobrist;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 const INFINITY: i16 = i16::MAX;
 
-#[derive(Debug)]
-pub struct Limits {
-    depth: Option<i16>,
-    // Maybe could be replaced with wtime, etc.
-    thinking_time: Option<Duration>,
-    // TODO: Add other limits, like searchmoves, mate, etc.
+// We will never search deeper then this, so any score closer to MATE then
+// MAX_PLY is a forced mate.
+pub const MAX_PLY: u16 = 256;
+
+// Small, but plenty for the depths we reach.
+pub const DEFAULT_HASH_MB: usize = 64;
+
+// Looking at the clock every node adds up, so only do it every this many nodes.
+// Our nodes are slow (full legal movegen), so this has to be small to stop
+// within a fraction of a millisecond. Must be a power of two.
+const CHECK_TIME_NODES: u64 = 16;
+
+// GUIs show currmove to let the user know we aren't frozen, on short searches
+// it's just noise.
+const CURRMOVE_AFTER: Duration = Duration::from_secs(1);
+
+// A single iteration can take a long time, so send some stats at least this
+// often or we look frozen.
+const INFO_INTERVAL: Duration = Duration::from_millis(500);
+
+// With movetime we stop this much before the deadline (or the move overhead, if
+// that's more), to unwind the search and get the move out in time.
+const MOVETIME_MARGIN: Duration = Duration::from_millis(5);
+
+pub fn is_mate_score(score: i16) -> bool {
+    score.abs() >= eval::MATE - MAX_PLY as i16
 }
 
-impl Limits {
-    pub fn none() -> Self {
-        Self {
-            depth: None,
-            thinking_time: None,
+// Moves (not plies) until mate for a mate score, negative if we're getting mated.
+pub fn mate_in(score: i16) -> Option<i16> {
+    if !is_mate_score(score) {
+        return None;
+    }
+    Some(if score > 0 {
+        (eval::MATE - score + 1) / 2
+    } else {
+        -(eval::MATE + score) / 2
+    })
+}
+
+// Mate scores in search are relative to the root (mated in `ply` plies), but the
+// same position can be reached at different plies, so the TP stores them relative
+// to the node instead.
+fn score_to_tp(score: i16, ply: u16) -> i16 {
+    if is_mate_score(score) {
+        score + score.signum() * ply as i16
+    } else {
+        score
+    }
+}
+
+fn score_from_tp(score: i16, ply: u16) -> i16 {
+    if is_mate_score(score) {
+        score - score.signum() * ply as i16
+    } else {
+        score
+    }
+}
+
+// When to stop searching, see Searcher::go. Same as the limits of the UCI go
+// command, with nothing set we think as if we were playing a 10 minute game.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<i16>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+
+    // Clock and increment for white and black.
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u8>,
+
+    // Stop when a mate in this many moves is found.
+    pub mate: Option<u8>,
+
+    // Ignore the clock and search until stopped.
+    pub infinite: bool,
+    // Searching on the opponent's time, the move we expect them to play is made.
+    // The clock only starts on ponderhit, see Searcher::ponderhit_flag.
+    pub ponder: bool,
+
+    // Root moves to search, all of them if empty (UCI searchmoves).
+    pub search_moves: Vec<Movement>,
+    // Root moves not to search, for asking "what else is there?"
+    pub exclude_moves: Vec<Movement>,
+
+    // Time lost between us sending a move and the clock stopping (GUI, network),
+    // taken off our clock before deciding how long to think.
+    pub move_overhead: Duration,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: i16) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+
+    pub fn movetime(movetime: Duration) -> Self {
+        SearchLimits {
+            movetime: Some(movetime),
+            ..SearchLimits::default()
+        }
+    }
+
+    pub fn infinite() -> Self {
+        SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        }
+    }
+
+    // Not every root move is searched.
+    pub fn restricts_root(&self) -> bool {
+        !self.search_moves.is_empty() || !self.exclude_moves.is_empty()
+    }
+
+    // `mv` is a root move we were asked to search.
+    pub fn searches_move(&self, mv: &Movement) -> bool {
+        (self.search_moves.is_empty() || self.search_moves.contains(mv))
+            && !self.exclude_moves.contains(mv)
+    }
+
+    // How long to think on this move, None for no limit.
+    pub fn thinking_time(&self, side: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+        if self.movetime.is_some() {
+            return self.movetime;
+        }
+
+        let (our_time, our_increment) = match side {
+            Color::White => (self.wtime, self.winc),
+            Color::Black => (self.btime, self.binc),
+        };
+        let our_time = match our_time {
+            Some(t) => t.saturating_sub(self.move_overhead),
+            // Something else will stop the search.
+            None if self.depth.is_some() || self.nodes.is_some() || self.mate.is_some() => {
+                return None
+            }
+            // Default to as if we had 10m no inc for correspondence games.
+            None => Duration::from_secs(600),
+        };
+
+        // Divide our remaining time by 30 to get the time for this move
+        // (totally arbitrary, fight me :D)
+        // If the GUI tells us when the next time control is, don't save time for
+        // after it, but keep some in case the next move takes longer.
+        let moves_left = self.movestogo.map_or(30, |n| n.clamp(2, 30));
+
+        // The increment only comes after the move, we can't spend more than we have.
+        let time = our_time / moves_left as u32 + our_increment.unwrap_or_default();
+        Some(time.min(our_time))
+    }
+
+    // Past this we're about to lose on time, so stop even if it means playing a
+    // move we barely looked at. Only the clock can make us lose on time.
+    // Thinking time is what we aim for, the search can run over it to finish an
+    // iteration, up to this. Except with movetime, where the move is due at the
+    // deadline, so we stop a margin before it. Either way the emergency time is
+    // the real limit.
+    pub fn hard_time(&self, side: Color) -> Option<Duration> {
+        let time = self.thinking_time(side)?;
+        if self.movetime.is_some() {
+            return Some(time.saturating_sub(self.movetime_margin()));
+        }
+        Some(time * tune::hard_time_percent() as u32 / 100)
+    }
+
+    // How long before the movetime deadline we stop, see hard_time.
+    pub fn movetime_margin(&self) -> Duration {
+        self.move_overhead.max(MOVETIME_MARGIN)
+    }
+
+    pub fn emergency_time(&self, side: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
         }
+        let our_time = match side {
+            Color::White => self.wtime,
+            Color::Black => self.btime,
+        };
+        our_time.map(|t| t.saturating_sub(self.move_overhead) / 2)
     }
 }
 
@@ -30,26 +215,322 @@ pub struct SearchResult {
 
     // Depth of this evaluation, with respect to the root node.
     pub depth: i16,
+    // What eval is, only a bound if the search failed high or low. Always
+    // exact from go.
+    pub bound: Bound,
+}
+
+// A search with a window (alpha, beta) only gets the exact score of positions
+// scoring inside it, from the side to move's point of view. Failing high, the best move was good enough to stop at
+// and the real score is at least that (a lower bound). Failing low, nothing got
+// above alpha and the real score is at most the best score (an upper bound).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// How a move at the root did in the last completed iteration, see Searcher::root_moves.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RootMove {
+    pub mv: Movement,
+    // From the side to move's point of view. Only exact for the best move, the
+    // others are upper bounds since alphabeta stops once a move is proven worse.
+    pub score: i16,
+    pub depth: i16,
+    // Starts with mv. For moves that weren't best this is just the refutation.
+    pub pv: Vec<Movement>,
+}
+
+// Progress report from a search, see Searcher::set_info_callback.
+// Mirrors the UCI info command, fields we don't know are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: Option<i16>,
+    pub seldepth: Option<u16>,
+    pub multipv: Option<u16>, // which line, only with more than one
+    pub score: Option<i16>,   // centipawns, from the side to move's point of view
+    pub bound: Option<Bound>, // when the score is only a bound, never Exact
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub hashfull: Option<u16>,
+    pub tbhits: Option<u64>,
+    pub time: Option<Duration>,
+    pub currmove: Option<Movement>,
+    pub currmovenumber: Option<usize>,
+    pub pv: Vec<Movement>,
+}
+
+// Formats as a UCI info line.
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "info")?;
+        if let Some(depth) = self.depth {
+            write!(f, " depth {}", depth)?;
+        }
+        if let Some(seldepth) = self.seldepth {
+            write!(f, " seldepth {}", seldepth)?;
+        }
+        if let Some(multipv) = self.multipv {
+            write!(f, " multipv {}", multipv)?;
+        }
+        if let Some(score) = self.score {
+            match mate_in(score) {
+                Some(moves) => write!(f, " score mate {}", moves)?,
+                None => write!(f, " score cp {}", score)?,
+            }
+            match self.bound {
+                Some(Bound::Lower) => write!(f, " lowerbound")?,
+                Some(Bound::Upper) => write!(f, " upperbound")?,
+                Some(Bound::Exact) | None => {}
+            }
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {}", nodes)?;
+        }
+        if let Some(nps) = self.nps {
+            write!(f, " nps {}", nps)?;
+        }
+        if let Some(hashfull) = self.hashfull {
+            write!(f, " hashfull {}", hashfull)?;
+        }
+        if let Some(tbhits) = self.tbhits {
+            write!(f, " tbhits {}", tbhits)?;
+        }
+        if let Some(time) = self.time {
+            write!(f, " time {}", time.as_millis())?;
+        }
+        if let Some(currmove) = &self.currmove {
+            write!(f, " currmove {}", currmove)?;
+        }
+        if let Some(currmovenumber) = self.currmovenumber {
+            write!(f, " currmovenumber {}", currmovenumber)?;
+        }
+        if !self.pv.is_empty() {
+            write!(f, " pv {}", moves_to_str(&self.pv))?;
+        }
+        Ok(())
+    }
+}
+
+// Boxed so Searcher doesn't need a type parameter, wrapped so it can be Debug.
+struct InfoCallback(Box<dyn FnMut(SearchInfo) + Send>);
+
+impl fmt::Debug for InfoCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InfoCallback")
+    }
+}
+
+// How good a quiet move has been as the answer to the move before it, both by
+// the piece moved and the square it moved to. Captures are ordered well enough
+// by what they take, quiet moves have nothing but killers to go on otherwise.
+// Kept between searches, the answers to a move don't change much.
+#[derive(Clone)]
+struct ContinuationHistory(Vec<i16>);
+
+// Scores stay within this, a bonus moves it less the closer it already is.
+const HISTORY_MAX: i32 = 16384;
+
+impl ContinuationHistory {
+    fn new() -> Self {
+        ContinuationHistory(vec![0; NUM_PIECES * 64 * NUM_PIECES * 64])
+    }
+
+    fn index(prev: (Piece, Square), mv: (Piece, Square)) -> usize {
+        (((prev.0 as usize * 64) + prev.1 .0 as usize) * NUM_PIECES + mv.0 as usize) * 64
+            + mv.1 .0 as usize
+    }
+
+    fn get(&self, prev: (Piece, Square), mv: (Piece, Square)) -> i16 {
+        self.0[Self::index(prev, mv)]
+    }
+
+    fn update(&mut self, prev: (Piece, Square), mv: (Piece, Square), bonus: i32) {
+        let score = &mut self.0[Self::index(prev, mv)];
+        let old = *score as i32;
+        *score = (old + bonus - old * bonus.abs() / HISTORY_MAX) as i16;
+    }
+
+    fn clear(&mut self) {
+        self.0.iter_mut().for_each(|score| *score = 0);
+    }
+}
+
+impl fmt::Debug for ContinuationHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ContinuationHistory")
+    }
+}
+
+// The piece and square a move takes it to, what continuation history goes by.
+// `board` is the position before the move, or after it with `after`.
+fn moved_to(board: &Board, mv: &Movement, after: bool) -> Option<(Piece, Square)> {
+    let from = if after { mv.to_square } else { mv.from_square };
+    board.piece_on(from).map(|piece| (piece, mv.to_square))
+}
+
+// Per-ply search state, so alphabeta doesn't need an ever growing list of
+// parameters. Entry `ply` belongs to the node being searched at that ply.
+#[derive(Debug, Clone, Default)]
+struct StackEntry {
+    // Zobrist key of the node, computed by the parent so it can prefetch the TP.
+    key: u64,
+    // Quiet moves that caused a beta cutoff at this ply, tried early in siblings.
+    killers: [Option<Movement>; 2],
+    // The move being searched from this node, and the piece it captured.
+    current_move: Option<Movement>,
+    captured: Option<(Square, Piece)>,
+    // Static eval of the node for the side to move, None in check where it
+    // doesn't mean much. Set once the node is searched, for the pruning to share.
+    static_eval: Option<i16>,
+    // Principal variation from this node, built up from the child's.
+    pv: Vec<Movement>,
+}
+
+// Parts of the search that can be turned off, to see what each one is worth
+// (see the search_stats bin). All on by default, of course.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchFeatures {
+    pub tt_cutoffs: bool,
+    pub upcoming_repetitions: bool,
+    pub check_extensions: bool,
+    pub recapture_extensions: bool,
+    pub killers: bool,
+    pub delta_pruning: bool,
+    pub razoring: bool,
+    pub iid: bool,
+    pub continuation_history: bool,
+}
+
+impl Default for SearchFeatures {
+    fn default() -> Self {
+        SearchFeatures {
+            tt_cutoffs: true,
+            upcoming_repetitions: true,
+            check_extensions: true,
+            recapture_extensions: true,
+            killers: true,
+            delta_pruning: true,
+            razoring: true,
+            iid: true,
+            continuation_history: true,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Searcher {
-    // Transposition table
+    // Transposition table, shared with the helper threads. (see go)
     // TODO: Store PV and use as move guesses for a/b search
-    pub tp: HashMap<Board, SearchResult>,
-    pub tp_max_len: usize,
+    pub tp: Arc<TranspositionTable>,
+    threads: usize,
 
     // Search statistics
-    pub nodes: u64, // including qs!
+    pub nodes: u64,    // including qs!
+    pub seldepth: u16, // deepest ply reached this iteration, including qs
     pub fail_high: u64,
     pub fail_high_first: u64,
+    pub tbhits: u64,
+    pub stats: SearchStats,       // of the current iteration, see stats.rs
+    pub total_stats: SearchStats, // of the whole search so far
+
+    features: SearchFeatures,
 
     // Used so I don't pass fucking everything as a parameter to alphabeta
     start_depth: i16, // start depth of this ID iteration
 
+    // Zobrist keys of the positions leading up to the current node, oldest first.
+    // Starts out as the game history set with set_history, and grows/shrinks during search.
+    history: Vec<u64>,
+
     // Used in should_stop
-    limits: Limits,
+    limits: SearchLimits,
+    thinking_time: Option<Duration>,
+    hard_time: Option<Duration>,
+    emergency_time: Option<Duration>,
     start: Instant,
+    // Set once should_stop says so, checked every node.
+    stopped: bool,
+    // Lets another thread stop the search, see stop_flag.
+    stop: Arc<AtomicBool>,
+    // A ponder search that hasn't had its ponderhit yet, see ponderhit_flag.
+    pondering: bool,
+    ponderhit: Arc<AtomicBool>,
+
+    // Indexed by ply, see StackEntry.
+    stack: Vec<StackEntry>,
+
+    // Opt-in search tree tracing.
+    tracer: Option<Tracer>,
+    // Opt-in statistics for every iteration.
+    stats_log: Option<StatsLog>,
+
+    // Where progress reports go, nowhere by default.
+    info_callback: Option<InfoCallback>,
+    last_info: Instant,
+
+    // Root moves of the last completed iteration, and the one in progress.
+    // With exact_root_scores every root move is searched with a full window, so
+    // their scores are exact instead of upper bounds. (much slower)
+    root_moves: Vec<RootMove>,
+    exact_root_scores: bool,
+
+    // MultiPV: the best `multi_pv` lines of the last completed iteration, each
+    // the best without the moves of the ones before it, see search_other_lines.
+    // While a line is searched, `pv_excluded` are those moves.
+    multi_pv: usize,
+    pv_lines: Vec<RootMove>,
+    pv_excluded: Vec<Movement>,
+
+    // See set_nodestime, available_nodes is our clock in nodes.
+    nodestime: u64,
+    available_nodes: Option<u64>,
+    next_root_moves: Vec<RootMove>,
+
+    // Endgame tablebases, probed at the root and after captures and pawn moves.
+    // Shared since the tables are read into memory as they're needed.
+    tablebases: Arc<Tablebases>,
+    tb_probe_depth: i16,
+    tb_50_move_rule: bool,
+    // Most pieces we probe in search with, 0 for not at all. (see iterative_deepening)
+    tb_cardinality: usize,
+    // The best root moves according to the tablebases, only these get searched.
+    // Empty if the root isn't in the tables.
+    tb_root_moves: Vec<Movement>,
+    tb_score: Option<i16>,
+
+    // Set by advance for the next search to carry on from, see Resume. While a
+    // resumed search runs, `root_guess` is the line it expects and searches first.
+    resume: Option<Resume>,
+    root_guess: Vec<Movement>,
+
+    continuation_history: ContinuationHistory,
+}
+
+// What the last search found that's still good after the move played from its
+// root, see Searcher::advance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Resume {
+    // The depth its PV got searched to from the new root, 0 if the move played
+    // wasn't the PV's.
+    depth: i16,
+    // The PV after the move played, empty if that wasn't the PV's move.
+    pv: Vec<Movement>,
+}
+
+// Log an event for the node at `ply`, if tracing is enabled. (see trace.rs)
+macro_rules! trace {
+    ($self:ident, $ply:expr, $($arg:tt)*) => {
+        if let Some(tracer) = &mut $self.tracer {
+            let line: Vec<Movement> = $self.stack[..$ply as usize]
+                .iter()
+                .filter_map(|e| e.current_move.clone())
+                .collect();
+            tracer.node(&line, format_args!($($arg)*));
+        }
+    };
 }
 
 // TODO: Move this to movement?
@@ -62,134 +543,681 @@ fn moves_to_str(moves: &Vec<Movement>) -> String {
 }
 
 // Sorting is very important for alpha beta search pruning
-fn sort_by_promise(board: &Board, moves: &mut Vec<Movement>) {
+// `history` is extra promise for quiet moves.
+fn sort_by_promise<H: Fn(&Movement) -> i16>(
+    board: &Board,
+    moves: &mut Vec<Movement>,
+    killers: &[Option<Movement>],
+    history: H,
+) {
     // negate eval::get_promise because we're sorting lowest to highest
-    moves.sort_by_cached_key(|m| -eval::get_promise(&board, m));
+    moves.sort_by_cached_key(|m| {
+        let mut promise = eval::get_promise(&board, m);
+        if killers.iter().any(|k| k.as_ref() == Some(m)) {
+            promise += tune::killer_promise();
+        }
+        if !board.is_capture(m) {
+            promise += history(m);
+        }
+        -promise
+    });
 }
 
 impl Searcher {
     pub fn new() -> Self {
-        let mut s = Searcher {
+        Searcher::with_tp(Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)))
+    }
+
+    fn with_tp(tp: Arc<TranspositionTable>) -> Self {
+        Searcher {
             nodes: 0,
-            tp: HashMap::new(),
-            tp_max_len: 0,
+            seldepth: 0,
+            tp,
+            threads: 1,
             start_depth: 0,
-            limits: Limits::none(),
+            history: Vec::new(),
+            limits: SearchLimits::default(),
+            thinking_time: None,
+            hard_time: None,
+            emergency_time: None,
             start: Instant::now(), // never used, reset in search() before a/b
+            stopped: false,
+            stop: Arc::new(AtomicBool::new(false)),
+            pondering: false,
+            ponderhit: Arc::new(AtomicBool::new(false)),
             fail_high_first: 0,
             fail_high: 0,
-        };
+            tbhits: 0,
+            stats: SearchStats::default(),
+            total_stats: SearchStats::default(),
+            features: SearchFeatures::default(),
+            stack: vec![StackEntry::default(); MAX_PLY as usize + 1],
+            tracer: None,
+            stats_log: None,
+            info_callback: None,
+            last_info: Instant::now(),
+            root_moves: Vec::new(),
+            exact_root_scores: false,
+            multi_pv: 1,
+            pv_lines: Vec::new(),
+            pv_excluded: Vec::new(),
+            nodestime: 0,
+            available_nodes: None,
+            next_root_moves: Vec::new(),
+            tablebases: Arc::new(Tablebases::default()),
+            tb_probe_depth: 1,
+            tb_50_move_rule: true,
+            tb_cardinality: 0,
+            tb_root_moves: Vec::new(),
+            tb_score: None,
+            resume: None,
+            root_guess: Vec::new(),
+            continuation_history: ContinuationHistory::new(),
+        }
+    }
 
-        // default to a 64mb hashtable (small)
-        s.set_hash_size(64);
+    // Throws away everything in the TP, so only call this between searches.
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.tp = Arc::new(TranspositionTable::new(mb));
+    }
 
-        return s;
+    // Threads searching together, including this one. See go.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = usize::max(threads, 1);
     }
 
-    pub fn set_hash_size(&mut self, mb: usize) {
-        use std::mem;
-        self.tp_max_len = (1024 * 1024 * mb) / mem::size_of::<Board>();
+    pub fn clear_hash(&mut self) {
+        self.tp.clear();
     }
 
-    pub fn search_depth(&mut self, board: &Board, depth: i16) -> SearchResult {
-        let mut limits = Limits::none();
-        limits.depth = Some(depth);
+    // Set the zobrist keys of the positions played before the one we're going to search,
+    // oldest first. Used to detect repetition draws.
+    pub fn set_history(&mut self, history: Vec<u64>) {
+        self.history = history;
+    }
 
-        self.search(board, limits)
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
     }
 
-    pub fn search_timed(&mut self, board: &Board, thinking_time: Duration) -> SearchResult {
-        let mut limits = Limits::none();
-        limits.thinking_time = Some(thinking_time);
-        self.search(board, limits)
+    pub fn set_stats_log(&mut self, stats_log: Option<StatsLog>) {
+        self.stats_log = stats_log;
     }
 
-    pub fn search(&mut self, board: &Board, limits: Limits) -> SearchResult {
-        self.reset_stats();
+    pub fn set_features(&mut self, features: SearchFeatures) {
+        self.features = features;
+    }
 
-        // so we don't use infinite memory
-        // TODO: Better replacement strategies
-        // https://www.chessprogramming.org/Transposition_Table#Replacement_Strategies
-        // NOTE: Tests rely on TP being available after search to verify PV.
-        // FIXME: max size can be exeeded during iterative deepening or alphabeta!
-        if self.tp.len() > self.tp_max_len {
-            eprintln!("hash clear, len {} max {}", self.tp.len(), self.tp_max_len);
-            self.tp.clear();
+    pub fn features(&self) -> SearchFeatures {
+        self.features
+    }
+
+    // Called with a SearchInfo after every iteration, and every now and then during
+    // long ones. The UCI engine prints them, library users can do whatever.
+    pub fn set_info_callback<F: FnMut(SearchInfo) + Send + 'static>(&mut self, callback: F) {
+        self.info_callback = Some(InfoCallback(Box::new(callback)));
+    }
+
+    // Setting the flag stops the search as soon as possible, and keeps stopping
+    // every search after it until cleared. Searches don't clear it themselves, so
+    // a stop that comes in before the search gets going isn't lost.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    // Setting the flag tells a ponder search the opponent played the move we
+    // expected. From then on it's a normal search, its clock starting then. Like
+    // stop, searches don't clear it, so clear it before starting one.
+    pub fn ponderhit_flag(&self) -> Arc<AtomicBool> {
+        self.ponderhit.clone()
+    }
+
+    fn info(&mut self, info: SearchInfo) {
+        if let Some(InfoCallback(callback)) = &mut self.info_callback {
+            callback(info);
+            self.last_info = Instant::now();
+        }
+    }
+
+    // Stats for the middle of an iteration, when we have no new score or pv to report.
+    fn progress_info(&mut self) {
+        let currmove = self.stack[0].current_move.clone();
+        self.info(SearchInfo {
+            nodes: Some(self.nodes),
+            nps: Some(self.nps()),
+            hashfull: Some(self.hashfull()),
+            tbhits: self.tbhits(),
+            time: Some(self.start.elapsed()),
+            currmove,
+            ..SearchInfo::default()
+        });
+    }
+
+    // Only reported when we have tablebases.
+    fn tbhits(&self) -> Option<u64> {
+        Some(self.tbhits).filter(|_| !self.tablebases.is_empty())
+    }
+
+    fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.start.elapsed().as_secs_f64()) as u64
+    }
+
+    // Every legal move at the root with its score, best first, as of the last
+    // completed iteration.
+    pub fn root_moves(&self) -> &[RootMove] {
+        &self.root_moves
+    }
+
+    // The reply we expect to `mv` from the last search, for the GUI to ponder on.
+    // Comes from the root move's PV, or the TP when the root never got searched.
+    pub fn ponder_move(&self, board: &Board, mv: &Movement) -> Option<Movement> {
+        let rm = self.root_moves.iter().find(|rm| &rm.mv == mv);
+        if let Some(reply) = rm.and_then(|rm| rm.pv.get(1)) {
+            return Some(reply.clone());
+        }
+
+        // Could be a key collision, so make sure it's legal.
+        let after = board.make_move(mv);
+        let reply = self.get_pv_next(&after)?;
+        MoveGen::new_legal(&after).find(|legal| *legal == reply)
+    }
+
+    // Count time in nodes instead of milliseconds, `nodestime` nodes per ms (0 to
+    // turn it off), so games with a time control play out the same on any hardware.
+    // Like Stockfish, we keep our own clock in nodes from the first move on and
+    // ignore what the GUI says our time is.
+    pub fn set_nodestime(&mut self, nodestime: u64) {
+        self.nodestime = nodestime;
+        self.available_nodes = None;
+    }
+
+    // Forget everything specific to the last game, but keep the settings (hash
+    // size, nodestime, ...) so the next game plays the same.
+    pub fn new_game(&mut self) {
+        self.available_nodes = None;
+        self.tp.clear();
+        self.history.clear();
+        self.root_moves.clear();
+        self.next_root_moves.clear();
+        self.resume = None;
+        self.continuation_history.clear();
+        for entry in &mut self.stack {
+            *entry = StackEntry::default();
+        }
+    }
+
+    // The position we searched last went on with `mv`, and the next search is of
+    // the position after it (live analysis, see engine::AnalysisSession). Instead
+    // of starting cold that search keeps the killers, a ply closer to the root,
+    // and if `mv` was what we expected carries on from the PV and its depth. The
+    // TP is kept between searches anyway.
+    pub fn advance(&mut self, mv: &Movement) {
+        let best = self
+            .root_moves
+            .first()
+            .filter(|rm| rm.pv.first() == Some(mv));
+        self.resume = Some(match best {
+            Some(rm) => Resume {
+                depth: rm.depth - 1,
+                pv: rm.pv[1..].to_vec(),
+            },
+            None => Resume {
+                depth: 0,
+                pv: Vec::new(),
+            },
+        });
+
+        let killers: Vec<_> = self
+            .stack
+            .iter()
+            .map(|entry| entry.killers.clone())
+            .collect();
+        for (entry, killers) in self.stack.iter_mut().zip(killers.into_iter().skip(1)) {
+            entry.killers = killers;
+        }
+        self.stack.last_mut().unwrap().killers = Default::default();
+    }
+
+    pub fn set_exact_root_scores(&mut self, exact: bool) {
+        self.exact_root_scores = exact;
+    }
+
+    pub fn set_tablebases(&mut self, tablebases: Tablebases) {
+        self.tablebases = Arc::new(tablebases);
+    }
+
+    // Only probe the tables in search with at least this much depth left, for
+    // positions with as many pieces as the biggest tables. Those are the most
+    // expensive to probe.
+    pub fn set_tb_probe_depth(&mut self, depth: i16) {
+        self.tb_probe_depth = depth;
+    }
+
+    // With the fifty move rule on, cursed wins and blessed losses are draws.
+    pub fn set_tb_50_move_rule(&mut self, rule50: bool) {
+        self.tb_50_move_rule = rule50;
+    }
+
+    // Search the best `lines` lines instead of just the best one, each with its
+    // own exact score and PV. Every extra line is another search of the root, so
+    // this costs depth.
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.max(1);
+    }
+
+    // The lines of the last completed iteration, best first. Just the one
+    // without set_multi_pv.
+    pub fn pv_lines(&self) -> &[RootMove] {
+        &self.pv_lines
+    }
+
+    // How full the TP is with this search's entries, in permille.
+    pub fn hashfull(&self) -> u16 {
+        self.tp.hashfull()
+    }
+
+    // Search the board until one of the limits is hit, and return the best move.
+    pub fn go(&mut self, board: &Board, mut limits: SearchLimits) -> SearchResult {
+        let side = board.side_to_move;
+        let (clock, increment) = match side {
+            Color::White => (limits.wtime, limits.winc),
+            Color::Black => (limits.btime, limits.binc),
+        };
+        let use_nodestime = self.nodestime > 0 && clock.is_some();
+        if use_nodestime {
+            // Pretend our clock is what we have left in nodes, and search the nodes
+            // we would get for the time we'd think.
+            let available = *self
+                .available_nodes
+                .get_or_insert(clock.unwrap().as_millis() as u64 * self.nodestime);
+            let clock = Some(Duration::from_millis(available / self.nodestime));
+            match side {
+                Color::White => limits.wtime = clock,
+                Color::Black => limits.btime = clock,
+            }
+
+            if let Some(time) = limits.thinking_time(side) {
+                let nodes = time.as_millis() as u64 * self.nodestime;
+                limits.nodes = Some(limits.nodes.map_or(nodes, |n| n.min(nodes)));
+                limits.wtime = None;
+                limits.btime = None;
+                limits.movetime = None;
+            }
+        }
+
+        self.tp.new_search();
+
+        // Lazy SMP: helper threads search the same position, sharing our TP. They
+        // don't report anything, but the main search gets to use what they find.
+        let helper_stop = Arc::new(AtomicBool::new(false));
+        let helper_limits = SearchLimits {
+            depth: limits.depth,
+            infinite: true,
+            search_moves: limits.search_moves.clone(),
+            exclude_moves: limits.exclude_moves.clone(),
+            ..SearchLimits::default()
+        };
+        let helpers: Vec<_> = (1..self.threads)
+            .map(|id| {
+                let mut helper = Searcher::with_tp(self.tp.clone());
+                helper.stop = helper_stop.clone();
+                helper.history = self.history.clone();
+                helper.tablebases = self.tablebases.clone();
+                helper.tb_probe_depth = self.tb_probe_depth;
+                helper.tb_50_move_rule = self.tb_50_move_rule;
+                helper.features = self.features;
+                let board = board.clone();
+                let limits = helper_limits.clone();
+                thread::spawn(move || helper.helper_search(&board, id, limits))
+            })
+            .collect();
+
+        let sr = self.iterative_deepening(board, limits);
+
+        helper_stop.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            helper.join().expect("search helper panicked");
         }
 
+        if use_nodestime {
+            let available = self.available_nodes.unwrap_or(0).saturating_sub(self.nodes);
+            let increment = increment.unwrap_or_default().as_millis() as u64 * self.nodestime;
+            self.available_nodes = Some(available + increment);
+        }
+
+        sr
+    }
+
+    // Reset everything for a new search of `board`.
+    fn start_search(&mut self, board: &Board, limits: SearchLimits) {
+        self.reset_stats();
+
         // TODO: Move start to uci code, we want to get start as soon as possible,
         // so we don't lose on time in scary 1s lightning games.
         // For now, we just subtract a little time to get some buffer.
         self.start = Instant::now() - Duration::from_millis(1);
+        self.last_info = self.start;
+        self.thinking_time = limits.thinking_time(board.side_to_move);
+        self.hard_time = limits.hard_time(board.side_to_move);
+        self.emergency_time = limits.emergency_time(board.side_to_move);
         self.limits = limits;
+        self.stopped = false;
+        self.pondering = self.limits.ponder && !self.ponderhit.load(Ordering::Relaxed);
+        self.root_moves.clear();
+        self.pv_lines.clear();
+        let resume = self.resume.take();
+        for entry in &mut self.stack {
+            let killers = entry.killers.clone();
+            *entry = StackEntry::default();
+            if resume.is_some() {
+                entry.killers = killers;
+            }
+        }
+        self.stack[0].key = zobrist::hash(board);
+        self.rank_tb_root_moves(board);
+        self.root_guess = resume.map(|r| r.pv).unwrap_or_default();
+    }
+
+    // A helper thread's search for Lazy SMP, see go. Every other helper starts a
+    // ply deeper, so they aren't all searching the same depth as the main thread.
+    fn helper_search(&mut self, board: &Board, id: usize, limits: SearchLimits) {
+        self.start_search(board, limits);
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as i16 / 2);
+        let mut depth = 1 + (id % 2) as i16;
+
+        while !self.stopped && depth <= max_depth {
+            self.start_depth = depth;
+            self.alphabeta(board, depth, 0, -INFINITY, INFINITY);
+            depth += 1;
+        }
+    }
 
-        let mut depth = 1;
+    fn iterative_deepening(&mut self, board: &Board, limits: SearchLimits) -> SearchResult {
+        // A resumed search already has the shallow depths in the TP.
+        let resume_depth = self.resume.as_ref().map_or(0, |r| r.depth);
+        self.start_search(board, limits);
+        let mut depth = resume_depth.clamp(1, self.limits.depth.unwrap_or(MAX_PLY as i16));
 
         loop {
             self.start_depth = depth;
+            self.seldepth = 0;
+            self.stats = SearchStats::default();
+
+            self.next_root_moves.clear();
+            self.alphabeta(board, depth, 0, -INFINITY, INFINITY);
+            // Before other MultiPV lines replace it.
+            let line = if self.stopped {
+                Vec::new()
+            } else {
+                self.stack[0].pv.clone()
+            };
+            let other_lines = if self.multi_pv > 1 && !self.stopped {
+                self.search_other_lines(board, depth)
+            } else {
+                Vec::new()
+            };
+            self.total_stats.add(&self.stats);
+            if let Some(tracer) = &mut self.tracer {
+                tracer.flush();
+            }
+            // Empty if the root came straight from the TP, keep what we had then.
+            if !self.stopped && !self.next_root_moves.is_empty() {
+                // Stable, so equal scores stay in the order we searched them.
+                self.next_root_moves.sort_by_key(|rm| -rm.score);
+                std::mem::swap(&mut self.root_moves, &mut self.next_root_moves);
+                if !self.root_guess.is_empty() {
+                    self.root_guess = self.root_moves[0].pv.clone();
+                }
+            }
+            let nps = self.nps();
+            let pv = self.get_pv(board, &line);
+
+            let sr = self.best_so_far(board);
+            // In the tables the search can't see the result, unless it's a mate.
+            let (score, bound) = match self.tb_score {
+                Some(tb_score) if !is_mate_score(sr.eval) => (tb_score, Bound::Exact),
+                _ => (sr.eval * board.side_to_move.polarize(), sr.bound),
+            };
+            if let Some(log) = &mut self.stats_log {
+                log.iteration(&IterationStats {
+                    depth,
+                    seldepth: self.seldepth,
+                    score,
+                    completed: !self.stopped,
+                    time: self.start.elapsed(),
+                    stats: self.stats.clone(),
+                });
+            }
 
-            self.alphabeta(board, depth, -INFINITY, INFINITY);
-            let nps = (self.nodes as f64 / self.start.elapsed().as_secs_f64()) as u64;
-            let pv = self.get_pv(board);
+            log::debug!(
+                "depth {} score {} nodes {} time {:?}{} pv {}",
+                depth,
+                sr.eval * board.side_to_move.polarize(),
+                self.nodes,
+                self.start.elapsed(),
+                if self.stopped { " (stopped)" } else { "" },
+                pv.iter()
+                    .map(|mv| mv.to_notation())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
 
-            let sr = self.tp.get(board).expect("no PV move in TP");
+            if !self.stopped || self.pv_lines.is_empty() {
+                self.pv_lines = vec![RootMove {
+                    mv: sr.mv.clone(),
+                    score,
+                    depth,
+                    pv: pv.clone(),
+                }];
+                self.pv_lines.extend_from_slice(&other_lines);
+            }
 
-            // NOTE: Maybe we shoulden't print this if alphabeta prematurely exited?
+            // NOTE: Maybe we shoulden't report this if alphabeta prematurely exited?
             // I think its fine though, since we don't update PV on premature exit.
             // This might signify a depth greater then what we actually searched
             // though.
-            println!(
-                "info depth {} score cp {} nodes {} nps {} time {} pv {}",
-                depth,
-                sr.eval,
-                self.nodes,
-                nps,
-                self.start.elapsed().as_millis(),
-                moves_to_str(&pv),
-            );
-            /*
-            eprintln!(
+            let info = SearchInfo {
+                depth: Some(depth),
+                seldepth: Some(self.seldepth),
+                multipv: if self.multi_pv > 1 { Some(1) } else { None },
+                score: Some(score),
+                bound: Some(bound).filter(|&bound| bound != Bound::Exact),
+                nodes: Some(self.nodes),
+                nps: Some(nps),
+                hashfull: Some(self.hashfull()),
+                tbhits: self.tbhits(),
+                time: Some(self.start.elapsed()),
+                pv,
+                ..SearchInfo::default()
+            };
+            self.info(info.clone());
+            for (i, line) in other_lines.into_iter().enumerate() {
+                self.info(SearchInfo {
+                    multipv: Some(i as u16 + 2),
+                    score: Some(line.score),
+                    bound: None,
+                    pv: line.pv,
+                    ..info.clone()
+                });
+            }
+            log::trace!(
                 "move ordering {}/{} = {:.4}",
                 self.fail_high_first,
                 self.fail_high,
-                self.fail_high_first as f64 / self.fail_high as f64
+                self.fail_high_first as f64 / self.fail_high.max(1) as f64
             );
-            */
 
             // Bound ply because of possible recursion limit in endgames.
-            if self.should_stop() || depth >= self.limits.depth.unwrap_or(1000) {
-                return sr.clone();
+            self.check_ponderhit();
+            if self.stopped
+                || self.should_stop()
+                || self.thought_enough()
+                || depth >= self.limits.depth.unwrap_or(1000)
+                || self.found_mate(board, &sr, depth)
+            {
+                return sr;
             }
             depth += 1;
         }
     }
 
+    // MultiPV lines after the first at `depth`, each the best line without the
+    // moves of the lines before it. Fewer if there aren't enough root moves, or
+    // the search is stopped.
+    fn search_other_lines(&mut self, board: &Board, depth: i16) -> Vec<RootMove> {
+        let root_moves = match MoveGen::new_legal(board)
+            .filter(|mv| self.limits.searches_move(mv))
+            .count()
+        {
+            0 => MoveGen::new_legal(board).count(),
+            n => n,
+        };
+
+        let mut lines = Vec::new();
+        self.pv_excluded = vec![self.best_so_far(board).mv];
+        while self.pv_excluded.len() < usize::min(self.multi_pv, root_moves) {
+            let score = self.alphabeta(board, depth, 0, -INFINITY, INFINITY);
+            if self.stopped {
+                break;
+            }
+            let pv = self.stack[0].pv.clone();
+            self.pv_excluded.push(pv[0].clone());
+            lines.push(RootMove {
+                mv: pv[0].clone(),
+                score,
+                depth,
+                pv,
+            });
+        }
+        self.pv_excluded.clear();
+        lines
+    }
+
+    // The root's TP entry, or if that got replaced (tiny hash, helper threads) or
+    // never made it there (stopped early), the best root move we know of. At worst
+    // any legal move, a bad move still beats losing on time.
+    fn best_so_far(&self, board: &Board) -> SearchResult {
+        let legal = |mv: &Movement| MoveGen::new_legal(board).any(|m| &m == mv);
+        if let Some(sr) = self
+            .tp
+            .get(self.stack[0].key)
+            .filter(|sr| legal(&sr.mv) && self.limits.searches_move(&sr.mv))
+        {
+            return sr;
+        }
+        if let Some(rm) = self.root_moves.first() {
+            return SearchResult {
+                eval: rm.score * board.side_to_move.polarize(),
+                mv: rm.mv.clone(),
+                depth: rm.depth,
+                bound: Bound::Exact,
+            };
+        }
+        // The tables' best moves are as good as any search.
+        let searches = |mv: &Movement| self.limits.searches_move(mv);
+        let mv = self
+            .tb_root_moves
+            .iter()
+            .find(|mv| searches(mv))
+            .cloned()
+            .or_else(|| MoveGen::new_legal(board).find(|mv| searches(mv)))
+            .or_else(|| MoveGen::new_legal(board).next())
+            .expect("searching a position without legal moves");
+        SearchResult {
+            eval: 0,
+            mv,
+            depth: 0,
+            bound: Bound::Exact,
+        }
+    }
+
+    fn out_of_time(&self) -> bool {
+        !self.pondering
+            && self
+                .emergency_time
+                .is_some_and(|time| self.start.elapsed() >= time)
+    }
+
     // Should a A/B search stop? uses self.limits
     pub fn should_stop(&self) -> bool {
-        if let Some(thinking_time) = self.limits.thinking_time {
-            self.start.elapsed() > thinking_time
-        } else {
-            false
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                return true;
+            }
+        }
+
+        match self.hard_time {
+            Some(hard_time) if !self.pondering => self.start.elapsed() > hard_time,
+            _ => false,
+        }
+    }
+
+    // The clock starts when pondering turns into a normal search.
+    fn check_ponderhit(&mut self) {
+        if self.pondering && self.ponderhit.load(Ordering::Relaxed) {
+            self.pondering = false;
+            self.start = Instant::now();
         }
     }
 
-    // TODO: Perhaps keep pv state and update from alphabeta?
-    // Need to see how stockfish does it.
+    // Each iteration takes about as long as all the ones before it, so one
+    // started after half the thinking time would end well past it, or be cut
+    // short by the hard time for nothing. With movetime all of it is ours to
+    // use, and the deadline stops the search mid iteration.
+    fn thought_enough(&self) -> bool {
+        !self.pondering
+            && self.limits.movetime.is_none()
+            && self
+                .thinking_time
+                .is_some_and(|time| self.start.elapsed() * 2 > time)
+    }
+
+    // Are we done looking for a mate? (see SearchLimits::mate)
+    // Either we found one short enough, or we searched deep enough that we would have.
+    fn found_mate(&self, board: &Board, sr: &SearchResult, depth: i16) -> bool {
+        let mate = match self.limits.mate {
+            Some(mate) => mate as i16,
+            None => return false,
+        };
+
+        let score = sr.eval * board.side_to_move.polarize();
+        let mate_plies = eval::MATE - score;
+        (is_mate_score(score) && score > 0 && mate_plies < 2 * mate) || depth >= 2 * mate - 1
+    }
+
+    // `line` (the PV alphabeta built up) as far as it goes, then on through
+    // exact TP entries. The built up PV ends where a TP entry cut the search
+    // off, the entry knows how it goes on. A bound doesn't know the best move,
+    // so the PV ends there.
     // NOTE: If we aren't careful, transpositions will cause an infinite loop.
-    fn get_pv(&self, board: &Board) -> Vec<Movement> {
+    // Positions are compared by key, the boards differ in their halfmove clocks.
+    fn get_pv(&self, board: &Board, line: &[Movement]) -> Vec<Movement> {
         use std::collections::HashSet;
 
         let mut moves = Vec::new();
         let mut curr = board.clone();
         let mut seen = HashSet::new();
-
-        while let Some(mv) = self.get_pv_next(&curr) {
+        seen.insert(zobrist::hash(&curr));
+
+        let mut line = line.iter().cloned();
+        while let Some(mv) = line.next().or_else(|| {
+            self.tp
+                .get(zobrist::hash(&curr))
+                .filter(|sr| sr.bound == Bound::Exact)
+                .map(|sr| sr.mv)
+        }) {
+            // Another thread could have put something else there since.
+            if moves.len() >= MAX_PLY as usize || !MoveGen::new_legal(&curr).any(|m| m == mv) {
+                break;
+            }
             curr.make_move_mut(&mv);
-            if seen.contains(&curr) {
-                // eprintln!("transposition!\n{}\nlastmove: {}", curr, mv);
+            if !seen.insert(zobrist::hash(&curr)) {
                 break;
             }
-            seen.insert(curr.clone());
             moves.push(mv.clone());
         }
 
@@ -200,36 +1228,233 @@ impl Searcher {
         self.nodes = 0;
         self.fail_high = 0;
         self.fail_high_first = 0;
+        self.tbhits = 0;
+        self.total_stats = SearchStats::default();
+    }
+
+    // If the root is in the tablebases, only search the moves that keep the
+    // best result. Then DTZ has already picked moves that make progress, so we
+    // stop probing in search, as we also do when we can't win anyway. The
+    // tables are for standard chess, other variants never probe them.
+    fn rank_tb_root_moves(&mut self, board: &Board) {
+        self.tb_cardinality = self.tablebases.max_pieces();
+        self.tb_root_moves.clear();
+        self.tb_score = None;
+        if board.variant != Variant::Standard {
+            self.tb_cardinality = 0;
+            return;
+        }
+
+        let ranking =
+            match self
+                .tablebases
+                .rank_root_moves(board, &self.history, self.tb_50_move_rule)
+            {
+                Some(ranking) => ranking,
+                None => return,
+            };
+        let best = &ranking.moves[0];
+        self.tb_root_moves = ranking
+            .moves
+            .iter()
+            .filter(|rm| rm.rank == best.rank)
+            .map(|rm| rm.mv.clone())
+            .collect();
+        self.tb_score = Some(best.score);
+        self.tbhits = ranking.moves.len() as u64;
+        if ranking.dtz || best.score <= 0 {
+            self.tb_cardinality = 0;
+        }
     }
 
     // Get the next PV move
     // NOTE: This assumes the TP will always hold the deepest search for a given board.
     // TODO: Remove this function?
-    fn get_pv_next(&self, board: &Board) -> Option<&Movement> {
-        Some(&self.tp.get(board)?.mv)
+    fn get_pv_next(&self, board: &Board) -> Option<Movement> {
+        Some(self.tp.get(zobrist::hash(board))?.mv)
     }
 
-    // alphabeta search in a negamax framework.
-    // 'alpha' is always our best score,
-    // 'beta' is always our opponent's best possible score
-    pub fn alphabeta(&mut self, board: &Board, mut depth: i16, mut alpha: i16, beta: i16) -> i16 {
-        if self.should_stop() {
+    // Has the position been seen before, since the last irreversible move?
+    // Positions repeat at the earliest two plies apart, with the same side to move.
+    fn is_repetition(&self, board: &Board, key: u64) -> bool {
+        self.history
+            .iter()
+            .rev()
+            .take(board.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&k| k == key)
+    }
+
+    // Can the side to move repeat a position from earlier in the search with one
+    // move? Then they can at least draw, without us searching the shuffling.
+    // Only looks inside the search tree, the move back is legal there for sure.
+    fn has_upcoming_repetition(&self, board: &Board, key: u64, ply: u16) -> bool {
+        let end = usize::min(board.halfmove_clock as usize, ply as usize - 1);
+        let end = usize::min(end, self.history.len());
+
+        // Other side to move, so an odd number of plies ago.
+        (3..=end).step_by(2).any(|i| {
+            let earlier = self.history[self.history.len() - i];
+            match zobrist::cuckoo_move(key ^ earlier) {
+                Some((s1, s2)) => zobrist::between(s1, s2) & board.combined() == BitBoard::empty(),
+                None => false,
+            }
+        })
+    }
+
+    // Every CHECK_TIME_NODES nodes, see if we should stop and send a progress
+    // info if one is due. Depth 1 always finishes, so we have a move to play no
+    // matter how early we stop. Unless finishing it would lose on time.
+    fn check_time(&mut self) {
+        if self.nodes & (CHECK_TIME_NODES - 1) == 0 {
+            self.check_ponderhit();
+            if (self.start_depth > 1 && self.should_stop()) || self.out_of_time() {
+                self.stopped = true;
+            } else if self.info_callback.is_some() && self.last_info.elapsed() >= INFO_INTERVAL {
+                self.progress_info();
+            }
+        }
+    }
+
+    // alphabeta search in a negamax framework.
+    // 'alpha' is always our best score,
+    // 'beta' is always our opponent's best possible score
+    // 'ply' is the distance from the root, and our index into self.stack
+    pub fn alphabeta(
+        &mut self,
+        board: &Board,
+        mut depth: i16,
+        ply: u16,
+        mut alpha: i16,
+        beta: i16,
+    ) -> i16 {
+        if depth < 0 {
+            return self.quiesce(board, ply, alpha, beta);
+        }
+
+        self.check_time();
+        if self.stopped {
+            trace!(self, ply, "stopped");
             return 0;
         }
 
         self.nodes += 1;
+        self.stats.nodes += 1;
+        self.seldepth = u16::max(self.seldepth, ply);
+        self.stack[ply as usize].pv.clear();
+        trace!(self, ply, "depth {} alpha {} beta {}", depth, alpha, beta);
+
+        // Out of stack, can only happen with absurd amounts of extensions.
+        if ply >= MAX_PLY {
+            return eval::get_score_ongoing(board) * board.side_to_move.polarize();
+        }
+
+        // Won by the variant's rules (three checks), scored like a mate that many plies away.
+        if let Some((winner, reason)) = board.variant.winner(board) {
+            let score = if winner == board.side_to_move {
+                eval::MATE - ply as i16
+            } else {
+                -eval::MATE + ply as i16
+            };
+            trace!(self, ply, "{} score {}", reason, score);
+            return score;
+        }
+
+        // Score twofold repetitions as draws, if we can force a repetition so can the
+        // opponent, so there is no point in searching further.
+        // TODO: Checkmate takes priority over the 50 move rule.
+        let key = self.stack[ply as usize].key;
+        if ply > 0 && (board.halfmove_clock >= 100 || self.is_repetition(board, key)) {
+            trace!(self, ply, "draw by repetition or fifty move rule");
+            self.stats.repetitions += 1;
+            return 0;
+        }
 
-        if let Some(sr) = self.tp.get(board) {
-            if sr.depth >= depth {
-                return sr.eval * board.side_to_move.polarize();
+        // Cuckoo moves can't tell a check from any other move, and checks count
+        // in three-check, so they aren't reversible there.
+        if ply > 0
+            && alpha < 0
+            && self.features.upcoming_repetitions
+            && !board.variant.counts_checks()
+            && self.has_upcoming_repetition(board, key, ply)
+        {
+            alpha = 0;
+            if alpha >= beta {
+                trace!(self, ply, "upcoming repetition");
+                self.stats.upcoming_repetitions += 1;
+                return alpha;
             }
+        }
+
+        let mut tt_move = None;
+        if let Some(sr) = self.tp.get(key) {
+            self.stats.tt_hits += 1;
+            // The root entry might be from a search of different root moves.
+            let root_excluding = ply == 0
+                && (self.limits.restricts_root()
+                    || !self.tb_root_moves.is_empty()
+                    || !self.pv_excluded.is_empty());
+            if sr.depth >= depth && !root_excluding && self.features.tt_cutoffs {
+                // A bound is only enough if it's outside the window. We don't
+                // narrow the window with it, the search would fail soft past
+                // the bound and store that as the score.
+                let score = score_from_tp(sr.eval * board.side_to_move.polarize(), ply);
+                let cutoff = match sr.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    trace!(
+                        self,
+                        ply,
+                        "tp cutoff depth {} score {} {:?}",
+                        sr.depth,
+                        score,
+                        sr.bound
+                    );
+                    self.stats.tt_cutoffs += 1;
+                    return score;
+                }
+            }
+
+            // Not deep enough to trust the score, but the move is a good guess.
+            tt_move = Some(sr.mv);
+        }
 
-            // TODO: Use sr as guess for the best move,
-            // even if the depth is not sufficent to return it immediately.
+        // After a capture or pawn move, the tables know how this ends. Positions
+        // as big as the biggest tables are expensive to probe, so only near the root.
+        let pieces = board.combined().count_ones() as usize;
+        if ply > 0
+            && depth > 0
+            && board.halfmove_clock == 0
+            && pieces <= self.tb_cardinality
+            && (pieces < self.tb_cardinality || depth >= self.tb_probe_depth)
+        {
+            if let Some(wdl) = self.tablebases.probe_wdl(board) {
+                self.tbhits += 1;
+
+                // Wins score below mate, and closer wins score higher. Without
+                // the fifty move rule cursed wins are wins, with it they're a
+                // draw that's a little better than a draw. A mate the search
+                // could still find would be better than a win, but we take it.
+                let draw = if self.tb_50_move_rule { 1 } else { 0 };
+                let value = wdl as i16;
+                let score = if value > draw {
+                    syzygy::TB_WIN - ply as i16
+                } else if value < -draw {
+                    -syzygy::TB_WIN + ply as i16
+                } else {
+                    2 * value * draw
+                };
+                trace!(self, ply, "tablebase {:?} score {}", wdl, score);
+                return score;
+            }
         }
 
-        // TODO: Check game over without generating all legal moves (expensive). Since
-        // MoveGen lazily checks legality, this would be A LOT more efficent because of pruning.
+        // We search all of them anyway, so there's no point asking
+        // movegen::has_legal_move first.
         let mut moves: Vec<Movement> = MoveGen::new_legal(board).collect();
         let is_game_over = moves.len() == 0;
 
@@ -237,45 +1462,203 @@ impl Searcher {
         if is_game_over {
             // Easier to inline instead of calling `eval::get_score`
             // and then have to check if it returned eval::MATE.
+            // Mated closer to the root is worse, so we prefer the fastest mate.
             let score = if board.in_check() {
-                -depth - eval::MATE
+                -eval::MATE + ply as i16
             } else {
                 0
             };
 
+            trace!(self, ply, "game over score {}", score);
             return score;
         }
 
+        if ply == 0 && !self.tb_root_moves.is_empty() {
+            let tb_root_moves = &self.tb_root_moves;
+            moves.retain(|mv| tb_root_moves.contains(mv));
+        }
+
+        // If every move is excluded (or none we were asked for is legal) there is
+        // nothing else to search, so ignore the restrictions.
+        if ply == 0 && self.limits.restricts_root() {
+            let limits = &self.limits;
+            if moves.iter().any(|mv| limits.searches_move(mv)) {
+                moves.retain(|mv| limits.searches_move(mv));
+            }
+        }
+        if ply == 0 && !self.pv_excluded.is_empty() {
+            moves.retain(|mv| !self.pv_excluded.contains(mv));
+        }
+
+        self.stack[ply as usize].static_eval = if board.in_check() {
+            None
+        } else {
+            Some(eval::get_score_ongoing(board) * board.side_to_move.polarize())
+        };
+
         // So simple, yet so effective!
-        if board.in_check() {
+        if board.in_check() && self.features.check_extensions {
             depth += 1;
+            self.stats.check_extensions += 1;
         }
 
-        if depth < 0 {
-            // Quiet search!
-            let score = eval::get_score(board, is_game_over) * board.side_to_move.polarize();
-            // It is our move, so if the static score is already better then
-            // Our previous best score, we can just return the static eval.
-            // FIXME: If we're in zugzwang, then this will prematurely prune.
-            if score >= alpha {
-                return score;
+        // Razoring: this close to the horizon, a position that is far below alpha
+        // won't get back up with a quiet move. If quiescence can't find a capture
+        // that does either, take its word for it instead of searching every move.
+        // Not at PV nodes, where a wrong guess would be the score we report.
+        let pv_node = beta as i32 - alpha as i32 > 1;
+        if let Some(static_eval) = self.stack[ply as usize].static_eval {
+            let margin = tune::razor_margin() as i32 * (depth as i32 + 1);
+            if self.features.razoring
+                && !pv_node
+                && ply > 0
+                && depth <= tune::razor_depth()
+                && !is_mate_score(alpha)
+                && static_eval as i32 + margin <= alpha as i32
+            {
+                let score = self.quiesce(board, ply, alpha, alpha + 1);
+                if score <= alpha {
+                    trace!(self, ply, "razored score {}", score);
+                    self.stats.razors += 1;
+                    return score;
+                }
             }
+        }
 
-            moves.retain(|mv| board.is_capture(&mv));
-
-            if moves.len() == 0 {
-                // End of QS, no captures remain
-                return score;
+        // Internal iterative deepening: a PV node without a hash move (never seen,
+        // or overwritten) would be searched in static order, so search it a bit
+        // shallower first and take the move that search stores.
+        if tt_move.is_none() && pv_node && self.features.iid && depth >= tune::iid_depth() {
+            self.stats.iid_searches += 1;
+            self.alphabeta(board, depth - tune::iid_reduction(), ply, alpha, beta);
+            if self.stopped {
+                return 0;
             }
+            // At the root the shallower search recorded its moves too, the full
+            // search below records them again.
+            if ply == 0 {
+                self.next_root_moves.clear();
+            }
+            tt_move = self.tp.get(key).map(|sr| sr.mv);
         }
 
-        sort_by_promise(board, &mut moves);
+        // The opponent's move that got us here, None at the root.
+        let prev = match ply {
+            0 => None,
+            _ => self.stack[ply as usize - 1]
+                .current_move
+                .as_ref()
+                .and_then(|prev| moved_to(board, prev, true))
+                .filter(|_| self.features.continuation_history),
+        };
+        let history = &self.continuation_history;
+        sort_by_promise(
+            board,
+            &mut moves,
+            &self.stack[ply as usize].killers,
+            |mv| match (prev, moved_to(board, mv, false)) {
+                (Some(prev), Some(mv)) => {
+                    (history.get(prev, mv) as i32 * tune::continuation_promise() as i32
+                        / HISTORY_MAX) as i16
+                }
+                _ => 0,
+            },
+        );
+        if let Some(i) = tt_move.and_then(|tm| moves.iter().position(|mv| *mv == tm)) {
+            let guess = moves.remove(i);
+            moves.insert(0, guess);
+        }
+        if ply == 0 {
+            if let Some(i) = moves
+                .iter()
+                .position(|mv| self.root_guess.first() == Some(mv))
+            {
+                let guess = moves.remove(i);
+                moves.insert(0, guess);
+            }
+        }
+        trace!(self, ply, "moves {}", moves_to_str(&moves));
 
+        let original_alpha = alpha;
         let mut score = -INFINITY;
         let mut best_move = moves[0].clone(); // moves len > 0 else gameover and return
+        let mut quiets_tried = Vec::new();
 
+        self.history.push(key);
         for (i, mv) in moves.into_iter().enumerate() {
-            let mv_score = -self.alphabeta(&board.make_move(&mv), depth - 1, -beta, -alpha);
+            if ply == 0 && self.start.elapsed() > CURRMOVE_AFTER {
+                self.info(SearchInfo {
+                    currmove: Some(mv.clone()),
+                    currmovenumber: Some(i + 1),
+                    ..SearchInfo::default()
+                });
+            }
+
+            // Even recaptures (taking back the same kind of piece on the same
+            // square) are forced most of the time, so extend them to see the end
+            // of the exchange. Only inside the nominal depth though, or check and
+            // recapture extensions feed each other and the tree blows up.
+            let capture = if board.is_capture(&mv) {
+                board
+                    .piece_on(mv.to_square)
+                    .map(|piece| (mv.to_square, piece))
+            } else {
+                None
+            };
+            let extension = if self.features.recapture_extensions
+                && depth > 0
+                && depth <= tune::recapture_extension_depth()
+                && ply < self.start_depth as u16
+                && capture.is_some()
+                && ply > 0
+                && capture == self.stack[ply as usize - 1].captured
+            {
+                self.stats.recapture_extensions += 1;
+                1
+            } else {
+                0
+            };
+
+            if prev.is_some() && capture.is_none() {
+                quiets_tried.push(mv.clone());
+            }
+            let entry = &mut self.stack[ply as usize];
+            entry.current_move = Some(mv.clone());
+            entry.captured = capture;
+            let child = board.make_move(&mv);
+            let child_key = zobrist::hash(&child);
+            self.tp.prefetch(child_key);
+            self.stack[ply as usize + 1].key = child_key;
+            let child_alpha = if ply == 0 && self.exact_root_scores {
+                -INFINITY
+            } else {
+                alpha
+            };
+            let mv_score =
+                -self.alphabeta(&child, depth - 1 + extension, ply + 1, -beta, -child_alpha);
+            let entry = &mut self.stack[ply as usize];
+            entry.current_move = None;
+            entry.captured = None;
+
+            if ply == 0 && !self.stopped && self.pv_excluded.is_empty() {
+                let mut pv = vec![mv.clone()];
+                pv.extend_from_slice(&self.stack[1].pv);
+                self.next_root_moves.push(RootMove {
+                    mv: mv.clone(),
+                    score: mv_score,
+                    depth,
+                    pv,
+                });
+            }
+
+            if mv_score > alpha {
+                let (ours, children) = self.stack.split_at_mut(ply as usize + 1);
+                let pv = &mut ours[ply as usize].pv;
+                pv.clear();
+                pv.push(mv.clone());
+                pv.extend_from_slice(&children[0].pv);
+            }
+
             if mv_score > score {
                 score = mv_score;
                 best_move = mv;
@@ -287,28 +1670,169 @@ impl Searcher {
                 // Used to measure move ordering, we want to fail high first
                 // as much as possible. (ie. bring best move to front.)
                 self.fail_high += 1;
+                self.stats.beta_cutoffs += 1;
                 if i == 0 {
                     self.fail_high_first += 1;
+                    self.stats.first_move_cutoffs += 1;
+                }
+                trace!(self, ply, "beta cutoff by {} (move {})", best_move, i + 1);
+
+                let killers = &mut self.stack[ply as usize].killers;
+                if self.features.killers
+                    && !board.is_capture(&best_move)
+                    && killers[0].as_ref() != Some(&best_move)
+                {
+                    killers[1] = killers[0].take();
+                    killers[0] = Some(best_move.clone());
+                }
+
+                // The quiet move that cut off is a good answer to prev, and the
+                // ones tried before it weren't.
+                if let Some(prev) = prev.filter(|_| !board.is_capture(&best_move)) {
+                    let bonus = i32::min(depth as i32 * depth as i32, HISTORY_MAX / 4);
+                    for mv in quiets_tried.iter().filter(|mv| **mv != best_move) {
+                        if let Some(mv) = moved_to(board, mv, false) {
+                            self.continuation_history.update(prev, mv, -bonus);
+                        }
+                    }
+                    if let Some(mv) = moved_to(board, &best_move, false) {
+                        self.continuation_history.update(prev, mv, bonus);
+                    }
                 }
                 break;
             }
         }
-
-        // Storing in TP after stop is too dangerous
-        if !self.should_stop() {
-            // Will always be deepest search of this position, since
-            // if there was a deeper search already, we would have returned it.
+        self.history.pop();
+        trace!(self, ply, "best {} score {}", best_move, score);
+
+        // Storing in TP after stop is too dangerous. MultiPV lines after the
+        // first would replace the root's best move with theirs.
+        if !self.stopped && (ply > 0 || self.pv_excluded.is_empty()) {
+            // Deeper than any entry we have for the position, unless that was
+            // a bound on the wrong side of the window.
+            let bound = if score >= beta {
+                Bound::Lower
+            } else if score <= original_alpha {
+                Bound::Upper
+            } else {
+                Bound::Exact
+            };
             self.tp.insert(
-                board.clone(),
-                SearchResult {
-                    eval: score * board.side_to_move.polarize(),
-                    depth: depth,
+                key,
+                &SearchResult {
+                    eval: score_to_tp(score, ply) * board.side_to_move.polarize(),
+                    depth,
                     mv: best_move,
+                    bound,
                 },
             );
         }
         score
     }
+
+    // Past the horizon only captures are searched, until the position is quiet
+    // enough for the static eval to mean something. Otherwise every depth ends
+    // with a capture that looks like it wins a piece, because the recapture is
+    // one ply too far to see.
+    //
+    // The side to move doesn't have to capture, so the static eval (stand pat)
+    // is a lower bound on the score. Except in check, where every evasion is
+    // searched and there's no standing pat.
+    fn quiesce(&mut self, board: &Board, ply: u16, mut alpha: i16, beta: i16) -> i16 {
+        self.check_time();
+        if self.stopped {
+            trace!(self, ply, "stopped");
+            return 0;
+        }
+
+        self.nodes += 1;
+        self.stats.nodes += 1;
+        self.stats.qs_nodes += 1;
+        self.seldepth = u16::max(self.seldepth, ply);
+        self.stack[ply as usize].pv.clear();
+        trace!(self, ply, "qs alpha {} beta {}", alpha, beta);
+
+        if ply >= MAX_PLY {
+            return eval::get_score_ongoing(board) * board.side_to_move.polarize();
+        }
+        if let Some((winner, reason)) = board.variant.winner(board) {
+            let score = if winner == board.side_to_move {
+                eval::MATE - ply as i16
+            } else {
+                -eval::MATE + ply as i16
+            };
+            trace!(self, ply, "{} score {}", reason, score);
+            return score;
+        }
+
+        let in_check = board.in_check();
+        let mut moves: Vec<Movement> = MoveGen::new_legal(board).collect();
+        if in_check && moves.is_empty() {
+            trace!(self, ply, "mated");
+            return -eval::MATE + ply as i16;
+        }
+
+        let mut score = -INFINITY;
+        if !in_check {
+            let stand_pat = eval::get_score_ongoing(board) * board.side_to_move.polarize();
+            if stand_pat >= beta {
+                trace!(self, ply, "stand pat score {}", stand_pat);
+                self.stats.stand_pats += 1;
+                return stand_pat;
+            }
+            score = stand_pat;
+            alpha = i16::max(alpha, stand_pat);
+            moves.retain(|mv| board.is_capture(mv));
+
+            // Delta pruning: a capture that doesn't get us to alpha even if we
+            // win the piece for free (plus a margin, for what else the capture
+            // does) isn't worth searching. Promotions are worth more than the
+            // piece, and in three-check a capture with check can win outright.
+            if self.features.delta_pruning && !board.variant.counts_checks() {
+                let captures = moves.len();
+                let best_case = |mv: &Movement| {
+                    let captured = board
+                        .piece_on(mv.to_square)
+                        .map_or(0, eval::get_piece_value);
+                    stand_pat as i32 + captured as i32 + tune::delta_margin() as i32
+                };
+                moves.retain(|mv| mv.promote.is_some() || best_case(mv) > alpha as i32);
+                self.stats.delta_prunes += (captures - moves.len()) as u64;
+            }
+        }
+
+        sort_by_promise(board, &mut moves, &[], |_| 0);
+        trace!(self, ply, "captures {}", moves_to_str(&moves));
+
+        for (i, mv) in moves.into_iter().enumerate() {
+            self.stack[ply as usize].current_move = Some(mv.clone());
+            let child = board.make_move(&mv);
+            let mv_score = -self.quiesce(&child, ply + 1, -beta, -alpha);
+            self.stack[ply as usize].current_move = None;
+
+            if mv_score > score {
+                score = mv_score;
+            }
+            if mv_score > alpha {
+                alpha = mv_score;
+                let (ours, children) = self.stack.split_at_mut(ply as usize + 1);
+                let pv = &mut ours[ply as usize].pv;
+                pv.clear();
+                pv.push(mv);
+                pv.extend_from_slice(&children[0].pv);
+            }
+            if alpha >= beta {
+                self.stats.beta_cutoffs += 1;
+                if i == 0 {
+                    self.stats.first_move_cutoffs += 1;
+                }
+                trace!(self, ply, "beta cutoff (move {})", i + 1);
+                break;
+            }
+        }
+        trace!(self, ply, "qs score {}", score);
+        score
+    }
 }
 
 #[cfg(test)]
@@ -323,11 +1847,65 @@ mod tests {
                 .unwrap();
 
         let mut moves = MoveGen::new_legal(&board).collect();
-        sort_by_promise(&board, &mut moves);
+        sort_by_promise(&board, &mut moves, &[], |_| 0);
 
         assert_eq!(moves[0], Movement::from_notation("h5f7").unwrap());
     }
 
+    #[test]
+    fn test_sort_by_promise_killers() {
+        let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K1N1 w - - 0 1").unwrap();
+        let killer = Movement::from_notation("g1f3").unwrap();
+
+        let mut moves = MoveGen::new_legal(&board).collect();
+        sort_by_promise(&board, &mut moves, &[None, Some(killer.clone())], |_| 0);
+
+        assert_eq!(moves[0], Movement::from_notation("e4d5").unwrap());
+        assert_eq!(moves[1], killer);
+    }
+
+    #[test]
+    fn test_continuation_history() {
+        let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K1N1 w - - 0 1").unwrap();
+        let mut history = ContinuationHistory::new();
+        let prev = (Piece::Pawn, Square::from_notation("d5").unwrap());
+        let knight = moved_to(&board, &Movement::from_notation("g1f3").unwrap(), false);
+        assert_eq!(
+            knight,
+            Some((Piece::Knight, Square::from_notation("f3").unwrap()))
+        );
+
+        // Bonuses pile up but never past the max.
+        for _ in 0..1000 {
+            history.update(prev, knight.unwrap(), 400);
+        }
+        let score = history.get(prev, knight.unwrap());
+        assert!(score > 0 && score as i32 <= HISTORY_MAX, "{}", score);
+        assert_eq!(history.get(prev, (Piece::Knight, Square(0))), 0);
+
+        // Quiet moves only, the capture still comes first.
+        let mut moves = MoveGen::new_legal(&board).collect();
+        sort_by_promise(&board, &mut moves, &[], |mv| {
+            (history.get(prev, moved_to(&board, mv, false).unwrap()) as i32
+                * tune::continuation_promise() as i32
+                / HISTORY_MAX) as i16
+        });
+        assert_eq!(moves[0], Movement::from_notation("e4d5").unwrap());
+        assert_eq!(moves[1], Movement::from_notation("g1f3").unwrap());
+
+        // Searches fill it in, and new games start over.
+        let mut s = Searcher::new();
+        s.go(&Board::from_start_pos(), SearchLimits::depth(4));
+        assert!(s.continuation_history.0.iter().any(|&h| h != 0));
+        s.new_game();
+        s.set_features(SearchFeatures {
+            continuation_history: false,
+            ..SearchFeatures::default()
+        });
+        s.go(&Board::from_start_pos(), SearchLimits::depth(4));
+        assert!(s.continuation_history.0.iter().all(|&h| h == 0));
+    }
+
     #[test]
     fn test_pv_deepest_startpos() {
         let depth = 4;
@@ -338,26 +1916,435 @@ mod tests {
         // Even when transposition's occur.
         let mut s = Searcher::new();
         let mut board = Board::from_start_pos();
-        let sr = s.search_depth(&board, depth);
-        let sr_tp = s.tp.get(&board).unwrap().clone();
+        let sr = s.go(&board, SearchLimits::depth(depth));
+        let sr_tp = s.tp.get(zobrist::hash(&board)).unwrap();
         assert_eq!(&sr, &sr_tp);
 
         board.make_move_mut(&sr_tp.mv);
-        let sr_tp = s.tp.get(&board).unwrap().clone();
+        let sr_tp = s.tp.get(zobrist::hash(&board)).unwrap();
         assert_eq!(sr_tp.depth, depth - 1);
 
         board.make_move_mut(&sr_tp.mv);
-        let sr_tp = s.tp.get(&board).unwrap().clone();
+        let sr_tp = s.tp.get(zobrist::hash(&board)).unwrap();
         assert_eq!(sr_tp.depth, depth - 2);
     }
 
+    #[test]
+    fn test_trace_max_ply() {
+        use crate::trace::TraceFilter;
+
+        let path = std::env::temp_dir().join("yobmef_test_trace_max_ply.txt");
+        let filter = TraceFilter {
+            max_ply: Some(1),
+            subtree: Vec::new(),
+        };
+
+        let mut s = Searcher::new();
+        s.set_tracer(Some(Tracer::to_file(&path, filter).unwrap()));
+        s.go(&Board::from_start_pos(), SearchLimits::depth(2));
+        s.set_tracer(None);
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            trace.contains("[] depth 2 alpha"),
+            "root missing:\n{}",
+            trace
+        );
+        assert!(trace.contains("  [e2e4] depth 1 alpha"));
+        assert!(!trace.contains("    ["), "ply 2 should be filtered");
+    }
+
+    #[test]
+    fn test_stats_log() {
+        let path = std::env::temp_dir().join("yobmef_test_stats_log.jsonl");
+        let mut s = Searcher::new();
+        s.set_stats_log(Some(StatsLog::to_file(&path).unwrap()));
+        s.go(&Board::from_start_pos(), SearchLimits::depth(3));
+        s.set_stats_log(None);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", log);
+        assert!(lines[0].starts_with("{\"depth\":1,\"seldepth\":"));
+        assert!(lines[2].starts_with("{\"depth\":3,"));
+        assert!(lines[2].contains("\"completed\":true"));
+        // The last iteration's stats are still there.
+        assert!(s.stats.nodes > 0 && s.stats.nodes <= s.nodes);
+        assert!(s.stats.qs_nodes > 0);
+        assert!(s.stats.first_move_cutoffs <= s.stats.beta_cutoffs);
+        assert!(lines[2].contains(&format!("\"nodes\":{},", s.stats.nodes)));
+    }
+
+    #[test]
+    fn test_features() {
+        // Lots of checks and recaptures.
+        let board =
+            Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        let mut s = Searcher::new();
+        s.go(&board, SearchLimits::depth(3));
+        assert!(s.total_stats.nodes >= s.stats.nodes && s.total_stats.nodes == s.nodes);
+        assert!(s.total_stats.check_extensions > 0);
+        assert!(s.total_stats.tt_cutoffs > 0);
+
+        s.clear_hash();
+        s.set_features(SearchFeatures {
+            check_extensions: false,
+            tt_cutoffs: false,
+            ..SearchFeatures::default()
+        });
+        let sr = s.go(&board, SearchLimits::depth(3));
+        assert_eq!(s.total_stats.check_extensions, 0);
+        assert_eq!(s.total_stats.tt_cutoffs, 0);
+        // Still finds mate.
+        assert_eq!(sr.mv, Movement::from_notation("h5f7").unwrap());
+    }
+
+    #[test]
+    fn test_razoring() {
+        // A queen down with nothing to take back, a ply from the horizon.
+        let board = Board::from_fen("k7/8/8/8/8/8/q7/7K w - - 0 1").unwrap();
+        let mut s = Searcher::new();
+        s.stack[1].key = zobrist::hash(&board);
+        let score = s.alphabeta(&board, 1, 1, 0, 1);
+        assert!(score < 0);
+        assert_eq!(s.stats.razors, 1);
+
+        // Not at PV nodes, or with razoring off.
+        s.alphabeta(&board, 1, 1, 0, 500);
+        assert_eq!(s.stats.razors, 1);
+        s.set_features(SearchFeatures {
+            razoring: false,
+            ..SearchFeatures::default()
+        });
+        s.alphabeta(&board, 1, 1, 0, 1);
+        assert_eq!(s.stats.razors, 1);
+    }
+
+    #[test]
+    fn test_recapture_extension() {
+        use crate::trace::TraceFilter;
+
+        let path = std::env::temp_dir().join("yobmef_test_recapture_extension.txt");
+        let filter = TraceFilter {
+            max_ply: Some(2),
+            subtree: vec![
+                Movement::from_notation("f3e5").unwrap(),
+                Movement::from_notation("d6e5").unwrap(),
+            ],
+        };
+
+        let mut s = Searcher::new();
+        s.set_tracer(Some(Tracer::to_file(&path, filter).unwrap()));
+        s.go(
+            &Board::from_fen("4k3/8/3p4/4n3/8/5N2/8/4K3 w - - 0 1").unwrap(),
+            SearchLimits::depth(2),
+        );
+        s.set_tracer(None);
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Without the extension, the recapture would be searched at depth 0.
+        assert!(
+            trace.contains("[f3e5 d6e5] depth 1 "),
+            "recapture not extended:\n{}",
+            trace
+        );
+    }
+
+    #[test]
+    fn test_quiesce() {
+        let mut s = Searcher::new();
+        let score = |s: &mut Searcher, fen: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            let stand_pat = eval::get_score_ongoing(&board) * board.side_to_move.polarize();
+            (s.quiesce(&board, 0, -INFINITY, INFINITY), stand_pat)
+        };
+
+        // Taking the pawn loses the queen, so don't.
+        let (qs, stand_pat) = score(&mut s, "4k3/8/3p4/4p3/8/8/4Q3/4K3 w - - 0 1");
+        assert_eq!(qs, stand_pat);
+        // The rook is free.
+        let (qs, stand_pat) = score(&mut s, "4k3/8/8/4r3/8/8/4Q3/4K3 w - - 0 1");
+        assert!(qs > stand_pat + 400, "{} {}", qs, stand_pat);
+        // In check there's no standing pat, and no way out is mate.
+        let (qs, _) = score(&mut s, "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(qs, -eval::MATE);
+        assert!(s.total_stats.qs_nodes == 0 && s.stats.qs_nodes > 0);
+
+        // A queen down, winning a pawn won't get us back to alpha.
+        let board = Board::from_fen("3qk3/8/8/4p3/8/8/4R3/4K3 w - - 0 1").unwrap();
+        let (_, stand_pat) = score(&mut s, &board.to_fen());
+        let alpha = stand_pat + 500;
+        s.quiesce(&board, 0, alpha, alpha + 1);
+        assert_eq!(s.stats.delta_prunes, 1);
+        s.set_features(SearchFeatures {
+            delta_pruning: false,
+            ..SearchFeatures::default()
+        });
+        s.quiesce(&board, 0, alpha, alpha + 1);
+        assert_eq!(s.stats.delta_prunes, 1);
+    }
+
+    #[test]
+    fn test_iid() {
+        let board = Board::from_start_pos();
+        let mut s = Searcher::new();
+        s.stack[0].key = zobrist::hash(&board);
+        // Nothing in the hash for the root, so the first move comes from a
+        // shallower search, which leaves an entry behind.
+        let score = s.alphabeta(&board, 4, 0, -INFINITY, INFINITY);
+        assert!(s.stats.iid_searches > 0);
+        assert_eq!(s.tp.get(s.stack[0].key).unwrap().depth, 4);
+
+        // Null windows aren't PV nodes.
+        let iid_searches = s.stats.iid_searches;
+        s.clear_hash();
+        s.alphabeta(&board, 4, 0, score - 1, score);
+        assert_eq!(s.stats.iid_searches, iid_searches);
+
+        s.clear_hash();
+        s.set_features(SearchFeatures {
+            iid: false,
+            ..SearchFeatures::default()
+        });
+        s.alphabeta(&board, 4, 0, -INFINITY, INFINITY);
+        assert_eq!(s.stats.iid_searches, iid_searches);
+    }
+
+    #[test]
+    fn test_iid_root_moves() {
+        let board = Board::from_start_pos();
+        let mut s = Searcher::new();
+        s.stack[0].key = zobrist::hash(&board);
+        s.alphabeta(&board, 4, 0, -INFINITY, INFINITY);
+        assert!(s.stats.iid_searches > 0);
+
+        // Each root move once, from the full depth search.
+        let legal = MoveGen::new_legal(&board).count();
+        assert_eq!(s.next_root_moves.len(), legal);
+        for (i, rm) in s.next_root_moves.iter().enumerate() {
+            assert_eq!(rm.depth, 4);
+            assert!(s.next_root_moves[..i].iter().all(|other| other.mv != rm.mv));
+        }
+    }
+
+    #[test]
+    fn test_info_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        {
+            let infos = infos.clone();
+            s.set_info_callback(move |info| infos.lock().unwrap().push(info));
+        }
+
+        // Black is up a queen, and to move.
+        let board = Board::from_fen("4k3/8/8/8/8/8/q7/4K3 b - - 0 1").unwrap();
+        let sr = s.go(&board, SearchLimits::depth(3));
+
+        let infos = infos.lock().unwrap();
+        let depths: Vec<_> = infos.iter().filter_map(|info| info.depth).collect();
+        assert_eq!(depths, vec![1, 2, 3]);
+
+        let last = infos.last().unwrap();
+        assert!(last.score.unwrap() > 500, "score {:?}", last.score);
+        assert_eq!(last.pv[0], sr.mv);
+    }
+
+    #[test]
+    fn test_progress_info() {
+        use std::sync::{Arc, Mutex};
+
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        {
+            let infos = infos.clone();
+            s.set_info_callback(move |info| infos.lock().unwrap().push(info));
+        }
+
+        s.go(
+            &Board::from_start_pos(),
+            SearchLimits::movetime(Duration::from_millis(1500)),
+        );
+
+        // Root moves say when they start, after the first second.
+        let infos = infos.lock().unwrap();
+        let root_moves: Vec<_> = infos
+            .iter()
+            .filter(|info| info.currmovenumber.is_some())
+            .collect();
+        assert!(!root_moves.is_empty(), "infos {:?}", infos);
+        for info in root_moves {
+            assert!(info.currmove.is_some() && info.currmovenumber >= Some(1));
+            assert!(info.nodes.is_none() && info.pv.is_empty());
+        }
+
+        // Stats without a pv are from the middle of an iteration.
+        let progress: Vec<_> = infos
+            .iter()
+            .filter(|info| info.pv.is_empty() && info.nodes.is_some())
+            .collect();
+        assert!(!progress.is_empty(), "infos {:?}", infos);
+        for info in progress {
+            assert!(info.nps.is_some() && info.hashfull.is_some() && info.time.is_some());
+            assert!(info.currmove.is_some());
+        }
+    }
+
+    #[test]
+    fn test_mate_in() {
+        assert_eq!(mate_in(eval::MATE - 1), Some(1));
+        assert_eq!(mate_in(eval::MATE - 3), Some(2));
+        assert_eq!(mate_in(-eval::MATE), Some(0));
+        assert_eq!(mate_in(-eval::MATE + 2), Some(-1));
+        assert_eq!(mate_in(-eval::MATE + 4), Some(-2));
+        assert_eq!(mate_in(900), None);
+
+        let info = SearchInfo {
+            score: Some(-eval::MATE + 4),
+            ..SearchInfo::default()
+        };
+        assert_eq!(info.to_string(), "info score mate -2");
+    }
+
+    #[test]
+    fn test_search_info_display() {
+        let info = SearchInfo {
+            depth: Some(3),
+            score: Some(-42),
+            time: Some(Duration::from_millis(1500)),
+            pv: vec![
+                Movement::from_notation("e2e4").unwrap(),
+                Movement::from_notation("e7e5").unwrap(),
+            ],
+            ..SearchInfo::default()
+        };
+        assert_eq!(
+            info.to_string(),
+            "info depth 3 score cp -42 time 1500 pv e2e4 e7e5"
+        );
+
+        let info = SearchInfo {
+            currmove: Some(Movement::from_notation("g1f3").unwrap()),
+            currmovenumber: Some(2),
+            ..SearchInfo::default()
+        };
+        assert_eq!(info.to_string(), "info currmove g1f3 currmovenumber 2");
+
+        let info = SearchInfo {
+            score: Some(35),
+            bound: Some(Bound::Lower),
+            ..SearchInfo::default()
+        };
+        assert_eq!(info.to_string(), "info score cp 35 lowerbound");
+        let info = SearchInfo {
+            score: Some(-eval::MATE + 4),
+            bound: Some(Bound::Upper),
+            ..SearchInfo::default()
+        };
+        assert_eq!(info.to_string(), "info score mate -2 upperbound");
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let mut s = Searcher::new();
+        s.set_hash_size(1);
+        assert_eq!(s.hashfull(), 0);
+
+        s.go(&Board::from_start_pos(), SearchLimits::depth(4));
+        let hashfull = s.hashfull();
+        assert!(0 < hashfull && hashfull <= 1000, "hashfull {}", hashfull);
+    }
+
+    #[test]
+    fn test_seldepth() {
+        // Lots of captures, so quiet search has to go past the nominal depth.
+        let board =
+            Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")
+                .unwrap();
+
+        let mut s = Searcher::new();
+        s.go(&board, SearchLimits::depth(3));
+        assert!(s.seldepth > 3, "seldepth {}", s.seldepth);
+    }
+
     #[test]
     fn test_pv_deepest_mate2() {
         let mut s = Searcher::new();
         let board = Board::from_fen("8/p4p1k/3p1P2/1p1br3/3p4/1Pr5/P6K/8 b - - 0 1").unwrap();
-        let sr = s.search_depth(&board, 5);
-        let sr_tp = s.tp.get(&board).unwrap();
-        assert_eq!(&sr, sr_tp);
+        let sr = s.go(&board, SearchLimits::depth(5));
+        let sr_tp = s.tp.get(zobrist::hash(&board)).unwrap();
+        assert_eq!(sr, sr_tp);
+    }
+
+    #[test]
+    fn test_tp_kept_between_moves() {
+        let mut s = Searcher::new();
+        let board = Board::from_start_pos();
+        let sr = s.go(&board, SearchLimits::depth(5));
+
+        // What we learned about the last move is still there for this one.
+        let next = board.make_move(&sr.mv);
+        s.go(&next, SearchLimits::depth(3));
+        assert_eq!(s.tp.get(zobrist::hash(&board)), Some(sr));
+
+        s.new_game();
+        assert!(s.tp.is_empty());
+    }
+
+    #[test]
+    fn test_pv_cycle() {
+        // Knights going back and forth, the boards only differ by halfmove clock.
+        let s = Searcher::new();
+        let mut board = Board::from_start_pos();
+        for mv in &["g1f3", "g8f6", "f3g1", "f6g8"] {
+            let mv = Movement::from_notation(mv).unwrap();
+            let sr = SearchResult {
+                eval: 0,
+                mv: mv.clone(),
+                depth: 1,
+                bound: Bound::Exact,
+            };
+            s.tp.insert(zobrist::hash(&board), &sr);
+            board.make_move_mut(&mv);
+        }
+        let pv = s.get_pv(&Board::from_start_pos(), &[]);
+        assert_eq!(moves_to_str(&pv), "g1f3 g8f6 f3g1");
+    }
+
+    #[test]
+    fn test_pv_line() {
+        let s = Searcher::new();
+        let mut board = Board::from_start_pos();
+        let line: Vec<_> = ["e2e4", "e7e5"]
+            .iter()
+            .map(|mv| Movement::from_notation(mv).unwrap())
+            .collect();
+        for mv in &line {
+            board.make_move_mut(mv);
+        }
+
+        // The line, then the TP's exact entries, up to the first bound.
+        for (mv, bound) in &[("g1f3", Bound::Exact), ("b8c6", Bound::Upper)] {
+            let mv = Movement::from_notation(mv).unwrap();
+            let sr = SearchResult {
+                eval: 0,
+                mv: mv.clone(),
+                depth: 1,
+                bound: *bound,
+            };
+            s.tp.insert(zobrist::hash(&board), &sr);
+            board.make_move_mut(&mv);
+        }
+        let pv = s.get_pv(&Board::from_start_pos(), &line);
+        assert_eq!(moves_to_str(&pv), "e2e4 e7e5 g1f3");
     }
 
     // The principled variation should always be legal.
@@ -371,8 +2358,8 @@ mod tests {
         eprintln!("board:\n{}", board);
 
         let mut s = Searcher::new();
-        s.search_depth(&board, 4);
-        let pv = s.get_pv(&board);
+        s.go(&board, SearchLimits::depth(4));
+        let pv = s.get_pv(&board, &[]);
         assert_eq!(moves_to_str(&pv), "h5f7");
     }
 
@@ -382,9 +2369,648 @@ mod tests {
         eprintln!("board:\n{}", board);
 
         let mut s = Searcher::new();
-        s.search_depth(&board, 5);
-        let pv = s.get_pv(&board);
-        assert_eq!(moves_to_str(&pv), "e5e2 h2g1 c3c1");
+        s.go(&board, SearchLimits::depth(5));
+        let pv = s.get_pv(&board, &[]);
+
+        // There are two mates in 2 (e5e2 h2g1 c3c1 and e5e1 .. e1h1), either is fine
+        // as long as the PV plays it out legally.
+        assert_eq!(pv.len(), 3, "pv {}", moves_to_str(&pv));
+        let mut curr = board.clone();
+        for mv in &pv {
+            assert!(
+                MoveGen::new_legal(&curr).any(|m| &m == mv),
+                "illegal {}",
+                mv
+            );
+            curr.make_move_mut(mv);
+        }
+        assert!(curr.in_check() && MoveGen::new_legal(&curr).count() == 0);
+    }
+
+    #[test]
+    fn test_mate_distance_through_tp() {
+        // Mate in 2 for black, the score must stay exact as deeper searches
+        // pick up mate scores stored in the TP from a different ply.
+        let board = Board::from_fen("8/p4p1k/3p1P2/1p1br3/3p4/1Pr5/P6K/8 b - - 0 1").unwrap();
+
+        let mut s = Searcher::new();
+        for depth in 3..=6 {
+            let sr = s.go(&board, SearchLimits::depth(depth));
+            assert_eq!(sr.eval, -(eval::MATE - 3), "depth {}", depth);
+            assert!(is_mate_score(sr.eval));
+        }
+    }
+
+    #[test]
+    fn test_tp_bounds() {
+        let board = Board::from_start_pos();
+        let key = zobrist::hash(&board);
+        let deep = |bound| SearchResult {
+            eval: -500,
+            mv: Movement::from_notation("e2e4").unwrap(),
+            depth: 20,
+            bound,
+        };
+
+        // The root's window is everything, so only an exact score will do.
+        let mut s = Searcher::new();
+        for bound in &[Bound::Lower, Bound::Upper] {
+            s.clear_hash();
+            s.tp.insert(key, &deep(*bound));
+            let sr = s.go(&board, SearchLimits::depth(3));
+            assert!(sr.eval.abs() < 100, "{:?} {}", bound, sr.eval);
+        }
+        s.clear_hash();
+        s.tp.insert(key, &deep(Bound::Exact));
+        assert_eq!(s.go(&board, SearchLimits::depth(3)).eval, -500);
+    }
+
+    #[test]
+    fn test_root_moves() {
+        let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K1N1 w - - 0 1").unwrap();
+        let mut s = Searcher::new();
+        let sr = s.go(&board, SearchLimits::depth(3));
+
+        let root_moves = s.root_moves();
+        assert_eq!(root_moves.len(), MoveGen::new_legal(&board).count());
+        assert_eq!(root_moves[0].mv, sr.mv);
+        assert_eq!(root_moves[0].score, sr.eval);
+        for (i, rm) in root_moves.iter().enumerate() {
+            assert_eq!(rm.depth, 3);
+            assert_eq!(rm.pv[0], rm.mv);
+            if i > 0 {
+                assert!(rm.score <= root_moves[i - 1].score);
+            }
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        use std::sync::{Arc, Mutex};
+
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        {
+            let infos = infos.clone();
+            s.set_info_callback(move |info| infos.lock().unwrap().push(info));
+        }
+        let depths = |infos: &Mutex<Vec<SearchInfo>>| -> Vec<i16> {
+            infos
+                .lock()
+                .unwrap()
+                .drain(..)
+                .filter_map(|info| info.depth)
+                .collect()
+        };
+
+        let board = Board::from_start_pos();
+        s.go(&board, SearchLimits::depth(5));
+        depths(&infos);
+        let pv = s.root_moves()[0].pv.clone();
+        let killers = s.stack[1].killers.clone();
+        s.advance(&pv[0]);
+        assert_eq!(
+            s.resume,
+            Some(Resume {
+                depth: 4,
+                pv: pv[1..].to_vec()
+            })
+        );
+        assert_eq!(s.stack[0].killers, killers);
+
+        // The expected move carries on at the depth we got to.
+        let after = board.make_move(&pv[0]);
+        s.go(&after, SearchLimits::depth(5));
+        assert_eq!(depths(&infos), vec![4, 5]);
+        assert_eq!(s.resume, None);
+
+        // Anything else starts over, with the killers.
+        let other = MoveGen::new_legal(&after)
+            .find(|mv| *mv != s.root_moves()[0].mv)
+            .unwrap();
+        s.advance(&other);
+        s.go(&after.make_move(&other), SearchLimits::depth(3));
+        assert_eq!(depths(&infos), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_exact_root_scores() {
+        let board = Board::from_fen("4k3/8/8/3p4/4P3/8/8/4K1N1 w - - 0 1").unwrap();
+
+        // Every root move searched on its own is what the exact scores should say.
+        let mut s = Searcher::new();
+        s.set_exact_root_scores(true);
+        s.go(&board, SearchLimits::depth(2));
+        for rm in s.root_moves() {
+            let mut single = Searcher::new();
+            let score = -single
+                .go(&board.make_move(&rm.mv), SearchLimits::depth(1))
+                .eval
+                * board.side_to_move.other().polarize();
+            assert_eq!(rm.score, score, "{}", rm.mv);
+        }
+    }
+
+    #[test]
+    fn test_upcoming_repetition() {
+        // Play the moves, returning the position and a searcher with the history.
+        let play = |fen: &str, moves: &[&str]| {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut s = Searcher::new();
+            for mv in moves {
+                s.history.push(zobrist::hash(&board));
+                board.make_move_mut(&Movement::from_notation(mv).unwrap());
+            }
+            (board, s)
+        };
+
+        // f6g8 goes back to the start position, 3 plies ago.
+        let start = Board::from_start_pos().to_fen();
+        let (board, s) = play(&start, &["g1f3", "g8f6", "f3g1"]);
+        let key = zobrist::hash(&board);
+        assert!(s.has_upcoming_repetition(&board, key, 4));
+        // Not when the start position was before the root.
+        assert!(!s.has_upcoming_repetition(&board, key, 3));
+
+        // a1a3 would repeat, if the pawn wasn't in the way.
+        let moves = ["a3b3", "e8d8", "b3b1", "d8e8", "b1a1"];
+        let (board, s) = play("4k3/8/8/8/8/R7/8/4K3 w - - 0 1", &moves);
+        assert!(s.has_upcoming_repetition(&board, zobrist::hash(&board), 6));
+        let (board, s) = play("4k3/8/8/8/8/R7/P7/4K3 w - - 0 1", &moves);
+        assert!(!s.has_upcoming_repetition(&board, zobrist::hash(&board), 6));
+    }
+
+    #[test]
+    fn test_nodestime() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(3)),
+            btime: Some(Duration::from_secs(3)),
+            ..SearchLimits::default()
+        };
+        let play = || {
+            let mut s = Searcher::new();
+            s.set_nodestime(10);
+            let mut board = Board::from_start_pos();
+            let mut nodes = Vec::new();
+            for _ in 0..4 {
+                let sr = s.go(&board, limits.clone());
+                nodes.push(s.nodes);
+                board.make_move_mut(&sr.mv);
+            }
+            (board, nodes)
+        };
+
+        // 3s is 30000 nodes, and we think for a 30th of that.
+        let (board, nodes) = play();
+        assert!(nodes[0] <= 1000 + CHECK_TIME_NODES, "nodes {:?}", nodes);
+        // The GUI clock didn't move, but ours did.
+        assert!(nodes[2] < nodes[0], "nodes {:?}", nodes);
+        // Same every time, however fast the machine is.
+        assert_eq!(play(), (board, nodes));
+    }
+
+    #[test]
+    fn test_go_mate() {
+        let board = Board::from_fen("8/p4p1k/3p1P2/1p1br3/3p4/1Pr5/P6K/8 b - - 0 1").unwrap();
+        let limits = |mate| SearchLimits {
+            mate: Some(mate),
+            ..SearchLimits::default()
+        };
+
+        // Stops as soon as the mate in 2 is found, check extensions may find it
+        // before depth 3.
+        let mut s = Searcher::new();
+        let sr = s.go(&board, limits(2));
+        assert_eq!(sr.eval, -(eval::MATE - 3));
+        assert!(sr.depth <= 3, "depth {}", sr.depth);
+
+        // There's no mate in 1, so give up after looking 1 ply deep.
+        let mut s = Searcher::new();
+        let sr = s.go(&board, limits(1));
+        assert_ne!(sr.eval, -(eval::MATE - 1));
+        assert_eq!(sr.depth, 1);
+    }
+
+    #[test]
+    fn test_three_check() {
+        // Qxf7+ hangs the queen, unless it's the third check.
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5Q2/PPPP1PPP/RNB1KBNR w KQkq - 1+3 0 1";
+        let qxf7 = Movement::from_notation("f3f7").unwrap();
+        let board = Board::from_fen(fen).unwrap();
+        let sr = Searcher::new().go(&board, SearchLimits::depth(3));
+        assert_eq!(sr.mv, qxf7);
+        assert_eq!(sr.eval, eval::MATE - 1);
+
+        let mut board = board.clone();
+        board.variant = Variant::Standard;
+        let sr = Searcher::new().go(&board, SearchLimits::depth(3));
+        assert_ne!(sr.mv, qxf7);
+    }
+
+    #[test]
+    fn test_go_nodes() {
+        let mut s = Searcher::new();
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+        let sr = s.go(&Board::from_start_pos(), limits.clone());
+        assert!(s.nodes <= 5000 + CHECK_TIME_NODES, "nodes {}", s.nodes);
+        assert!(s.nodes >= 5000, "nodes {}", s.nodes);
+
+        // Same nodes, same search, for testing engines against each other.
+        let nodes = s.nodes;
+        s.new_game();
+        assert_eq!(s.go(&Board::from_start_pos(), limits), sr);
+        assert_eq!(s.nodes, nodes);
+    }
+
+    #[test]
+    fn test_stop_mid_iteration() {
+        let board = Board::from_start_pos();
+        let mut s = Searcher::new();
+        let stop = s.stop_flag();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            stop.store(true, Ordering::Relaxed);
+            Instant::now()
+        });
+        // Nowhere near done with depth 30 when told to stop, but it stops
+        // right away with the best move so far.
+        let sr = s.go(&board, SearchLimits::depth(30));
+        let stopped_at = stopper.join().unwrap();
+        assert!(stopped_at.elapsed() < Duration::from_millis(250));
+        assert!(MoveGen::new_legal(&board).any(|mv| mv == sr.mv));
+    }
+
+    #[test]
+    fn test_exclude_moves() {
+        // Qxa8 wins a free rook.
+        let board = Board::from_fen("r3k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        let qxa8 = Movement::from_notation("a1a8").unwrap();
+
+        let mut s = Searcher::new();
+        assert_eq!(s.go(&board, SearchLimits::depth(3)).mv, qxa8);
+
+        let limits = SearchLimits {
+            depth: Some(3),
+            exclude_moves: vec![qxa8.clone()],
+            ..SearchLimits::default()
+        };
+        let sr = s.go(&board, limits);
+        assert_ne!(sr.mv, qxa8);
+        assert!(s.root_moves().iter().all(|rm| rm.mv != qxa8));
+    }
+
+    #[test]
+    fn test_search_moves() {
+        // Qxa8 wins a free rook, but we only get to look at king moves.
+        let board = Board::from_fen("r3k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        let king_moves = vec![
+            Movement::from_notation("e1d2").unwrap(),
+            Movement::from_notation("e1e2").unwrap(),
+        ];
+
+        let mut s = Searcher::new();
+        assert_eq!(
+            s.go(&board, SearchLimits::depth(3)).mv,
+            Movement::from_notation("a1a8").unwrap()
+        );
+        let limits = SearchLimits {
+            depth: Some(3),
+            search_moves: king_moves.clone(),
+            ..SearchLimits::default()
+        };
+        let sr = s.go(&board, limits.clone());
+        assert!(king_moves.contains(&sr.mv));
+        assert_eq!(s.root_moves().len(), 2);
+
+        // Both ways at once.
+        let limits = SearchLimits {
+            exclude_moves: vec![sr.mv.clone()],
+            ..limits
+        };
+        let other = s.go(&board, limits).mv;
+        assert!(king_moves.contains(&other) && other != sr.mv);
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        use std::sync::{Arc, Mutex};
+
+        // Qxa8 wins a free rook, a few checks win it a move later.
+        let board = Board::from_fen("r3k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        {
+            let infos = infos.clone();
+            s.set_info_callback(move |info| infos.lock().unwrap().push(info));
+        }
+        s.set_multi_pv(3);
+        let sr = s.go(&board, SearchLimits::depth(3));
+
+        let lines = s.pv_lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].mv, Movement::from_notation("a1a8").unwrap());
+        assert_eq!(lines[0].mv, sr.mv);
+        assert!(lines[1].mv != lines[0].mv && lines[2].mv != lines[1].mv);
+        assert!(lines[0].score >= lines[1].score, "{:?}", lines);
+        assert!(lines[1].score >= lines[2].score, "{:?}", lines);
+        assert!(lines.iter().all(|line| line.pv[0] == line.mv));
+
+        // An info line for each, numbered.
+        let infos = infos.lock().unwrap();
+        let last: Vec<_> = infos.iter().filter(|info| info.depth == Some(3)).collect();
+        assert_eq!(
+            last.iter().map(|info| info.multipv).collect::<Vec<_>>(),
+            [Some(1), Some(2), Some(3)]
+        );
+        assert!(last[2].to_string().starts_with("info depth 3 seldepth "));
+        assert!(last[2].to_string().contains(" multipv 3 score cp "));
+        drop(infos);
+
+        // No more lines than moves.
+        let board = Board::from_fen("k6R/8/8/8/8/8/8/1R2K3 b - - 0 1").unwrap();
+        s.go(&board, SearchLimits::depth(2));
+        assert_eq!(s.pv_lines().len(), 1);
+    }
+
+    #[test]
+    fn test_exclude_all_moves() {
+        // Only one legal move, excluding it leaves nothing so it's searched anyway.
+        let board = Board::from_fen("k6R/8/8/8/8/8/8/1R2K3 b - - 0 1").unwrap();
+        let ka7 = Movement::from_notation("a8a7").unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            exclude_moves: vec![ka7.clone()],
+            ..SearchLimits::default()
+        };
+        assert_eq!(Searcher::new().go(&board, limits).mv, ka7);
+    }
+
+    #[test]
+    fn test_threads() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut s = Searcher::new();
+        s.set_threads(4);
+        let sr = s.go(&board, SearchLimits::depth(5));
+        assert_eq!(sr.mv, Movement::from_notation("a1a8").unwrap());
+        assert_eq!(mate_in(sr.eval), Some(1));
+
+        // Stopping the main search stops the helpers too.
+        let start = Instant::now();
+        s.go(&board, SearchLimits::movetime(Duration::from_millis(200)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        s.set_threads(0);
+        assert_eq!(s.threads, 1);
+    }
+
+    #[test]
+    fn test_thinking_time_movestogo() {
+        let mut limits = SearchLimits {
+            btime: Some(Duration::from_secs(60)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            limits.thinking_time(Color::Black),
+            Some(Duration::from_secs(2))
+        );
+
+        limits.movestogo = Some(10);
+        assert_eq!(
+            limits.thinking_time(Color::Black),
+            Some(Duration::from_secs(6))
+        );
+
+        // Don't blow all our time right before the time control.
+        limits.movestogo = Some(1);
+        assert_eq!(
+            limits.thinking_time(Color::Black),
+            Some(Duration::from_secs(30))
+        );
+
+        // Overhead comes off the clock first.
+        limits.movestogo = None;
+        limits.move_overhead = Duration::from_secs(30);
+        assert_eq!(
+            limits.thinking_time(Color::Black),
+            Some(Duration::from_secs(1))
+        );
+
+        // Searching for a fixed depth doesn't need the default clock.
+        assert_eq!(SearchLimits::depth(5).thinking_time(Color::White), None);
+    }
+
+    #[test]
+    fn test_thinking_time_low_clock() {
+        // A big increment doesn't let us think longer than our clock.
+        let mut limits = SearchLimits {
+            wtime: Some(Duration::from_millis(300)),
+            winc: Some(Duration::from_secs(2)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            limits.thinking_time(Color::White),
+            Some(Duration::from_millis(300))
+        );
+
+        // Or with less left than the overhead, not at all.
+        limits.move_overhead = Duration::from_millis(500);
+        assert_eq!(
+            limits.thinking_time(Color::White),
+            Some(Duration::from_millis(0))
+        );
+    }
+
+    #[test]
+    fn test_hard_time() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            ..SearchLimits::default()
+        };
+        let thinking_time = limits.thinking_time(Color::White).unwrap();
+        let hard_time = limits.hard_time(Color::White).unwrap();
+        assert_eq!(
+            hard_time,
+            thinking_time * tune::hard_time_percent() as u32 / 100
+        );
+        assert!(hard_time > thinking_time);
+        assert!(hard_time < limits.emergency_time(Color::White).unwrap());
+
+        let mut movetime = SearchLimits::movetime(Duration::from_millis(300));
+        assert_eq!(
+            movetime.hard_time(Color::White),
+            Some(Duration::from_millis(300) - MOVETIME_MARGIN)
+        );
+        movetime.move_overhead = Duration::from_millis(50);
+        assert_eq!(
+            movetime.hard_time(Color::White),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(SearchLimits::infinite().hard_time(Color::White), None);
+        assert_eq!(SearchLimits::depth(5).hard_time(Color::White), None);
+
+        // Sudden death with 3s left: no new iteration past 50ms, none past 300ms.
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(3)),
+            ..SearchLimits::default()
+        };
+        let mut s = Searcher::new();
+        let start = Instant::now();
+        s.go(&Board::from_start_pos(), limits);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(s.thought_enough());
+    }
+
+    #[test]
+    fn test_emergency_time() {
+        let mut limits = SearchLimits {
+            wtime: Some(Duration::from_millis(1000)),
+            move_overhead: Duration::from_millis(100),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            limits.emergency_time(Color::White),
+            Some(Duration::from_millis(450))
+        );
+        assert_eq!(limits.emergency_time(Color::Black), None);
+        assert_eq!(
+            SearchLimits::movetime(Duration::from_millis(10)).emergency_time(Color::White),
+            None
+        );
+
+        // Out of time before we even start, we still get a legal move, and don't
+        // wait for depth 1 to finish.
+        limits.wtime = Some(Duration::from_millis(0));
+        let board =
+            Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let mut s = Searcher::new();
+        let sr = s.go(&board, limits);
+        assert!(MoveGen::new_legal(&board).any(|mv| mv == sr.mv));
+        assert!(s.nodes < CHECK_TIME_NODES, "searched {} nodes", s.nodes);
+    }
+
+    #[test]
+    fn test_repetition_draw() {
+        // Black is lost, but can repeat the position by going back to g8.
+        let mut board = Board::from_fen("7k/8/8/8/8/8/2Q5/K7 w - - 0 1").unwrap();
+        let mut history = Vec::new();
+        for mv in "c2d2 h8g8 d2c2 g8h8 c2d2".split(' ') {
+            history.push(zobrist::hash(&board));
+            board.make_move_mut(&Movement::from_notation(mv).unwrap());
+        }
+
+        let mut s = Searcher::new();
+        s.set_history(history);
+        let sr = s.go(&board, SearchLimits::depth(3));
+        assert_eq!(sr.mv, Movement::from_notation("h8g8").unwrap());
+        assert_eq!(sr.eval, 0);
+    }
+
+    #[test]
+    fn test_fifty_move_draw() {
+        let fen = "7k/8/8/8/8/8/2Q5/K7 w - - 99 80";
+        let mut s = Searcher::new();
+        let sr = s.go(&Board::from_fen(fen).unwrap(), SearchLimits::depth(3));
+        assert_eq!(sr.eval, 0);
+
+        let fen = "7k/8/8/8/8/8/2Q5/K7 w - - 0 80";
+        let mut s = Searcher::new();
+        let sr = s.go(&Board::from_fen(fen).unwrap(), SearchLimits::depth(3));
+        assert!(sr.eval > 500, "eval {} should be winning", sr.eval);
+    }
+
+    #[test]
+    fn test_tablebase_root() {
+        use crate::syzygy::testing::{temp_dir, write_krk};
+        use std::sync::{Arc, Mutex};
+
+        let dir = temp_dir("search_root");
+        write_krk(&dir, false);
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut s = Searcher::new();
+        s.set_tablebases(Tablebases::open(dir.to_str().unwrap()).unwrap());
+        {
+            let infos = infos.clone();
+            s.set_info_callback(move |info| infos.lock().unwrap().push(info));
+        }
+
+        // Moving the rook next to the black king gives it away, those moves
+        // aren't searched.
+        let board = Board::from_fen("8/8/8/4k3/8/3R4/8/K7 w - - 0 1").unwrap();
+        let hanging = ["d3d4", "d3d5", "d3d6"];
+        s.go(&board, SearchLimits::depth(3));
+        let root_moves = s.root_moves();
+        assert_eq!(
+            root_moves.len(),
+            MoveGen::new_legal(&board).count() - hanging.len()
+        );
+        assert!(root_moves
+            .iter()
+            .all(|rm| !hanging.contains(&rm.mv.to_notation().as_str())));
+
+        let infos = infos.lock().unwrap();
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, Some(syzygy::TB_WIN));
+        assert!(last.tbhits.unwrap() >= root_moves.len() as u64);
+        assert!(last.to_string().contains(" tbhits "), "{}", last);
+        drop(infos);
+
+        // Not for three-check.
+        let mut board = board.clone();
+        board.variant = Variant::ThreeCheck;
+        s.go(&board, SearchLimits::depth(3));
+        assert_eq!(s.root_moves().len(), MoveGen::new_legal(&board).count());
+        assert_eq!(s.tbhits, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tablebase_search() {
+        use crate::syzygy::testing::{temp_dir, write_krk};
+
+        let dir = temp_dir("search");
+        write_krk(&dir, false);
+        let mut s = Searcher::new();
+        s.set_tablebases(Tablebases::open(dir.to_str().unwrap()).unwrap());
+
+        // Rxc2 leaves KRvK, which the tables say is won.
+        let board = Board::from_fen("8/8/8/4k3/8/8/R1n5/6K1 w - - 0 1").unwrap();
+        let sr = s.go(&board, SearchLimits::depth(3));
+        assert_eq!(sr.mv, Movement::from_notation("a2c2").unwrap());
+        assert_eq!(sr.eval, syzygy::TB_WIN - 1);
+        assert!(s.tbhits > 0);
+
+        // Too shallow to probe as many pieces as the tables have.
+        s.set_tb_probe_depth(10);
+        s.clear_hash();
+        s.go(&board, SearchLimits::depth(3));
+        assert_eq!(s.tbhits, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_movetime_deadline() {
+        // All of it gets used but the margin, the last iteration stopped halfway
+        // instead of finishing past the deadline.
+        let board = Board::from_start_pos();
+        let mut s = Searcher::new();
+        let limits = SearchLimits {
+            move_overhead: Duration::from_millis(50),
+            ..SearchLimits::movetime(Duration::from_millis(300))
+        };
+        let deadline = Duration::from_millis(300) - limits.movetime_margin();
+        let start = Instant::now();
+        s.go(&board, limits);
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= deadline - Duration::from_millis(10) && elapsed <= deadline,
+            "{:?}",
+            elapsed
+        );
+        assert!(s.stopped);
     }
 
     macro_rules! test_think_time {
@@ -395,8 +3021,10 @@ mod tests {
 
                 let mut s = Searcher::new();
                 let start = Instant::now();
-                let think_time = Duration::from_millis($think_time);
-                s.search_timed(&board, think_time);
+                let limits = SearchLimits::movetime(Duration::from_millis($think_time));
+                // The margin is for sending the move, the search is done before.
+                let think_time = limits.hard_time(Color::White).unwrap();
+                s.go(&board, limits);
                 let elapsed = start.elapsed();
                 if elapsed > think_time {
                     panic!(
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+// Search statistics, for seeing what a change to the search really did instead
+// of guessing from nps and depth. The searcher counts what happens in every
+// iteration, and a StatsLog writes them out as JSON lines, one per iteration:
+//
+//     {"depth":5,"seldepth":9,"score":35,"completed":true,"time_ms":120,"nodes":21034,...}
+//
+// Counts are for that iteration alone, of the main thread only. `score` is
+// from the side to move's point of view.
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchStats {
+    pub nodes: u64,    // including qs
+    pub qs_nodes: u64, // nodes past the nominal depth, searching captures only
+    pub tt_hits: u64,  // probes that found the position
+    // Nodes cut short, by what.
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    pub first_move_cutoffs: u64, // beta cutoffs by the first move
+    pub stand_pats: u64,
+    pub repetitions: u64, // including the fifty move rule
+    pub upcoming_repetitions: u64,
+    pub check_extensions: u64,
+    pub recapture_extensions: u64,
+    pub delta_prunes: u64, // captures not searched in qs
+    pub razors: u64,       // nodes that dropped into qs and failed low
+    pub iid_searches: u64, // shallower searches for a move to try first
+}
+
+impl SearchStats {
+    // Column names for to_csv, in the same order.
+    pub const CSV_HEADER: &'static str = "nodes,qs_nodes,tt_hits,tt_cutoffs,beta_cutoffs,\
+                                          first_move_cutoffs,stand_pats,repetitions,\
+                                          upcoming_repetitions,check_extensions,\
+                                          recapture_extensions,delta_prunes,razors,\
+                                          iid_searches";
+
+    pub fn add(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.qs_nodes += other.qs_nodes;
+        self.tt_hits += other.tt_hits;
+        self.tt_cutoffs += other.tt_cutoffs;
+        self.beta_cutoffs += other.beta_cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.stand_pats += other.stand_pats;
+        self.repetitions += other.repetitions;
+        self.upcoming_repetitions += other.upcoming_repetitions;
+        self.check_extensions += other.check_extensions;
+        self.recapture_extensions += other.recapture_extensions;
+        self.delta_prunes += other.delta_prunes;
+        self.razors += other.razors;
+        self.iid_searches += other.iid_searches;
+    }
+
+    pub fn to_csv(&self) -> String {
+        [
+            self.nodes,
+            self.qs_nodes,
+            self.tt_hits,
+            self.tt_cutoffs,
+            self.beta_cutoffs,
+            self.first_move_cutoffs,
+            self.stand_pats,
+            self.repetitions,
+            self.upcoming_repetitions,
+            self.check_extensions,
+            self.recapture_extensions,
+            self.delta_prunes,
+            self.razors,
+            self.iid_searches,
+        ]
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+    }
+}
+
+// What one iteration of iterative deepening did.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IterationStats {
+    pub depth: i16,
+    pub seldepth: u16,
+    pub score: i16,
+    // False if the search was stopped in the middle of the iteration.
+    pub completed: bool,
+    // Since the search started.
+    pub time: Duration,
+    pub stats: SearchStats,
+}
+
+impl IterationStats {
+    pub fn to_json(&self) -> String {
+        let s = &self.stats;
+        format!(
+            concat!(
+                "{{\"depth\":{},\"seldepth\":{},\"score\":{},\"completed\":{},\"time_ms\":{},",
+                "\"nodes\":{},\"qs_nodes\":{},\"tt_hits\":{},\"tt_cutoffs\":{},",
+                "\"beta_cutoffs\":{},\"first_move_cutoffs\":{},\"stand_pats\":{},",
+                "\"repetitions\":{},\"upcoming_repetitions\":{},",
+                "\"check_extensions\":{},\"recapture_extensions\":{},",
+                "\"delta_prunes\":{},\"razors\":{},\"iid_searches\":{}}}"
+            ),
+            self.depth,
+            self.seldepth,
+            self.score,
+            self.completed,
+            self.time.as_millis(),
+            s.nodes,
+            s.qs_nodes,
+            s.tt_hits,
+            s.tt_cutoffs,
+            s.beta_cutoffs,
+            s.first_move_cutoffs,
+            s.stand_pats,
+            s.repetitions,
+            s.upcoming_repetitions,
+            s.check_extensions,
+            s.recapture_extensions,
+            s.delta_prunes,
+            s.razors,
+            s.iid_searches,
+        )
+    }
+}
+
+pub struct StatsLog {
+    out: Box<dyn Write + Send>,
+}
+
+impl StatsLog {
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<StatsLog> {
+        let file = File::create(path)?;
+        Ok(StatsLog::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub fn new(out: Box<dyn Write + Send>) -> StatsLog {
+        StatsLog { out }
+    }
+
+    pub fn iteration(&mut self, stats: &IterationStats) {
+        // Best effort, like tracing.
+        let _ = writeln!(self.out, "{}", stats.to_json());
+        let _ = self.out.flush();
+    }
+}
+
+impl fmt::Debug for StatsLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StatsLog")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_csv() {
+        let mut stats = SearchStats {
+            nodes: 10,
+            recapture_extensions: 2,
+            ..SearchStats::default()
+        };
+        stats.add(&stats.clone());
+        assert_eq!(stats.to_csv(), "20,0,0,0,0,0,0,0,0,0,4,0,0,0");
+        assert_eq!(
+            SearchStats::CSV_HEADER.split(',').count(),
+            stats.to_csv().split(',').count()
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let stats = IterationStats {
+            depth: 3,
+            seldepth: 7,
+            score: -20,
+            completed: true,
+            time: Duration::from_millis(15),
+            stats: SearchStats {
+                nodes: 1000,
+                qs_nodes: 400,
+                beta_cutoffs: 90,
+                ..SearchStats::default()
+            },
+        };
+        assert_eq!(
+            stats.to_json(),
+            "{\"depth\":3,\"seldepth\":7,\"score\":-20,\"completed\":true,\"time_ms\":15,\
+             \"nodes\":1000,\"qs_nodes\":400,\"tt_hits\":0,\"tt_cutoffs\":0,\
+             \"beta_cutoffs\":90,\"first_move_cutoffs\":0,\"stand_pats\":0,\
+             \"repetitions\":0,\"upcoming_repetitions\":0,\
+             \"check_extensions\":0,\"recapture_extensions\":0,\"delta_prunes\":0,\"razors\":0,\
+             \"iid_searches\":0}"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::chess::Movement;
+use crate::search::{is_mate_score, RootMove};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+// Playing strength limiting, for UCI_LimitStrength and UCI_Elo.
+//
+// We weaken the engine two ways: a small node budget so it can't see far, and
+// playing a random move from the ones that are nearly as good as the best. The
+// numbers are made up, but feel about right against humans.
+
+pub const MIN_ELO: u16 = 800;
+pub const MAX_ELO: u16 = 2200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strength {
+    elo: u16,
+}
+
+impl Strength {
+    pub fn new(elo: u16) -> Self {
+        Strength {
+            elo: elo.clamp(MIN_ELO, MAX_ELO),
+        }
+    }
+
+    pub fn elo(&self) -> u16 {
+        self.elo
+    }
+
+    // Nodes we get to search per move, doubles every 200 elo.
+    pub fn nodes(&self) -> u64 {
+        500 << ((self.elo - MIN_ELO) / 200)
+    }
+
+    // How much worse than the best move (in centipawns) a move can be for us to
+    // still consider playing it. 200cp at the bottom, nothing at the top.
+    pub fn margin(&self) -> i16 {
+        (200 * (MAX_ELO - self.elo) as u32 / (MAX_ELO - MIN_ELO) as u32) as i16
+    }
+
+    // Pick a move to play from the root moves of a search (best first, with
+    // exact scores). Better moves are more likely to be picked.
+    pub fn pick<R: Rng>(&self, root_moves: &[RootMove], rng: &mut R) -> Option<Movement> {
+        let best = root_moves.first()?.score;
+        let margin = self.margin();
+
+        // Never throw away a mate, or walk into one.
+        let candidates: Vec<&RootMove> = root_moves
+            .iter()
+            .filter(|rm| {
+                rm.score == best || (best - rm.score <= margin && !is_mate_score(rm.score))
+            })
+            .collect();
+
+        let weights = candidates
+            .iter()
+            .map(|rm| (margin - (best - rm.score) + 1) as u32);
+        let index = WeightedIndex::new(weights).ok()?.sample(rng);
+
+        Some(candidates[index].mv.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn root_move(mv: &str, score: i16) -> RootMove {
+        let mv = Movement::from_notation(mv).unwrap();
+        RootMove {
+            mv: mv.clone(),
+            score,
+            depth: 1,
+            pv: vec![mv],
+        }
+    }
+
+    #[test]
+    fn test_nodes_and_margin() {
+        assert!(Strength::new(MIN_ELO).nodes() < Strength::new(MAX_ELO).nodes());
+        assert_eq!(Strength::new(MAX_ELO).margin(), 0);
+        assert_eq!(Strength::new(MIN_ELO).margin(), 200);
+        assert_eq!(Strength::new(10).elo(), MIN_ELO);
+    }
+
+    #[test]
+    fn test_pick() {
+        let root_moves = vec![
+            root_move("e2e4", 50),
+            root_move("d2d4", 40),
+            root_move("g1f3", 30),
+            root_move("f2f3", -400),
+        ];
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // At full strength we always play the best move.
+        let strength = Strength::new(MAX_ELO);
+        for _ in 0..20 {
+            assert_eq!(
+                strength.pick(&root_moves, &mut rng),
+                Some(root_moves[0].mv.clone())
+            );
+        }
+
+        // Weaker, we play all the decent moves but never the blunder.
+        let strength = Strength::new(1200);
+        let mut seen = Vec::new();
+        for _ in 0..200 {
+            let mv = strength.pick(&root_moves, &mut rng).unwrap();
+            assert_ne!(mv, root_moves[3].mv);
+            if !seen.contains(&mv) {
+                seen.push(mv);
+            }
+        }
+        assert_eq!(seen.len(), 3);
+
+        assert_eq!(strength.pick(&[], &mut rng), None);
+    }
+}
@@ -0,0 +1,60 @@
+// std's OnceLock, or a spinning one without std. Only the movegen and zobrist
+// tables are built with it, once at startup, so spinning while another thread
+// builds them is fine.
+
+#[cfg(feature = "std")]
+pub use std::sync::OnceLock;
+
+#[cfg(not(feature = "std"))]
+pub use self::spin::OnceLock;
+
+#[cfg(not(feature = "std"))]
+mod spin {
+    use core::cell::UnsafeCell;
+    use core::mem::MaybeUninit;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    const NEW: u8 = 0;
+    const RUNNING: u8 = 1;
+    const DONE: u8 = 2;
+
+    // Meant for statics, the value is never dropped.
+    pub struct OnceLock<T> {
+        state: AtomicU8,
+        value: UnsafeCell<MaybeUninit<T>>,
+    }
+
+    // The value is written once, before DONE is stored, and only read after.
+    unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+    impl<T> OnceLock<T> {
+        pub const fn new() -> OnceLock<T> {
+            OnceLock {
+                state: AtomicU8::new(NEW),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            if self.state.load(Ordering::Acquire) != DONE {
+                match self.state.compare_exchange(
+                    NEW,
+                    RUNNING,
+                    Ordering::Acquire,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*self.value.get()).write(f()) };
+                        self.state.store(DONE, Ordering::Release);
+                    }
+                    Err(_) => {
+                        while self.state.load(Ordering::Acquire) != DONE {
+                            core::hint::spin_loop();
+                        }
+                    }
+                }
+            }
+            unsafe { (*self.value.get()).assume_init_ref() }
+        }
+    }
+}
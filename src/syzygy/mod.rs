@@ -0,0 +1,617 @@
+use crate::chess::{Board, Movement, Piece};
+use crate::eval;
+use crate::movegen::MoveGen;
+use crate::search::MAX_PLY;
+use crate::zobrist;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Neg;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+mod table;
+
+use table::{Dtz, Kind, Material, Table};
+
+// Syzygy endgame tablebases: whether every position with up to 7 pieces is won,
+// drawn or lost (WDL tables, .rtbw), and how many plies until the next capture
+// or pawn move on the way there (DTZ tables, .rtbz). The probing is a port of
+// Stockfish's, DTZ is only used at the root to pick a move that makes progress.
+
+// Worse than any mate, better than any evaluation. (see Searcher::alphabeta)
+pub const TB_WIN: i16 = eval::MATE - MAX_PLY as i16 - 1;
+
+// Root moves are ranked from -MAX_DTZ to MAX_DTZ, see rank_root_moves.
+pub const MAX_DTZ: i32 = 1 << 18;
+
+// Cursed wins and blessed losses are won or lost, but the fifty move rule
+// comes first if the loser defends well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_i32(n: i32) -> Option<Wdl> {
+        Some(match n {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            2 => Wdl::Win,
+            _ => return None,
+        })
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_i32(-(self as i32)).unwrap()
+    }
+}
+
+// DTZ of a position whose best move zeroes the fifty move counter.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+// Tables don't know about en passant, so it's a capture like any other here.
+fn is_capture(board: &Board, mv: &Movement) -> bool {
+    board.is_capture(mv) || (board.en_passant == Some(mv.to_square) && is_pawn_move(board, mv))
+}
+
+fn is_pawn_move(board: &Board, mv: &Movement) -> bool {
+    board.piece_on(mv.from_square) == Some(Piece::Pawn)
+}
+
+fn is_mate(board: &Board) -> bool {
+    board.in_check() && MoveGen::new_legal(board).next().is_none()
+}
+
+// A root move with its tablebase rank, higher is better, and the score to show
+// for it. See Tablebases::rank_root_moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankedMove {
+    pub mv: Movement,
+    pub rank: i32,
+    pub score: i16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootRanking {
+    // Best first.
+    pub moves: Vec<RankedMove>,
+    // Ranked by DTZ, if we have the DTZ tables, otherwise by WDL only.
+    pub dtz: bool,
+}
+
+// The tables we have, WDL and DTZ for the same material.
+struct Entry {
+    wdl: Table,
+    dtz: Option<Table>,
+}
+
+// Tables are found by name in the SyzygyPath directories, and read the first
+// time they're probed.
+#[derive(Default)]
+pub struct Tablebases {
+    // By material key, every entry is under its key with either color as the
+    // stronger side.
+    entries: HashMap<u64, Arc<Entry>>,
+    len: usize,
+    max_pieces: usize,
+}
+
+impl fmt::Debug for Tablebases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Tablebases {{ {} tables, {} pieces }}",
+            self.len, self.max_pieces
+        )
+    }
+}
+
+impl Tablebases {
+    // Every table in `paths`, directories separated like PATH is. (':', or ';'
+    // on Windows) Tables without a DTZ file can still be probed for WDL.
+    pub fn open(paths: &str) -> Result<Tablebases, String> {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        let mut files: HashMap<(String, Kind), PathBuf> = HashMap::new();
+        for dir in paths.split(separator).filter(|dir| !dir.is_empty()) {
+            let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir, e))?;
+            for entry in entries.filter_map(Result::ok) {
+                let path = entry.path();
+                let name = path.file_stem().and_then(|name| name.to_str());
+                let kind = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("rtbw") => Kind::Wdl,
+                    Some("rtbz") => Kind::Dtz,
+                    _ => continue,
+                };
+                if let Some(name) = name {
+                    // The first directory with a table wins.
+                    let name = name.to_string();
+                    files.entry((name, kind)).or_insert_with(|| path.clone());
+                }
+            }
+        }
+
+        let mut tablebases = Tablebases::default();
+        for ((name, kind), path) in &files {
+            let material = match Material::from_name(name) {
+                Some(material) if *kind == Kind::Wdl => material,
+                _ => continue,
+            };
+            let dtz = files
+                .get(&(name.clone(), Kind::Dtz))
+                .map(|path| Table::new(Kind::Dtz, path.clone(), &material));
+            let entry = Arc::new(Entry {
+                wdl: Table::new(Kind::Wdl, path.clone(), &material),
+                dtz,
+            });
+            tablebases.entries.insert(material.key(), entry.clone());
+            tablebases.entries.insert(material.flipped().key(), entry);
+            tablebases.len += 1;
+            tablebases.max_pieces = usize::max(tablebases.max_pieces, material.len());
+        }
+        Ok(tablebases)
+    }
+
+    // Number of WDL tables.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Most pieces in any table, positions with more can't be probed.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    fn entry(&self, board: &Board) -> Option<&Entry> {
+        let key = Material::from_board(board).key();
+        self.entries.get(&key).map(|entry| &**entry)
+    }
+
+    fn probe_wdl_table(&self, board: &Board) -> Option<Wdl> {
+        // Only the kings are left.
+        if board.combined().count_ones() == 2 {
+            return Some(Wdl::Draw);
+        }
+        self.entry(board)?.wdl.probe_wdl(board)
+    }
+
+    fn probe_dtz_table(&self, board: &Board, wdl: Wdl) -> Option<Dtz> {
+        if board.combined().count_ones() == 2 {
+            return Some(Dtz::Plies(0));
+        }
+        self.entry(board)?.dtz.as_ref()?.probe_dtz(board, wdl)
+    }
+
+    // Win, draw or loss for the side to move, None if we don't have the tables.
+    // Positions with castling rights aren't in the tables.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if board.castling != 0 {
+            return None;
+        }
+        Some(self.search(board, false)?.0)
+    }
+
+    // Plies to the next capture or pawn move when playing for the result,
+    // positive when winning and negative when losing. 0 for draws, and +-1 for
+    // positions where the move that wins (or the only move) zeroes. Cursed wins
+    // and blessed losses count 100 extra plies.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if board.castling != 0 {
+            return None;
+        }
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_dtz_table(board, wdl)? {
+            Dtz::Plies(dtz) => {
+                let cursed = wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss;
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum())
+            }
+            // Search a ply and take the best move's DTZ.
+            Dtz::OtherSide => {
+                let mut best = i32::MAX;
+                for mv in MoveGen::new_legal(board) {
+                    let zeroing = is_capture(board, &mv) || is_pawn_move(board, &mv);
+                    let child = board.make_move(&mv);
+                    let mut dtz = if zeroing {
+                        -dtz_before_zeroing(self.search(&child, false)?.0)
+                    } else {
+                        -self.probe_dtz(&child)?
+                    };
+                    if dtz == 1 && is_mate(&child) {
+                        best = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < best && dtz.signum() == wdl.signum() {
+                        best = dtz;
+                    }
+                }
+                // No legal moves, we're mated.
+                Some(if best == i32::MAX { -1 } else { best })
+            }
+        }
+    }
+
+    // The tables don't store positions with en passant, and the DTZ tables
+    // store don't-care values where the best move zeroes, so try captures (and
+    // pawn moves for DTZ) first. Also says if the best move is one of those.
+    fn search(&self, board: &Board, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves: Vec<Movement> = MoveGen::new_legal(board).collect();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for mv in &moves {
+            if !is_capture(board, mv) && (!check_zeroing || !is_pawn_move(board, mv)) {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(&board.make_move(mv), false)?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // Nothing else to play, the table might not even have the position.
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(board)?
+        };
+        if best >= value {
+            return Some((best, best > Wdl::Draw || no_more_moves));
+        }
+        Some((value, false))
+    }
+
+    // Rank the root moves, so the search only looks at the best ones: with DTZ
+    // the moves that win without the fifty move rule getting in the way, or
+    // that lose slowest. `history` are the zobrist keys of the positions before
+    // `board`, oldest first. With `rule50` off cursed wins are scored as wins.
+    // None if the position isn't in the tables.
+    pub fn rank_root_moves(
+        &self,
+        board: &Board,
+        history: &[u64],
+        rule50: bool,
+    ) -> Option<RootRanking> {
+        if board.combined().count_ones() as usize > self.max_pieces || board.castling != 0 {
+            return None;
+        }
+        let moves: Vec<Movement> = MoveGen::new_legal(board).collect();
+        if moves.is_empty() {
+            return None;
+        }
+
+        let (mut moves, dtz) = match self.rank_by_dtz(board, &moves, history, rule50) {
+            Some(ranked) => (ranked, true),
+            None => (self.rank_by_wdl(board, &moves, history, rule50)?, false),
+        };
+        // Stable, so equal ranks keep their move generation order.
+        moves.sort_by_key(|rm| -rm.rank);
+        Some(RootRanking { moves, dtz })
+    }
+
+    fn rank_by_dtz(
+        &self,
+        board: &Board,
+        moves: &[Movement],
+        history: &[u64],
+        rule50: bool,
+    ) -> Option<Vec<RankedMove>> {
+        let key = zobrist::hash(board);
+        let cnt50 = board.halfmove_clock as i32;
+        // A repetition since the last zeroing move means the fifty move rule
+        // is closer than it looks, so make progress.
+        let recent = since_zeroing(board, key, history);
+        let repeated = recent
+            .iter()
+            .enumerate()
+            .any(|(i, k)| recent[i + 1..].contains(k));
+        let bound = if rule50 { MAX_DTZ - 100 } else { 1 };
+
+        let mut ranked = Vec::new();
+        for mv in moves {
+            let child = board.make_move(mv);
+            let mut dtz = if child.halfmove_clock == 0 {
+                dtz_before_zeroing(-self.probe_wdl(&child)?)
+            } else if is_draw(&child, zobrist::hash(&child), &recent) {
+                0
+            } else {
+                let dtz = -self.probe_dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            if dtz == 2 && is_mate(&child) {
+                dtz = 1;
+            }
+
+            // All wins that don't hit the fifty move rule are equal, the search
+            // can pick the best looking one. Losses that do are better.
+            let rank = if dtz > 0 {
+                if dtz + cnt50 <= 99 && !repeated {
+                    MAX_DTZ
+                } else {
+                    MAX_DTZ - (dtz + cnt50)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + cnt50 < 100 {
+                    -MAX_DTZ
+                } else {
+                    -MAX_DTZ + (-dtz + cnt50)
+                }
+            } else {
+                0
+            };
+
+            // Cursed wins get at least 1cp, going up to 49cp closer to a win.
+            let score = if rank >= bound {
+                TB_WIN
+            } else if rank > 0 {
+                (i32::max(3, rank - (MAX_DTZ - 200)) * PAWN / 200) as i16
+            } else if rank == 0 {
+                0
+            } else if rank > -bound {
+                (i32::min(-3, rank + (MAX_DTZ - 200)) * PAWN / 200) as i16
+            } else {
+                -TB_WIN
+            };
+            ranked.push(RankedMove {
+                mv: mv.clone(),
+                rank,
+                score,
+            });
+        }
+        Some(ranked)
+    }
+
+    fn rank_by_wdl(
+        &self,
+        board: &Board,
+        moves: &[Movement],
+        history: &[u64],
+        rule50: bool,
+    ) -> Option<Vec<RankedMove>> {
+        let recent = since_zeroing(board, zobrist::hash(board), history);
+        let mut ranked = Vec::new();
+        for mv in moves {
+            let child = board.make_move(mv);
+            let wdl = if is_draw(&child, zobrist::hash(&child), &recent) {
+                Wdl::Draw
+            } else {
+                -self.probe_wdl(&child)?
+            };
+            let (rank, score) = match wdl {
+                Wdl::Loss => (-MAX_DTZ, -TB_WIN),
+                Wdl::BlessedLoss if rule50 => (-MAX_DTZ + 101, -2),
+                Wdl::BlessedLoss => (-MAX_DTZ + 101, -TB_WIN),
+                Wdl::Draw => (0, 0),
+                Wdl::CursedWin if rule50 => (MAX_DTZ - 101, 2),
+                Wdl::CursedWin => (MAX_DTZ - 101, TB_WIN),
+                Wdl::Win => (MAX_DTZ, TB_WIN),
+            };
+            ranked.push(RankedMove {
+                mv: mv.clone(),
+                rank,
+                score,
+            });
+        }
+        Some(ranked)
+    }
+}
+
+// Centipawns, for scoring cursed wins.
+const PAWN: i32 = 100;
+
+// The keys of `board` and the positions before it back to the last zeroing
+// move, latest first.
+fn since_zeroing(board: &Board, key: u64, history: &[u64]) -> Vec<u64> {
+    std::iter::once(key)
+        .chain(history.iter().rev().copied())
+        .take(board.halfmove_clock as usize + 1)
+        .collect()
+}
+
+// Is a position right after the root drawn by the fifty move rule or by
+// repeating a position in `recent`? Like the search, once is enough.
+fn is_draw(child: &Board, key: u64, recent: &[u64]) -> bool {
+    if child.halfmove_clock >= 100 && !is_mate(child) {
+        return true;
+    }
+    recent
+        .iter()
+        .take(child.halfmove_clock as usize)
+        .any(|&k| k == key)
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    pub use super::table::testing::*;
+    use super::*;
+    use std::path::Path;
+
+    // KRvK as it is: the side with the rook wins, unless it's stalemate or the
+    // rook can be taken.
+    pub fn krk(board: &Board) -> Wdl {
+        let rooks = board.pieces(Piece::Rook);
+        let rook_side = if (*rooks & *board.color_combined(board.side_to_move)).count_ones() > 0 {
+            board.side_to_move
+        } else {
+            board.side_to_move.other()
+        };
+        let takes_rook = MoveGen::new_legal(board).any(|mv| rooks.get(mv.to_square));
+        if board.side_to_move == rook_side {
+            Wdl::Win
+        } else if is_stalemate(board) || takes_rook {
+            Wdl::Draw
+        } else {
+            Wdl::Loss
+        }
+    }
+
+    // KRvK tables in `dir`, with a DTZ table (when `dtz`) that has every
+    // position with white to move 15 plies from zeroing.
+    pub fn write_krk(dir: &Path, dtz: bool) {
+        write_wdl(&dir.join("KRvK.rtbw"), "KRvK", krk);
+        if dtz {
+            write_single_value(&dir.join("KRvK.rtbz"), Kind::Dtz, "KRvK", &[(0, 7)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    fn mv(s: &str) -> Movement {
+        Movement::from_notation(s).unwrap()
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = temp_dir("open");
+        write_krk(&dir, true);
+        write_single_value(&dir.join("KNvK.rtbw"), Kind::Wdl, "KNvK", &[(0, 2), (0, 2)]);
+        fs::write(dir.join("KNvK.txt"), "not a table").unwrap();
+        fs::write(dir.join("KvKvK.rtbw"), "not a table").unwrap();
+
+        let tablebases = Tablebases::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(tablebases.len(), 2);
+        assert_eq!(tablebases.max_pieces(), 3);
+        let knk = board("4k3/8/8/8/8/4N3/8/4K3 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&knk), Some(Wdl::Draw));
+        assert_eq!(tablebases.probe_dtz(&knk), Some(0));
+        let kbk = board("4k3/8/8/8/8/4B3/8/4K3 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&kbk), None);
+        let kk = board("4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&kk), Some(Wdl::Draw));
+        // Castling rights aren't in the tables.
+        let castling = board("4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(tablebases.probe_wdl(&castling), None);
+
+        // Missing directories are an error, empty ones are fine.
+        let missing = dir.join("missing");
+        assert!(Tablebases::open(missing.to_str().unwrap()).is_err());
+        assert!(Tablebases::open("").unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_probe_wdl() {
+        let dir = temp_dir("probe_wdl");
+        write_krk(&dir, false);
+        let tablebases = Tablebases::open(dir.to_str().unwrap()).unwrap();
+        let material = Material::from_name("KRvK").unwrap();
+        let mut probed = 0;
+        for_each_position(&material, |board| {
+            assert_eq!(
+                tablebases.probe_wdl(board),
+                Some(krk(board)),
+                "{}",
+                board.to_fen()
+            );
+            probed += 1;
+        });
+        assert!(probed > 300_000);
+
+        // Black as the stronger side, table lookups already check all of these.
+        let probe = |fen| tablebases.probe_wdl(&board(fen));
+        assert_eq!(probe("r3k3/8/8/8/4K3/8/8/8 b - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("r3k3/8/8/8/4K3/8/8/8 w - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(probe("8/8/8/8/8/8/1r6/K2k4 w - - 0 1"), Some(Wdl::Draw));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_probe_dtz() {
+        let dir = temp_dir("probe_dtz");
+        write_krk(&dir, true);
+        let tablebases = Tablebases::open(dir.to_str().unwrap()).unwrap();
+        let dtz = |fen| tablebases.probe_dtz(&board(fen));
+        assert_eq!(dtz("8/8/8/4k3/8/8/8/R3K3 w - - 0 1"), Some(15));
+        assert_eq!(dtz("r3k3/8/8/8/4K3/8/8/8 b - - 0 1"), Some(15));
+        // Stored for white to move only, black's moves all go to 15.
+        assert_eq!(dtz("8/8/8/4k3/8/8/8/R3K3 b - - 0 1"), Some(-16));
+        // Taking the rook draws.
+        assert_eq!(dtz("8/8/8/8/8/8/3k4/R2RK3 b - - 0 1"), None);
+        assert_eq!(dtz("8/8/8/8/8/8/3kR3/4K3 b - - 0 1"), Some(0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rank_root_moves() {
+        // Moving the rook next to the black king gives it away.
+        let root = board("8/8/8/4k3/8/3R4/8/K7 w - - 0 1");
+        let hanging = ["d3d4", "d3d5", "d3d6"];
+        let dir = temp_dir("rank_root_moves");
+        write_krk(&dir, false);
+
+        for dtz in [false, true] {
+            if dtz {
+                write_krk(&dir, true);
+            }
+            let tablebases = Tablebases::open(dir.to_str().unwrap()).unwrap();
+            let ranking = tablebases.rank_root_moves(&root, &[], true).unwrap();
+            assert_eq!(ranking.dtz, dtz);
+            assert_eq!(ranking.moves.len(), MoveGen::new_legal(&root).count());
+            for rm in &ranking.moves {
+                let draws = hanging.contains(&rm.mv.to_notation().as_str());
+                let (rank, score) = if draws { (0, 0) } else { (MAX_DTZ, TB_WIN) };
+                assert_eq!((rm.rank, rm.score), (rank, score), "{}", rm.mv);
+            }
+            assert!(ranking.moves[..ranking.moves.len() - hanging.len()]
+                .iter()
+                .all(|rm| rm.rank == MAX_DTZ));
+
+            // Going back to a position since the last zeroing move draws.
+            let mut root = root.clone();
+            root.halfmove_clock = 10;
+            let repeated = zobrist::hash(&root.make_move(&mv("d3d1")));
+            let ranking = tablebases
+                .rank_root_moves(&root, &[repeated, 1, 2], true)
+                .unwrap();
+            let d1 = ranking.moves.iter().find(|rm| rm.mv == mv("d3d1")).unwrap();
+            assert_eq!(d1.rank, 0);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
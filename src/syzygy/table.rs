@@ -0,0 +1,1276 @@
+use super::Wdl;
+use crate::bitboard::BitBoard;
+use crate::chess::{Board, Color, Piece};
+use std::convert::TryInto;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// Reading Syzygy table files. The format has no documentation besides the
+// generator and the probing code, this follows Stockfish's tbprobe.cpp. A file is:
+//
+//     magic, flags, per file (a-d for pawns): group order and pieces
+//     per side and file: sizes, Huffman code and symbol tree
+//     DTZ only: the value maps
+//     per side and file: sparse index, block lengths, compressed blocks
+//
+// Positions are turned into an index (see Table::lookup) and the value at that
+// index is found by decompressing the block it's in. Values are WDL + 2 for WDL
+// tables, and (mapped) distances to zeroing for DTZ tables.
+
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// Flags of the file, first byte after the magic.
+const SPLIT: u8 = 1; // Both sides to move are stored
+const HAS_PAWNS: u8 = 2;
+
+// Flags of each side and file.
+const STM: u8 = 1; // DTZ: black to move is stored instead of white
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Wdl,
+    Dtz,
+}
+
+impl Kind {
+    fn magic(self) -> [u8; 4] {
+        match self {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        }
+    }
+}
+
+// Piece counts by color, what tables are looked up by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Material {
+    counts: [[u8; 6]; 2], // [color][piece]
+}
+
+impl Material {
+    pub fn from_board(board: &Board) -> Material {
+        let mut material = Material::default();
+        for color in [Color::White, Color::Black] {
+            for piece in 0..6 {
+                let pieces = board.pieces[piece] & *board.color_combined(color);
+                material.counts[color as usize][piece] = pieces.count_ones();
+            }
+        }
+        material
+    }
+
+    // From a table name like KRvKN, white first.
+    pub fn from_name(name: &str) -> Option<Material> {
+        let mut material = Material::default();
+        let mut sides = name.split('v');
+        for color in 0..2 {
+            for ch in sides.next()?.chars() {
+                let piece = Piece::from_char(ch.to_ascii_lowercase())?;
+                material.counts[color][piece as usize] += 1;
+            }
+            if material.counts[color][Piece::King as usize] != 1 {
+                return None;
+            }
+        }
+        if sides.next().is_some() || material.len() > MAX_PIECES {
+            return None;
+        }
+        Some(material)
+    }
+
+    pub fn key(&self) -> u64 {
+        self.counts
+            .iter()
+            .flatten()
+            .fold(0, |key, &n| key << 4 | n as u64)
+    }
+
+    // The same pieces with the colors swapped.
+    pub fn flipped(&self) -> Material {
+        Material {
+            counts: [self.counts[1], self.counts[0]],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.iter().flatten().map(|&n| n as usize).sum()
+    }
+
+    fn count(&self, color: usize, piece: Piece) -> usize {
+        self.counts[color][piece as usize] as usize
+    }
+}
+
+// The squares and index tables of the position encoding, see init_maps.
+struct Maps {
+    pawns: [u64; 64],
+    b1h1h7: [u64; 64],
+    a1d1d4: [u64; 64],
+    kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static MAPS: OnceLock<Maps> = OnceLock::new();
+
+fn maps() -> &'static Maps {
+    MAPS.get_or_init(init_maps)
+}
+
+fn rank(sq: u8) -> u8 {
+    sq >> 3
+}
+
+fn file(sq: u8) -> u8 {
+    sq & 7
+}
+
+// Negative below the a1-h8 diagonal, 0 on it.
+fn off_a1h8(sq: u8) -> i8 {
+    rank(sq) as i8 - file(sq) as i8
+}
+
+fn init_maps() -> Maps {
+    let mut maps = Maps {
+        pawns: [0; 64],
+        b1h1h7: [0; 64],
+        a1d1d4: [0; 64],
+        kk: [[0; 64]; 10],
+        binomial: [[0; 64]; MAX_PIECES],
+        lead_pawn_idx: [[0; 64]; 6],
+        lead_pawns_size: [[0; 4]; 6],
+    };
+
+    // Squares below the a1-h8 diagonal to 0..27.
+    for (code, sq) in (0..64).filter(|&sq| off_a1h8(sq) < 0).enumerate() {
+        maps.b1h1h7[sq as usize] = code as u64;
+    }
+
+    // The a1-d1-d4 triangle to 0..9, the diagonal last.
+    let triangle = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+    let mut code = 0;
+    for &sq in triangle.iter().filter(|&&sq| off_a1h8(sq) < 0) {
+        maps.a1d1d4[sq as usize] = code;
+        code += 1;
+    }
+    for &sq in triangle.iter().filter(|&&sq| off_a1h8(sq) == 0) {
+        maps.a1d1d4[sq as usize] = code;
+        code += 1;
+    }
+
+    // The 462 ways to place two kings with the first in the triangle. With the
+    // first one on the diagonal the second can't be above it, and both on the
+    // diagonal come last.
+    let a1d1d4 = maps.a1d1d4;
+    let mut both_on_diagonal = Vec::new();
+    let mut code = 0;
+    for idx in 0..10 {
+        for &s1 in triangle.iter().filter(|&&sq| a1d1d4[sq as usize] == idx) {
+            for s2 in 0..64 {
+                let adjacent = (rank(s1) as i8 - rank(s2) as i8).abs() <= 1
+                    && (file(s1) as i8 - file(s2) as i8).abs() <= 1;
+                if adjacent || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                    continue;
+                }
+                if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                    both_on_diagonal.push((idx, s2));
+                } else {
+                    maps.kk[idx as usize][s2 as usize] = code;
+                    code += 1;
+                }
+            }
+        }
+    }
+    for (idx, s2) in both_on_diagonal {
+        maps.kk[idx as usize][s2 as usize] = code;
+        code += 1;
+    }
+
+    // binomial[k][n] ways to choose k of n.
+    maps.binomial[0][0] = 1;
+    for n in 1..64 {
+        for k in 0..usize::min(MAX_PIECES, n + 1) {
+            maps.binomial[k][n] = if k > 0 {
+                maps.binomial[k - 1][n - 1]
+            } else {
+                0
+            } + if k < n { maps.binomial[k][n - 1] } else { 0 };
+        }
+    }
+
+    // Pawns on a2-h7 to 0..47, the leading pawn (the one with the highest
+    // number) is the one closest to the edge, lowest rank first. The tables are
+    // split by the file of the leading pawn, with its own index for each.
+    let mut available = 48;
+    for lead_pawns in 1..6 {
+        for f in 0..4 {
+            let mut idx = 0;
+            for r in 1..7 {
+                let sq = r * 8 + f;
+                if lead_pawns == 1 {
+                    available -= 1;
+                    maps.pawns[sq] = available;
+                    available -= 1;
+                    maps.pawns[sq ^ 7] = available;
+                }
+                maps.lead_pawn_idx[lead_pawns][sq] = idx;
+                idx += maps.binomial[lead_pawns - 1][maps.pawns[sq] as usize];
+            }
+            maps.lead_pawns_size[lead_pawns][f] = idx;
+        }
+    }
+
+    maps
+}
+
+fn binomial(k: usize, n: usize) -> u64 {
+    maps()
+        .binomial
+        .get(k)
+        .and_then(|row| row.get(n))
+        .copied()
+        .unwrap_or(0)
+}
+
+fn byte(bytes: &[u8], i: usize) -> Option<u8> {
+    bytes.get(i).copied()
+}
+
+fn u16_le(bytes: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().ok()?))
+}
+
+// Big endian, with zeros past the end. Decompressing reads ahead of the
+// symbols it needs, which can go past the last block.
+fn be_padded<const N: usize>(bytes: &[u8], i: usize) -> u64 {
+    (0..N).fold(0, |n, j| {
+        n << 8 | bytes.get(i + j).copied().unwrap_or(0) as u64
+    })
+}
+
+// The table of one side to move and leading pawn file.
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    // Every `span` values there's a sparse index entry.
+    span: u64,
+    num_blocks: usize,
+    min_sym_len: u8, // the value itself for single value tables
+    // Byte offsets of the lowest symbol of each length, the symbol tree, the
+    // sparse index, block lengths and the compressed data.
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_length: usize,
+    block_length_size: usize,
+    data: usize,
+    // base64[l] is the lowest symbol of length l + min_sym_len, padded to 64 bits.
+    base64: Vec<u64>,
+    // How many values (minus one) each symbol stands for.
+    symlen: Vec<u8>,
+    // Pieces in the order they're encoded, then the groups they make up: KRRvK
+    // is grouped (3, 1), and each group's starting index.
+    pieces: [u8; MAX_PIECES],
+    group_len: [usize; MAX_PIECES + 1],
+    group_idx: [u64; MAX_PIECES + 1],
+    // DTZ: where the value maps for win, loss, cursed win and blessed loss start.
+    map_idx: [u16; 4],
+}
+
+impl PairsData {
+    // The left and right halves of a symbol, 12 bits each.
+    fn tree(&self, bytes: &[u8], sym: usize) -> Option<(u16, u16)> {
+        let lr = bytes.get(self.btree + 3 * sym..self.btree + 3 * sym + 3)?;
+        let left = (lr[1] as u16 & 0xf) << 8 | lr[0] as u16;
+        let right = (lr[2] as u16) << 4 | (lr[1] as u16) >> 4;
+        Some((left, right))
+    }
+
+    fn block_length(&self, bytes: &[u8], block: usize) -> Option<i64> {
+        if block >= self.block_length_size {
+            return None;
+        }
+        Some(u16_le(bytes, self.block_length + 2 * block)? as i64)
+    }
+
+    fn size(&self) -> Option<u64> {
+        let groups = self.group_len.iter().position(|&len| len == 0)?;
+        Some(self.group_idx[groups])
+    }
+
+    // The value at `idx`.
+    fn decompress(&self, bytes: &[u8], idx: u64) -> Option<u16> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as u16);
+        }
+
+        // The sparse index points into the block lengths every `span` values,
+        // from there walk the blocks to the one with our value.
+        let k = (idx / self.span) as usize;
+        let entry = self.sparse_index + 6 * k;
+        if k >= self.sparse_index_size {
+            return None;
+        }
+        let mut block = u32_le(bytes, entry)? as usize;
+        let mut offset = u16_le(bytes, entry + 4)? as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += self.block_length(bytes, block)? + 1;
+        }
+        while offset > self.block_length(bytes, block)? {
+            offset -= self.block_length(bytes, block)? + 1;
+            block += 1;
+        }
+
+        // Canonical Huffman: symbols of the same length are consecutive, and
+        // longer symbols have lower values. Skip symbols until the one that
+        // covers our offset.
+        let mut ptr = self.data + block * self.block_size;
+        let mut buf = be_padded::<8>(bytes, ptr);
+        ptr += 8;
+        let mut buf_size = 64;
+        let min_len = self.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < *self.base64.get(len)? {
+                len += 1;
+            }
+            let shift = (64 - len - min_len) as u32;
+            sym = ((buf - self.base64[len]).checked_shr(shift).unwrap_or(0)) as u16;
+            sym = sym.wrapping_add(u16_le(bytes, self.lowest_sym + 2 * len)?);
+
+            let values = *self.symlen.get(sym as usize)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            let len = len + min_len;
+            buf = buf.checked_shl(len as u32).unwrap_or(0);
+            buf_size -= len as i64;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= be_padded::<4>(bytes, ptr) << (64 - buf_size);
+                ptr += 4;
+            }
+        }
+
+        // Symbols stand for pairs of symbols (recursive pairing), follow the
+        // side our offset is in down to a single value.
+        while self.symlen[sym as usize] != 0 {
+            let (left, right) = self.tree(bytes, sym as usize)?;
+            let left_values = *self.symlen.get(left as usize)? as i64 + 1;
+            if offset < left_values {
+                sym = left;
+            } else {
+                offset -= left_values;
+                sym = right;
+            }
+        }
+        Some(self.tree(bytes, sym as usize)?.0)
+    }
+}
+
+// Everything in a table file, read on first use.
+struct Data {
+    bytes: Vec<u8>,
+    // [side][file], one side for DTZ tables and tables with the same pieces on
+    // both sides, one file without pawns.
+    pairs: Vec<Vec<PairsData>>,
+    // DTZ: start of the value maps.
+    map: usize,
+}
+
+impl Data {
+    fn pairs(&self, side: usize, file: usize) -> &PairsData {
+        let files = &self.pairs[usize::min(side, self.pairs.len() - 1)];
+        &files[usize::min(file, files.len() - 1)]
+    }
+}
+
+// Where a position is in a DTZ table, or that it's stored for the other side
+// to move only.
+pub enum Dtz {
+    Plies(i32),
+    OtherSide,
+}
+
+pub struct Table {
+    kind: Kind,
+    path: PathBuf,
+    // Keys of the table's material with white as the stronger side, and with
+    // the colors swapped.
+    key: u64,
+    key2: u64,
+    piece_count: usize,
+    has_pawns: bool,
+    // At least one piece other than a king is alone of its kind and color.
+    has_unique_pieces: bool,
+    // Pawns of the leading color first, the one with fewer pawns if both have some.
+    pawn_count: [usize; 2],
+    // None if the file can't be read, then every probe fails.
+    data: OnceLock<Option<Data>>,
+}
+
+impl Table {
+    // Opening is lazy, nothing gets read until the first probe.
+    pub fn new(kind: Kind, path: PathBuf, material: &Material) -> Table {
+        let pawns = |color| material.count(color, Piece::Pawn);
+        let leading = if pawns(1) == 0 || (pawns(0) > 0 && pawns(1) >= pawns(0)) {
+            0
+        } else {
+            1
+        };
+        let has_unique_pieces = (0..2).any(|color| {
+            [
+                Piece::Pawn,
+                Piece::Knight,
+                Piece::Bishop,
+                Piece::Rook,
+                Piece::Queen,
+            ]
+            .iter()
+            .any(|&piece| material.count(color, piece) == 1)
+        });
+
+        Table {
+            kind,
+            path,
+            key: material.key(),
+            key2: material.flipped().key(),
+            piece_count: material.len(),
+            has_pawns: pawns(0) + pawns(1) > 0,
+            has_unique_pieces,
+            pawn_count: [pawns(leading), pawns(1 - leading)],
+            data: OnceLock::new(),
+        }
+    }
+
+    fn data(&self) -> Option<&Data> {
+        self.data
+            .get_or_init(|| {
+                let bytes = fs::read(&self.path).ok()?;
+                self.parse(bytes)
+            })
+            .as_ref()
+    }
+
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        debug_assert_eq!(self.kind, Kind::Wdl);
+        let data = self.data()?;
+        let (side, file, idx) = self.lookup(data, board)?.ok()?;
+        let value = data.pairs(side, file).decompress(&data.bytes, idx)?;
+        Wdl::from_i32(value as i32 - 2)
+    }
+
+    // Plies to zeroing for a won or lost position, given whether it's won or lost.
+    pub fn probe_dtz(&self, board: &Board, wdl: Wdl) -> Option<Dtz> {
+        debug_assert_eq!(self.kind, Kind::Dtz);
+        let data = self.data()?;
+        let (side, file, idx) = match self.lookup(data, board)? {
+            Ok(found) => found,
+            Err(OtherSide) => return Some(Dtz::OtherSide),
+        };
+        let d = data.pairs(side, file);
+        let mut value = d.decompress(&data.bytes, idx)? as usize;
+
+        // Maps are in the order win, loss, cursed win, blessed loss.
+        if d.flags & MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win => d.map_idx[0],
+                Wdl::Loss => d.map_idx[1],
+                Wdl::CursedWin => d.map_idx[2],
+                Wdl::BlessedLoss => d.map_idx[3],
+                Wdl::Draw => d.map_idx[0],
+            } as usize;
+            value = if d.flags & WIDE != 0 {
+                u16_le(&data.bytes, data.map + 2 * (map + value))? as usize
+            } else {
+                byte(&data.bytes, data.map + map + value)? as usize
+            };
+        }
+
+        // Stored in moves unless the flags say plies, which we want.
+        let in_moves = match wdl {
+            Wdl::Win => d.flags & WIN_PLIES == 0,
+            Wdl::Loss => d.flags & LOSS_PLIES == 0,
+            Wdl::CursedWin | Wdl::BlessedLoss => true,
+            Wdl::Draw => false,
+        };
+        if in_moves {
+            value *= 2;
+        }
+        Some(Dtz::Plies(value as i32 + 1))
+    }
+
+    // Which of the sub-tables `board` is in, and its index there. Tables are
+    // stored with white as the stronger side, so positions where black is get
+    // their colors swapped and board flipped first. Then the board is mirrored
+    // so the leading piece (or pawn) is in the a1-d1-d4 triangle (or on files
+    // a-d) to only store one of every symmetric position.
+    fn lookup(&self, data: &Data, board: &Board) -> Option<Result<(usize, usize, u64), OtherSide>> {
+        let maps = maps();
+        let black_symmetric = board.side_to_move == Color::Black && self.key == self.key2;
+        let black_stronger = Material::from_board(board).key() != self.key;
+        let flip = black_symmetric || black_stronger;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ board.side_to_move as usize;
+
+        let mut squares = [0u8; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = BitBoard::empty();
+        let mut lead_pawns_cnt = 0;
+        let mut tb_file = 0;
+
+        // With pawns the table is split by the file of the leading pawn, the
+        // pawns of the leading color come first.
+        if self.has_pawns {
+            let pc = data.pairs(0, 0).pieces[0] ^ flip_color;
+            let color = if pc & 8 == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+            lead_pawns = *board.pieces(Piece::Pawn) & *board.color_combined(color);
+            for sq in lead_pawns {
+                squares[size] = sq.0 ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_cnt = size;
+            let lead = (0..lead_pawns_cnt).max_by_key(|&i| maps.pawns[squares[i] as usize])?;
+            squares.swap(0, lead);
+            tb_file = u8::min(file(squares[0]), 7 - file(squares[0])) as usize;
+        }
+
+        // DTZ tables only store one side to move.
+        if self.kind == Kind::Dtz {
+            let flags = data.pairs(0, tb_file).flags;
+            if (flags & STM) as usize != stm && (self.key != self.key2 || self.has_pawns) {
+                return Some(Err(OtherSide));
+            }
+        }
+
+        for sq in board.combined() ^ lead_pawns {
+            if size == MAX_PIECES {
+                return None;
+            }
+            let piece = board.piece_on(sq)? as u8 + 1;
+            let color = board.color_on(sq)? as u8 * 8;
+            squares[size] = sq.0 ^ flip_squares;
+            pieces[size] = (piece | color) ^ flip_color;
+            size += 1;
+        }
+        if size != self.piece_count {
+            return None;
+        }
+
+        // Put the pieces in the order they're encoded in.
+        let d = data.pairs(stm, tb_file);
+        for i in lead_pawns_cnt..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        if file(squares[0]) > 3 {
+            for sq in &mut squares[..size] {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = maps.lead_pawn_idx[lead_pawns_cnt][squares[0] as usize];
+            squares[1..lead_pawns_cnt].sort_by_key(|&sq| maps.pawns[sq as usize]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawns_cnt).skip(1) {
+                idx += binomial(i, maps.pawns[sq as usize] as usize);
+            }
+        } else {
+            if rank(squares[0]) > 3 {
+                for sq in &mut squares[..size] {
+                    *sq ^= 56;
+                }
+            }
+            // The first piece of the leading group off the a1-h8 diagonal goes below it.
+            for i in 0..d.group_len[0] {
+                let off = off_a1h8(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for sq in &mut squares[i..size] {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            // Three unique pieces are encoded together, otherwise the two kings.
+            let sq = |i: usize| squares[i] as u64;
+            let r = |i: usize| rank(squares[i]) as u64;
+            if self.has_unique_pieces {
+                let adjust1 = (sq(1) > sq(0)) as u64;
+                let adjust2 = (sq(2) > sq(0)) as u64 + (sq(2) > sq(1)) as u64;
+                let b1h1h7 = |i: usize| maps.b1h1h7[squares[i] as usize];
+                idx = if off_a1h8(squares[0]) != 0 {
+                    (maps.a1d1d4[squares[0] as usize] * 63 + sq(1) - adjust1) * 62 + sq(2) - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + r(0) * 28 + b1h1h7(1)) * 62 + sq(2) - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62 + r(0) * 7 * 28 + (r(1) - adjust1) * 28 + b1h1h7(2)
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + r(0) * 7 * 6
+                        + (r(1) - adjust1) * 6
+                        + (r(2) - adjust2)
+                };
+            } else {
+                idx = maps.kk[maps.a1d1d4[squares[0] as usize] as usize][squares[1] as usize];
+            }
+        }
+
+        // The rest of the groups, each piece's square counted without the
+        // squares taken by earlier groups.
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            if start + len > size {
+                return None;
+            }
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                let skip = if remaining_pawns { 8 } else { 0 };
+                n += binomial(i + 1, (sq as usize).checked_sub(adjust + skip)?);
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        Some(Ok((stm, tb_file, idx)))
+    }
+
+    fn parse(&self, bytes: Vec<u8>) -> Option<Data> {
+        if bytes.get(..4)? != self.kind.magic() {
+            return None;
+        }
+        let flags = byte(&bytes, 4)?;
+        if (flags & HAS_PAWNS != 0) != self.has_pawns
+            || (flags & SPLIT != 0) != (self.key != self.key2)
+        {
+            return None;
+        }
+
+        let sides = if self.kind == Kind::Wdl && self.key != self.key2 {
+            2
+        } else {
+            1
+        };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+        let mut pos = 5;
+
+        for f in 0..files {
+            let b0 = byte(&bytes, pos)?;
+            let b1 = if pp { byte(&bytes, pos + 1)? } else { 0xff };
+            let order = [[b0 & 0xf, b1 & 0xf], [b0 >> 4, b1 >> 4]];
+            pos += 1 + pp as usize;
+            for k in 0..self.piece_count {
+                let b = byte(&bytes, pos)?;
+                for (i, side) in pairs.iter_mut().enumerate() {
+                    side[f].pieces[k] = if i == 0 { b & 0xf } else { b >> 4 };
+                }
+                pos += 1;
+            }
+            for (i, side) in pairs.iter_mut().enumerate() {
+                self.set_groups(&mut side[f], order[i], f)?;
+            }
+        }
+        pos += pos & 1;
+
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                pos = set_sizes(&mut side[f], &bytes, pos)?;
+            }
+        }
+
+        let map = pos;
+        if self.kind == Kind::Dtz {
+            for d in pairs[0].iter_mut() {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = ((pos - map) / 2 + 1) as u16;
+                        pos += 2 * u16_le(&bytes, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - map + 1) as u16;
+                        pos += byte(&bytes, pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                side[f].sparse_index = pos;
+                pos += 6 * side[f].sparse_index_size;
+            }
+        }
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                side[f].block_length = pos;
+                pos += 2 * side[f].block_length_size;
+            }
+        }
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                pos = (pos + 0x3f) & !0x3f;
+                side[f].data = pos;
+                pos += side[f].num_blocks * side[f].block_size;
+            }
+        }
+        if pos > bytes.len() {
+            return None;
+        }
+
+        Some(Data { bytes, pairs, map })
+    }
+
+    // Split the pieces into groups and work out each group's starting index.
+    // Pieces of the same kind are encoded together, and so are the leading
+    // pieces: the pawns of the leading color, three unique pieces or the kings.
+    // Groups come in the order given by the file.
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], f: usize) -> Option<()> {
+        let mut n = 0;
+        let mut first_len = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if pp { 2 } else { 1 };
+        let mut free = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    *maps().lead_pawns_size.get(d.group_len[0])?.get(f)?
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                d.group_idx[1] = idx;
+                idx *= binomial(d.group_len[1], 48 - d.group_len[0]);
+            } else {
+                d.group_idx[next] = idx;
+                idx *= binomial(d.group_len[next], free);
+                free = free.checked_sub(d.group_len[next])?;
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+        Some(())
+    }
+}
+
+// Marks a position stored for the other side to move only, see Table::lookup.
+pub struct OtherSide;
+
+// Read the sizes and Huffman code of a sub-table, returns where the next one starts.
+fn set_sizes(d: &mut PairsData, bytes: &[u8], mut pos: usize) -> Option<usize> {
+    d.flags = byte(bytes, pos)?;
+    pos += 1;
+    if d.flags & SINGLE_VALUE != 0 {
+        d.min_sym_len = byte(bytes, pos)?;
+        return Some(pos + 1);
+    }
+
+    d.block_size = 1usize.checked_shl(byte(bytes, pos)? as u32)?;
+    d.span = 1u64.checked_shl(byte(bytes, pos + 1)? as u32)?;
+    d.sparse_index_size = d.size()?.div_ceil(d.span) as usize;
+    let padding = byte(bytes, pos + 2)? as usize;
+    d.num_blocks = u32_le(bytes, pos + 3)? as usize;
+    d.block_length_size = d.num_blocks + padding;
+    let max_sym_len = byte(bytes, pos + 7)? as usize;
+    d.min_sym_len = byte(bytes, pos + 8)?;
+    let min_sym_len = d.min_sym_len as usize;
+    pos += 9;
+    if max_sym_len < min_sym_len || max_sym_len > 64 {
+        return None;
+    }
+
+    // The lowest symbol of each length, longest last. Shifted up to 64 bits
+    // every length's symbols are above the next length's.
+    d.lowest_sym = pos;
+    let lengths = max_sym_len - min_sym_len + 1;
+    let lowest = |i: usize| u16_le(bytes, pos + 2 * i).map(|sym| sym as u64);
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        d.base64[i] = (d.base64[i + 1]
+            .wrapping_add(lowest(i)?)
+            .wrapping_sub(lowest(i + 1)?))
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base.checked_shl((64 - i - min_sym_len) as u32).unwrap_or(0);
+    }
+    pos += 2 * lengths;
+
+    let symbols = u16_le(bytes, pos)? as usize;
+    pos += 2;
+    d.btree = pos;
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(d, bytes, sym, &mut visited)?;
+        }
+    }
+    Some(pos + 3 * symbols + (symbols & 1))
+}
+
+// How many values (minus one) a symbol stands for, from its halves.
+fn set_symlen(d: &mut PairsData, bytes: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+    let (left, right) = d.tree(bytes, sym)?;
+    if right == 0xfff {
+        return Some(0);
+    }
+    let (left, right) = (left as usize, right as usize);
+    if left >= visited.len() || right >= visited.len() {
+        return None;
+    }
+    if !visited[left] {
+        d.symlen[left] = set_symlen(d, bytes, left, visited)?;
+    }
+    if !visited[right] {
+        d.symlen[right] = set_symlen(d, bytes, right, visited)?;
+    }
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+// Writing tables for the tests, real ones are too big to check in. Files have
+// the layout the generator's do, with every value in one fixed length code.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use crate::chess::Square;
+    use crate::movegen::{self, MoveGen};
+    use std::path::Path;
+
+    // Values per block, 3 bits each.
+    const BLOCK_VALUES: usize = 128;
+
+    // A piece code and its square.
+    type Placed = (u8, u8);
+
+    fn leading_color(material: &Material) -> usize {
+        let pawns = |color| material.count(color, Piece::Pawn);
+        if pawns(1) == 0 || (pawns(0) > 0 && pawns(1) >= pawns(0)) {
+            0
+        } else {
+            1
+        }
+    }
+
+    // Piece codes as the files have them, pawn 1 to king 6, plus 8 for black.
+    // In the order they're encoded: the leading color's pawns, the other pawns,
+    // kings, the rest.
+    pub fn pieces(material: &Material) -> Vec<u8> {
+        let lead = leading_color(material);
+        let mut order = vec![(lead, Piece::Pawn), (1 - lead, Piece::Pawn)];
+        order.extend([(0, Piece::King), (1, Piece::King)]);
+        for color in 0..2 {
+            for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+                order.push((color, piece));
+            }
+        }
+        let mut pieces = Vec::new();
+        for (color, piece) in order {
+            for _ in 0..material.count(color, piece) {
+                pieces.push(piece as u8 + 1 + 8 * color as u8);
+            }
+        }
+        pieces
+    }
+
+    fn files(material: &Material) -> usize {
+        let pawns = material.count(0, Piece::Pawn) + material.count(1, Piece::Pawn);
+        if pawns > 0 {
+            4
+        } else {
+            1
+        }
+    }
+
+    fn header(kind: Kind, material: &Material) -> Vec<u8> {
+        let mut bytes = kind.magic().to_vec();
+        let split = material.key() != material.flipped().key();
+        let has_pawns = files(material) > 1;
+        bytes.push(if split { SPLIT } else { 0 } | if has_pawns { HAS_PAWNS } else { 0 });
+        let both_pawns = material.count(0, Piece::Pawn) > 0 && material.count(1, Piece::Pawn) > 0;
+        for _ in 0..files(material) {
+            bytes.push(0x00);
+            if both_pawns {
+                bytes.push(0x11);
+            }
+            bytes.extend(pieces(material).iter().map(|&p| p | p << 4));
+        }
+        bytes.resize(bytes.len() + bytes.len() % 2, 0);
+        bytes
+    }
+
+    fn pad(bytes: &mut Vec<u8>) {
+        bytes.resize((bytes.len() + 0x3f) & !0x3f, 0);
+    }
+
+    // A table with one value per side, `sides` is (flags, value) for each.
+    pub fn write_single_value(path: &Path, kind: Kind, name: &str, sides: &[(u8, u8)]) {
+        let material = Material::from_name(name).unwrap();
+        let mut bytes = header(kind, &material);
+        for _ in 0..files(&material) {
+            for &(flags, value) in sides {
+                bytes.extend([flags | SINGLE_VALUE, value]);
+            }
+        }
+        pad(&mut bytes);
+        fs::write(path, bytes).unwrap();
+    }
+
+    // A pawnless WDL table with `value` of every legal position. Positions with
+    // the same index must have the same value.
+    pub fn write_wdl(path: &Path, name: &str, value: impl Fn(&Board) -> Wdl) {
+        let material = Material::from_name(name).unwrap();
+        assert_eq!(files(&material), 1, "{} has pawns", name);
+        let split = material.key() != material.flipped().key();
+        let sides = if split { 2 } else { 1 };
+
+        // The index only needs the header, so look it up in a placeholder.
+        write_single_value(path, Kind::Wdl, name, &vec![(0, 2); sides]);
+        let table = Table::new(Kind::Wdl, path.to_path_buf(), &material);
+        let data = table.data().unwrap();
+        let mut values: Vec<Vec<Option<u8>>> = (0..sides)
+            .map(|side| vec![None; data.pairs(side, 0).size().unwrap() as usize])
+            .collect();
+        for_each_position(&material, |board| {
+            let (side, _, idx) = table.lookup(data, board).unwrap().ok().unwrap();
+            let v = (value(board) as i32 + 2) as u8;
+            let old = values[side][idx as usize].replace(v);
+            assert!(old.is_none() || old == Some(v), "{}", board.to_fen());
+        });
+
+        let mut bytes = header(Kind::Wdl, &material);
+        for side in &values {
+            let num_blocks = side.len().div_ceil(BLOCK_VALUES);
+            // Blocks of 64 bytes, a sparse index entry every 64 values, one
+            // block of padding.
+            bytes.extend([0, 6, 6, 1]);
+            bytes.extend((num_blocks as u32).to_le_bytes());
+            // Symbols 0 to 7 are 3 bits long and stand for themselves.
+            bytes.extend([3, 3]);
+            bytes.extend(0u16.to_le_bytes());
+            bytes.extend(8u16.to_le_bytes());
+            for sym in 0..8 {
+                bytes.extend([sym, 0xf0, 0xff]);
+            }
+        }
+        for side in &values {
+            for k in 0..side.len().div_ceil(64) {
+                let i = k * 64 + 32;
+                bytes.extend(((i / BLOCK_VALUES) as u32).to_le_bytes());
+                bytes.extend(((i % BLOCK_VALUES) as u16).to_le_bytes());
+            }
+        }
+        for side in &values {
+            for block in side.chunks(BLOCK_VALUES) {
+                bytes.extend((block.len() as u16 - 1).to_le_bytes());
+            }
+            bytes.extend((BLOCK_VALUES as u16 - 1).to_le_bytes());
+        }
+        for side in &values {
+            pad(&mut bytes);
+            for block in side.chunks(BLOCK_VALUES) {
+                let mut bits = [0u8; 64];
+                for (j, v) in block.iter().enumerate() {
+                    let v = v.unwrap_or(2);
+                    for bit in 0..3 {
+                        if v >> (2 - bit) & 1 != 0 {
+                            let at = 3 * j + bit;
+                            bits[at / 8] |= 0x80 >> (at % 8);
+                        }
+                    }
+                }
+                bytes.extend(bits);
+            }
+        }
+        pad(&mut bytes);
+        fs::write(path, bytes).unwrap();
+    }
+
+    // Pieces given by code and square.
+    pub fn board(pieces: &[(u8, u8)], side_to_move: Color) -> Board {
+        movegen::gen_moves_once();
+        let mut board = Board::empty();
+        board.castling = 0;
+        board.side_to_move = side_to_move;
+        for &(code, sq) in pieces {
+            board.pieces[(code & 7) as usize - 1].flip_mut(Square(sq));
+            board.color_combined[(code >> 3) as usize].flip_mut(Square(sq));
+        }
+        board.update_attackers();
+        board
+    }
+
+    pub fn placement(board: &Board) -> Vec<(u8, u8)> {
+        board
+            .combined()
+            .map(|sq| {
+                let piece = board.piece_on(sq).unwrap() as u8;
+                let color = board.color_on(sq).unwrap() as u8;
+                (piece + 1 + 8 * color, sq.0)
+            })
+            .collect()
+    }
+
+    // The same position with the colors swapped.
+    pub fn flip_colors(board: &Board) -> Board {
+        let pieces: Vec<(u8, u8)> = placement(board)
+            .iter()
+            .map(|&(code, sq)| (code ^ 8, sq ^ 56))
+            .collect();
+        self::board(&pieces, board.side_to_move.other())
+    }
+
+    // Every legal position with `material`, with either side to move.
+    pub fn for_each_position(material: &Material, mut f: impl FnMut(&Board)) {
+        fn place(pieces: &[u8], placed: &mut Vec<Placed>, f: &mut dyn FnMut(&[Placed])) {
+            let code = match pieces.first() {
+                Some(&code) => code,
+                None => return f(placed),
+            };
+            for sq in 0..64 {
+                let pawn_rank = code & 7 == 1 && !(8..56).contains(&sq);
+                if pawn_rank || placed.iter().any(|&(_, s)| s == sq) {
+                    continue;
+                }
+                placed.push((code, sq));
+                place(&pieces[1..], placed, f);
+                placed.pop();
+            }
+        }
+
+        place(&pieces(material), &mut Vec::new(), &mut |placed| {
+            for stm in [Color::White, Color::Black] {
+                // Not with the side that just moved in check, or the kings touching.
+                let board = board(placed, stm);
+                if !board.attacked(stm).get(board.king(stm.other())) {
+                    f(&board);
+                }
+            }
+        });
+    }
+
+    // A fresh directory for a test's tables.
+    pub fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yobmef_test_syzygy_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Whether the side to move has no moves and isn't in check.
+    pub fn is_stalemate(board: &Board) -> bool {
+        !board.in_check() && MoveGen::new_legal(board).next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    // The least of `board`'s images under the first `symmetries` of: mirroring
+    // the files, the ranks, and along the a1-h8 diagonal.
+    fn canonical(board: &Board, symmetries: usize) -> (Color, Vec<(u8, u8)>) {
+        let image = |t: usize| {
+            let mut pieces: Vec<(u8, u8)> = placement(board)
+                .into_iter()
+                .map(|(code, mut sq)| {
+                    if t & 1 != 0 {
+                        sq ^= 7;
+                    }
+                    if t & 2 != 0 {
+                        sq ^= 56;
+                    }
+                    if t & 4 != 0 {
+                        sq = ((sq >> 3) | (sq << 3)) & 63;
+                    }
+                    (code, sq)
+                })
+                .collect();
+            pieces.sort_unstable();
+            pieces
+        };
+        (
+            board.side_to_move,
+            (0..symmetries).map(image).min().unwrap(),
+        )
+    }
+
+    // Every position has an index in range, and positions share one only if
+    // they're the same up to symmetry. The colors swapped is the same position.
+    fn check_lookup(name: &str, symmetries: usize) {
+        let dir = temp_dir(&format!("lookup_{}", name));
+        let path = dir.join(format!("{}.rtbw", name));
+        write_single_value(&path, Kind::Wdl, name, &[(0, 2), (0, 2)]);
+        let material = Material::from_name(name).unwrap();
+        let table = Table::new(Kind::Wdl, path, &material);
+        let data = table.data().unwrap();
+
+        let mut positions = HashMap::new();
+        let mut indexes = HashSet::new();
+        for_each_position(&material, |board| {
+            let found = table.lookup(data, board).unwrap().ok().unwrap();
+            let (side, file, idx) = found;
+            assert!(
+                idx < data.pairs(side, file).size().unwrap(),
+                "{}",
+                board.to_fen()
+            );
+            let flipped = flip_colors(board);
+            assert_eq!(table.lookup(data, &flipped).unwrap().ok(), Some(found));
+
+            let position = canonical(board, symmetries);
+            let first = positions.entry(found).or_insert_with(|| position.clone());
+            assert_eq!(*first, position, "{}", board.to_fen());
+            indexes.insert(position);
+        });
+        assert_eq!(positions.len(), indexes.len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_maps() {
+        let maps = maps();
+        let kk: HashSet<u64> = maps.kk.iter().flatten().copied().collect();
+        assert_eq!(kk.len(), 462);
+        assert!(kk.iter().all(|&code| code < 462));
+
+        let mut pawns: Vec<u64> = (8..56).map(|sq| maps.pawns[sq]).collect();
+        pawns.sort_unstable();
+        assert_eq!(pawns, (0..48).collect::<Vec<u64>>());
+        assert_eq!(maps.lead_pawns_size[1], [6; 4]);
+
+        assert_eq!(binomial(2, 5), 10);
+        assert_eq!(binomial(3, 2), 0);
+        assert_eq!(binomial(MAX_PIECES, 10), 0);
+    }
+
+    #[test]
+    fn test_material() {
+        let board = testing::board(&[(6, 4), (4, 0), (14, 35), (10, 9)], Color::White);
+        let krkn = Material::from_name("KRvKN").unwrap();
+        assert_eq!(Material::from_board(&board), krkn);
+        assert_eq!(krkn.len(), 4);
+        assert_eq!(krkn.flipped(), Material::from_name("KNvKR").unwrap());
+        assert_ne!(krkn.key(), krkn.flipped().key());
+        let kk = Material::from_name("KvK").unwrap();
+        assert_eq!(kk.key(), kk.flipped().key());
+
+        for name in &["KRK", "KvKvK", "RvK", "KKvK", "KXvK", "KQQQQvKQQ"] {
+            assert_eq!(Material::from_name(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_lookup_pawnless() {
+        check_lookup("KRvK", 8);
+    }
+
+    #[test]
+    fn test_lookup_pawns() {
+        check_lookup("KPvK", 2);
+    }
+
+    #[test]
+    fn test_parse() {
+        let dir = temp_dir("parse");
+        let path = dir.join("KNvK.rtbw");
+        let material = Material::from_name("KNvK").unwrap();
+        let board = testing::board(&[(6, 4), (2, 20), (14, 60)], Color::Black);
+        write_single_value(&path, Kind::Wdl, "KNvK", &[(0, 2), (0, 2)]);
+        let table = Table::new(Kind::Wdl, path.clone(), &material);
+        assert_eq!(table.probe_wdl(&board), Some(Wdl::Draw));
+
+        // Another table's pieces, then not a table at all.
+        let table = Table::new(
+            Kind::Wdl,
+            path.clone(),
+            &Material::from_name("KNNvK").unwrap(),
+        );
+        assert_eq!(table.probe_wdl(&board), None);
+        fs::write(&path, b"not a table").unwrap();
+        let table = Table::new(Kind::Wdl, path.clone(), &material);
+        assert_eq!(table.probe_wdl(&board), None);
+        let table = Table::new(Kind::Wdl, dir.join("KBvK.rtbw"), &material);
+        assert_eq!(table.probe_wdl(&board), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
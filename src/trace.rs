@@ -0,0 +1,106 @@
+use crate::chess::Movement;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Search tree tracing, for debugging pruning bugs without sprinkling eprintln
+// all over alphabeta. Every node the searcher enters gets logged with its line
+// from the root, so the output can be grepped for a specific subtree.
+
+#[derive(Debug, Default, Clone)]
+pub struct TraceFilter {
+    // Only trace nodes up to (and including) this ply.
+    pub max_ply: Option<u16>,
+    // Only trace this line from the root (and the nodes leading to it).
+    pub subtree: Vec<Movement>,
+}
+
+impl TraceFilter {
+    fn wants(&self, line: &[Movement]) -> bool {
+        if let Some(max_ply) = self.max_ply {
+            if line.len() > max_ply as usize {
+                return false;
+            }
+        }
+
+        let n = usize::min(line.len(), self.subtree.len());
+        line[..n] == self.subtree[..n]
+    }
+}
+
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+    pub fn to_file<P: AsRef<Path>>(path: P, filter: TraceFilter) -> io::Result<Tracer> {
+        let file = File::create(path)?;
+        Ok(Tracer::new(Box::new(BufWriter::new(file)), filter))
+    }
+
+    pub fn new(out: Box<dyn Write + Send>, filter: TraceFilter) -> Tracer {
+        Tracer { out, filter }
+    }
+
+    // Log an event for the node at the end of `line`, indented by ply.
+    pub fn node(&mut self, line: &[Movement], args: fmt::Arguments) {
+        if !self.filter.wants(line) {
+            return;
+        }
+
+        // Tracing is best effort, we don't want to kill a search over a full disk.
+        let _ = write!(self.out, "{:width$}[", "", width = line.len() * 2);
+        for (i, mv) in line.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            let _ = write!(self.out, "{}{}", sep, mv);
+        }
+        let _ = writeln!(self.out, "] {}", args);
+    }
+
+    pub fn flush(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tracer({:?})", self.filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(s: &str) -> Vec<Movement> {
+        s.split_whitespace()
+            .map(|mv| Movement::from_notation(mv).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_max_ply() {
+        let filter = TraceFilter {
+            max_ply: Some(1),
+            subtree: Vec::new(),
+        };
+        assert!(filter.wants(&line("")));
+        assert!(filter.wants(&line("e2e4")));
+        assert!(!filter.wants(&line("e2e4 e7e5")));
+    }
+
+    #[test]
+    fn test_filter_subtree() {
+        let filter = TraceFilter {
+            max_ply: None,
+            subtree: line("e2e4 e7e5"),
+        };
+        assert!(filter.wants(&line("")));
+        assert!(filter.wants(&line("e2e4")));
+        assert!(filter.wants(&line("e2e4 e7e5 g1f3")));
+        assert!(!filter.wants(&line("d2d4")));
+        assert!(!filter.wants(&line("e2e4 c7c5 g1f3")));
+    }
+}
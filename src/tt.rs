@@ -0,0 +1,391 @@
+use crate::chess::{Movement, Piece, Square};
+use crate::search::{Bound, SearchResult};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+// Transposition table, indexed by zobrist key.
+//
+// Entries are packed into 16 bytes and grouped in buckets of four, so a bucket
+// is exactly one cache line and a probe costs at most one cache miss. Since the
+// searcher knows the child's key before recursing, it can prefetch the child's
+// bucket while it does other work.
+//
+// Search threads share one table without locking. Each entry stores its key
+// xored with its data, so if two threads write an entry at once the key won't
+// match the data and the entry just reads as a miss. (lockless hashing, from
+// Crafty)
+//
+// The table is kept from one move to the next, only a new game clears it. Each
+// search is a new generation, and entries remember the one that stored them,
+// so entries from moves ago make way for the current search's even if they're
+// deeper.
+
+const BUCKET_SIZE: usize = 4;
+
+// An entry a generation older counts as this much shallower when picking one
+// to replace.
+const AGE_WEIGHT: i32 = 8;
+
+// Entries hashfull looks at, like most engines.
+const HASHFULL_SAMPLE: usize = 1000;
+
+#[derive(Debug, Default)]
+#[repr(C, align(16))]
+struct Entry {
+    key: AtomicU64,  // full key ^ data, zero (with zero data) means empty
+    data: AtomicU64, // see pack_data
+}
+
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+struct Bucket([Entry; BUCKET_SIZE]);
+
+// move | eval << 16 | depth << 32 | bound << 48 | generation << 56
+fn pack_data(sr: &SearchResult, generation: u8) -> u64 {
+    let bound = match sr.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    pack_move(&sr.mv) as u64
+        | (sr.eval as u16 as u64) << 16
+        | (sr.depth as u16 as u64) << 32
+        | bound << 48
+        | (generation as u64) << 56
+}
+
+fn unpack_data(data: u64) -> SearchResult {
+    SearchResult {
+        mv: unpack_move(data as u16),
+        eval: (data >> 16) as u16 as i16,
+        depth: (data >> 32) as u16 as i16,
+        bound: match (data >> 48) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        },
+    }
+}
+
+fn unpack_generation(data: u64) -> u8 {
+    (data >> 56) as u8
+}
+
+// from | to << 6 | (promote + 1) << 12
+fn pack_move(mv: &Movement) -> u16 {
+    let promote = mv.promote.map_or(0, |p| p as u16 + 1);
+    mv.from_square.0 as u16 | (mv.to_square.0 as u16) << 6 | promote << 12
+}
+
+fn unpack_move(mv: u16) -> Movement {
+    let promote = match mv >> 12 {
+        0 => None,
+        p => Piece::from_usize(p as usize - 1),
+    };
+    Movement::new(
+        Square((mv & 63) as u8),
+        Square((mv >> 6 & 63) as u8),
+        promote,
+    )
+}
+
+#[derive(Debug)]
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    used: AtomicUsize, // entries in use, for hashfull
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(mb: usize) -> Self {
+        TranspositionTable::with_capacity(mb * 1024 * 1024 / std::mem::size_of::<Entry>())
+    }
+
+    // Room for at least `entries` entries (and one bucket), allocated up front.
+    // The table never grows, and clear keeps the memory.
+    pub fn with_capacity(entries: usize) -> Self {
+        let len = usize::max(1, entries.div_ceil(BUCKET_SIZE));
+        TranspositionTable {
+            buckets: (0..len).map(|_| Bucket::default()).collect(),
+            used: AtomicUsize::new(0),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    // Number of entries the table can hold.
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    // Only approximate while several threads are writing.
+    pub fn len(&self) -> usize {
+        usize::min(self.used.load(Ordering::Relaxed), self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // How full the table is for the current search, in permille: the share of the
+    // first HASHFULL_SAMPLE entries this search stored. Counting every entry
+    // would say full for good once the table has been filled, since old
+    // generations are only replaced, never removed.
+    pub fn hashfull(&self) -> u16 {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = usize::min(HASHFULL_SAMPLE, self.capacity());
+        let current = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| &bucket.0)
+            .take(sample)
+            .filter(|e| {
+                let data = e.data.load(Ordering::Relaxed);
+                let empty = e.key.load(Ordering::Relaxed) == 0 && data == 0;
+                !empty && unpack_generation(data) == generation
+            })
+            .count();
+        (current * 1000 / sample) as u16
+    }
+
+    // Maps the key onto 0..buckets.len() without needing a power of two length.
+    fn index(&self, key: u64) -> usize {
+        ((key as u128 * self.buckets.len() as u128) >> 64) as usize
+    }
+
+    pub fn get(&self, key: u64) -> Option<SearchResult> {
+        let bucket = &self.buckets[self.index(key)];
+        bucket.0.iter().find_map(|e| {
+            let data = e.data.load(Ordering::Relaxed);
+            let stored = e.key.load(Ordering::Relaxed) ^ data;
+            if stored == key && key != 0 {
+                Some(unpack_data(data))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Called before every search (not by its helper threads), so what it stores
+    // is newer than what's already there. Wraps around after 256 searches, when
+    // the oldest entries look new again for a while.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Replace the entry for the same position if there is one, otherwise an empty
+    // slot, otherwise the shallowest search in the bucket, counting older
+    // generations as shallower.
+    pub fn insert(&self, key: u64, sr: &SearchResult) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = &self.buckets[self.index(key)].0;
+        // Every node stores, so no allocating here.
+        let entries: [(u64, u64); BUCKET_SIZE] = std::array::from_fn(|i| {
+            let data = bucket[i].data.load(Ordering::Relaxed);
+            (bucket[i].key.load(Ordering::Relaxed) ^ data, data)
+        });
+
+        let slot = match entries.iter().position(|&(k, _)| k == key) {
+            Some(i) => i,
+            None => {
+                let worth = |(_, data): (u64, u64)| {
+                    let age = generation.wrapping_sub(unpack_generation(data)) as i32;
+                    unpack_data(data).depth as i32 - AGE_WEIGHT * age
+                };
+                let i = (0..BUCKET_SIZE)
+                    .min_by_key(|&i| (entries[i] != (0, 0), worth(entries[i])))
+                    .unwrap();
+                if entries[i] == (0, 0) {
+                    self.used.fetch_add(1, Ordering::Relaxed);
+                }
+                i
+            }
+        };
+
+        let data = pack_data(sr, generation);
+        bucket[slot].key.store(key ^ data, Ordering::Relaxed);
+        bucket[slot].data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            for entry in &bucket.0 {
+                entry.key.store(0, Ordering::Relaxed);
+                entry.data.store(0, Ordering::Relaxed);
+            }
+        }
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    // Hint the CPU to pull the key's bucket into cache, we're going to probe it soon.
+    #[inline]
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let bucket = &self.buckets[self.index(key)] as *const Bucket;
+            _mm_prefetch(bucket as *const i8, _MM_HINT_T0);
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sr(mv: &str, eval: i16, depth: i16) -> SearchResult {
+        SearchResult {
+            eval,
+            mv: Movement::from_notation(mv).unwrap(),
+            depth,
+            bound: Bound::Exact,
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(std::mem::size_of::<Entry>(), 16);
+        assert_eq!(std::mem::size_of::<Bucket>(), 64);
+    }
+
+    #[test]
+    fn test_pack_move() {
+        for mv in &["e2e4", "a7a8q", "h2h1n", "a1h8", "e7e8r", "b2b1b"] {
+            let mv = Movement::from_notation(mv).unwrap();
+            assert_eq!(unpack_move(pack_move(&mv)), mv);
+        }
+    }
+
+    #[test]
+    fn test_pack_data() {
+        for sr in &[
+            sr("e2e4", -30, 3),
+            sr("a7a8q", i16::MAX, -1),
+            sr("h2h1n", -500, 0),
+            SearchResult {
+                bound: Bound::Lower,
+                ..sr("e7e5", 250, 7)
+            },
+            SearchResult {
+                bound: Bound::Upper,
+                ..sr("b2b1b", i16::MIN, i16::MAX)
+            },
+        ] {
+            assert_eq!(&unpack_data(pack_data(sr, 0)), sr);
+            let data = pack_data(sr, 255);
+            assert_eq!(&unpack_data(data), sr);
+            assert_eq!(unpack_generation(data), 255);
+        }
+    }
+
+    #[test]
+    fn test_torn_entry() {
+        let tt = TranspositionTable::new(0);
+        tt.insert(42, &sr("e2e4", -30, 3));
+
+        // Another thread's data landed, but not its key.
+        let entry = tt.buckets[0]
+            .0
+            .iter()
+            .find(|e| e.data.load(Ordering::Relaxed) != 0)
+            .unwrap();
+        entry
+            .data
+            .store(pack_data(&sr("d2d4", 10, 5), 0), Ordering::Relaxed);
+        assert_eq!(tt.get(42), None);
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.get(42), None);
+
+        tt.insert(42, &sr("e2e4", -30, 3));
+        assert_eq!(tt.get(42), Some(sr("e2e4", -30, 3)));
+        assert_eq!(tt.len(), 1);
+
+        // Same position again replaces it.
+        tt.insert(42, &sr("d2d4", 15, 4));
+        assert_eq!(tt.get(42), Some(sr("d2d4", 15, 4)));
+        assert_eq!(tt.len(), 1);
+
+        tt.clear();
+        assert_eq!(tt.get(42), None);
+        assert!(tt.is_empty());
+    }
+
+    #[test]
+    fn test_replace_shallowest() {
+        // One bucket, so every key collides.
+        let tt = TranspositionTable::new(0);
+        assert_eq!(tt.capacity(), BUCKET_SIZE);
+
+        for key in 1..=4 {
+            tt.insert(key, &sr("e2e4", 0, key as i16));
+        }
+        tt.insert(5, &sr("e2e4", 0, 5));
+
+        assert_eq!(tt.get(1), None, "depth 1 should be replaced");
+        for key in 2..=5 {
+            assert!(tt.get(key).is_some(), "key {}", key);
+        }
+        assert_eq!(tt.len(), 4);
+    }
+
+    #[test]
+    fn test_replace_old() {
+        let tt = TranspositionTable::new(0);
+        for key in 1..=3 {
+            tt.insert(key, &sr("e2e4", 0, 10));
+        }
+        tt.new_search();
+        tt.new_search();
+        tt.insert(4, &sr("e2e4", 0, 1));
+
+        // Deep but two searches old loses to shallow from this one.
+        tt.insert(5, &sr("e2e4", 0, 1));
+        assert!(tt.get(4).is_some());
+        assert_eq!((1..=3).filter(|&key| tt.get(key).is_some()).count(), 2);
+
+        // Entries survive new searches, until they're replaced.
+        tt.new_search();
+        assert_eq!(tt.get(5), Some(sr("e2e4", 0, 1)));
+        assert_eq!(tt.len(), 4);
+    }
+
+    #[test]
+    fn test_hashfull() {
+        // Two buckets, the top bit of the key picks one.
+        let tt = TranspositionTable::with_capacity(8);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 1..16 {
+            tt.insert(key << 60, &sr("e2e4", 0, 1));
+        }
+        assert_eq!(tt.hashfull(), 1000);
+
+        // The last search's entries are still there, but don't count.
+        tt.new_search();
+        assert_eq!(tt.len(), 8);
+        assert_eq!(tt.hashfull(), 0);
+        tt.insert(1, &sr("e2e4", 0, 1));
+        assert_eq!(tt.hashfull(), 125);
+
+        // Big tables only look at their first 250 buckets, key i << 50 is in bucket i.
+        let tt = TranspositionTable::new(1);
+        for i in 0..400 {
+            tt.insert((i << 50) + 1, &sr("e2e4", 0, 1));
+        }
+        assert_eq!(tt.len(), 400);
+        assert_eq!(tt.hashfull(), 250);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        assert_eq!(TranspositionTable::with_capacity(0).capacity(), BUCKET_SIZE);
+        assert_eq!(TranspositionTable::with_capacity(1000).capacity(), 1000);
+        assert_eq!(TranspositionTable::with_capacity(1001).capacity(), 1004);
+        // 16 byte entries.
+        assert_eq!(TranspositionTable::new(1).capacity(), 65536);
+    }
+}
@@ -0,0 +1,134 @@
+#[cfg(feature = "std")]
+use crate::options::UciOption;
+#[cfg(feature = "tune")]
+use core::sync::atomic::Ordering;
+
+// Search and eval constants that can be tuned, instead of guessed by hand.
+//
+// Built normally every tunable is a constant. Built with `--features tune`
+// they become UCI spin options named after them, so the SPSA driver
+// (src/bin/spsa.rs) can try other values in self-play. The values are global,
+// every engine in the process sees the same ones.
+//
+// To make a constant tunable, move it here and call its function where it was
+// used. The range is where the tuner is allowed to go, keep it sane.
+
+pub struct Tunable {
+    pub name: &'static str,
+    pub default: i16,
+    pub min: i16,
+    pub max: i16,
+}
+
+macro_rules! tunables {
+    ($($(#[$doc:meta])* $get:ident $name:literal = $default:literal, $min:literal..=$max:literal;)*) => {
+        #[cfg(feature = "tune")]
+        #[allow(non_upper_case_globals)]
+        mod values {
+            use core::sync::atomic::AtomicI16;
+            $(pub static $get: AtomicI16 = AtomicI16::new($default);)*
+        }
+
+        $(
+            $(#[$doc])*
+            #[inline]
+            pub fn $get() -> i16 {
+                #[cfg(feature = "tune")]
+                return values::$get.load(Ordering::Relaxed);
+                #[cfg(not(feature = "tune"))]
+                return $default;
+            }
+        )*
+
+        pub const TUNABLES: &[Tunable] = &[$(Tunable {
+            name: $name,
+            default: $default,
+            min: $min,
+            max: $max,
+        }),*];
+
+        // Set a tunable by its option name, false if there is no such tunable.
+        // The value is clamped to the tunable's range.
+        #[cfg(feature = "tune")]
+        pub fn set(name: &str, value: i16) -> bool {
+            match name {
+                $($name => values::$get.store(value.clamp($min, $max), Ordering::Relaxed),)*
+                _ => return false,
+            }
+            true
+        }
+    };
+}
+
+tunables! {
+    // Piece values in centipawns, a pawn is always 100.
+    knight_value "KnightValue" = 320, 200..=450;
+    bishop_value "BishopValue" = 330, 200..=450;
+    rook_value "RookValue" = 500, 350..=700;
+    queen_value "QueenValue" = 975, 700..=1300;
+
+    // The side to move gets an inherent advantage.
+    tempo "Tempo" = 10, 0..=50;
+
+    // Killer moves are tried after captures (which get a promise of at least a
+    // pawn, give or take) but before other quiet moves.
+    killer_promise "KillerPromise" = 50, 0..=100;
+
+    // Quiet moves that answered the previous move best get up to this much.
+    continuation_promise "ContinuationPromise" = 40, 0..=100;
+
+    // Recaptures are extended by a ply when there is this much depth left or
+    // less, deeper in the tree the full width search resolves them anyway.
+    recapture_extension_depth "RecaptureExtensionDepth" = 3, 0..=8;
+
+    // An iteration can run over the thinking time to this percent of it.
+    hard_time_percent "HardTimePercent" = 300, 100..=600;
+
+    // Captures in quiescence that can't get within this much of alpha, even
+    // winning the piece for nothing, aren't searched.
+    delta_margin "DeltaMargin" = 200, 0..=600;
+
+    // Razoring drops into quiescence at this depth or less, when the static
+    // eval is this much per ply of depth left below alpha.
+    razor_depth "RazorDepth" = 1, 0..=3;
+    razor_margin "RazorMargin" = 300, 100..=900;
+
+    // PV nodes this deep without a hash move get one from a search this much
+    // shallower.
+    iid_depth "IidDepth" = 4, 2..=10;
+    iid_reduction "IidReduction" = 2, 1..=4;
+}
+
+// The UCI options for the tunables, none unless built with the tune feature.
+#[cfg(feature = "std")]
+pub fn options() -> Vec<UciOption> {
+    if !cfg!(feature = "tune") {
+        return Vec::new();
+    }
+    TUNABLES
+        .iter()
+        .map(|t| UciOption::spin(t.name, t.default as i64, t.min as i64, t.max as i64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tunables() {
+        for t in TUNABLES {
+            assert!(t.min <= t.default && t.default <= t.max, "{}", t.name);
+        }
+        assert_eq!(options().is_empty(), !cfg!(feature = "tune"));
+    }
+
+    #[cfg(feature = "tune")]
+    #[test]
+    fn test_set() {
+        // Other tests search in parallel with us, so leave the values alone.
+        assert!(set("RecaptureExtensionDepth", 3));
+        assert_eq!(recapture_extension_depth(), 3);
+        assert!(!set("PawnValue", 100));
+    }
+}
@@ -1,7 +1,17 @@
-use chess::Board;
+use chess::{Board, PieceStyle, RenderOptions};
 
 use crate::chess;
-use std::str::{FromStr, Split};
+use crate::engine::{Engine, SearchEvent};
+use crate::movegen::{perft, MoveGen};
+use crate::search::{self, SearchLimits};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::{FromStr, SplitWhitespace};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum GoVariant {
@@ -13,6 +23,8 @@ pub enum GoVariant {
 #[derive(Debug, PartialEq, Eq)]
 pub struct Go {
     pub search_moves: Option<Vec<chess::Movement>>,
+    // Not part of UCI, the inverse of searchmoves.
+    pub exclude_moves: Option<Vec<chess::Movement>>,
 
     pub white_time: Option<u64>,
     pub black_time: Option<u64>,
@@ -34,6 +46,7 @@ impl Go {
     pub fn empty() -> Self {
         Go {
             search_moves: None,
+            exclude_moves: None,
 
             white_time: None,
             black_time: None,
@@ -56,6 +69,30 @@ impl Go {
         go.variant = v;
         go
     }
+
+    pub fn limits(&self) -> SearchLimits {
+        SearchLimits {
+            depth: self.depth,
+            nodes: self.nodes,
+            movetime: self.move_time.map(|ms| Duration::from_millis(ms as u64)),
+
+            wtime: self.white_time.map(Duration::from_millis),
+            btime: self.black_time.map(Duration::from_millis),
+            winc: self.white_increment.map(Duration::from_millis),
+            binc: self.black_increment.map(Duration::from_millis),
+            movestogo: self.moves_to_go,
+
+            mate: self.mate,
+            infinite: self.variant == GoVariant::Infinite,
+            ponder: self.variant == GoVariant::Ponder,
+
+            search_moves: self.search_moves.clone().unwrap_or_default(),
+            exclude_moves: self.exclude_moves.clone().unwrap_or_default(),
+
+            // An engine option, not part of go.
+            move_overhead: Duration::default(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -64,6 +101,7 @@ pub enum EngineMessage {
     Debug(bool),
     IsReady,
 
+    SetOption(String, Option<String>), // name, value
     UCINewGame,
     Position(Board, Vec<chess::Movement>),
     Go(Go),
@@ -72,10 +110,17 @@ pub enum EngineMessage {
     PonderHit,
     Quit,
 
+    // We're free and need no registration, but GUIs may still ask.
+    Register,
+    CopyProtection,
+
+    // Not UCI, prints the board for people typing at us (like Stockfish's "d").
+    Show(RenderOptions),
+
     DontMissTheShredderChessAnnualBarbeque, // Very important 10/10
 }
 
-fn get_moves(mut words: Split<char>) -> Option<Vec<chess::Movement>> {
+fn get_moves(mut words: SplitWhitespace) -> Option<Vec<chess::Movement>> {
     let mut moves = Vec::new();
 
     while let Some(word) = words.next() {
@@ -85,19 +130,62 @@ fn get_moves(mut words: Split<char>) -> Option<Vec<chess::Movement>> {
     Some(moves)
 }
 
+const COMMANDS: &[&str] = &[
+    "uci",
+    "debug",
+    "isready",
+    "setoption",
+    "ucinewgame",
+    "position",
+    "go",
+    "stop",
+    "ponderhit",
+    "quit",
+    "register",
+    "copyprotection",
+    "d",
+    "uwu",
+];
+
 pub fn parse(s: &str) -> Option<EngineMessage> {
-    let mut words = s.split(' ');
+    // Tokens are separated by any amount of whitespace, and unknown tokens before
+    // the command are skipped, so "joho debug on" is debug on. (UCI spec)
+    let mut words = s.split_whitespace();
+    let command = words.by_ref().find(|word| COMMANDS.contains(word))?;
 
-    Some(match words.next()? {
+    Some(match command {
         "uci" => EngineMessage::UCI,
         "debug" => match words.next()? {
-            "true" => EngineMessage::Debug(true),
-            "false" => EngineMessage::Debug(false),
+            "on" => EngineMessage::Debug(true),
+            "off" => EngineMessage::Debug(false),
             _ => return None,
         },
         "isready" => EngineMessage::IsReady,
 
+        "setoption" => {
+            // Both the name and value can contain spaces
+            if words.next()? != "name" {
+                return None;
+            }
+
+            let mut name = Vec::new();
+            let mut value = None;
+            while let Some(word) = words.next() {
+                if word == "value" {
+                    value = Some(words.collect::<Vec<_>>().join(" "));
+                    break;
+                }
+                name.push(word);
+            }
+
+            EngineMessage::SetOption(name.join(" "), value)
+        }
+
         "ucinewgame" => EngineMessage::UCINewGame,
+        // "register later" or "register name <name> code <code>", we take anything.
+        "register" => EngineMessage::Register,
+        // Engine to GUI only, but some GUIs send it back to ask.
+        "copyprotection" => EngineMessage::CopyProtection,
         "position" => {
             let board;
             let mut moves = Vec::new();
@@ -133,6 +221,7 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
             EngineMessage::Position(board, moves)
         }
         "go" => {
+            // Ponder/infinite and the limits are independent, and can come in any order.
             let mut go = Go::empty();
 
             while let Some(word) = words.next() {
@@ -150,6 +239,16 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
                         break;
                     }
 
+                    "excludemoves" => {
+                        let mut moves = Vec::new();
+                        for word in words.by_ref() {
+                            moves.push(chess::Movement::from_notation(word)?);
+                        }
+
+                        go.exclude_moves = Some(moves);
+                        break;
+                    }
+
                     "wtime" => go.white_time = u64::from_str(words.next()?).ok(),
                     "btime" => go.black_time = u64::from_str(words.next()?).ok(),
                     "winc" => go.white_increment = u64::from_str(words.next()?).ok(),
@@ -172,6 +271,20 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
             EngineMessage::Go(go)
         }
 
+        // "d [black] [unicode] [nocoords]"
+        "d" => {
+            let mut options = RenderOptions::default();
+            for word in words {
+                match word {
+                    "black" => options.orientation = chess::Color::Black,
+                    "unicode" => options.style = PieceStyle::Unicode,
+                    "nocoords" => options.coordinates = false,
+                    _ => return None,
+                }
+            }
+            EngineMessage::Show(options)
+        }
+
         "stop" => EngineMessage::Stop,
         "ponderhit" => EngineMessage::PonderHit,
         "quit" => EngineMessage::Quit,
@@ -182,11 +295,555 @@ pub fn parse(s: &str) -> Option<EngineMessage> {
     })
 }
 
+// What the UCI loop waits on. Searches run on their own thread, so their events
+// come through the same channel as the GUI's commands.
+enum Input {
+    Line(String),
+    Closed(io::Result<()>), // no more input
+    Search(SearchEvent),
+}
+
+// Every line to and from the GUI, with the time since we started. When we
+// misbehave inside a GUI this is the only way to see what happened.
+struct Transcript {
+    out: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl Transcript {
+    fn new(mut out: Box<dyn Write + Send>) -> Transcript {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "# yobmef {} started at unix time {}",
+            env!("CARGO_PKG_VERSION"),
+            now.as_secs()
+        );
+        Transcript {
+            out,
+            start: Instant::now(),
+        }
+    }
+
+    // YOBMEF_LOG=<file>, appended to so several runs can share one.
+    fn from_env() -> Option<Transcript> {
+        let path = std::env::var("YOBMEF_LOG").ok()?;
+        match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => Some(Transcript::new(Box::new(file))),
+            Err(e) => {
+                log::warn!("could not open log file {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    // Best effort, like tracing. `dir` is < for lines in and > for lines out.
+    fn log(&mut self, dir: &str, line: &dyn fmt::Display) {
+        let t = self.start.elapsed();
+        let _ = writeln!(
+            self.out,
+            "[{:5}.{:03}] {} {}",
+            t.as_secs(),
+            t.subsec_millis(),
+            dir,
+            line
+        );
+    }
+}
+
+// Write a line to the GUI, see Uci::send.
+macro_rules! send {
+    ($self:ident, $($arg:tt)*) => {
+        $self.send(format_args!($($arg)*))
+    };
+}
+
+// Speaks UCI for an Engine, over stdin/stdout unless told otherwise.
+pub struct Uci {
+    engine: Engine,
+    out: Box<dyn Write + Send>,
+    transcript: Option<Transcript>,
+    // With debug on we explain ourselves in info strings.
+    debug: bool,
+
+    sender: Sender<Input>,
+    inputs: Receiver<Input>,
+    // Searches we haven't sent the bestmove for yet.
+    searches: usize,
+    // For go mate, the N and whether the search proved a mate that short.
+    mate_search: Option<(u8, bool)>,
+}
+
+impl Uci {
+    pub fn new(engine: Engine) -> Uci {
+        Uci::with_output(engine, Box::new(io::stdout()))
+    }
+
+    pub fn with_output(engine: Engine, out: Box<dyn Write + Send>) -> Uci {
+        let (sender, inputs) = mpsc::channel();
+        Uci {
+            engine,
+            out,
+            transcript: Transcript::from_env(),
+            debug: false,
+            sender,
+            inputs,
+            searches: 0,
+            mate_search: None,
+        }
+    }
+
+    // If the GUI is gone there's nobody to complain to, so errors are ignored.
+    fn send(&mut self, args: fmt::Arguments) {
+        if let Some(transcript) = &mut self.transcript {
+            transcript.log(">", &args);
+        }
+        let _ = writeln!(self.out, "{}", args);
+        let _ = self.out.flush();
+    }
+
+    fn debug(&mut self, msg: &str) {
+        if self.debug {
+            send!(self, "info string {}", msg);
+        }
+    }
+
+    // Runs until quit or the GUI closes stdin (and the search, if any, finishes).
+    pub fn run(&mut self) -> io::Result<()> {
+        self.run_with_input(BufReader::new(io::stdin()))
+    }
+
+    pub fn run_with_input<R: BufRead + Send + 'static>(&mut self, input: R) -> io::Result<()> {
+        // Reading input on its own thread lets us take stop and quit mid search.
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            for line in input.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => return sender.send(Input::Closed(Err(e))),
+                };
+                sender.send(Input::Line(line))?;
+            }
+            sender.send(Input::Closed(Ok(())))
+        });
+
+        let mut closed = false;
+        while !closed || self.searches > 0 {
+            // We hold a sender, so this can't fail.
+            match self.inputs.recv().unwrap() {
+                Input::Line(line) => {
+                    if let Some(transcript) = &mut self.transcript {
+                        transcript.log("<", &line);
+                    }
+                    let msg = parse(&line);
+
+                    log::debug!("got {:?}, parsed as {:?}", line, msg);
+
+                    match msg {
+                        Some(EngineMessage::Quit) => {
+                            self.engine.stop();
+                            break;
+                        }
+                        Some(msg) => self.handle(msg),
+                        None => {}
+                    }
+                }
+                Input::Closed(result) => {
+                    result?;
+                    closed = true;
+                }
+                Input::Search(event) => self.search_event(event),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Divide, nodes under each root move then the total. Same format as Stockfish
+    // so the output can be diffed against it.
+    fn perft(&mut self, depth: u16) {
+        let board = self.engine.position().clone();
+        if depth == 0 {
+            send!(self, "\nNodes searched: 1");
+            return;
+        }
+
+        let mut nodes = 0;
+        for mv in MoveGen::new_legal(&board) {
+            let n = perft(&board.make_move(&mv), depth - 1);
+            send!(self, "{}: {}", mv, n);
+            nodes += n;
+        }
+
+        send!(self, "\nNodes searched: {}", nodes);
+    }
+
+    fn go(&mut self, opts: Go) {
+        // For debugging
+        if let Some(depth) = opts.perft {
+            self.perft(depth);
+            return;
+        }
+
+        let limits = opts.limits();
+        match self.engine.thinking_time(&limits) {
+            Some(time) => self.debug(&format!("thinking for {}ms", time.as_millis())),
+            None => self.debug("thinking until a limit or stop"),
+        }
+
+        self.mate_search = limits.mate.map(|n| (n, false));
+        let search = self.engine.go(limits);
+        self.searches += 1;
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            for event in search {
+                if sender.send(Input::Search(event)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn search_event(&mut self, event: SearchEvent) {
+        match event {
+            SearchEvent::Info(info) => {
+                if let (Some((n, found)), Some(score)) = (&mut self.mate_search, info.score) {
+                    *found |= search::mate_in(score).is_some_and(|m| m > 0 && m <= *n as i16);
+                }
+                send!(self, "{}", info)
+            }
+            SearchEvent::BestMove(mv, ponder) => {
+                // Before bestmove, the GUI might not listen after it.
+                self.debug_search_stats();
+                if let Some((n, false)) = self.mate_search.take() {
+                    send!(self, "info string no mate in {} found", n);
+                }
+                match (mv, ponder) {
+                    (Some(mv), Some(ponder)) => send!(self, "bestmove {} ponder {}", mv, ponder),
+                    (Some(mv), None) => send!(self, "bestmove {}", mv),
+                    // The null move, what UCI wants when we have no moves.
+                    (None, _) => send!(self, "bestmove 0000"),
+                }
+                self.searches -= 1;
+            }
+        }
+    }
+
+    fn debug_search_stats(&mut self) {
+        if !self.debug {
+            return;
+        }
+        let searcher = self.engine.searcher();
+        let msg = format!(
+            "tt {}/{} entries, move ordering {}/{} first move cutoffs",
+            searcher.tp.len(),
+            searcher.tp.capacity(),
+            searcher.fail_high_first,
+            searcher.fail_high,
+        );
+        self.debug(&msg);
+    }
+
+    fn handle(&mut self, msg: EngineMessage) {
+        match msg {
+            EngineMessage::UCI => {
+                send!(self, "id name Yobmef {}", env!("CARGO_PKG_VERSION"));
+                send!(self, "id author PwnSquad");
+                for option in Engine::options() {
+                    send!(self, "{}", option);
+                }
+                send!(self, "uciok");
+            }
+            EngineMessage::Debug(debug) => self.debug = debug,
+            EngineMessage::IsReady => send!(self, "readyok"),
+            EngineMessage::SetOption(name, value) => {
+                if let Err(e) = self.engine.set_option(&name, value.as_deref()) {
+                    log::warn!("{}", e);
+                    self.debug(&e);
+                }
+            }
+
+            EngineMessage::Position(board, moves) => {
+                // Tell the GUI, it's probably out of sync with us.
+                if let Err(e) = self.engine.set_position(board, &moves) {
+                    send!(self, "info string {}", e);
+                }
+                log::debug!("position {}", self.engine.position().to_fen());
+            }
+
+            EngineMessage::Show(options) => {
+                let board = self.engine.position();
+                let lines = board.render(&options) + &format!("\nFen: {}", board.to_fen());
+                for line in lines.lines() {
+                    send!(self, "{}", line);
+                }
+            }
+
+            EngineMessage::Go(opts) => self.go(opts),
+            EngineMessage::Stop => self.engine.stop(),
+            EngineMessage::PonderHit => self.engine.ponder_hit(),
+            EngineMessage::UCINewGame => self.engine.new_game(),
+            EngineMessage::Register => {
+                send!(self, "registration checking");
+                send!(self, "registration ok");
+            }
+            EngineMessage::CopyProtection => {
+                send!(self, "copyprotection checking");
+                send!(self, "copyprotection ok");
+            }
+
+            _ => {}
+        }
+    }
+}
+
+// UCI over TCP instead of stdio, so GUIs can use an engine running on another
+// machine. One client at a time, each gets a fresh engine.
+pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    log::info!("listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        // A client going wrong shouldn't take the server down with it.
+        if let Err(e) = stream.and_then(serve) {
+            log::warn!("connection: {}", e);
+        }
+    }
+    Ok(())
+}
+
+pub fn serve(stream: TcpStream) -> io::Result<()> {
+    log::info!("connection from {}", stream.peer_addr()?);
+    let mut uci = Uci::with_output(Engine::new(), Box::new(stream.try_clone()?));
+    let result = uci.run_with_input(BufReader::new(stream.try_clone()?));
+
+    // After quit the input thread is still reading, hang up so it sees EOF.
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use chess::Movement;
 
     use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Output we can look at after the loop is done.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_uci(input: &str) -> Vec<String> {
+        let out = SharedOutput::default();
+        let mut uci = Uci::with_output(Engine::new(), Box::new(out.clone()));
+        uci.run_with_input(io::Cursor::new(input.to_string()))
+            .unwrap();
+
+        let out = out.0.lock().unwrap();
+        String::from_utf8_lossy(&out)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_serve_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || serve(listener.accept().unwrap().0));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        writeln!(client, "uci\nisready\nquit").unwrap();
+        let lines: Vec<String> = BufReader::new(client).lines().map(Result::unwrap).collect();
+        assert!(lines[0].starts_with("id name Yobmef"));
+        assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_uci_handshake() {
+        let lines = run_uci("uci\nisready\n");
+        assert!(lines[0].starts_with("id name Yobmef"));
+        assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+    }
+
+    #[test]
+    fn test_uci_search() {
+        let lines = run_uci("position startpos moves e2e4\ngo depth 3\n");
+        assert!(lines[0].starts_with("info depth 1 "), "{:?}", lines);
+        assert!(
+            lines.last().unwrap().starts_with("bestmove "),
+            "{:?}",
+            lines
+        );
+
+        // A bare go thinks like it's a 10 minute game, but stops when told to.
+        let lines = run_uci("go\nstop\n");
+        assert!(
+            lines.last().unwrap().starts_with("bestmove "),
+            "{:?}",
+            lines
+        );
+
+        let lines = run_uci("position fen 6Rk/5Kpp/8/8/8/8/8/8 b - - 0 1\ngo\n");
+        assert_eq!(lines, vec!["bestmove 0000"]);
+
+        // Stop comes after isready, so readyok has to be before bestmove.
+        let lines = run_uci("go infinite\nisready\nstop\n");
+        let bestmove = lines
+            .iter()
+            .position(|line| line.starts_with("bestmove "))
+            .unwrap();
+        assert!(lines[..bestmove].contains(&"readyok".to_string()));
+        assert_eq!(bestmove, lines.len() - 1);
+
+        // Even when the search itself is over long before stop.
+        for go in &["go infinite depth 2", "go depth 2 infinite"] {
+            let lines = run_uci(&format!("{}\nisready\nstop\n", go));
+            let readyok = lines.iter().position(|line| line == "readyok").unwrap();
+            let bestmove = lines
+                .iter()
+                .position(|line| line.starts_with("bestmove "))
+                .unwrap();
+            assert!(readyok < bestmove, "{:?}", lines);
+            assert_eq!(bestmove, lines.len() - 1);
+        }
+        let lines =
+            run_uci("position fen 6Rk/5Kpp/8/8/8/8/8/8 b - - 0 1\ngo infinite\nisready\nstop\n");
+        assert_eq!(lines, ["readyok", "bestmove 0000"]);
+
+        // And pondering, until ponderhit.
+        let lines = run_uci("go ponder depth 2\nisready\nponderhit\n");
+        let readyok = lines.iter().position(|line| line == "readyok").unwrap();
+        assert!(lines[readyok + 1..]
+            .iter()
+            .any(|line| line.starts_with("bestmove ")));
+
+        // Same with options that change the searcher.
+        let lines =
+            run_uci("go infinite\nsetoption name Hash value 2\nucinewgame\nisready\nstop\n");
+        let readyok = lines.iter().position(|line| line == "readyok").unwrap();
+        assert!(lines[readyok + 1..]
+            .iter()
+            .any(|line| line.starts_with("bestmove ")));
+    }
+
+    #[test]
+    fn test_transcript() {
+        let transcript = SharedOutput::default();
+        let mut uci = Uci::with_output(Engine::new(), Box::new(SharedOutput::default()));
+        uci.transcript = Some(Transcript::new(Box::new(transcript.clone())));
+        uci.run_with_input(io::Cursor::new("isready\nquit\n"))
+            .unwrap();
+
+        let transcript = transcript.0.lock().unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&transcript).unwrap().lines().collect();
+        assert!(lines[0].starts_with("# yobmef "), "{:?}", lines);
+        assert!(lines[1].ends_with("] < isready"), "{:?}", lines);
+        assert!(lines[2].ends_with("] > readyok"), "{:?}", lines);
+        assert!(lines[3].ends_with("] < quit"), "{:?}", lines);
+    }
+
+    #[test]
+    fn test_parse_debug() {
+        assert_eq!(parse("debug on"), Some(EngineMessage::Debug(true)));
+        assert_eq!(parse("debug off"), Some(EngineMessage::Debug(false)));
+        assert_eq!(parse("debug maybe"), None);
+    }
+
+    #[test]
+    fn test_register() {
+        assert_eq!(parse("register later"), Some(EngineMessage::Register));
+        assert_eq!(
+            parse("register name Stefan MK code 4359874324"),
+            Some(EngineMessage::Register)
+        );
+
+        let lines = run_uci("register later\ncopyprotection\nisready\n");
+        assert_eq!(
+            lines,
+            [
+                "registration checking",
+                "registration ok",
+                "copyprotection checking",
+                "copyprotection ok",
+                "readyok"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_show() {
+        assert_eq!(
+            parse("d black unicode"),
+            Some(EngineMessage::Show(RenderOptions {
+                orientation: chess::Color::Black,
+                coordinates: true,
+                style: PieceStyle::Unicode,
+            }))
+        );
+        assert_eq!(parse("d sideways"), None);
+
+        let lines = run_uci(
+            "position startpos moves e2e4
+d nocoords
+",
+        );
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "r n b q k b n r");
+        assert_eq!(lines[4], ". . . . P . . .");
+        assert_eq!(lines[8], "");
+        assert_eq!(
+            lines[9],
+            "Fen: rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        let lines = run_uci("hello\n\nposition nonsense\nsetoption\n\x01\u{e9}\nisready\n");
+        assert_eq!(lines, ["readyok"]);
+    }
+
+    #[test]
+    fn test_parse_tolerant() {
+        // Examples from the UCI spec.
+        assert_eq!(parse("joho debug on"), Some(EngineMessage::Debug(true)));
+        assert_eq!(parse("   debug     on  "), Some(EngineMessage::Debug(true)));
+        assert_eq!(
+            parse("\t  debug \t  \t\ton\t  "),
+            Some(EngineMessage::Debug(true))
+        );
+        assert_eq!(parse("debug joho on"), None);
+
+        assert_eq!(parse("hello there"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("xyzzy isready"), Some(EngineMessage::IsReady));
+
+        let mut want = Go::empty();
+        want.depth = Some(4);
+        assert_eq!(parse("go  depth\t4 "), Some(EngineMessage::Go(want)));
+        assert_eq!(
+            parse("position  startpos  moves e2e4 "),
+            Some(EngineMessage::Position(
+                Board::from_start_pos(),
+                vec![Movement::from_notation("e2e4").unwrap()]
+            ))
+        );
+    }
 
     #[test]
     fn test_parse_go() {
@@ -209,6 +866,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_go_clock() {
+        let mut want = Go::empty();
+        want.white_time = Some(30000);
+        want.black_time = Some(30000);
+        assert_eq!(
+            parse("go wtime 30000 btime 30000"),
+            Some(EngineMessage::Go(want))
+        );
+    }
+
+    #[test]
+    fn test_parse_go_combinations() {
+        let mut want = Go::variant(GoVariant::Ponder);
+        want.white_time = Some(1000);
+        want.black_time = Some(2000);
+        want.white_increment = Some(10);
+        want.black_increment = Some(20);
+        want.moves_to_go = Some(5);
+        let want = Some(EngineMessage::Go(want));
+
+        assert_eq!(
+            parse("go ponder wtime 1000 btime 2000 winc 10 binc 20 movestogo 5"),
+            want
+        );
+        assert_eq!(
+            parse("go wtime 1000 btime 2000 winc 10 binc 20 movestogo 5 ponder"),
+            want
+        );
+        assert_eq!(
+            parse("go movestogo 5 binc 20 ponder winc 10 btime 2000 wtime 1000"),
+            want
+        );
+
+        let mut want = Go::variant(GoVariant::Infinite);
+        want.search_moves = Some(vec![Movement::from_notation("e2e4").unwrap()]);
+        assert_eq!(
+            parse("go infinite searchmoves e2e4"),
+            Some(EngineMessage::Go(want))
+        );
+
+        let mut want = Go::empty();
+        want.depth = Some(6);
+        want.nodes = Some(100000);
+        want.move_time = Some(500);
+        assert_eq!(
+            parse("go nodes 100000 movetime 500 depth 6"),
+            Some(EngineMessage::Go(want))
+        );
+    }
+
+    #[test]
+    fn test_go_mate() {
+        let position = "position fen 8/p4p1k/3p1P2/1p1br3/3p4/1Pr5/P6K/8 b - - 0 1";
+        let lines = run_uci(&format!("{}\ngo mate 2\n", position));
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        assert!(lines.iter().any(|line| line.contains(" score mate 2 ")));
+        assert!(!lines.iter().any(|line| line.starts_with("info string")));
+
+        // Nothing that short, and we say so.
+        let lines = run_uci(&format!("{}\ngo mate 1\n", position));
+        assert_eq!(
+            lines[lines.len() - 2],
+            "info string no mate in 1 found",
+            "{:?}",
+            lines
+        );
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_go_limits() {
+        let go = match parse("go wtime 60000 btime 30000 winc 1000 movestogo 20 depth 7") {
+            Some(EngineMessage::Go(go)) => go,
+            msg => panic!("not a go: {:?}", msg),
+        };
+        assert_eq!(
+            go.limits(),
+            SearchLimits {
+                depth: Some(7),
+                wtime: Some(Duration::from_secs(60)),
+                btime: Some(Duration::from_secs(30)),
+                winc: Some(Duration::from_secs(1)),
+                movestogo: Some(20),
+                ..SearchLimits::default()
+            }
+        );
+
+        assert!(Go::variant(GoVariant::Infinite).limits().infinite);
+
+        let go = match parse("go depth 5 searchmoves e2e4 d2d4") {
+            Some(EngineMessage::Go(go)) => go,
+            msg => panic!("not a go: {:?}", msg),
+        };
+        assert_eq!(
+            go.limits().search_moves,
+            vec![
+                chess::Movement::from_notation("e2e4").unwrap(),
+                chess::Movement::from_notation("d2d4").unwrap(),
+            ]
+        );
+
+        let go = match parse("go depth 5 excludemoves e2e4 d2d4") {
+            Some(EngineMessage::Go(go)) => go,
+            msg => panic!("not a go: {:?}", msg),
+        };
+        assert_eq!(go.depth, Some(5));
+        assert_eq!(
+            go.limits().exclude_moves,
+            vec![
+                chess::Movement::from_notation("e2e4").unwrap(),
+                chess::Movement::from_notation("d2d4").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_setoption() {
+        assert_eq!(
+            parse("setoption name Hash value 128"),
+            Some(EngineMessage::SetOption("Hash".into(), Some("128".into())))
+        );
+        assert_eq!(
+            parse("setoption name Clear Hash"),
+            Some(EngineMessage::SetOption("Clear Hash".into(), None))
+        );
+        assert_eq!(parse("setoption Hash value 128"), None);
+    }
+
     #[test]
     fn test_uci() {
         assert_eq!(parse("uci"), Some(EngineMessage::UCI))
@@ -242,4 +1028,41 @@ mod tests {
             )),
         );
     }
+
+    #[test]
+    fn test_position_without_moves() {
+        let fen = "2k5/2r5/8/3K4/8/8/8/8 b - - 0 1";
+        let want = Some(EngineMessage::Position(
+            Board::from_fen(fen).unwrap(),
+            Vec::new(),
+        ));
+        assert_eq!(parse(&format!("position fen {}", fen)), want);
+        assert_eq!(parse(&format!("position fen {} moves", fen)), want);
+
+        assert_eq!(
+            parse("position startpos moves"),
+            Some(EngineMessage::Position(Board::from_start_pos(), Vec::new()))
+        );
+        assert_eq!(
+            parse("position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Some(EngineMessage::Position(Board::from_start_pos(), Vec::new()))
+        );
+    }
+
+    #[test]
+    fn test_think_time() {
+        // Reasonable bounds on thinking time
+
+        let mut opts = Go::empty();
+        opts.white_time = Some(300_000);
+        // black_time: 300_000,
+        let t = opts
+            .limits()
+            .thinking_time(chess::Color::White)
+            .unwrap()
+            .as_millis();
+
+        // Assume you will think between 1s and 20s per move in a 5 minute game
+        assert!(1_000 < t && t < 20_000, "1s < t({}s) < 10s", t / 1000);
+    }
 }
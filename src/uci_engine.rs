@@ -0,0 +1,211 @@
+use crate::chess::{Board, Movement};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A UCI engine running as another process, seen from the GUI's side.
+//
+// Its output is read on another thread, so we can give up on an engine that
+// hangs instead of hanging with it.
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct UciEngine {
+    pub name: String,
+    pub options: Vec<String>, // names, as it told us after uci
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+// What the engine said about the move it played, from its own point of view.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Thinking {
+    pub score: Option<i16>, // centipawns
+    pub mate: Option<i16>,  // moves
+    pub depth: Option<i16>,
+}
+
+impl Thinking {
+    // Fold in an info line.
+    fn update(&mut self, line: &str) {
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "depth" => self.depth = words.next().and_then(|d| d.parse().ok()),
+                "score" => match (words.next(), words.next().and_then(|n| n.parse().ok())) {
+                    (Some("cp"), Some(cp)) => {
+                        self.score = Some(cp);
+                        self.mate = None;
+                    }
+                    (Some("mate"), Some(moves)) => {
+                        self.score = None;
+                        self.mate = Some(moves);
+                    }
+                    _ => {}
+                },
+                // Anything after is the move list, or free text.
+                "pv" | "string" => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl UciEngine {
+    // `command` is the binary followed by its arguments, split on whitespace.
+    pub fn start(command: &str) -> io::Result<UciEngine> {
+        let mut args = command.split_whitespace();
+        let program = args
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty engine command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            name: program.to_string(),
+            options: Vec::new(),
+            child,
+            stdin,
+            lines,
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine.read_line(deadline)?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            } else if let Some(option) = line.strip_prefix("option name ") {
+                // Names can have spaces, the type comes after.
+                let name = option.split(" type ").next().unwrap_or(option);
+                engine.options.push(name.trim().to_string());
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()
+    }
+
+    fn read_line(&mut self, deadline: Instant) -> io::Result<String> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => {
+                io::Error::new(io::ErrorKind::TimedOut, "engine timed out")
+            }
+            RecvTimeoutError::Disconnected => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited")
+            }
+        })
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.read_line(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    // Think about the position after `moves` with the given go arguments, and
+    // return the move it picked. Errors if it takes longer than `timeout`.
+    pub fn go(
+        &mut self,
+        start: &Board,
+        moves: &[Movement],
+        go: &str,
+        timeout: Duration,
+    ) -> io::Result<(Option<Movement>, Thinking)> {
+        let mut position = if *start == Board::from_start_pos() {
+            "position startpos".to_string()
+        } else {
+            format!("position fen {}", start.to_fen())
+        };
+        if !moves.is_empty() {
+            position.push_str(" moves");
+            for mv in moves {
+                position.push(' ');
+                position.push_str(&mv.to_notation());
+            }
+        }
+        self.send(&position)?;
+        self.send(&format!("go {}", go))?;
+
+        let deadline = Instant::now() + timeout;
+        let mut thinking = Thinking::default();
+        loop {
+            let line = self.read_line(deadline)?;
+            if line.starts_with("info ") {
+                thinking.update(&line);
+            } else if let Some(rest) = line.strip_prefix("bestmove") {
+                let mv = rest.split_whitespace().next().unwrap_or("0000");
+                return Ok((Movement::from_notation(mv), thinking));
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give it a moment to quit on its own.
+        for _ in 0..50 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thinking() {
+        let mut thinking = Thinking::default();
+        thinking.update("info depth 5 seldepth 9 score cp -35 nodes 1000 pv e2e4 e7e5");
+        assert_eq!(thinking.score, Some(-35));
+        assert_eq!(thinking.depth, Some(5));
+
+        thinking.update("info depth 6 score mate -3 pv e2e4 depth 9");
+        assert_eq!(thinking.score, None);
+        assert_eq!(thinking.mate, Some(-3));
+        assert_eq!(thinking.depth, Some(6));
+
+        thinking.update("info string depth 10 score cp 5");
+        assert_eq!(thinking.depth, Some(6));
+    }
+}
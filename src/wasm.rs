@@ -0,0 +1,257 @@
+use crate::chess::{Board, Movement};
+use crate::movegen::{self, MoveGen};
+use crate::search::{self, SearchInfo, SearchLimits, Searcher};
+use crate::zobrist;
+use js_sys::{Array, Function, Object, Reflect};
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+// JavaScript bindings, built with `wasm-pack build -- --features wasm`.
+//
+//     const pos = new Position();
+//     pos.play("e2e4");
+//     const engine = new Engine();
+//     const result = engine.search(pos, { movetime: 1000 }, info => console.log(info));
+//     console.log(result.bestmove, result.score);
+//
+// Searching blocks until it's done (there are no threads in wasm), so run the
+// engine in a web worker or the page freezes while it thinks.
+
+fn error(msg: String) -> JsValue {
+    js_sys::Error::new(&msg).into()
+}
+
+fn set(object: &Object, key: &str, value: impl Into<JsValue>) {
+    // Can only fail on frozen objects or throwing setters, not a fresh Object.
+    Reflect::set(object, &key.into(), &value.into()).unwrap();
+}
+
+fn moves_to_array(moves: &[Movement]) -> Array {
+    moves
+        .iter()
+        .map(|mv| JsValue::from(mv.to_notation()))
+        .collect()
+}
+
+// {cp: 35} or {mate: -3}, like UCI's score.
+fn score_to_object(score: i16) -> Object {
+    let object = Object::new();
+    match search::mate_in(score) {
+        Some(moves) => set(&object, "mate", moves),
+        None => set(&object, "cp", score),
+    }
+    object
+}
+
+fn info_to_object(info: &SearchInfo) -> Object {
+    let object = Object::new();
+    if let Some(depth) = info.depth {
+        set(&object, "depth", depth);
+    }
+    if let Some(seldepth) = info.seldepth {
+        set(&object, "seldepth", seldepth);
+    }
+    if let Some(score) = info.score {
+        set(&object, "score", score_to_object(score));
+    }
+    // Node counts as numbers, not BigInts. Doubles are exact up to 2^53 nodes.
+    if let Some(nodes) = info.nodes {
+        set(&object, "nodes", nodes as f64);
+    }
+    if let Some(nps) = info.nps {
+        set(&object, "nps", nps as f64);
+    }
+    if let Some(hashfull) = info.hashfull {
+        set(&object, "hashfull", hashfull);
+    }
+    if let Some(time) = info.time {
+        set(&object, "time", time.as_millis() as f64);
+    }
+    if let Some(currmove) = &info.currmove {
+        set(&object, "currmove", currmove.to_notation());
+    }
+    if !info.pv.is_empty() {
+        set(&object, "pv", moves_to_array(&info.pv));
+    }
+    object
+}
+
+// Limits from a JS object, same names as the UCI go command. Times are in ms.
+fn limits_from_object(object: &JsValue) -> Result<SearchLimits, JsValue> {
+    let get = |key: &str| -> Result<Option<f64>, JsValue> {
+        let value = Reflect::get(object, &key.into())?;
+        if value.is_undefined() || value.is_null() {
+            return Ok(None);
+        }
+        match value.as_f64() {
+            Some(n) if n >= 0.0 => Ok(Some(n)),
+            _ => Err(error(format!("{} must be a non-negative number", key))),
+        }
+    };
+    let ms = |key: &str| -> Result<Option<Duration>, JsValue> {
+        Ok(get(key)?.map(|ms| Duration::from_millis(ms as u64)))
+    };
+
+    if object.is_undefined() || object.is_null() {
+        return Ok(SearchLimits::default());
+    }
+    Ok(SearchLimits {
+        depth: get("depth")?.map(|d| d.min(search::MAX_PLY as f64 / 2.0) as i16),
+        nodes: get("nodes")?.map(|n| n as u64),
+        movetime: ms("movetime")?,
+        wtime: ms("wtime")?,
+        btime: ms("btime")?,
+        winc: ms("winc")?,
+        binc: ms("binc")?,
+        movestogo: get("movestogo")?.map(|n| n.min(u8::MAX as f64) as u8),
+        mate: get("mate")?.map(|n| n.min(u8::MAX as f64) as u8),
+        ..SearchLimits::default()
+    })
+}
+
+// A game in progress: the current board and the keys of the positions before
+// it, so the search knows about repetitions.
+#[wasm_bindgen]
+pub struct Position {
+    board: Board,
+    history: Vec<u64>,
+}
+
+#[wasm_bindgen]
+impl Position {
+    // The starting position.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Position {
+        movegen::gen_moves_once();
+        Position {
+            board: Board::from_start_pos(),
+            history: Vec::new(),
+        }
+    }
+
+    #[wasm_bindgen(js_name = fromFen)]
+    pub fn from_fen(fen: &str) -> Result<Position, JsValue> {
+        movegen::gen_moves_once();
+        let board = Board::from_fen(fen).ok_or_else(|| error(format!("invalid fen {}", fen)))?;
+        Ok(Position {
+            board,
+            history: Vec::new(),
+        })
+    }
+
+    pub fn fen(&self) -> String {
+        self.board.to_fen()
+    }
+
+    // Every legal move, in UCI notation.
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self) -> Array {
+        let moves: Vec<Movement> = MoveGen::new_legal(&self.board).collect();
+        moves_to_array(&moves)
+    }
+
+    // Play a move given in UCI notation, throws if it isn't legal.
+    pub fn play(&mut self, uci: &str) -> Result<(), JsValue> {
+        let mv =
+            Movement::from_notation(uci).ok_or_else(|| error(format!("invalid move {}", uci)))?;
+        let key = zobrist::hash(&self.board);
+        self.board.try_make_move_mut(&mv).map_err(|e| {
+            error(format!(
+                "illegal move {} in {}: {}",
+                uci,
+                self.board.to_fen(),
+                e
+            ))
+        })?;
+        self.history.push(key);
+        Ok(())
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new()
+    }
+}
+
+// The JS function given to search, called with every info.
+struct InfoFunction(Function);
+
+// Searcher wants a Send callback since the UCI engine searches on its own thread.
+// A JS function can't be sent anywhere, but wasm has only the one thread.
+unsafe impl Send for InfoFunction {}
+
+#[wasm_bindgen]
+pub struct Engine {
+    searcher: Searcher,
+}
+
+#[wasm_bindgen]
+impl Engine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Engine {
+        movegen::gen_moves_once();
+        Engine {
+            searcher: Searcher::new(),
+        }
+    }
+
+    // Throws away the hash, so only call this between searches.
+    #[wasm_bindgen(js_name = setHashSize)]
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.searcher.set_hash_size(mb);
+    }
+
+    // Forget everything from the last game, before analysing an unrelated one.
+    #[wasm_bindgen(js_name = newGame)]
+    pub fn new_game(&mut self) {
+        self.searcher.new_game();
+    }
+
+    // Search `position` with limits like {depth: 10} or {movetime: 1000}, nothing
+    // set thinks as if playing a 10 minute game. `on_info` gets progress
+    // reports as they come in. Returns {bestmove, score, depth, pv}, bestmove
+    // is null when there are no legal moves.
+    pub fn search(
+        &mut self,
+        position: &Position,
+        limits: JsValue,
+        on_info: Option<Function>,
+    ) -> Result<Object, JsValue> {
+        let limits = limits_from_object(&limits)?;
+        let result = Object::new();
+        if !movegen::has_legal_move(&position.board) {
+            set(&result, "bestmove", JsValue::NULL);
+            return Ok(result);
+        }
+
+        match on_info {
+            Some(f) => {
+                let f = InfoFunction(f);
+                self.searcher.set_info_callback(move |info| {
+                    // An exception in the callback shouldn't stop the search.
+                    let _ = f.0.call1(&JsValue::NULL, &info_to_object(&info));
+                });
+            }
+            None => self.searcher.set_info_callback(|_| {}),
+        }
+        self.searcher.set_history(position.history.clone());
+
+        let sr = self.searcher.go(&position.board, limits);
+        let pv = match self.searcher.root_moves().iter().find(|rm| rm.mv == sr.mv) {
+            Some(rm) => rm.pv.clone(),
+            None => vec![sr.mv.clone()],
+        };
+        set(&result, "bestmove", sr.mv.to_notation());
+        set(&result, "score", score_to_object(sr.eval));
+        set(&result, "depth", sr.depth);
+        set(&result, "pv", moves_to_array(&pv));
+        Ok(result)
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
@@ -1,61 +1,337 @@
-use std::sync::Once;
+use crate::sync::OnceLock;
 
 use rand::rngs::StdRng;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 
 use crate::bitboard::BitBoard;
-use crate::chess::{Board, Square, NUM_COLORS, NUM_PIECES};
+use crate::chess::{Board, Color, Piece, Square, CHECKS_TO_WIN, NUM_COLORS, NUM_PIECES};
 
 const NUM_RANDOMS: usize = NUM_PIECES * NUM_COLORS;
-static mut RANDOMS: [[BitBoard; 64]; NUM_RANDOMS] = [[BitBoard::empty(); 64]; NUM_RANDOMS];
-// static mut EMPTY_RANDOM: BitBoard = BitBoard::empty();
 
-static START: Once = Once::new();
+struct Keys {
+    pieces: [[BitBoard; 64]; NUM_RANDOMS],
+    black_to_move: BitBoard,
+    // Indexed by the board's castling bits, so all rights are hashed in one lookup.
+    castling: [BitBoard; 16],
+    en_passant: [BitBoard; 8],
+    // Checks given by each color, only hashed in three-check. Zero checks has a key
+    // too, so three-check positions never share keys with normal ones.
+    checks: [[BitBoard; CHECKS_TO_WIN as usize + 1]; NUM_COLORS],
+}
+
+// Cuckoo hash tables of every reversible move, keyed by how the move changes the
+// zobrist key of a position. Used to spot moves that repeat an earlier position
+// without generating moves, see cuckoo_move. (idea from Stockfish)
+const CUCKOO_SIZE: usize = 8192;
+
+struct Cuckoo {
+    keys: [u64; CUCKOO_SIZE],
+    moves: [(Square, Square); CUCKOO_SIZE],
+}
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+static CUCKOO: OnceLock<Cuckoo> = OnceLock::new();
+
+fn keys() -> &'static Keys {
+    KEYS.get_or_init(gen_keys)
+}
+
+fn cuckoo() -> &'static Cuckoo {
+    CUCKOO.get_or_init(gen_cuckoo)
+}
+
+// Keys are the same every run, so hashes are reproducible between runs. With
+// unlucky keys the cuckoo tables can't be built (init_cuckoo goes around in a
+// cycle forever), and this seed is known to be fine.
+const SEED: u64 = 31415;
+
+// BitBoard::random is sparse, which is what magics want. Keys want every bit random,
+// or they collide (and the cuckoo tables can't be built).
+fn random(rng: &mut StdRng) -> BitBoard {
+    BitBoard(rng.gen())
+}
 
 pub fn init_once() {
-    START.call_once(|| {
-        let mut rng = StdRng::from_entropy();
+    keys();
+    cuckoo();
+}
 
-        for i in 0..NUM_RANDOMS {
-            for j in 0..64 {
-                unsafe {
-                    RANDOMS[i][j] = BitBoard::random(&mut rng);
-                }
+fn gen_keys() -> Keys {
+    let mut rng = StdRng::seed_from_u64(SEED);
+
+    let mut pieces = [[BitBoard::empty(); 64]; NUM_RANDOMS];
+    for randoms in pieces.iter_mut() {
+        randoms.iter_mut().for_each(|r| *r = random(&mut rng));
+    }
+
+    let mut sides = [BitBoard::empty(); 4];
+    sides.iter_mut().for_each(|r| *r = random(&mut rng));
+
+    let mut castling = [BitBoard::empty(); 16];
+    for (rights, random) in castling.iter_mut().enumerate() {
+        for (side, side_random) in sides.iter().enumerate() {
+            if (rights >> side) & 1 == 1 {
+                *random ^= side_random;
             }
         }
+    }
+
+    let mut en_passant = [BitBoard::empty(); 8];
+    en_passant.iter_mut().for_each(|r| *r = random(&mut rng));
 
-        // unsafe {
-        //     EMPTY_RANDOM = BitBoard::random(&mut rng);
-        // }
-    });
+    let black_to_move = random(&mut rng);
+
+    // After the others, so their keys stay what they were.
+    let mut checks = [[BitBoard::empty(); CHECKS_TO_WIN as usize + 1]; NUM_COLORS];
+    checks
+        .iter_mut()
+        .flatten()
+        .for_each(|r| *r = random(&mut rng));
+
+    Keys {
+        pieces,
+        black_to_move,
+        castling,
+        en_passant,
+        checks,
+    }
 }
 
-// TODO: Update incrementally on board
-pub fn hash(board: &Board) -> u64 {
-    let mut hash = BitBoard(0);
+fn cuckoo_h1(key: u64) -> usize {
+    (key & 0x1fff) as usize
+}
 
-    for i in 0..64 {
-        let piece = board.piece_on(Square(i));
+fn cuckoo_h2(key: u64) -> usize {
+    ((key >> 16) & 0x1fff) as usize
+}
 
-        if let Some(piece) = piece {
-            let color = board.color_on(Square(i));
+// Can the piece go between the squares on an empty board?
+fn reversible_move(piece: Piece, s1: Square, s2: Square) -> bool {
+    let dr = (s1.rank() as i8 - s2.rank() as i8).abs();
+    let df = (s1.file() as i8 - s2.file() as i8).abs();
 
-            if let Some(color) = color {
-                unsafe { hash ^= RANDOMS[(piece as usize) * (color as usize)][i as usize] }
+    match piece {
+        Piece::Knight => (dr == 1 && df == 2) || (dr == 2 && df == 1),
+        Piece::Bishop => dr == df,
+        Piece::Rook => dr == 0 || df == 0,
+        Piece::Queen => dr == df || dr == 0 || df == 0,
+        Piece::King => dr <= 1 && df <= 1,
+        Piece::Pawn => false,
+    }
+}
+
+fn gen_cuckoo() -> Cuckoo {
+    let mut cuckoo = Cuckoo {
+        keys: [0; CUCKOO_SIZE],
+        moves: [(Square(0), Square(0)); CUCKOO_SIZE],
+    };
+    let black_to_move = keys().black_to_move;
+
+    for &piece in &[
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+        Piece::King,
+    ] {
+        for &color in &[Color::White, Color::Black] {
+            let randoms = piece_random(piece, color);
+
+            for s1 in 0..64 {
+                for s2 in (s1 + 1)..64 {
+                    if !reversible_move(piece, Square(s1), Square(s2)) {
+                        continue;
+                    }
+
+                    let mut key = (randoms[s1 as usize] ^ randoms[s2 as usize] ^ black_to_move).0;
+                    let mut mv = (Square(s1), Square(s2));
+
+                    // Kick out whatever is in our slot, and move it to its other slot,
+                    // until we find an empty one.
+                    let mut i = cuckoo_h1(key);
+                    loop {
+                        core::mem::swap(&mut cuckoo.keys[i], &mut key);
+                        core::mem::swap(&mut cuckoo.moves[i], &mut mv);
+                        if key == 0 {
+                            break;
+                        }
+                        i = if i == cuckoo_h1(key) {
+                            cuckoo_h2(key)
+                        } else {
+                            cuckoo_h1(key)
+                        };
+                    }
+                }
             }
         }
     }
+    cuckoo
+}
+
+// The reversible move (in either direction) that changes a position's key by
+// `move_key`, if there is one. Side to move is included, so this is the xor of
+// the keys of two positions with different sides to move.
+pub fn cuckoo_move(move_key: u64) -> Option<(Square, Square)> {
+    let cuckoo = cuckoo();
+    for &i in &[cuckoo_h1(move_key), cuckoo_h2(move_key)] {
+        if cuckoo.keys[i] == move_key && move_key != 0 {
+            return Some(cuckoo.moves[i]);
+        }
+    }
+    None
+}
 
+// The squares strictly between two squares on a line, empty if they aren't on one.
+pub fn between(s1: Square, s2: Square) -> BitBoard {
+    let dr = s2.rank() as i8 - s1.rank() as i8;
+    let df = s2.file() as i8 - s1.file() as i8;
+    if !(dr == 0 || df == 0 || dr.abs() == df.abs()) {
+        return BitBoard::empty();
+    }
+
+    let mut bb = BitBoard::empty();
+    let (mut rank, mut file) = (s1.rank() as i8 + dr.signum(), s1.file() as i8 + df.signum());
+    while (rank, file) != (s2.rank() as i8, s2.file() as i8) {
+        bb.flip_mut(Square::new(rank as u8, file as u8));
+        rank += dr.signum();
+        file += df.signum();
+    }
+    bb
+}
+
+#[inline]
+fn piece_random(piece: Piece, color: Color) -> &'static [BitBoard; 64] {
+    &keys().pieces[(piece as usize) * NUM_COLORS + (color as usize)]
+}
+
+// The position's key, for the TP and repetitions. Boards keep theirs up to date
+// (see Board::key), so this is only the three-check part on top.
+pub fn hash(board: &Board) -> u64 {
+    let mut hash = BitBoard(board.key);
+    if board.variant.counts_checks() {
+        let keys = keys();
+        for (color, &checks) in board.checks.iter().enumerate() {
+            hash ^= keys.checks[color][usize::min(checks as usize, CHECKS_TO_WIN as usize)];
+        }
+    }
     hash.0
 }
 
+// Board::key from scratch: the pieces, side to move, castling and en passant.
+pub fn board_key(board: &Board) -> u64 {
+    let mut key = BitBoard(state_key(board));
+    for piece in 0..NUM_PIECES {
+        let piece = Piece::from_usize(piece).unwrap();
+
+        for &color in &[Color::White, Color::Black] {
+            let randoms = piece_random(piece, color);
+            for sq in *board.pieces(piece) & board.color_combined(color) {
+                key ^= randoms[sq.0 as usize];
+            }
+        }
+    }
+    key.0
+}
+
+// The part of Board::key that isn't pieces.
+pub(crate) fn state_key(board: &Board) -> u64 {
+    let mut key = BitBoard(0);
+    let keys = keys();
+    if board.side_to_move == Color::Black {
+        key ^= keys.black_to_move;
+    }
+
+    key ^= keys.castling[board.castling as usize];
+
+    if let Some(sq) = board.en_passant {
+        key ^= keys.en_passant[sq.file() as usize];
+    }
+    key.0
+}
+
+// How the pieces' part of Board::key changed since the board had `pieces` and
+// `colors`. Only the squares a move touched differ, two to four of them.
+pub(crate) fn pieces_key_change(
+    pieces: &[BitBoard; NUM_PIECES],
+    colors: &[BitBoard; NUM_COLORS],
+    board: &Board,
+) -> u64 {
+    let recolored = (colors[0] ^ board.color_combined[0]) | (colors[1] ^ board.color_combined[1]);
+    let mut change = BitBoard(0);
+    for (i, (&before, &after)) in pieces.iter().zip(&board.pieces).enumerate() {
+        if before == after && before & recolored == BitBoard::empty() {
+            continue;
+        }
+        let piece = Piece::from_usize(i).unwrap();
+        for &color in &[Color::White, Color::Black] {
+            let c = color as usize;
+            let randoms = piece_random(piece, color);
+            for sq in (before & colors[c]) ^ (after & board.color_combined[c]) {
+                change ^= randoms[sq.0 as usize];
+            }
+        }
+    }
+    change.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chess::Board;
+    use crate::chess::{Board, Movement, Variant};
     use crate::movegen::{gen_moves_once, MoveGen};
     use std::collections::HashMap;
 
+    #[test]
+    fn test_cuckoo_count() {
+        let count = cuckoo().keys.iter().filter(|&&k| k != 0).count();
+        // 2 * (knight 168 + bishop 280 + rook 448 + queen 728 + king 210)
+        assert_eq!(count, 3668);
+    }
+
+    #[test]
+    fn test_cuckoo_move() {
+        let board = Board::from_start_pos();
+        let after = board.make_move(&Movement::from_notation("g1f3").unwrap());
+        let g1 = Square::from_notation("g1").unwrap();
+        let f3 = Square::from_notation("f3").unwrap();
+        assert_eq!(cuckoo_move(hash(&board) ^ hash(&after)), Some((g1, f3)));
+
+        // Pawn moves can't be undone
+        let after = board.make_move(&Movement::from_notation("e2e3").unwrap());
+        assert_eq!(cuckoo_move(hash(&board) ^ hash(&after)), None);
+    }
+
+    #[test]
+    fn test_three_check_keys() {
+        let board = Board::from_start_pos();
+        let mut three_check = board.clone();
+        three_check.variant = Variant::ThreeCheck;
+        assert_ne!(hash(&board), hash(&three_check));
+
+        // Checks only count in three-check.
+        let mut checked = three_check.clone();
+        checked.checks = [1, 0];
+        assert_ne!(hash(&three_check), hash(&checked));
+        let mut board = board.clone();
+        board.checks = [1, 0];
+        assert_eq!(hash(&board), hash(&Board::from_start_pos()));
+    }
+
+    #[test]
+    fn test_between() {
+        let sq = |s: &str| Square::from_notation(s).unwrap();
+        let bb = |squares: &[&str]| {
+            let mut bb = BitBoard::empty();
+            squares.iter().for_each(|s| bb.flip_mut(sq(s)));
+            bb
+        };
+
+        assert_eq!(between(sq("a1"), sq("a4")), bb(&["a2", "a3"]));
+        assert_eq!(between(sq("f6"), sq("c3")), bb(&["e5", "d4"]));
+        assert_eq!(between(sq("a1"), sq("b2")), BitBoard::empty());
+        assert_eq!(between(sq("g1"), sq("f3")), BitBoard::empty());
+    }
+
     fn test_zobrist_collisions_hashmap(s: &mut HashMap<u64, Board>, depth: u16, board: &Board) {
         if depth == 0 {
             return;
@@ -65,7 +341,11 @@ mod tests {
             let h = hash(board);
             let previous = s.insert(h, board.clone());
             if let Some(previous) = &previous {
-                if previous != board {
+                // The halfmove clock is not part of the key, transpositions can differ in it.
+                let mut previous = previous.clone();
+                previous.halfmove_clock = board.halfmove_clock;
+
+                if &previous != board {
                     eprintln!("previous:\n{}\ncurrent:\n{}\n", previous, board);
                     eprintln!(
                         "prev enp: {:?} curr enp {:?}",
@@ -79,7 +359,6 @@ mod tests {
         }
     }
 
-    #[ignore]
     #[test]
     fn test_zobrist_collisions() {
         init_once();
@@ -87,7 +366,59 @@ mod tests {
 
         let mut tp = HashMap::new();
 
-        // NOTE: Once you get this to pass, increase ply to 5.
+        // NOTE: Passes at ply 5 too, but takes a while.
         test_zobrist_collisions_hashmap(&mut tp, 4, &Board::from_start_pos());
     }
+
+    #[test]
+    fn test_hash_transposition() {
+        gen_moves_once();
+
+        let mut a = Board::from_start_pos();
+        let mut b = Board::from_start_pos();
+        for mv in "g1f3 g8f6 b1c3".split(' ') {
+            a.make_move_mut(&Movement::from_notation(mv).unwrap());
+        }
+        for mv in "b1c3 g8f6 g1f3".split(' ') {
+            b.make_move_mut(&Movement::from_notation(mv).unwrap());
+        }
+        assert_eq!(hash(&a), hash(&b));
+
+        // Same pieces, different side to move.
+        assert_ne!(hash(&a), hash(&a.other_side()));
+    }
+
+    fn check_incremental_keys(board: &Board, depth: u16) {
+        for mv in MoveGen::new_legal(board) {
+            let child = board.make_move(&mv);
+            assert_eq!(
+                child.key,
+                board_key(&child),
+                "{} after {} in {}",
+                child.to_fen(),
+                mv,
+                board.to_fen()
+            );
+            if depth > 1 {
+                check_incremental_keys(&child, depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_keys() {
+        gen_moves_once();
+        // Castling both ways, en passant, promotions and captures of castling rooks.
+        for fen in &[
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            check_incremental_keys(&Board::from_fen(fen).unwrap(), 3);
+        }
+        // Chess960 castling, where the king and rook can swap squares.
+        let mut board = Board::from_fen("2rk1r2/8/8/8/8/8/8/2RK1R2 w CFcf - 0 1").unwrap();
+        board.chess960 = true;
+        check_incremental_keys(&board, 2);
+    }
 }
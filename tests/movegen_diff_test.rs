@@ -0,0 +1,138 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess, EnPassantMode, Position, PositionError};
+use yobmef::chess::{Board, Movement};
+use yobmef::movegen::{gen_moves_once, MoveGen};
+use yobmef::random;
+
+// Differential testing against shakmaty: play random games from odd positions
+// and check that both agree on the legal moves and the position after every
+// move. Perft only compares counts from a handful of positions, this catches a
+// wrong move hiding behind a missing one, and positions perft never reaches.
+//
+// Everything is seeded, a failure prints the line that got there.
+
+const GAMES_PER_POSITION: u64 = 50;
+const MAX_PLIES: usize = 200;
+
+const POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    // En passant out of and into pins, and discovered checks.
+    "8/8/3p4/KPp4r/1R2Pp1k/8/6P1/8 w - c6 0 1",
+    "4k3/8/8/2KPp2r/8/8/8/8 w - e6 0 1",
+];
+
+const CHESS960_POSITIONS: &[&str] = &[
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+    "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w KQkq - 0 9",
+    "2r1kr2/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1",
+];
+
+// Material for random placements (see random.rs), kings are added.
+const MATERIAL: &[&str] = &["", "Qr", "RBnp", "PPPppp", "QRRBNPPqrbnnpp", "NNBBbbnn"];
+const PLACEMENTS_PER_MATERIAL: u64 = 20;
+
+// The FEN fields both can be compared on: no fullmove number on our side, and
+// Chess960 castling rights are written differently.
+fn fields(fen: &str, chess960: bool) -> Vec<String> {
+    fen.split_whitespace()
+        .take(5)
+        .enumerate()
+        .filter(|(i, _)| !(chess960 && *i == 2))
+        .map(|(_, f)| f.to_string())
+        .collect()
+}
+
+fn sorted_moves(board: &Board) -> Vec<String> {
+    let mut moves: Vec<String> = MoveGen::new_legal(board).map(|m| m.to_notation()).collect();
+    moves.sort();
+    moves
+}
+
+fn play_random_game(fen: &str, chess960: bool, seed: u64) {
+    let mode = if chess960 {
+        CastlingMode::Chess960
+    } else {
+        CastlingMode::Standard
+    };
+    let mut board = Board::from_fen(fen).unwrap();
+    board.chess960 = chess960;
+    // Random placements can have checks no move could have given.
+    let mut pos: Chess = fen
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(mode)
+        .or_else(PositionError::ignore_impossible_check)
+        .unwrap();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut line = Vec::new();
+
+    for _ in 0..MAX_PLIES {
+        let context = || format!("from {} after {}", fen, line.join(" "));
+
+        let ours = sorted_moves(&board);
+        let mut theirs: Vec<String> = pos
+            .legal_moves()
+            .iter()
+            .map(|m| m.to_uci(mode).to_string())
+            .collect();
+        theirs.sort();
+        assert_eq!(ours, theirs, "legal moves differ {}", context());
+
+        let fen_theirs = Fen::from_position(&pos, EnPassantMode::Always).to_string();
+        assert_eq!(
+            fields(&board.to_fen(), chess960),
+            fields(&fen_theirs, chess960),
+            "positions differ {}",
+            context()
+        );
+        assert_eq!(board.in_check(), pos.is_check(), "check {}", context());
+
+        if ours.is_empty() {
+            break;
+        }
+        let uci = &ours[rng.gen_range(0..ours.len())];
+        board.make_move_mut(&Movement::from_notation(uci).unwrap());
+        let mv = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        pos.play_unchecked(mv);
+        line.push(uci.clone());
+    }
+}
+
+#[test]
+fn test_movegen_matches_shakmaty() {
+    gen_moves_once();
+    for (i, fen) in POSITIONS.iter().enumerate() {
+        for game in 0..GAMES_PER_POSITION {
+            play_random_game(fen, false, i as u64 * 1000 + game);
+        }
+    }
+}
+
+#[test]
+fn test_movegen_matches_shakmaty_chess960() {
+    gen_moves_once();
+    for (i, fen) in CHESS960_POSITIONS.iter().enumerate() {
+        for game in 0..GAMES_PER_POSITION {
+            play_random_game(fen, true, i as u64 * 1000 + game);
+        }
+    }
+}
+
+#[test]
+fn test_movegen_matches_shakmaty_random_placements() {
+    gen_moves_once();
+    for (i, material) in MATERIAL.iter().enumerate() {
+        let mut rng = StdRng::seed_from_u64(i as u64);
+        for game in 0..PLACEMENTS_PER_MATERIAL {
+            let board = random::random_placement(material, &mut rng).unwrap();
+            play_random_game(&board.to_fen(), false, i as u64 * 1000 + game);
+        }
+    }
+}
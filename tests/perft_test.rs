@@ -55,3 +55,24 @@ fn test_perft_5_kiwipete() {
             .unwrap();
     test_perft(&board, 5, 193690690);
 }
+
+// Chess960 positions, castling is the king taking its own rook.
+fn chess960(fen: &str) -> Board {
+    let mut board = Board::from_fen(fen).unwrap();
+    board.chess960 = true;
+    board
+}
+
+#[test]
+fn test_perft_4_chess960() {
+    gen_moves_once();
+    let board = chess960("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9");
+    test_perft(&board, 4, 326672);
+}
+
+#[test]
+fn test_perft_4_chess960_king_next_to_rook() {
+    gen_moves_once();
+    let board = chess960("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w KQkq - 0 9");
+    test_perft(&board, 4, 287739);
+}
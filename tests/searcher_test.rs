@@ -1,7 +1,7 @@
 use yobmef::{
     chess::{Board, Movement},
     movegen::gen_moves_once,
-    search::Searcher,
+    search::{SearchLimits, Searcher},
 };
 
 // TODO: Optional parameter for what range the evaluation should be in
@@ -15,7 +15,7 @@ macro_rules! test {
             gen_moves_once();
             let board = Board::from_fen($fen).expect("fen should be valid");
             let mut searcher = Searcher::new();
-            let search_result = searcher.search_depth(&board, 5);
+            let search_result = searcher.go(&board, SearchLimits::depth(5));
             let got = search_result.mv;
             let want = Movement::from_notation($want).unwrap();
             eprintln!("{}", board);
@@ -30,7 +30,7 @@ macro_rules! test {
             gen_moves_once();
             let board = Board::from_fen($fen).expect("fen should be valid");
             let mut searcher = Searcher::new();
-            let search_result = searcher.search_depth(&board, 5);
+            let search_result = searcher.go(&board, SearchLimits::depth(5));
             let got = search_result.mv;
             let not = Movement::from_notation($not).unwrap();
             eprintln!("{}", board);
@@ -0,0 +1,111 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Talk to the engine binary like a GUI would.
+fn run_engine(input: &str) -> String {
+    let mut engine = Command::new(env!("CARGO_BIN_EXE_yobmef"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start engine");
+
+    engine
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = engine.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_handshake() {
+    let out = run_engine("uci\nisready\nquit\n");
+    let lines: Vec<&str> = out.lines().collect();
+
+    assert!(lines[0].starts_with("id name Yobmef"));
+    assert!(lines.contains(&"option name Hash type spin default 64 min 1 max 4096"));
+    assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+}
+
+#[test]
+fn test_game() {
+    let out = run_engine(
+        "uci\n\
+         ucinewgame\n\
+         position startpos moves e2e4 e7e5\n\
+         go depth 3\n\
+         position fen 4k3/8/8/8/8/8/q7/4K3 b - - 0 1\n\
+         go depth 3\n",
+    );
+
+    let bestmoves: Vec<&str> = out
+        .lines()
+        .filter(|line| line.starts_with("bestmove "))
+        .collect();
+    assert_eq!(bestmoves.len(), 2, "output:\n{}", out);
+    // Deep enough to have a reply to ponder on.
+    assert!(bestmoves[0].contains(" ponder "), "output:\n{}", out);
+    assert!(out.lines().any(|line| line.starts_with("info depth 3")));
+}
+
+#[test]
+fn test_quit() {
+    // Nothing after quit gets answered, and closing stdin also ends the loop.
+    let out = run_engine("quit\nisready\n");
+    assert!(out.is_empty(), "output:\n{}", out);
+    assert_eq!(run_engine("isready\n"), "readyok\n");
+}
+
+#[test]
+fn test_stop() {
+    // Answered while searching, not after.
+    let out = run_engine("go infinite\nisready\nstop\n");
+    let lines: Vec<&str> = out.lines().collect();
+    let readyok = lines.iter().position(|&line| line == "readyok");
+    let bestmove = lines.iter().position(|line| line.starts_with("bestmove "));
+    assert!(readyok < bestmove && readyok.is_some(), "output:\n{}", out);
+    assert_eq!(bestmove, Some(lines.len() - 1), "output:\n{}", out);
+
+    // Quit doesn't wait for the search.
+    let out = run_engine("go infinite\nquit\n");
+    assert!(!out.contains("bestmove"), "output:\n{}", out);
+}
+
+#[test]
+fn test_debug() {
+    // Closing stdin waits for the search to finish, unlike quit.
+    let out = run_engine("go depth 2\n");
+    assert!(!out.contains("info string"), "output:\n{}", out);
+
+    let out = run_engine("debug on\nsetoption name Contempt value 1\ngo depth 2\n");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "info string unknown option Contempt");
+    assert!(
+        lines[1].starts_with("info string thinking"),
+        "output:\n{}",
+        out
+    );
+    assert!(lines[lines.len() - 2].starts_with("info string tt "));
+}
+
+#[test]
+fn test_go_perft() {
+    let out = run_engine("position startpos moves e2e4\ngo perft 2\ngo perft 1\nquit\n");
+    let lines: Vec<&str> = out.lines().collect();
+
+    // 20 replies to e2e4, each with around 30 moves for white.
+    assert!(lines.contains(&"e7e5: 29"), "output:\n{}", out);
+    assert!(lines.contains(&"Nodes searched: 600"), "output:\n{}", out);
+    assert!(
+        lines.contains(&"g8f6: 1") && lines.contains(&"e7e5: 1"),
+        "output:\n{}",
+        out
+    );
+    assert!(lines.contains(&"Nodes searched: 20"), "output:\n{}", out);
+    assert!(!out.contains("bestmove"));
+}